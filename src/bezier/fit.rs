@@ -3,6 +3,7 @@
 use super::{de_casteljau2, de_casteljau3, BezierCurve};
 use pathfinder_geometry::vector::Vector2F;
 use rayon::prelude::*;
+use std::mem;
 
 /// Maximum number of iterations to perform when trying to improve the curve fit
 const MAX_ITERATIONS: usize = 4;
@@ -64,6 +65,60 @@ pub fn fit_curve(points: &[Vector2F], max_error: f32) -> Option<Vec<BezierCurve>
     }
 }
 
+/// Number of points sampled from each curve when checking whether curves can be merged
+const MERGE_SAMPLES: usize = 8;
+
+///
+/// Fuses runs of consecutive curves into single curves wherever one curve can follow all of
+/// them to within the tolerance
+///
+/// Fitting leaves behind lots of tiny segments along nearly straight or gently bending parts of
+/// a stroke, and this cleans those up.
+///
+pub fn merge_collinear(curves: Vec<BezierCurve>, tolerance: f32) -> Vec<BezierCurve> {
+    let sample = |curve: &BezierCurve, samples: &mut Vec<Vector2F>| {
+        // skip the start, since it is the end of the previous curve
+        samples.extend(curve.eval_uniform(MERGE_SAMPLES + 1).into_iter().skip(1));
+    };
+
+    let mut merged = Vec::with_capacity(curves.len());
+    let mut curves = curves.into_iter();
+    let mut current = match curves.next() {
+        Some(current) => current,
+        None => return merged,
+    };
+    let mut run = vec![current.points()[0]];
+    sample(&current, &mut run);
+
+    for next in curves {
+        let run_len = run.len();
+        sample(&next, &mut run);
+
+        let fused = if run.len() <= MAX_POINTS_TO_FIT {
+            let mut fit =
+                fit_curve_cubic(&run, &start_tangent(&run), &end_tangent(&run), tolerance);
+            if fit.len() == 1 {
+                fit.pop()
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        match fused {
+            Some(fused) => current = fused,
+            None => {
+                merged.push(mem::replace(&mut current, next));
+                run.drain(..run_len - 1);
+            }
+        }
+    }
+
+    merged.push(current);
+    merged
+}
+
 ///
 /// Fits a bezier curve to a subset of points
 ///
@@ -330,7 +385,11 @@ fn reparameterize(points: &[Vector2F], chords: &[f32], curve: &BezierCurve) -> V
 ///
 /// Uses newton-raphson to find a root for a curve
 ///
-fn newton_raphson_root_find(curve: &BezierCurve, point: &Vector2F, estimated_t: f32) -> f32 {
+pub(super) fn newton_raphson_root_find(
+    curve: &BezierCurve,
+    point: &Vector2F,
+    estimated_t: f32,
+) -> f32 {
     let [start, cp1, cp2, end] = curve.clone().into_points();
 
     // Compute Q(t) (where Q is our curve)
@@ -359,3 +418,203 @@ fn newton_raphson_root_find(curve: &BezierCurve, point: &Vector2F, estimated_t:
         estimated_t - (numerator / denominator)
     }
 }
+
+///
+/// Fits a curve to points as they arrive, such as while a stroke is being drawn
+///
+/// Only the points since the last committed curve are refitted when a point is added. Once
+/// that tail reaches MAX_POINTS_TO_FIT points its curves are committed, so the cost of adding
+/// a point doesn't grow with the length of the stroke.
+///
+#[derive(Debug, Clone)]
+pub struct IncrementalFit {
+    max_error: f32,
+    committed: Vec<BezierCurve>,
+    tail: Vec<Vector2F>,
+    tail_curves: Vec<BezierCurve>,
+    // the tangent the committed curves leave off with, so the tail continues smoothly
+    tail_start_tangent: Option<Vector2F>,
+}
+
+impl IncrementalFit {
+    pub fn new(max_error: f32) -> Self {
+        Self {
+            max_error,
+            committed: vec![],
+            tail: vec![],
+            tail_curves: vec![],
+            tail_start_tangent: None,
+        }
+    }
+
+    ///
+    /// Adds a point to the end of the stroke and refits the tail
+    ///
+    pub fn push(&mut self, point: Vector2F) {
+        // Repeated points make for undefined tangents
+        if self.tail.last() == Some(&point) {
+            return;
+        }
+
+        self.tail.push(point);
+        if self.tail.len() < 2 {
+            return;
+        }
+
+        let start_tangent = self
+            .tail_start_tangent
+            .unwrap_or_else(|| start_tangent(&self.tail));
+        let end_tangent = end_tangent(&self.tail);
+        self.tail_curves =
+            fit_curve_cubic(&self.tail, &start_tangent, &end_tangent, self.max_error);
+
+        if self.tail.len() >= MAX_POINTS_TO_FIT {
+            self.commit_tail();
+        }
+    }
+
+    ///
+    /// Moves the tail's curves into the committed set, starting a new tail where they end
+    ///
+    fn commit_tail(&mut self) {
+        let last = match self.tail_curves.last() {
+            Some(last) => last.clone(),
+            None => return,
+        };
+
+        let [_, _, p2, p3] = *last.points();
+        let tangent = if p2 == p3 {
+            None
+        } else {
+            Some((p3 - p2).normalize())
+        };
+
+        self.committed.append(&mut self.tail_curves);
+        self.tail.clear();
+        self.tail.push(p3);
+        self.tail_start_tangent = tangent;
+    }
+
+    ///
+    /// The curves fitted so far, including the provisional ones at the tail
+    ///
+    pub fn curves(&self) -> impl Iterator<Item = &BezierCurve> {
+        self.committed.iter().chain(self.tail_curves.iter())
+    }
+
+    ///
+    /// Finishes the stroke, returning every curve that was fitted
+    ///
+    pub fn finish(mut self) -> Vec<BezierCurve> {
+        self.committed.append(&mut self.tail_curves);
+        merge_collinear(self.committed, self.max_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    // a long wavy stroke, like one drawn by hand
+    #[inline]
+    fn stroke(len: usize) -> Vec<Vector2F> {
+        (0..len)
+            .map(|i| {
+                let x = i as f32 * 0.5;
+                Vector2F::new(x, (x / 20.0).sin() * 40.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tail_never_grows_past_the_limit() {
+        let mut fit = IncrementalFit::new(1.0);
+        for point in stroke(1000) {
+            fit.push(point);
+            assert!(fit.tail.len() < MAX_POINTS_TO_FIT);
+        }
+        assert!(!fit.committed.is_empty());
+    }
+
+    #[test]
+    fn finished_curves_span_the_stroke() {
+        let points = stroke(500);
+        let mut fit = IncrementalFit::new(1.0);
+        points.iter().for_each(|p| fit.push(*p));
+        let curves = fit.finish();
+
+        assert_eq!(curves[0].points()[0], points[0]);
+        assert_eq!(curves.last().unwrap().points()[3], *points.last().unwrap());
+        // each curve picks up where the last one left off
+        curves
+            .windows(2)
+            .for_each(|w| assert_eq!(w[0].points()[3], w[1].points()[0]));
+    }
+
+    #[test]
+    fn near_straight_strokes_collapse() {
+        // a straight stroke with a little hand wobble
+        let points: Vec<Vector2F> = (0..80)
+            .map(|i| {
+                let x = i as f32;
+                Vector2F::new(x, x * 0.3 + (x / 6.0).sin() * 0.2)
+            })
+            .collect();
+        // fitted tightly, the wobble takes several curves
+        let curves = fit_curve(&points, 0.05).unwrap();
+        assert!(curves.len() > 2);
+        let merged = merge_collinear(curves, 0.5);
+        assert!(merged.len() <= 2, "{} curves are left", merged.len());
+        assert_eq!(merged[0].points()[0], points[0]);
+        assert_eq!(merged.last().unwrap().points()[3], points[79]);
+    }
+
+    #[test]
+    fn corners_are_not_merged() {
+        let line = |from: Vector2F, to: Vector2F| {
+            BezierCurve::from_points([from, from.lerp(to, 1.0 / 3.0), from.lerp(to, 2.0 / 3.0), to])
+        };
+        let (a, b, c) = (
+            Vector2F::new(0.0, 0.0),
+            Vector2F::new(50.0, 0.0),
+            Vector2F::new(50.0, 50.0),
+        );
+        let merged = merge_collinear(vec![line(a, b), line(b, c)], 0.5);
+        assert_eq!(merged.len(), 2);
+
+        // but the pieces of a straight line are
+        let d = Vector2F::new(100.0, 0.0);
+        let merged = merge_collinear(vec![line(a, b), line(b, d)], 0.5);
+        assert_eq!(merged.len(), 1);
+        assert!(merge_collinear(vec![], 0.5).is_empty());
+    }
+
+    // run with `cargo test --release -- --ignored push_latency` to see the numbers
+    #[test]
+    #[ignore]
+    fn push_latency() {
+        let points = stroke(10_000);
+        let mut fit = IncrementalFit::new(1.0);
+        let mut worst = Duration::default();
+        let start = Instant::now();
+        for point in &points {
+            let push_start = Instant::now();
+            fit.push(*point);
+            worst = worst.max(push_start.elapsed());
+        }
+        let total = start.elapsed();
+
+        let refit_start = Instant::now();
+        fit_curve(&points, 1.0);
+        let refit = refit_start.elapsed();
+
+        println!(
+            "{} points: {:?} per push on average, {:?} at worst, {:?} to fit it all at once",
+            points.len(),
+            total / points.len() as u32,
+            worst,
+            refit
+        );
+    }
+}
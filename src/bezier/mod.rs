@@ -1,12 +1,23 @@
 // GPL v3.0
 
-use super::{Brush, DrawTarget, Point, Rasterizable};
-use pathfinder_geometry::{line_segment::LineSegment2F, vector::Vector2F};
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use super::{
+    layout, loading_version, quantize::PackedCoords, rasterize_polyline_into, Brush, PixelSink,
+    Point, Polygon, Rasterizable,
+};
+use euclid::default::Rect;
+use pathfinder_geometry::{
+    line_segment::LineSegment2F, transform2d::Transform2F, vector::Vector2F,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use smallvec::SmallVec;
-use std::{mem, ops::Range};
 
 mod fit;
+pub use fit::IncrementalFit;
+
+/// The default distance, in pixels, that a flattened curve may stray from the true curve.
+pub const DEFAULT_TOLERANCE: f32 = 0.25;
+/// A looser tolerance for drawing the interactive preview, where speed matters more.
+pub const PREVIEW_TOLERANCE: f32 = 1.0;
 
 // I don't know how to write deserialization code, so here's a Vec version
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -14,6 +25,22 @@ struct BezierDeser {
     coords: Vec<f32>,
 }
 
+/// How a bezier curve is fitted to a set of points.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FitMethod {
+    /// Least-squares fitting, which approximates the points within an error bound.
+    LeastSquares,
+    /// Catmull-Rom interpolation, which passes through every point.
+    CatmullRom,
+}
+
+impl Default for FitMethod {
+    #[inline]
+    fn default() -> Self {
+        Self::LeastSquares
+    }
+}
+
 /// A bezier curve.
 #[derive(Debug, Clone)]
 #[repr(transparent)]
@@ -25,32 +52,50 @@ impl Serialize for BezierCurve {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         use smallvec::smallvec;
 
-        let ser_form = BezierDeser {
-            coords: self
-                .points
-                .iter()
-                .map::<SmallVec<[f32; 2]>, _>(|vctr| smallvec![vctr.x(), vctr.y()])
-                .flat_map(|i| i.into_iter())
-                .collect(),
-        };
-        Serialize::serialize(&ser_form, serializer)
+        let coords: Vec<f32> = self
+            .points
+            .iter()
+            .map::<SmallVec<[f32; 2]>, _>(|vctr| smallvec![vctr.x(), vctr.y()])
+            .flat_map(|i| i.into_iter())
+            .collect();
+
+        // binary formats get the compact form
+        if serializer.is_human_readable() {
+            Serialize::serialize(&BezierDeser { coords }, serializer)
+        } else {
+            Serialize::serialize(&PackedCoords::pack(&coords), serializer)
+        }
     }
 }
 
 impl<'de> Deserialize<'de> for BezierCurve {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        let ser_form: BezierDeser = Deserialize::deserialize(deserializer)?;
-
-        let mut sm: SmallVec<[Vector2F; 4]> = SmallVec::with_capacity(4);
-        let mut iter = ser_form.coords.into_iter();
-        while let Some(fl1) = iter.next() {
-            // group into two
-            let fl2 = iter.next().expect("Invalid data");
-            sm.push(Vector2F::new(fl1, fl2));
+        // binary data from before coordinates were packed has them in full
+        let ser_form = if deserializer.is_human_readable() || loading_version() < layout::QUANTIZED
+        {
+            Deserialize::deserialize(deserializer)?
+        } else {
+            let packed: PackedCoords = Deserialize::deserialize(deserializer)?;
+            BezierDeser {
+                coords: packed.unpack(),
+            }
+        };
+
+        // a damaged file shouldn't be able to take the whole program down with it
+        match ser_form.coords[..] {
+            [x1, y1, x2, y2, x3, y3, x4, y4] => Ok(Self {
+                points: [
+                    Vector2F::new(x1, y1),
+                    Vector2F::new(x2, y2),
+                    Vector2F::new(x3, y3),
+                    Vector2F::new(x4, y4),
+                ],
+            }),
+            ref coords => Err(de::Error::custom(format_args!(
+                "expected 8 coordinates for a curve, found {}",
+                coords.len()
+            ))),
         }
-        Ok(Self {
-            points: sm.into_inner().unwrap(),
-        })
     }
 }
 
@@ -60,6 +105,30 @@ impl BezierCurve {
         Self { points: points }
     }
 
+    /// Elevate a quadratic bezier curve into an equivalent cubic curve.
+    #[inline]
+    pub fn from_quadratic(p0: Vector2F, p1: Vector2F, p2: Vector2F) -> Self {
+        const TWO_THIRDS: f32 = 2.0 / 3.0;
+        Self::from_points([
+            p0,
+            p0 + ((p1 - p0) * TWO_THIRDS),
+            p2 + ((p1 - p2) * TWO_THIRDS),
+            p2,
+        ])
+    }
+
+    /// Create a degenerate cubic curve that forms a straight line.
+    #[inline]
+    pub fn from_line(p0: Vector2F, p1: Vector2F) -> Self {
+        let direction = p1 - p0;
+        Self::from_points([
+            p0,
+            p0 + (direction * (1.0 / 3.0)),
+            p0 + (direction * (2.0 / 3.0)),
+            p1,
+        ])
+    }
+
     #[inline]
     pub fn fit_to(mut points: SmallVec<[Vector2F; 12]>, error: f32) -> Vec<Self> {
         // fit points to a line
@@ -75,8 +144,116 @@ impl BezierCurve {
             t1.cmp(&t2)
         });*/
 
-        println!("Fitting points: {:?}", &points);
-        fit::fit_curve(&points, error).unwrap()
+        fit::merge_collinear(fit::fit_curve(&points, error).unwrap(), error)
+    }
+
+    /// Create a chain of curves that passes through each of the points, with the tangent at each
+    /// point running parallel to the line between its neighbors.
+    pub fn from_catmull_rom(points: &[Vector2F]) -> Vec<Self> {
+        let mut points: SmallVec<[Vector2F; 12]> = points.iter().copied().collect();
+        points.dedup();
+
+        let last = match points.len() {
+            0 | 1 => return vec![],
+            len => len - 1,
+        };
+
+        // the ends are treated as though they were repeated
+        (0..last)
+            .map(|i| {
+                let prev = points[i.saturating_sub(1)];
+                let (p1, p2) = (points[i], points[i + 1]);
+                let next = points[(i + 2).min(last)];
+
+                Self::from_points([
+                    p1,
+                    p1 + (p2 - prev) * (1.0 / 6.0),
+                    p2 - (next - p1) * (1.0 / 6.0),
+                    p2,
+                ])
+            })
+            .collect()
+    }
+
+    /// Fit a set of points using the given method.
+    #[inline]
+    pub fn fit_with(points: SmallVec<[Vector2F; 12]>, error: f32, method: FitMethod) -> Vec<Self> {
+        match method {
+            FitMethod::LeastSquares => Self::fit_to(points, error),
+            FitMethod::CatmullRom => Self::from_catmull_rom(&points),
+        }
+    }
+
+    /// Get the tight axis-aligned bounds of the curve. Unlike the hull of the control points,
+    /// this only covers where the curve actually goes.
+    pub fn bounding_box(&self) -> Rect<f32> {
+        let [p0, p1, p2, p3] = self.points;
+
+        // the extrema are where the derivative along either axis is zero
+        let axis_roots = |c0: f32, c1: f32, c2: f32, c3: f32| {
+            quadratic_roots(
+                -c0 + 3.0 * c1 - 3.0 * c2 + c3,
+                2.0 * (c0 - 2.0 * c1 + c2),
+                c1 - c0,
+            )
+        };
+
+        let extrema = axis_roots(p0.x(), p1.x(), p2.x(), p3.x())
+            .into_iter()
+            .chain(axis_roots(p0.y(), p1.y(), p2.y(), p3.y()))
+            .filter(|t| *t > 0.0 && *t < 1.0)
+            .map(|t| self.eval(t));
+
+        Rect::from_points(
+            [p0, p3]
+                .iter()
+                .copied()
+                .chain(extrema)
+                .map(|pt| pt.into_euclid()),
+        )
+    }
+
+    /// Find the point on the curve closest to `pt`. Returns its parameter and its distance
+    /// from `pt`.
+    pub fn closest_point(&self, pt: Vector2F) -> (f32, f32) {
+        const SAMPLES: usize = 16;
+        const ITERATIONS: usize = 4;
+
+        let dist_at = |t: f32| distance(&self.eval(t), &pt);
+
+        // start at the closest of a few samples, then refine it with newton's method
+        let mut t = self
+            .eval_uniform(SAMPLES + 1)
+            .into_iter()
+            .enumerate()
+            .fold((0.0, f32::INFINITY), |best, (i, sample)| {
+                let dist = distance(&sample, &pt);
+                if dist < best.1 {
+                    (i as f32 / SAMPLES as f32, dist)
+                } else {
+                    best
+                }
+            })
+            .0;
+
+        for _ in 0..ITERATIONS {
+            let next = fit::newton_raphson_root_find(self, &pt, t).clamp(0.0, 1.0);
+            if next.is_nan() || dist_at(next) > dist_at(t) {
+                break;
+            }
+            t = next;
+        }
+
+        (t, dist_at(t))
+    }
+
+    /// Get the filled outline of this curve when it is stroked `width` wide.
+    #[inline]
+    pub fn to_outline(&self, width: f32) -> Polygon {
+        let points: SmallVec<[Vector2F; 12]> = std::iter::once(self.points[0])
+            .chain(self.edges().map(|l| l.to()))
+            .collect();
+        Polygon::from_stroke(&points, width).expect("Curve outline has no points")
     }
 
     #[inline]
@@ -91,6 +268,42 @@ impl BezierCurve {
         }
     }
 
+    /// Evaluate the curve at `count` evenly spaced parameters, from the start to the end
+    /// inclusive. This uses forward differencing, so each point after the first costs a few
+    /// additions instead of a full evaluation.
+    pub fn eval_uniform(&self, count: usize) -> SmallVec<[Vector2F; 16]> {
+        let [p0, p1, p2, p3] = self.points;
+        match count {
+            0 => return SmallVec::new(),
+            1 => return std::iter::once(p0).collect(),
+            _ => (),
+        }
+
+        // the curve as a polynomial a t^3 + b t^2 + c t + p0
+        let a = p3 - p0 + (p1 - p2) * 3.0;
+        let b = (p0 + p2) * 3.0 - p1 * 6.0;
+        let c = (p1 - p0) * 3.0;
+
+        let h = 1.0 / (count - 1) as f32;
+        let (h2, h3) = (h * h, h * h * h);
+        let mut pt = p0;
+        let mut d1 = a * h3 + b * h2 + c * h;
+        let mut d2 = a * (6.0 * h3) + b * (2.0 * h2);
+        let d3 = a * (6.0 * h3);
+
+        let mut points: SmallVec<[Vector2F; 16]> = SmallVec::with_capacity(count);
+        points.push(p0);
+        (1..count - 1).for_each(|_| {
+            pt += d1;
+            d1 += d2;
+            d2 += d3;
+            points.push(pt);
+        });
+        // land exactly on the end, rather than wherever the rounding error leaves us
+        points.push(p3);
+        points
+    }
+
     #[inline]
     pub fn into_points(self) -> [Vector2F; 4] {
         self.points
@@ -106,71 +319,147 @@ impl BezierCurve {
         &mut self.points
     }
 
+    /// Reverse the direction of this curve, so that it runs from its end to its start.
+    #[inline]
+    pub fn reverse(&mut self) {
+        self.points.reverse();
+    }
+
+    /// Move this curve by an offset.
+    #[inline]
+    pub fn translate(&mut self, offset: Vector2F) {
+        self.points.iter_mut().for_each(|p| *p += offset);
+    }
+
+    /// Apply an affine transform to this curve.
+    #[inline]
+    pub fn transform(&mut self, transform: &Transform2F) {
+        self.points.iter_mut().for_each(|p| *p = *transform * *p);
+    }
+
+    /// Get a copy of this curve that runs in the opposite direction.
+    #[inline]
+    pub fn reversed(&self) -> Self {
+        let mut curve = self.clone();
+        curve.reverse();
+        curve
+    }
+
+    /// Split this curve into two at the given parameter.
+    #[inline]
+    pub fn split(&self, t: f32) -> (Self, Self) {
+        let [p1, p2, p3, p4] = self.points;
+
+        let p12 = de_casteljau2(t, p1, p2);
+        let p23 = de_casteljau2(t, p2, p3);
+        let p34 = de_casteljau2(t, p3, p4);
+        let p123 = de_casteljau2(t, p12, p23);
+        let p234 = de_casteljau2(t, p23, p34);
+        let mid = de_casteljau2(t, p123, p234);
+
+        (
+            Self::from_points([p1, p12, p123, mid]),
+            Self::from_points([mid, p234, p34, p4]),
+        )
+    }
+
+    /// An upper bound on how far this curve strays from the straight line between its endpoints.
     #[inline]
-    pub fn edges(&self) -> Edges<'_> {
+    pub fn flatness(&self) -> f32 {
         let [start, control_a, control_b, end] = self.points();
 
-        let curve_length_bound =
-            distance(start, control_a) + distance(control_a, control_b) + distance(control_b, end);
-        let clb2 = curve_length_bound.powi(2);
+        // the curve lies within the convex hull of its control points, so the control point
+        // furthest from the chord bounds the deviation
+        let chord = *end - *start;
+        let chord_length = chord.length();
+        let deviation = |pt: &Vector2F| {
+            if chord_length <= f32::EPSILON {
+                distance(pt, start)
+            } else {
+                chord.cross_product(&(*pt - *start)).abs() / chord_length
+            }
+        };
 
-        let num_segments = ((clb2 + 800.0).sqrt() / 8.0) as i32;
-        let t_interval = 1f32 / (num_segments as f32);
+        deviation(control_a).max(deviation(control_b))
+    }
 
-        Edges {
-            curve: self,
-            internal: (0..num_segments),
-            t_interval,
-            prev: 0.0,
-        }
+    /// The length of this curve, measured along its edges when flattened to within `tolerance`.
+    #[inline]
+    pub fn length(&self, tolerance: f32) -> f32 {
+        self.edges_with_tolerance(tolerance)
+            .map(|l| l.vector().length())
+            .sum()
+    }
+
+    /// Flatten this curve into line segments, using the default tolerance.
+    #[inline]
+    pub fn edges(&self) -> Edges {
+        self.edges_with_tolerance(DEFAULT_TOLERANCE)
+    }
+
+    /// Flatten this curve into line segments that are each within `tolerance` of the curve.
+    #[inline]
+    pub fn edges_with_tolerance(&self, tolerance: f32) -> Edges {
+        let mut stack = SmallVec::new();
+        stack.push((self.clone(), 0));
+
+        Edges { stack, tolerance }
     }
 }
 
 impl Rasterizable for BezierCurve {
     #[inline]
-    fn rasterize(&self, target: &DrawTarget, brush: &Brush) {
-        self.edges().for_each(|l| l.rasterize(target, brush))
+    fn rasterize_into(&self, img: &mut dyn PixelSink, brush: &Brush, tolerance: f32) {
+        rasterize_polyline_into(img, self.edges_with_tolerance(tolerance), brush);
     }
 }
 
-/// Iterate over a Bezier curve's edges.
-pub struct Edges<'a> {
-    curve: &'a BezierCurve,
-    internal: Range<i32>,
-    prev: f32,
-    t_interval: f32,
+// don't subdivide forever if the tolerance is tiny
+const MAX_EDGE_DEPTH: u32 = 16;
+
+/// Iterate over a Bezier curve's edges. There is always at least one edge, but how many there
+/// are depends on how the curve bends, so the exact count isn't known until the end.
+pub struct Edges {
+    // curves left to flatten, and how many times each one has been subdivided
+    stack: SmallVec<[(BezierCurve, u32); 16]>,
+    tolerance: f32,
 }
 
-impl<'a> Iterator for Edges<'a> {
+impl Iterator for Edges {
     type Item = LineSegment2F;
 
     #[inline]
     fn next(&mut self) -> Option<LineSegment2F> {
-        let i = match self.internal.next() {
-            Some(i) => i,
-            None => return None,
-        };
-
-        // figure out which T's to evaluate at
-        let mut t1 = (i as f32 + 1.0) * self.t_interval;
-        let t2 = t1;
-        mem::swap(&mut self.prev, &mut t1);
-
-        // evaluate the points at the t's
-        let a1 = self.curve.eval(t1);
-        let a2 = self.curve.eval(t2);
+        loop {
+            let (curve, depth) = self.stack.pop()?;
+
+            // a curve with a NaN in it can't be split into anything better
+            let flatness = curve.flatness();
+            if depth >= MAX_EDGE_DEPTH || flatness <= self.tolerance || flatness.is_nan() {
+                let [start, _, _, end] = curve.into_points();
+                return Some(LineSegment2F::new(start, end));
+            }
 
-        Some(LineSegment2F::new(a1, a2))
+            // push the second half first, so the segments come out in order
+            let (first, second) = curve.split(0.5);
+            self.stack.push((second, depth + 1));
+            self.stack.push((first, depth + 1));
+        }
     }
 
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.internal.size_hint()
+        // every curve left gives at least one edge, and at most one for each time it could
+        // still be split in half
+        let most = self
+            .stack
+            .iter()
+            .map(|(_, depth)| 1usize << (MAX_EDGE_DEPTH - (*depth).min(MAX_EDGE_DEPTH)))
+            .sum();
+        (self.stack.len(), Some(most))
     }
 }
 
-impl<'a> ExactSizeIterator for Edges<'a> {}
-
 ///
 /// de Casteljau's algorithm for cubic bezier curves
 ///
@@ -207,3 +496,435 @@ pub fn distance(v1: &Vector2F, v2: &Vector2F) -> f32 {
     let a = (v1.x() - v2.x()).powi(2) + (v1.y() - v2.y()).powi(2);
     a.sqrt()
 }
+
+// distance from a point to the segment between two others
+#[inline]
+fn distance_to_segment(pt: Vector2F, start: Vector2F, end: Vector2F) -> f32 {
+    let seg = end - start;
+    let len2 = seg.square_length();
+    if len2 == 0.0 {
+        return distance(&pt, &start);
+    }
+
+    let t = ((pt - start).dot(seg) / len2).clamp(0.0, 1.0);
+    distance(&pt, &(start + seg * t))
+}
+
+///
+/// Remove points that lie within `epsilon` of the path drawn by the remaining points, using
+/// the Ramer-Douglas-Peucker algorithm. The first and last points are always kept.
+///
+pub fn simplify_points(points: &[Vector2F], epsilon: f32) -> SmallVec<[Vector2F; 12]> {
+    if points.len() < 3 {
+        return points.iter().copied().collect();
+    }
+
+    let mut keep = vec![false; points.len()];
+    let last = points.len() - 1;
+    keep[0] = true;
+    keep[last] = true;
+
+    // ranges that still need to be checked for a point that's too far out
+    let mut ranges: SmallVec<[(usize, usize); 16]> = SmallVec::new();
+    ranges.push((0, last));
+
+    while let Some((start, end)) = ranges.pop() {
+        if end - start < 2 {
+            continue;
+        }
+
+        let (index, dist) = (start + 1..end)
+            .map(|i| {
+                (
+                    i,
+                    distance_to_segment(points[i], points[start], points[end]),
+                )
+            })
+            .fold(
+                (start, 0.0),
+                |best, cur| if cur.1 > best.1 { cur } else { best },
+            );
+
+        if dist > epsilon {
+            keep[index] = true;
+            ranges.push((start, index));
+            ranges.push((index, end));
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_pt, keep)| *keep)
+        .map(|(pt, _keep)| *pt)
+        .collect()
+}
+
+// real roots of at^2 + bt + c, falling back to the linear case when a is zero
+fn quadratic_roots(a: f32, b: f32, c: f32) -> SmallVec<[f32; 2]> {
+    const EPSILON: f32 = 1.0e-6;
+
+    let mut roots = SmallVec::new();
+    if a.abs() < EPSILON {
+        if b.abs() >= EPSILON {
+            roots.push(-c / b);
+        }
+        return roots;
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant >= 0.0 {
+        let sqrt = discriminant.sqrt();
+        roots.push((-b + sqrt) / (2.0 * a));
+        roots.push((-b - sqrt) / (2.0 * a));
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::with_loading_version;
+    use std::{hint::black_box, time::Instant};
+
+    const EPSILON: f32 = 1.0e-4;
+
+    fn assert_close(a: Vector2F, b: Vector2F) {
+        assert!(
+            distance(&a, &b) < EPSILON,
+            "({}, {}) is not ({}, {})",
+            a.x(),
+            a.y(),
+            b.x(),
+            b.y()
+        );
+    }
+
+    // a big, tightly bent curve, of the kind the old segment count flattened too coarsely
+    fn large_curve() -> BezierCurve {
+        BezierCurve::from_points([
+            Vector2F::new(0.0, 0.0),
+            Vector2F::new(2000.0, 1500.0),
+            Vector2F::new(-500.0, 1800.0),
+            Vector2F::new(1200.0, 100.0),
+        ])
+    }
+
+    // how far the curve strays from its flattened edges
+    fn max_deviation(curve: &BezierCurve, tolerance: f32) -> f32 {
+        let edges: Vec<LineSegment2F> = curve.edges_with_tolerance(tolerance).collect();
+        (0..=1000)
+            .map(|i| curve.eval(i as f32 / 1000.0))
+            .map(|pt| {
+                edges
+                    .iter()
+                    .map(|e| distance_to_segment(pt, e.from(), e.to()))
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn flattening_stays_within_tolerance() {
+        let curve = large_curve();
+        [4.0, 1.0, DEFAULT_TOLERANCE, 0.05]
+            .iter()
+            .for_each(|tolerance| {
+                let deviation = max_deviation(&curve, *tolerance);
+                assert!(
+                    deviation <= *tolerance + EPSILON,
+                    "deviation {} is over the tolerance {}",
+                    deviation,
+                    tolerance
+                );
+            });
+    }
+
+    #[test]
+    fn tighter_tolerances_make_more_edges() {
+        let curve = large_curve();
+        let loose = curve.edges_with_tolerance(PREVIEW_TOLERANCE).count();
+        let tight = curve.edges_with_tolerance(0.01).count();
+        assert!(
+            tight > loose,
+            "{} edges at 0.01, {} at preview",
+            tight,
+            loose
+        );
+        assert_eq!(
+            curve.edges().count(),
+            curve.edges_with_tolerance(DEFAULT_TOLERANCE).count()
+        );
+    }
+
+    #[test]
+    fn straight_curves_make_one_edge() {
+        let curve = BezierCurve::from_points([
+            Vector2F::new(0.0, 0.0),
+            Vector2F::new(1.0, 1.0),
+            Vector2F::new(2.0, 2.0),
+            Vector2F::new(3.0, 3.0),
+        ]);
+        assert_eq!(curve.edges_with_tolerance(0.01).count(), 1);
+    }
+
+    #[test]
+    fn flattened_edges_are_joined() {
+        let curve = large_curve();
+        let edges: Vec<LineSegment2F> = curve.edges().collect();
+        assert_close(edges[0].from(), curve.points()[0]);
+        assert_close(edges[edges.len() - 1].to(), curve.points()[3]);
+        edges
+            .windows(2)
+            .for_each(|pair| assert_close(pair[0].to(), pair[1].from()));
+    }
+
+    #[test]
+    fn reversed_curve_runs_backwards() {
+        let curve = BezierCurve::from_points([
+            Vector2F::new(0.0, 0.0),
+            Vector2F::new(10.0, 30.0),
+            Vector2F::new(45.0, -10.0),
+            Vector2F::new(50.0, 20.0),
+        ]);
+        let reversed = curve.reversed();
+
+        (0..=10).map(|i| i as f32 / 10.0).for_each(|t| {
+            assert_close(reversed.eval(t), curve.eval(1.0 - t));
+        });
+        assert_eq!(reversed.reversed().points(), curve.points());
+    }
+
+    #[test]
+    fn elevated_quadratic_matches_the_original() {
+        let (p0, p1, p2) = (
+            Vector2F::new(3.0, 4.0),
+            Vector2F::new(40.0, -25.0),
+            Vector2F::new(70.0, 12.0),
+        );
+        let cubic = BezierCurve::from_quadratic(p0, p1, p2);
+
+        (0..=10).map(|i| i as f32 / 10.0).for_each(|t| {
+            assert_close(cubic.eval(t), de_casteljau3(t, p0, p1, p2));
+        });
+    }
+
+    #[test]
+    fn line_is_a_straight_cubic() {
+        let (p0, p1) = (Vector2F::new(-5.0, 2.0), Vector2F::new(15.0, 12.0));
+        let cubic = BezierCurve::from_line(p0, p1);
+
+        (0..=10).map(|i| i as f32 / 10.0).for_each(|t| {
+            assert_close(cubic.eval(t), de_casteljau2(t, p0, p1));
+        });
+    }
+
+    fn traced_points() -> Vec<Vector2F> {
+        vec![
+            Vector2F::new(0.0, 0.0),
+            Vector2F::new(10.0, 25.0),
+            Vector2F::new(30.0, 20.0),
+            Vector2F::new(35.0, -5.0),
+            Vector2F::new(60.0, 10.0),
+        ]
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_each_point() {
+        let points = traced_points();
+        let curves = BezierCurve::from_catmull_rom(&points);
+
+        assert_eq!(curves.len(), points.len() - 1);
+        curves.iter().zip(points.windows(2)).for_each(|(c, pair)| {
+            assert_close(c.eval(0.0), pair[0]);
+            assert_close(c.eval(1.0), pair[1]);
+        });
+    }
+
+    #[test]
+    fn catmull_rom_is_smooth_at_the_joins() {
+        let curves = BezierCurve::from_catmull_rom(&traced_points());
+        curves.windows(2).for_each(|pair| {
+            let [_, _, before, join] = *pair[0].points();
+            let [_, after, _, _] = *pair[1].points();
+            assert_close(join - before, after - join);
+        });
+    }
+
+    #[test]
+    fn catmull_rom_needs_two_points() {
+        let point = Vector2F::new(1.0, 1.0);
+        assert!(BezierCurve::from_catmull_rom(&[point]).is_empty());
+        assert!(BezierCurve::from_catmull_rom(&[point, point]).is_empty());
+    }
+
+    #[test]
+    fn straight_runs_simplify_to_their_ends() {
+        let points: Vec<Vector2F> = (0..20)
+            .map(|i| Vector2F::new(i as f32, i as f32 * 0.5))
+            .collect();
+        let simplified = simplify_points(&points, 0.1);
+        assert_eq!(simplified.as_slice(), &[points[0], points[19]]);
+    }
+
+    #[test]
+    fn corners_survive_simplification() {
+        let points = [
+            Vector2F::new(0.0, 0.0),
+            Vector2F::new(5.0, 0.05),
+            Vector2F::new(10.0, 0.0),
+            Vector2F::new(10.0, 10.0),
+        ];
+        let simplified = simplify_points(&points, 0.1);
+        assert_eq!(simplified.as_slice(), &[points[0], points[2], points[3]]);
+        // with no leeway, every point that is off the line stays
+        assert_eq!(simplify_points(&points, 0.0).len(), 4);
+    }
+
+    // an arch, with its peak at t = 0.5
+    fn arch() -> BezierCurve {
+        BezierCurve::from_points([
+            Vector2F::new(0.0, 0.0),
+            Vector2F::new(0.0, 100.0),
+            Vector2F::new(100.0, 100.0),
+            Vector2F::new(100.0, 0.0),
+        ])
+    }
+
+    #[test]
+    fn bounding_box_reaches_the_peak() {
+        let bounds = arch().bounding_box();
+        assert_close(bounds.origin.into_pathfinder(), Vector2F::new(0.0, 0.0));
+        assert_close(
+            Vector2F::new(bounds.size.width, bounds.size.height),
+            Vector2F::new(100.0, 75.0),
+        );
+    }
+
+    #[test]
+    fn bounding_box_covers_both_extremes() {
+        // an s-bend that overshoots its ends on both sides
+        let curve = BezierCurve::from_points([
+            Vector2F::new(0.0, 0.0),
+            Vector2F::new(90.0, 60.0),
+            Vector2F::new(-90.0, 60.0),
+            Vector2F::new(0.0, 120.0),
+        ]);
+        let bounds = curve.bounding_box();
+        let expected = (0..=1000)
+            .map(|i| curve.eval(i as f32 / 1000.0).x())
+            .fold(0.0f32, f32::max);
+
+        assert!((bounds.max_x() - expected).abs() < 0.01);
+        assert!((bounds.min_x() + expected).abs() < 0.01);
+        assert!((bounds.max_y() - 120.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn closest_point_finds_the_middle() {
+        let curve = arch();
+        let (t, dist) = curve.closest_point(Vector2F::new(50.0, 80.0));
+        assert!((t - 0.5).abs() < 1.0e-3, "t is {}", t);
+        assert!((dist - 5.0).abs() < 1.0e-3, "distance is {}", dist);
+    }
+
+    #[test]
+    fn closest_point_stops_at_the_ends() {
+        let curve = arch();
+        let (t, dist) = curve.closest_point(Vector2F::new(-30.0, -40.0));
+        assert_eq!(t, 0.0);
+        assert!((dist - 50.0).abs() < 1.0e-3);
+
+        let (t, _) = curve.closest_point(Vector2F::new(130.0, -40.0));
+        assert_eq!(t, 1.0);
+    }
+
+    #[test]
+    fn uniform_points_match_single_evaluations() {
+        let curve = arch();
+        [2, 3, 17, 100].iter().for_each(|count| {
+            let points = curve.eval_uniform(*count);
+            assert_eq!(points.len(), *count);
+            points.iter().enumerate().for_each(|(i, pt)| {
+                let t = i as f32 / (*count - 1) as f32;
+                let expected = curve.eval(t);
+                assert!(distance(pt, &expected) < 1.0e-3);
+            });
+        });
+
+        assert!(curve.eval_uniform(0).is_empty());
+        assert_eq!(curve.eval_uniform(1)[0], curve.points()[0]);
+    }
+
+    // run with `cargo test --release -- --ignored eval_uniform_speedup` to see the numbers
+    #[test]
+    #[ignore]
+    fn eval_uniform_speedup() {
+        const RUNS: usize = 10_000;
+        const COUNT: usize = 64;
+        let curve = large_curve();
+
+        let start = Instant::now();
+        (0..RUNS).for_each(|_| {
+            black_box(curve.eval_uniform(COUNT));
+        });
+        let batched = start.elapsed();
+        let start = Instant::now();
+        (0..RUNS).for_each(|_| {
+            let points: SmallVec<[Vector2F; 16]> = (0..COUNT)
+                .map(|i| curve.eval(i as f32 / (COUNT - 1) as f32))
+                .collect();
+            black_box(points);
+        });
+        let pointwise = start.elapsed();
+
+        println!(
+            "{} points: {:?} batched, {:?} one at a time",
+            COUNT,
+            batched / RUNS as u32,
+            pointwise / RUNS as u32
+        );
+    }
+
+    #[test]
+    fn edge_size_hints_hold_the_remaining_count() {
+        let tiny = BezierCurve::from_line(Vector2F::new(1.0, 1.0), Vector2F::new(1.0, 1.0));
+        let broken = BezierCurve::from_line(Vector2F::new(0.0, 0.0), Vector2F::new(f32::NAN, 0.0));
+        [large_curve(), arch(), tiny, broken]
+            .iter()
+            .for_each(|curve| {
+                let total = curve.edges_with_tolerance(0.05).count();
+                let mut edges = curve.edges_with_tolerance(0.05);
+                assert!(total >= 1);
+                (0..=total).rev().for_each(|left| {
+                    let (least, most) = edges.size_hint();
+                    assert!(least <= left && left <= most.unwrap());
+                    edges.next();
+                });
+                assert_eq!(edges.size_hint(), (0, Some(0)));
+            });
+    }
+
+    #[test]
+    fn wrong_coordinate_counts_are_errors() {
+        let json = |count: usize| {
+            let coords: Vec<f32> = (0..count).map(|i| i as f32).collect();
+            serde_json::to_string(&BezierDeser { coords }).unwrap()
+        };
+        [6, 7, 9].iter().for_each(|count| {
+            assert!(serde_json::from_str::<BezierCurve>(&json(*count)).is_err());
+        });
+        let curve: BezierCurve = serde_json::from_str(&json(8)).unwrap();
+        assert_eq!(curve.points()[3], Vector2F::new(6.0, 7.0));
+
+        // curves saved in full before they were packed are checked too
+        let bytes = bincode::serialize(&BezierDeser {
+            coords: vec![0.0; 7],
+        })
+        .unwrap();
+        let loaded = with_loading_version(layout::QUANTIZED - 1, || {
+            bincode::deserialize::<BezierCurve>(&bytes)
+        });
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,174 @@
+// GPLv3 License
+
+use image::{Primitive, Rgba};
+use num_traits::{AsPrimitive, Bounded};
+use serde::{Deserialize, Serialize};
+
+/// How a brush's color is combined with the pixels already on the canvas.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Add,
+    Darken,
+    Lighten,
+}
+
+impl Default for BlendMode {
+    #[inline]
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+impl BlendMode {
+    /// The mode after this one, wrapping around to `Normal`.
+    #[inline]
+    pub fn next(self) -> Self {
+        match self {
+            Self::Normal => Self::Multiply,
+            Self::Multiply => Self::Screen,
+            Self::Screen => Self::Add,
+            Self::Add => Self::Darken,
+            Self::Darken => Self::Lighten,
+            Self::Lighten => Self::Normal,
+        }
+    }
+
+    /// Blend a single normalized source channel onto a destination channel.
+    #[inline]
+    pub fn blend_channel(self, src: f32, dst: f32) -> f32 {
+        match self {
+            Self::Normal => src,
+            Self::Multiply => src * dst,
+            Self::Screen => src + dst - (src * dst),
+            Self::Add => (src + dst).min(1.0),
+            Self::Darken => src.min(dst),
+            Self::Lighten => src.max(dst),
+        }
+    }
+
+    /// Composite a source pixel over a destination pixel, using this mode to mix the colors
+    /// where they overlap.
+    #[inline]
+    pub fn composite<T: Bounded + Copy + Into<f32> + Primitive + 'static>(
+        self,
+        src: Rgba<T>,
+        dst: Rgba<T>,
+    ) -> Rgba<T>
+    where
+        f32: AsPrimitive<T>,
+    {
+        let max: f32 = T::max_value().into();
+        let normalize = |c: T| -> f32 { c.into() / max };
+
+        let (src_a, dst_a) = (normalize(src[3]), normalize(dst[3]));
+        let out_a = src_a + dst_a * (1.0 - src_a);
+        if out_a <= 0.0 {
+            return Rgba([T::zero(); 4]);
+        }
+
+        let mut out = [T::zero(); 4];
+        (0..3).for_each(|i| {
+            let (s, d) = (normalize(src[i]), normalize(dst[i]));
+
+            // only blend where there is something underneath to blend with
+            let mixed = (1.0 - dst_a) * s + dst_a * self.blend_channel(s, d);
+            let c = (src_a * mixed + (1.0 - src_a) * dst_a * d) / out_a;
+
+            out[i] = (c.clamp(0.0, 1.0) * max).as_();
+        });
+        out[3] = (out_a.min(1.0) * max).as_();
+
+        Rgba(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX: f32 = u16::MAX as f32;
+
+    fn rgba(r: f32, g: f32, b: f32, a: f32) -> Rgba<u16> {
+        Rgba([
+            (r * MAX) as u16,
+            (g * MAX) as u16,
+            (b * MAX) as u16,
+            (a * MAX) as u16,
+        ])
+    }
+
+    fn assert_composites(mode: BlendMode, src: Rgba<u16>, dst: Rgba<u16>, expected: [f32; 4]) {
+        let out = mode.composite(src, dst);
+        (0..4).for_each(|i| {
+            let channel = out[i] as f32 / MAX;
+            assert!(
+                (channel - expected[i]).abs() < 0.002,
+                "{:?} gave {:?}, expected {:?}",
+                mode,
+                out,
+                expected
+            );
+        });
+    }
+
+    // a mid grey on opaque orange, so that every mode gives something different
+    fn opaque_pair() -> (Rgba<u16>, Rgba<u16>) {
+        (rgba(0.5, 0.5, 0.5, 1.0), rgba(1.0, 0.6, 0.2, 1.0))
+    }
+
+    #[test]
+    fn normal_covers() {
+        let (src, dst) = opaque_pair();
+        assert_composites(BlendMode::Normal, src, dst, [0.5, 0.5, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn multiply_darkens() {
+        let (src, dst) = opaque_pair();
+        assert_composites(BlendMode::Multiply, src, dst, [0.5, 0.3, 0.1, 1.0]);
+    }
+
+    #[test]
+    fn screen_lightens() {
+        let (src, dst) = opaque_pair();
+        assert_composites(BlendMode::Screen, src, dst, [1.0, 0.8, 0.6, 1.0]);
+    }
+
+    #[test]
+    fn add_saturates() {
+        let (src, dst) = opaque_pair();
+        assert_composites(BlendMode::Add, src, dst, [1.0, 1.0, 0.7, 1.0]);
+    }
+
+    #[test]
+    fn darken_and_lighten_pick_a_side() {
+        let (src, dst) = opaque_pair();
+        assert_composites(BlendMode::Darken, src, dst, [0.5, 0.5, 0.2, 1.0]);
+        assert_composites(BlendMode::Lighten, src, dst, [1.0, 0.6, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn modes_only_apply_over_something() {
+        // onto a transparent pixel, every mode just paints the source
+        let src = rgba(0.5, 0.5, 0.5, 1.0);
+        let mut mode = BlendMode::Normal;
+        loop {
+            assert_composites(mode, src, rgba(1.0, 0.6, 0.2, 0.0), [0.5, 0.5, 0.5, 1.0]);
+            mode = mode.next();
+            if mode == BlendMode::Normal {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn translucent_sources_mix() {
+        let (_, dst) = opaque_pair();
+        let src = rgba(0.0, 0.0, 0.0, 0.5);
+        assert_composites(BlendMode::Normal, src, dst, [0.5, 0.3, 0.1, 1.0]);
+        assert_composites(BlendMode::Lighten, src, dst, [1.0, 0.6, 0.2, 1.0]);
+    }
+}
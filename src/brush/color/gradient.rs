@@ -0,0 +1,383 @@
+// GPLv3 License
+
+use super::{Color, LocationInfo, SolidColor};
+use crate::{layout, loading_version};
+use euclid::default::Point2D;
+use ordered_float::NotNan;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use std::convert::TryFrom;
+
+/// The most stops a gradient can have. Colors have to be `Copy`, so the stops are kept inline.
+pub const MAX_GRADIENT_STOPS: usize = 8;
+
+// the stops as a plain list, for ser/deser
+#[derive(Serialize, Deserialize)]
+struct GradientDeser {
+    stops: Vec<(f32, SolidColor)>,
+}
+
+// gradients as they were written before they could have more than two colors
+#[derive(Deserialize)]
+struct TwoColorDeser {
+    start: SolidColor,
+    end: SolidColor,
+}
+
+/// A blend between colors placed at positions from 0 to 1.
+#[derive(Copy, Clone, Serialize)]
+#[serde(into = "GradientDeser")]
+pub struct GradientColor {
+    // sorted by position, only the first `len` are used
+    stops: [(f32, SolidColor); MAX_GRADIENT_STOPS],
+    len: usize,
+}
+
+impl From<GradientColor> for GradientDeser {
+    #[inline]
+    fn from(gc: GradientColor) -> Self {
+        Self {
+            stops: gc.stops().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<GradientDeser> for GradientColor {
+    type Error = &'static str;
+
+    #[inline]
+    fn try_from(gd: GradientDeser) -> Result<Self, Self::Error> {
+        Self::from_stops(&gd.stops).ok_or("Gradient must have between 1 and 8 stops")
+    }
+}
+
+impl<'de> Deserialize<'de> for GradientColor {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        if loading_version() < layout::GRADIENT_STOPS {
+            let TwoColorDeser { start, end } = Deserialize::deserialize(de)?;
+            Ok(Self::new(start, end))
+        } else {
+            Self::try_from(GradientDeser::deserialize(de)?).map_err(D::Error::custom)
+        }
+    }
+}
+
+impl GradientColor {
+    /// A gradient from one color to another.
+    #[inline]
+    pub fn new(start: SolidColor, end: SolidColor) -> Self {
+        Self::from_stops(&[(0.0, start), (1.0, end)]).unwrap()
+    }
+
+    /// Create a gradient from a set of stops, in any order. Positions are clamped to [0, 1].
+    /// Returns `None` if there are no stops, or more than `MAX_GRADIENT_STOPS`.
+    pub fn from_stops(stops: &[(f32, SolidColor)]) -> Option<Self> {
+        let first = *stops.first()?;
+        if stops.len() > MAX_GRADIENT_STOPS {
+            return None;
+        }
+
+        let mut inline = [first; MAX_GRADIENT_STOPS];
+        inline
+            .iter_mut()
+            .zip(stops.iter())
+            .for_each(|(slot, (pos, color))| {
+                let pos = if pos.is_nan() {
+                    0.0
+                } else {
+                    pos.clamp(0.0, 1.0)
+                };
+                *slot = (pos, *color);
+            });
+        inline[..stops.len()].sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        Some(Self {
+            stops: inline,
+            len: stops.len(),
+        })
+    }
+
+    #[inline]
+    pub fn stops(&self) -> &[(f32, SolidColor)] {
+        &self.stops[..self.len]
+    }
+
+    /// Get the color at a position along the gradient. Positions before the first stop or
+    /// after the last one take that stop's color.
+    pub fn at(&self, t: f32, loc_info: &LocationInfo) -> [NotNan<f32>; 4] {
+        let stops = self.stops();
+        let t = if t.is_nan() { 0.0 } else { t };
+
+        // find the stops on either side of the position
+        let (before, after) = match stops.iter().position(|(pos, _c)| *pos > t) {
+            None => return stops[stops.len() - 1].1.parts(loc_info),
+            Some(0) => return stops[0].1.parts(loc_info),
+            Some(i) => (stops[i - 1], stops[i]),
+        };
+
+        let span = after.0 - before.0;
+        let t = if span > 0.0 {
+            (t - before.0) / span
+        } else {
+            1.0
+        };
+        let (start, end) = (before.1.parts(loc_info), after.1.parts(loc_info));
+
+        let mut parts = start;
+        parts.iter_mut().zip(end.iter()).for_each(|(s, e)| {
+            *s = *s + (*e - *s) * t;
+        });
+        parts
+    }
+}
+
+/// A gradient that blends from one color at a center point to another at a radius around it.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct RadialGradient {
+    gradient: GradientColor,
+    center: Point2D<f32>,
+    radius: f32,
+}
+
+impl RadialGradient {
+    #[inline]
+    pub fn new(gradient: GradientColor, center: Point2D<f32>, radius: f32) -> Self {
+        Self {
+            gradient,
+            center,
+            radius,
+        }
+    }
+}
+
+impl From<RadialGradient> for super::DynamicColor {
+    #[inline]
+    fn from(rg: RadialGradient) -> Self {
+        Self::Radial(rg)
+    }
+}
+
+impl<'a> Color<'a> for RadialGradient {
+    #[inline]
+    fn parts(&self, loc_info: &LocationInfo) -> [NotNan<f32>; 4] {
+        let dist = (loc_info.x as f32 - self.center.x).hypot(loc_info.y as f32 - self.center.y);
+
+        // with no radius to blend over, everything is past the end
+        let t = if self.radius > 0.0 {
+            dist / self.radius
+        } else {
+            1.0
+        };
+        self.gradient.at(t, loc_info)
+    }
+}
+
+/// A gradient that blends along the length of the stroke it is drawn with.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct StrokeGradient {
+    gradient: GradientColor,
+}
+
+impl StrokeGradient {
+    #[inline]
+    pub fn new(gradient: GradientColor) -> Self {
+        Self { gradient }
+    }
+}
+
+impl From<StrokeGradient> for super::DynamicColor {
+    #[inline]
+    fn from(sg: StrokeGradient) -> Self {
+        Self::Stroke(sg)
+    }
+}
+
+impl<'a> Color<'a> for StrokeGradient {
+    #[inline]
+    fn parts(&self, loc_info: &LocationInfo) -> [NotNan<f32>; 4] {
+        self.gradient.at(loc_info.stroke, loc_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{colors, Brush, ColorAtom, Polygon, PolygonType, Rasterizable, TCImage};
+    use pathfinder_geometry::{line_segment::LineSegment2F, vector::Vector2F};
+
+    #[inline]
+    fn at(x: u32, y: u32) -> LocationInfo {
+        LocationInfo {
+            x,
+            y,
+            width: 32,
+            height: 32,
+            stroke: 0.0,
+        }
+    }
+
+    #[inline]
+    fn rgb(parts: [NotNan<f32>; 4]) -> [f32; 3] {
+        [
+            parts[0].into_inner(),
+            parts[1].into_inner(),
+            parts[2].into_inner(),
+        ]
+    }
+
+    #[inline]
+    fn red_to_blue(radius: f32) -> RadialGradient {
+        RadialGradient::new(
+            GradientColor::new(colors::RED, colors::BLUE),
+            Point2D::new(16.0, 16.0),
+            radius,
+        )
+    }
+
+    #[test]
+    fn radial_gradients_blend_outwards() {
+        let gradient = red_to_blue(16.0);
+        assert_eq!(rgb(gradient.parts(&at(16, 16))), [1.0, 0.0, 0.0]);
+        assert_eq!(rgb(gradient.parts(&at(24, 16))), [0.5, 0.0, 0.5]);
+        assert_eq!(rgb(gradient.parts(&at(16, 32))), [0.0, 0.0, 1.0]);
+        // past the radius, the last color carries on
+        assert_eq!(rgb(gradient.parts(&at(0, 0))), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn zero_radius_is_the_end_color() {
+        let gradient = red_to_blue(0.0);
+        assert_eq!(rgb(gradient.parts(&at(16, 16))), [0.0, 0.0, 1.0]);
+        assert_eq!(rgb(gradient.parts(&at(3, 7))), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn radial_filled_shapes() {
+        let corners = [(0.0, 0.0), (32.0, 0.0), (32.0, 32.0), (0.0, 32.0)];
+        let v = |(x, y): (f32, f32)| Vector2F::new(x, y);
+        let square = Polygon::new(
+            corners
+                .iter()
+                .zip(corners.iter().cycle().skip(1))
+                .map(|(a, b)| LineSegment2F::new(v(*a), v(*b))),
+            PolygonType::Fill,
+        );
+        let mut img = TCImage::new(32, 32);
+        square.rasterize_into(&mut img, &Brush::new(red_to_blue(16.0), 1), 0.25);
+
+        let channel = |x: u32, y: u32, c: usize| {
+            f32::from(img.get_pixel(x, y)[c]) / f32::from(ColorAtom::MAX)
+        };
+        assert!(channel(16, 16, 0) > 0.95 && channel(16, 16, 2) < 0.05);
+        assert!(channel(16, 0, 0) < 0.05 && channel(16, 0, 2) > 0.95);
+        assert!(channel(31, 31, 0) < 0.05 && channel(31, 31, 2) > 0.95);
+        assert!((channel(24, 16, 0) - 0.5).abs() < 0.05);
+    }
+
+    #[inline]
+    fn three_stops() -> GradientColor {
+        // given out of order, to be sorted
+        GradientColor::from_stops(&[
+            (1.0, colors::BLUE),
+            (0.0, colors::BLACK),
+            (0.5, colors::RED),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn stops_are_sorted_and_clamped() {
+        let positions: Vec<f32> = three_stops().stops().iter().map(|s| s.0).collect();
+        assert_eq!(positions, vec![0.0, 0.5, 1.0]);
+
+        let clamped =
+            GradientColor::from_stops(&[(-2.0, colors::RED), (3.0, colors::BLUE)]).unwrap();
+        let positions: Vec<f32> = clamped.stops().iter().map(|s| s.0).collect();
+        assert_eq!(positions, vec![0.0, 1.0]);
+
+        assert!(GradientColor::from_stops(&[]).is_none());
+        assert!(GradientColor::from_stops(&[(0.5, colors::RED); MAX_GRADIENT_STOPS + 1]).is_none());
+    }
+
+    #[test]
+    fn blending_within_the_second_interval() {
+        let gradient = three_stops();
+        assert_eq!(rgb(gradient.at(0.75, &at(0, 0))), [0.5, 0.0, 0.5]);
+        assert_eq!(rgb(gradient.at(0.25, &at(0, 0))), [0.5, 0.0, 0.0]);
+        assert_eq!(rgb(gradient.at(0.5, &at(0, 0))), [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn out_of_range_uses_the_nearest_stop() {
+        let gradient =
+            GradientColor::from_stops(&[(0.25, colors::RED), (0.75, colors::BLUE)]).unwrap();
+        assert_eq!(rgb(gradient.at(-1.0, &at(0, 0))), [1.0, 0.0, 0.0]);
+        assert_eq!(rgb(gradient.at(0.1, &at(0, 0))), [1.0, 0.0, 0.0]);
+        assert_eq!(rgb(gradient.at(0.9, &at(0, 0))), [0.0, 0.0, 1.0]);
+        assert_eq!(rgb(gradient.at(f32::NAN, &at(0, 0))), [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn stops_round_trip() {
+        let json = serde_json::to_string(&three_stops()).unwrap();
+        let loaded: GradientColor = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.stops().len(), 3);
+        assert_eq!(rgb(loaded.at(0.75, &at(0, 0))), [0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn two_color_gradients_are_upgraded() {
+        let old = serde_json::json!({
+            "start": colors::RED,
+            "end": colors::BLUE,
+        });
+        let loaded: GradientColor = crate::with_loading_version(layout::GRADIENT_STOPS - 1, || {
+            serde_json::from_value(old).unwrap()
+        });
+        let positions: Vec<f32> = loaded.stops().iter().map(|s| s.0).collect();
+        assert_eq!(positions, vec![0.0, 1.0]);
+        assert_eq!(rgb(loaded.at(0.5, &at(0, 0))), [0.5, 0.0, 0.5]);
+    }
+
+    // how bright a line drawn with a black to white stroke gradient is at each column
+    fn stroke_brightness<R: Rasterizable>(item: &R) -> Vec<Option<f32>> {
+        let gradient = StrokeGradient::new(GradientColor::new(colors::BLACK, colors::WHITE));
+        let mut img = TCImage::new(32, 32);
+        item.rasterize_into(&mut img, &Brush::new(gradient, 1), 0.25);
+        (0..32)
+            .map(|x| {
+                let px = img.get_pixel(x, 16);
+                if px[3] == 0 {
+                    None
+                } else {
+                    Some(f32::from(px[0]) / f32::from(ColorAtom::MAX))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stroke_gradients_lighten_along_lines() {
+        let line = LineSegment2F::new(Vector2F::new(2.0, 16.0), Vector2F::new(30.0, 16.0));
+        let brightness = stroke_brightness(&line);
+        assert!(brightness[2].unwrap() < 0.1);
+        assert!(brightness[29].unwrap() > 0.9);
+
+        // and gets lighter the whole way
+        let drawn: Vec<f32> = brightness.into_iter().flatten().collect();
+        drawn.windows(2).for_each(|w| assert!(w[1] >= w[0]));
+    }
+
+    #[test]
+    fn stroke_gradients_lighten_along_curves() {
+        let curve = crate::BezierCurve::from_points([
+            Vector2F::new(2.0, 16.0),
+            Vector2F::new(10.0, 16.0),
+            Vector2F::new(20.0, 16.0),
+            Vector2F::new(30.0, 16.0),
+        ]);
+        let brightness = stroke_brightness(&curve);
+        assert!(brightness[2].unwrap() < 0.1);
+        assert!(brightness[29].unwrap() > 0.9);
+    }
+}
@@ -1,6 +1,8 @@
 // GPL v3.0 License
 
+mod gradient;
 mod solid;
+pub use gradient::*;
 pub use solid::*;
 
 use image::{Primitive, Rgba};
@@ -15,6 +17,8 @@ pub struct LocationInfo {
     pub y: u32,
     pub width: u32,
     pub height: u32,
+    /// How far along the stroke being drawn this point is, from 0 at its start to 1 at its end.
+    pub stroke: f32,
 }
 
 /// A trait representing something where a color can be derived from.
@@ -67,6 +71,8 @@ pub trait Color<'de>: Serialize + Deserialize<'de> + Copy + Into<DynamicColor> {
 #[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum DynamicColor {
     Solid(SolidColor),
+    Radial(RadialGradient),
+    Stroke(StrokeGradient),
 }
 
 impl From<SolidColor> for DynamicColor {
@@ -80,6 +86,15 @@ impl DynamicColor {
     pub fn from_color<'de, C: Color<'de>>(color: C) -> Self {
         color.into()
     }
+
+    /// Get the color if it is a single solid color.
+    #[inline]
+    pub fn as_solid(&self) -> Option<&SolidColor> {
+        match self {
+            Self::Solid(ref s) => Some(s),
+            _ => None,
+        }
+    }
 }
 
 impl<'de> Color<'de> for DynamicColor {
@@ -87,6 +102,8 @@ impl<'de> Color<'de> for DynamicColor {
     fn parts(&self, loc_info: &LocationInfo) -> [NotNan<f32>; 4] {
         match self {
             Self::Solid(ref s) => s.parts(loc_info),
+            Self::Radial(ref r) => r.parts(loc_info),
+            Self::Stroke(ref s) => s.parts(loc_info),
         }
     }
 }
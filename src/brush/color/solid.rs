@@ -11,7 +11,7 @@ struct ColorDeser {
 }
 
 /// A three-float color.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub struct SolidColor {
     r: NotNan<f32>,
     g: NotNan<f32>,
@@ -49,6 +49,74 @@ impl SolidColor {
         })
     }
 
+    /// Blend linearly towards another color, where `t` is 0 for this color and 1 for the other.
+    #[inline]
+    pub fn mix(&self, other: &SolidColor, t: f32) -> SolidColor {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let lerp = |a: NotNan<f32>, b: NotNan<f32>| a + (b - a) * t;
+
+        Self {
+            r: lerp(self.r, other.r),
+            g: lerp(self.g, other.g),
+            b: lerp(self.b, other.b),
+        }
+    }
+
+    /// Blend towards another color in linear light, which keeps mixes from looking muddy.
+    pub fn mix_linear(&self, other: &SolidColor, t: f32) -> SolidColor {
+        // approximate the sRGB transfer curve with a plain gamma
+        const GAMMA: f32 = 2.2;
+        let to_linear = |c: NotNan<f32>| NotNan::new(c.into_inner().powf(GAMMA)).unwrap();
+        let from_linear = |c: NotNan<f32>| NotNan::new(c.into_inner().powf(1.0 / GAMMA)).unwrap();
+
+        let (a, b) = (
+            Self {
+                r: to_linear(self.r),
+                g: to_linear(self.g),
+                b: to_linear(self.b),
+            },
+            Self {
+                r: to_linear(other.r),
+                g: to_linear(other.g),
+                b: to_linear(other.b),
+            },
+        );
+        let mixed = a.mix(&b, t);
+
+        Self {
+            r: from_linear(mixed.r),
+            g: from_linear(mixed.g),
+            b: from_linear(mixed.b),
+        }
+    }
+
+    /// The average of a set of colors, mixed in linear light.
+    #[inline]
+    pub fn average<'a, I: IntoIterator<Item = &'a SolidColor>>(colors: I) -> Option<SolidColor> {
+        colors
+            .into_iter()
+            .enumerate()
+            .fold(None, |avg: Option<SolidColor>, (i, c)| match avg {
+                None => Some(*c),
+                Some(avg) => Some(avg.mix_linear(c, 1.0 / (i + 1) as f32)),
+            })
+    }
+
+    /// Read a color from a hex code like `#ff8000`, with or without the `#`.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+
+        let channel = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .ok()
+                .map(|c| c as f32 / u8::MAX as f32)
+        };
+        Self::new(channel(0)?, channel(2)?, channel(4)?)
+    }
+
     #[inline]
     pub const unsafe fn new_unchecked(r: f32, g: f32, b: f32) -> Self {
         Self {
@@ -67,3 +135,55 @@ impl<'a> Color<'a> for SolidColor {
         [self.r, self.g, self.b, MAX_COLOR]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors;
+
+    #[inline]
+    fn rgb(c: &SolidColor) -> [f32; 3] {
+        [c.r.into_inner(), c.g.into_inner(), c.b.into_inner()]
+    }
+
+    #[test]
+    fn black_and_white_mix_to_gray() {
+        let gray = colors::BLACK.mix(&colors::WHITE, 0.5);
+        assert_eq!(rgb(&gray), [0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn mixing_is_clamped() {
+        assert_eq!(rgb(&colors::RED.mix(&colors::BLUE, -1.0)), [1.0, 0.0, 0.0]);
+        assert_eq!(rgb(&colors::RED.mix(&colors::BLUE, 2.0)), [0.0, 0.0, 1.0]);
+        assert_eq!(
+            rgb(&colors::RED.mix(&colors::BLUE, f32::NAN)),
+            [1.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn linear_mixes_are_lighter() {
+        let gray = colors::BLACK.mix_linear(&colors::WHITE, 0.5);
+        let expected = 0.5f32.powf(1.0 / 2.2);
+        rgb(&gray)
+            .iter()
+            .for_each(|c| assert!((c - expected).abs() < 1.0e-4, "{} is not {}", c, expected));
+    }
+
+    #[test]
+    fn averages_weigh_colors_evenly() {
+        let gray = SolidColor::new(0.5, 0.5, 0.5).unwrap();
+        let colors = [gray, gray, gray];
+        rgb(&SolidColor::average(&colors).unwrap())
+            .iter()
+            .for_each(|c| assert!((c - 0.5).abs() < 1.0e-4));
+
+        let pair = [colors::BLACK, colors::WHITE];
+        assert_eq!(
+            rgb(&SolidColor::average(&pair).unwrap()),
+            rgb(&colors::BLACK.mix_linear(&colors::WHITE, 0.5))
+        );
+        assert!(SolidColor::average(&[]).is_none());
+    }
+}
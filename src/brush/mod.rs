@@ -1,9 +1,15 @@
 // GPLv3 License
 
+mod blend;
 mod color;
+pub use blend::*;
 pub use color::*;
 
-use serde::{Deserialize, Serialize};
+use crate::{layout, loading_version, since, since_or};
+use image::{Primitive, Rgba};
+use num_traits::{AsPrimitive, Bounded};
+use ordered_float::NotNan;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::boxed::Box;
 
 pub mod colors {
@@ -15,11 +21,109 @@ pub mod colors {
     pub const BLUE: SolidColor = unsafe { SolidColor::new_unchecked(0.0, 0.0, 1.0) };
 }
 
+const OPAQUE: NotNan<f32> = unsafe { NotNan::unchecked_new(1.0) };
+
+#[inline]
+fn opaque() -> NotNan<f32> {
+    OPAQUE
+}
+
+#[inline]
+fn deserialize_opacity<'de, D: Deserializer<'de>>(de: D) -> Result<NotNan<f32>, D::Error> {
+    since_or(de, layout::BRUSH_OPACITY, opaque)
+}
+
+/// Where an object gets its brush from.
+#[derive(Clone, Copy, Serialize)]
+pub enum BrushRef {
+    /// A brush in the project's palette, shared with every other object that uses it.
+    Index(usize),
+    /// A brush of the object's own.
+    Inline(Brush),
+}
+
+impl From<usize> for BrushRef {
+    #[inline]
+    fn from(index: usize) -> Self {
+        Self::Index(index)
+    }
+}
+
+impl From<Brush> for BrushRef {
+    #[inline]
+    fn from(brush: Brush) -> Self {
+        Self::Inline(brush)
+    }
+}
+
+impl BrushRef {
+    /// Look up the brush, using the project's palette for indices.
+    #[inline]
+    pub fn resolve<'a>(&'a self, palette: &'a [Brush]) -> Option<&'a Brush> {
+        match self {
+            Self::Index(i) => palette.get(*i),
+            Self::Inline(ref brush) => Some(brush),
+        }
+    }
+}
+
+// the tagged form of a brush reference, as it's written
+#[derive(Deserialize)]
+#[serde(rename = "BrushRef")]
+enum BrushRefTagged {
+    Index(usize),
+    Inline(Brush),
+}
+
+impl From<BrushRefTagged> for BrushRef {
+    #[inline]
+    fn from(br: BrushRefTagged) -> Self {
+        match br {
+            BrushRefTagged::Index(i) => Self::Index(i),
+            BrushRefTagged::Inline(brush) => Self::Inline(brush),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BrushRef {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        // before objects could have their own brush, they were written as a bare index
+        if loading_version() < layout::BRUSH_REFS {
+            usize::deserialize(de).map(Self::Index)
+        } else {
+            BrushRefTagged::deserialize(de).map(Into::into)
+        }
+    }
+}
+
+/// Scatters the points of a stroke, for a chalky or sprayed look.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Jitter {
+    /// How far, in pixels, each point may be moved.
+    pub radius: f32,
+    /// The fraction of points that are drawn at all.
+    pub density: f32,
+    // picks the offsets, this is derived from the frame's or the project's seed when rasterizing
+    #[serde(skip)]
+    pub seed: u64,
+}
+
 /// A brush.
 #[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct Brush {
     color: DynamicColor,
     width: u32,
+    // brushes saved before opacity was introduced are fully opaque
+    #[serde(default = "opaque", deserialize_with = "deserialize_opacity")]
+    opacity: NotNan<f32>,
+    #[serde(default, deserialize_with = "since::<_, _, { layout::BLEND_MODES }>")]
+    blend_mode: BlendMode,
+    #[serde(default, deserialize_with = "since::<_, _, { layout::JITTER }>")]
+    jitter: Option<Jitter>,
+    // fill polygons without anti-aliasing, for pixel art
+    #[serde(default, deserialize_with = "since::<_, _, { layout::HARD_EDGES }>")]
+    hard_edges: bool,
 }
 
 impl Brush {
@@ -28,12 +132,38 @@ impl Brush {
         Self {
             color: DynamicColor::from_color(color),
             width,
+            opacity: OPAQUE,
+            blend_mode: BlendMode::Normal,
+            jitter: None,
+            hard_edges: false,
         }
     }
 
     #[inline]
     pub const fn new_const(dn: DynamicColor, width: u32) -> Self {
-        Self { color: dn, width }
+        Self {
+            color: dn,
+            width,
+            opacity: OPAQUE,
+            blend_mode: BlendMode::Normal,
+            jitter: None,
+            hard_edges: false,
+        }
+    }
+
+    /// Get the color this brush paints at a location, with the brush's opacity applied.
+    #[inline]
+    pub fn as_rgba<T: Bounded + Copy + Into<f32> + Primitive + 'static>(
+        &self,
+        loc_info: &LocationInfo,
+    ) -> Rgba<T>
+    where
+        f32: AsPrimitive<T>,
+    {
+        let mut rgba: Rgba<T> = self.color.as_rgba(loc_info);
+        let alpha: f32 = rgba[3].into();
+        rgba[3] = (alpha * self.opacity.into_inner()).as_();
+        rgba
     }
 
     #[inline]
@@ -55,4 +185,141 @@ impl Brush {
     pub fn set_width(&mut self, val: u32) {
         self.width = val;
     }
+
+    #[inline]
+    pub fn opacity(&self) -> NotNan<f32> {
+        self.opacity
+    }
+
+    #[inline]
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    #[inline]
+    pub fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.blend_mode = mode;
+    }
+
+    #[inline]
+    pub fn jitter(&self) -> Option<&Jitter> {
+        self.jitter.as_ref()
+    }
+
+    /// How far past an object's outline this brush can draw, counting how far jitter scatters it.
+    #[inline]
+    pub fn reach(&self) -> f32 {
+        self.width as f32 + self.jitter.map_or(0.0, |j| j.radius)
+    }
+
+    #[inline]
+    pub fn set_jitter(&mut self, jitter: Option<Jitter>) {
+        self.jitter = jitter;
+    }
+
+    /// Set the seed used to scatter this brush's strokes, if it has jitter.
+    #[inline]
+    pub fn set_jitter_seed(&mut self, seed: u64) {
+        if let Some(ref mut jitter) = self.jitter {
+            jitter.seed = seed;
+        }
+    }
+
+    /// Whether polygons filled with this brush have hard, aliased edges instead of smooth ones.
+    #[inline]
+    pub fn hard_edges(&self) -> bool {
+        self.hard_edges
+    }
+
+    #[inline]
+    pub fn set_hard_edges(&mut self, hard_edges: bool) {
+        self.hard_edges = hard_edges;
+    }
+
+    /// Set the opacity of this brush, clamped between 0 and 1.
+    #[inline]
+    pub fn set_opacity(&mut self, val: NotNan<f32>) {
+        self.opacity = NotNan::new(val.into_inner().clamp(0.0, 1.0)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rasterize_thin_line_into, TCImage};
+    use pathfinder_geometry::{line_segment::LineSegment2F, vector::Vector2F};
+
+    // the most opaque pixel of a line drawn with the brush, thin so no pixel is drawn twice
+    fn drawn_alpha(brush: &Brush) -> f32 {
+        let mut img = TCImage::new(16, 16);
+        let line = LineSegment2F::new(Vector2F::new(2.0, 8.0), Vector2F::new(14.0, 8.0));
+        rasterize_thin_line_into(&mut img, &line, brush);
+        img.pixels().map(|p| p[3] as f32).fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn half_opacity_halves_the_alpha() {
+        let mut brush = Brush::new(colors::RED, 2);
+        let opaque = drawn_alpha(&brush);
+        assert!(opaque > 0.0);
+
+        brush.set_opacity(NotNan::new(0.5).unwrap());
+        let half = drawn_alpha(&brush);
+        assert!((half - opaque / 2.0).abs() <= 1.0, "{} vs {}", half, opaque);
+    }
+
+    #[test]
+    fn opacity_is_clamped() {
+        let mut brush = Brush::new(colors::RED, 2);
+        brush.set_opacity(NotNan::new(3.0).unwrap());
+        assert_eq!(brush.opacity().into_inner(), 1.0);
+        brush.set_opacity(NotNan::new(-1.0).unwrap());
+        assert_eq!(brush.opacity().into_inner(), 0.0);
+    }
+
+    // the brush a reference resolves to, against a palette of one black brush
+    fn resolved_width(brush: &BrushRef) -> Option<u32> {
+        brush
+            .resolve(&[Brush::new(colors::BLACK, 1)])
+            .map(|b| b.width())
+    }
+
+    #[test]
+    fn brush_refs_resolve() {
+        assert_eq!(resolved_width(&BrushRef::Index(0)), Some(1));
+        assert_eq!(resolved_width(&BrushRef::Index(1)), None);
+        assert_eq!(resolved_width(&Brush::new(colors::RED, 5).into()), Some(5));
+    }
+
+    #[test]
+    fn brush_refs_round_trip() {
+        for brush in &[BrushRef::Index(3), Brush::new(colors::RED, 5).into()] {
+            let bytes = bincode::serialize(brush).unwrap();
+            let from_bincode: BrushRef = bincode::deserialize(&bytes).unwrap();
+            let json = serde_json::to_string(brush).unwrap();
+            let from_json: BrushRef = serde_json::from_str(&json).unwrap();
+
+            for loaded in &[from_bincode, from_json] {
+                match (brush, loaded) {
+                    (BrushRef::Index(a), BrushRef::Index(b)) => assert_eq!(a, b),
+                    (BrushRef::Inline(_), BrushRef::Inline(b)) => assert_eq!(b.width(), 5),
+                    _ => panic!("the brush reference changed kind"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bare_indices_are_upgraded() {
+        let old = layout::BRUSH_REFS - 1;
+        let bytes = bincode::serialize(&3usize).unwrap();
+        let from_bincode: BrushRef =
+            crate::with_loading_version(old, || bincode::deserialize(&bytes).unwrap());
+        let from_json: BrushRef =
+            crate::with_loading_version(old, || serde_json::from_str("3").unwrap());
+
+        for loaded in &[from_bincode, from_json] {
+            assert!(matches!(loaded, BrushRef::Index(3)));
+        }
+    }
 }
@@ -1,68 +1,193 @@
 // GPLv3 License
 
-use super::{Brush, Color, DrawTarget, Line, LocationInfo, TCImage};
-use imageproc::drawing::{self, BresenhamLineIter, Canvas};
+use super::{BlendMode, Brush, ColorAtom, DrawTarget, Jitter, Line, LocationInfo, TCImage};
+use image::{ImageBuffer, Rgba};
+use imageproc::drawing::{self, BresenhamLineIter};
 use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard};
+use pathfinder_geometry::line_segment::LineSegment2F;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rayon::prelude::*;
-use std::mem;
+use smallvec::SmallVec;
+use std::f32::consts::PI;
 
-// function to rasterize a line with a drawing function
+/// Something that can be drawn onto, pixel by pixel.
+pub trait PixelSink {
+    fn dimensions(&self) -> (u32, u32);
+    /// Composite a color onto the pixel at `(x, y)`.
+    fn composite(&mut self, x: u32, y: u32, src: Rgba<ColorAtom>, mode: BlendMode);
+}
+
+impl PixelSink for TCImage {
+    #[inline]
+    fn dimensions(&self) -> (u32, u32) {
+        ImageBuffer::dimensions(self)
+    }
+
+    #[inline]
+    fn composite(&mut self, x: u32, y: u32, src: Rgba<ColorAtom>, mode: BlendMode) {
+        let dst = *self.get_pixel(x, y);
+        self.put_pixel(x, y, mode.composite(src, dst));
+    }
+}
+
+/// Pixels recorded to be composited onto an image later. Items are drawn into these without
+/// holding the lock on the target, which is then only taken to copy the pixels over.
+pub struct PixelBatch {
+    width: u32,
+    height: u32,
+    pixels: Vec<(u32, u32, Rgba<ColorAtom>, BlendMode)>,
+}
+
+impl PixelBatch {
+    #[inline]
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: Vec::new(),
+        }
+    }
+
+    /// Composite the recorded pixels onto an image, in the order they were drawn.
+    #[inline]
+    pub fn apply(&self, img: &mut TCImage) {
+        self.pixels
+            .iter()
+            .for_each(|(x, y, src, mode)| img.composite(*x, *y, *src, *mode));
+    }
+}
+
+impl PixelSink for PixelBatch {
+    #[inline]
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    #[inline]
+    fn composite(&mut self, x: u32, y: u32, src: Rgba<ColorAtom>, mode: BlendMode) {
+        self.pixels.push((x, y, src, mode));
+    }
+}
+
+/// Draw onto a batch, and then copy it onto the target while holding the lock only once.
 #[inline]
-fn rasterize_line_custom<F, Ln: Line<f32>>(width: u32, height: u32, line: &Ln, mut f: F)
-where
-    F: FnMut(i32, i32),
+pub fn draw_batched<F: FnOnce(&mut PixelBatch)>(target: &DrawTarget, f: F) {
+    let (width, height) = target.read().0.dimensions();
+    let mut batch = PixelBatch::new(width, height);
+    f(&mut batch);
+
+    let mut img = target.write();
+    batch.apply(&mut img.0);
+    img.1 = true;
+}
+
+// function to rasterize a line with a drawing function, optionally scattering its points
+// the function is also given how far along the line the point is, from 0 to 1
+#[inline]
+fn rasterize_line_custom<F, Ln: Line<f32>>(
+    width: u32,
+    height: u32,
+    line: &Ln,
+    jitter: Option<&Jitter>,
+    mut f: F,
+) where
+    F: FnMut(i32, i32, f32),
 {
     let line_iter =
         BresenhamLineIter::new((line.from_x(), line.from_y()), (line.to_x(), line.to_y()));
 
+    let (x0, y0) = (line.from_x(), line.from_y());
+    let length = (line.to_x() - x0).hypot(line.to_y() - y0);
+    let t_at = |x: i32, y: i32| {
+        if length > 0.0 {
+            ((x as f32 - x0).hypot(y as f32 - y0) / length).min(1.0)
+        } else {
+            0.0
+        }
+    };
+
+    // mix the endpoints into the seed, so each segment of a stroke is scattered differently
+    let mut rng = jitter.map(|j| {
+        let endpoints = [line.from_x(), line.from_y(), line.to_x(), line.to_y()];
+        let seed = endpoints
+            .iter()
+            .fold(j.seed, |s, e| s.rotate_left(16) ^ e.to_bits() as u64);
+        (j, StdRng::seed_from_u64(seed))
+    });
+
     line_iter
-        .filter(|(x, y)| *x >= 0 && *x < width as i32 && *y >= 0 && *y < height as i32)
-        .for_each(|(x, y)| {
-            f(x, y);
+        .filter_map(|(x, y)| match rng {
+            None => Some((x, y, t_at(x, y))),
+            Some((j, ref mut rng)) => {
+                if rng.gen::<f32>() >= j.density {
+                    return None;
+                }
+
+                let angle = rng.gen_range(0.0, 2.0 * PI);
+                let dist = rng.gen::<f32>() * j.radius;
+                Some((
+                    (x as f32 + dist * angle.cos()).round() as i32,
+                    (y as f32 + dist * angle.sin()).round() as i32,
+                    t_at(x, y),
+                ))
+            }
+        })
+        .filter(|(x, y, _t)| *x >= 0 && *x < width as i32 && *y >= 0 && *y < height as i32)
+        .for_each(|(x, y, t)| {
+            f(x, y, t);
         });
 }
 
 // function to draw a 1-pixel wide line segment onto the canvas
 #[inline]
-fn rasterize_thin_line_internal<Ln: Line<f32>>(
-    c: &mut TCImage,
+fn rasterize_thin_line_internal<S: PixelSink + ?Sized, Ln: Line<f32>>(
+    c: &mut S,
     width: u32,
     height: u32,
     line: &Ln,
     brush: &Brush,
+    stroke: [f32; 2],
 ) {
-    rasterize_line_custom(width, height, line, |x, y| {
-        c.draw_pixel(
-            x as u32,
-            y as u32,
-            brush.color().as_rgba(&LocationInfo {
-                x: x as u32,
-                y: y as u32,
-                width,
-                height,
-            }),
-        )
+    rasterize_line_custom(width, height, line, None, |x, y, t| {
+        let (x, y) = (x as u32, y as u32);
+        let src = brush.as_rgba(&LocationInfo {
+            x,
+            y,
+            width,
+            height,
+            stroke: stroke[0] + (stroke[1] - stroke[0]) * t,
+        });
+
+        c.composite(x, y, src, brush.blend_mode())
     });
 }
 
 #[inline]
 pub fn rasterize_thin_line<Ln: Line<f32>>(c: &DrawTarget, line: &Ln, brush: &Brush) {
-    let mut img = c.write();
-    let (width, height) = img.0.dimensions();
+    draw_batched(c, |batch| rasterize_thin_line_into(batch, line, brush))
+}
 
-    rasterize_thin_line_internal(&mut img.0, width, height, line, brush)
+/// Draw a 1-pixel wide line directly onto an image.
+#[inline]
+pub fn rasterize_thin_line_into<S: PixelSink + ?Sized, Ln: Line<f32>>(
+    img: &mut S,
+    line: &Ln,
+    brush: &Brush,
+) {
+    let (width, height) = img.dimensions();
+    rasterize_thin_line_internal(img, width, height, line, brush, [0.0, 1.0])
 }
 
 // function to draw an ellipse
 #[inline]
-fn rasterize_circle(
-    c: &mut TCImage,
+fn rasterize_circle<S: PixelSink + ?Sized>(
+    c: &mut S,
     width: u32,
     height: u32,
-    x0: f32,
-    y0: f32,
+    center: (f32, f32),
     radius: u32,
     brush: &Brush,
+    stroke: f32,
 ) {
     struct CircleRasterizer {
         x: i32,
@@ -92,7 +217,7 @@ fn rasterize_circle(
         }
     }
 
-    let (x0, y0) = (x0 as i32, y0 as i32);
+    let (x0, y0) = (center.0 as i32, center.1 as i32);
 
     CircleRasterizer {
         x: 0,
@@ -109,6 +234,7 @@ fn rasterize_circle(
                 ((x0 + x) as f32, (y0 + y) as f32),
             ),
             brush,
+            [stroke, stroke],
         );
 
         rasterize_thin_line_internal(
@@ -120,6 +246,7 @@ fn rasterize_circle(
                 ((x0 + y) as f32, (y0 + x) as f32),
             ),
             brush,
+            [stroke, stroke],
         );
 
         rasterize_thin_line_internal(
@@ -130,7 +257,8 @@ fn rasterize_circle(
                 ((x0 - x) as f32, (y0 - y) as f32),
                 ((x0 + x) as f32, (y0 - y) as f32),
             ),
-            &brush,
+            brush,
+            [stroke, stroke],
         );
 
         rasterize_thin_line_internal(
@@ -142,6 +270,7 @@ fn rasterize_circle(
                 ((x0 + y) as f32, (y0 - x) as f32),
             ),
             brush,
+            [stroke, stroke],
         );
     });
 }
@@ -149,28 +278,245 @@ fn rasterize_circle(
 // function to draw a thicker line segment onto a canvas
 #[inline]
 pub fn rasterize_thick_line<Ln: Line<f32>>(c: &DrawTarget, line: &Ln, brush: &Brush) {
-    let mut img = c.write();
-    let (width, height) = img.0.dimensions();
-    rasterize_line_custom(width, height, line, |x, y| {
+    draw_batched(c, |batch| rasterize_thick_line_into(batch, line, brush));
+}
+
+/// Draw a line as wide as the brush directly onto an image.
+#[inline]
+pub fn rasterize_thick_line_into<S: PixelSink + ?Sized, Ln: Line<f32>>(
+    img: &mut S,
+    line: &Ln,
+    brush: &Brush,
+) {
+    let width = brush.width() as f32;
+    rasterize_tapered_line_into(img, line, brush, width, width);
+}
+
+/// Draw a line whose width changes linearly from `start` at its beginning to `end` at its end.
+#[inline]
+pub fn rasterize_tapered_line<Ln: Line<f32>>(
+    c: &DrawTarget,
+    line: &Ln,
+    brush: &Brush,
+    start: f32,
+    end: f32,
+) {
+    draw_batched(c, |batch| {
+        rasterize_tapered_line_into(batch, line, brush, start, end)
+    });
+}
+
+/// Draw a tapered line directly onto an image.
+#[inline]
+pub fn rasterize_tapered_line_into<S: PixelSink + ?Sized, Ln: Line<f32>>(
+    img: &mut S,
+    line: &Ln,
+    brush: &Brush,
+    start: f32,
+    end: f32,
+) {
+    rasterize_stroke_segment_into(img, line, brush, [start, end], [0.0, 1.0]);
+}
+
+/// Draw one segment of a longer stroke, which covers the part of the stroke from `stroke[0]`
+/// to `stroke[1]`. The width changes linearly from `widths[0]` to `widths[1]` along it.
+pub fn rasterize_stroke_segment_into<S: PixelSink + ?Sized, Ln: Line<f32>>(
+    img: &mut S,
+    line: &Ln,
+    brush: &Brush,
+    widths: [f32; 2],
+    stroke: [f32; 2],
+) {
+    let (width, height) = img.dimensions();
+    let [start, end] = widths;
+
+    rasterize_line_custom(width, height, line, brush.jitter(), |x, y, t| {
+        // interpolate the radius by how far along the line we are
+        let radius = (start + (end - start) * t).round().max(0.0) as u32;
+        let stroke = stroke[0] + (stroke[1] - stroke[0]) * t;
+
         rasterize_circle(
-            &mut img.0,
+            img,
             width,
             height,
-            x as f32,
-            y as f32,
-            brush.width(),
+            (x as f32, y as f32),
+            radius,
             brush,
+            stroke,
         );
     });
 }
 
+/// Draw a chain of line segments as one continuous stroke.
+pub fn rasterize_polyline_into<S: PixelSink + ?Sized, I: IntoIterator<Item = LineSegment2F>>(
+    img: &mut S,
+    edges: I,
+    brush: &Brush,
+) {
+    let edges: SmallVec<[LineSegment2F; 16]> = edges.into_iter().collect();
+    let total: f32 = edges.iter().map(|l| l.vector().length()).sum();
+    let width = brush.width() as f32;
+
+    let mut travelled = 0.0;
+    edges.iter().for_each(|l| {
+        let len = l.vector().length();
+        let stroke = if total > 0.0 {
+            [travelled / total, (travelled + len) / total]
+        } else {
+            [0.0, 1.0]
+        };
+        travelled += len;
+
+        rasterize_stroke_segment_into(img, l, brush, [width, width], stroke);
+    });
+}
+
+/// A line that tapers from one width to another.
+pub struct TaperedLine<Ln> {
+    pub line: Ln,
+    pub widths: [f32; 2],
+}
+
+impl<Ln: Line<f32>> Rasterizable for TaperedLine<Ln> {
+    #[inline]
+    fn rasterize_into(&self, img: &mut dyn PixelSink, brush: &Brush, _tolerance: f32) {
+        let [start, end] = self.widths;
+        rasterize_tapered_line_into(img, &self.line, brush, start, end);
+    }
+}
+
 pub trait Rasterizable {
-    fn rasterize(&self, target: &DrawTarget, brush: &Brush);
+    /// Draw this item onto the target. Curves are flattened into segments that stay within
+    /// `tolerance` pixels of the true curve.
+    #[inline]
+    fn rasterize(&self, target: &DrawTarget, brush: &Brush, tolerance: f32) {
+        draw_batched(target, |batch| self.rasterize_into(batch, brush, tolerance));
+    }
+
+    /// Draw this item directly onto an image or a batch of pixels, without going through a lock.
+    fn rasterize_into(&self, img: &mut dyn PixelSink, brush: &Brush, tolerance: f32);
 }
 
 impl<T: Line<f32>> Rasterizable for T {
     #[inline]
-    fn rasterize(&self, target: &DrawTarget, brush: &Brush) {
-        rasterize_thick_line(target, self, brush);
+    fn rasterize_into(&self, img: &mut dyn PixelSink, brush: &Brush, _tolerance: f32) {
+        rasterize_thick_line_into(img, self, brush);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors;
+    use pathfinder_geometry::vector::Vector2F;
+
+    // how many pixels down a column are drawn on
+    fn column_coverage(img: &TCImage, x: u32) -> usize {
+        (0..img.height())
+            .filter(|y| img.get_pixel(x, *y)[3] > 0)
+            .count()
+    }
+
+    fn tapered(start: f32, end: f32) -> TCImage {
+        let mut img = TCImage::new(64, 64);
+        let line = LineSegment2F::new(Vector2F::new(10.0, 32.0), Vector2F::new(54.0, 32.0));
+        let brush = Brush::new(colors::BLACK, 1);
+        rasterize_tapered_line_into(&mut img, &line, &brush, start, end);
+        img
+    }
+
+    #[test]
+    fn tapered_lines_narrow_along_their_length() {
+        let img = tapered(10.0, 1.0);
+        let (start, middle, end) = (
+            column_coverage(&img, 10),
+            column_coverage(&img, 32),
+            column_coverage(&img, 54),
+        );
+
+        // the brush is a circle with the width as its radius
+        assert_eq!(start, 21);
+        assert!(end <= 3, "{} pixels drawn at the narrow end", end);
+        assert!(start > middle && middle > end);
+
+        // and the other way around
+        let img = tapered(1.0, 10.0);
+        assert_eq!(column_coverage(&img, 54), 21);
+        assert!(column_coverage(&img, 10) <= 3);
+    }
+
+    #[test]
+    fn batches_draw_what_the_image_would() {
+        let line = LineSegment2F::new(Vector2F::new(3.0, 60.0), Vector2F::new(50.0, 7.0));
+        let brush = Brush::new(colors::BLACK, 3);
+        let mut direct = TCImage::new(64, 64);
+        rasterize_thick_line_into(&mut direct, &line, &brush);
+
+        let target = RwLock::new((TCImage::new(64, 64), false));
+        rasterize_thick_line(&target, &line, &brush);
+        let (batched, changed) = target.into_inner();
+        assert!(changed);
+        assert_eq!(*batched, *direct);
+    }
+
+    // draws straight onto the target, taking the lock for every pixel
+    struct Locked<'a>(&'a DrawTarget);
+
+    impl PixelSink for Locked<'_> {
+        fn dimensions(&self) -> (u32, u32) {
+            self.0.read().0.dimensions()
+        }
+
+        fn composite(&mut self, x: u32, y: u32, src: Rgba<ColorAtom>, mode: BlendMode) {
+            let mut img = self.0.write();
+            img.0.composite(x, y, src, mode);
+            img.1 = true;
+        }
+    }
+
+    // run with `cargo test --release -- --ignored lock_contention` to see the numbers, and
+    // leave out the deadlock_detection feature since it slows down every lock
+    #[test]
+    #[ignore]
+    fn lock_contention() {
+        use std::time::Instant;
+
+        let lines: Vec<LineSegment2F> = (0..2000)
+            .map(|i| {
+                let y = (i % 500) as f32 + 6.0;
+                LineSegment2F::new(Vector2F::new(6.0, y), Vector2F::new(500.0, 506.0 - y))
+            })
+            .collect();
+        let brush = Brush::new(colors::BLACK, 4);
+
+        let target = RwLock::new((TCImage::new(512, 512), false));
+        let start = Instant::now();
+        lines.par_iter().for_each(|line| {
+            rasterize_thick_line_into(&mut Locked(&target), line, &brush);
+        });
+        let per_pixel = start.elapsed();
+
+        let target = RwLock::new((TCImage::new(512, 512), false));
+        let start = Instant::now();
+        lines
+            .par_iter()
+            .for_each(|line| rasterize_thick_line(&target, line, &brush));
+        let batched = start.elapsed();
+
+        println!(
+            "{} lines on {} threads: {:?} locking every pixel, {:?} batched",
+            lines.len(),
+            rayon::current_num_threads(),
+            per_pixel,
+            batched
+        );
+    }
+
+    #[test]
+    fn untapered_lines_are_thick_lines() {
+        let mut thick = TCImage::new(64, 64);
+        let line = LineSegment2F::new(Vector2F::new(10.0, 32.0), Vector2F::new(54.0, 32.0));
+        rasterize_thick_line_into(&mut thick, &line, &Brush::new(colors::BLACK, 4));
+        assert_eq!(*tapered(4.0, 4.0), *thick);
     }
 }
@@ -0,0 +1,49 @@
+// GPLv3 License
+
+use std::{error::Error as StdError, io};
+use thiserror::Error;
+
+/// A boxed error from one of our dependencies.
+pub type BoxedError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// An error that may occur while loading, saving, or rendering a project.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Unable to serialize: {0}")]
+    Serialize(#[source] BoxedError),
+    #[error("Unable to deserialize: {0}")]
+    Deserialize(#[source] BoxedError),
+    #[error("Unable to render: {0}")]
+    Render(String),
+    #[error("Unsupported format: {0}")]
+    UnsupportedFormat(&'static str),
+    #[error("Invalid input: {0}")]
+    InvalidInput(&'static str),
+    #[error("Cancelled")]
+    Cancelled,
+    #[error("Invalid project: {0}")]
+    InvalidProject(String),
+    #[error("Line {line}: {message}")]
+    Script { line: usize, message: String },
+}
+
+impl Error {
+    #[inline]
+    pub fn serialize<E: Into<BoxedError>>(err: E) -> Self {
+        Self::Serialize(err.into())
+    }
+
+    #[inline]
+    pub fn deserialize<E: Into<BoxedError>>(err: E) -> Self {
+        Self::Deserialize(err.into())
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    #[inline]
+    fn from(err: png::EncodingError) -> Self {
+        Self::Render(err.to_string())
+    }
+}
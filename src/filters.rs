@@ -0,0 +1,173 @@
+// GPLv3 License
+
+use image::{ImageBuffer, Primitive, Rgba};
+use num_traits::{Bounded, NumCast};
+use rayon::prelude::*;
+
+/// An RGBA image of any color depth, such as a `TCImage` or an `RgbaImage`.
+pub type RgbaBuffer<P> = ImageBuffer<Rgba<P>, Vec<P>>;
+
+#[inline]
+fn max_value<P: Primitive>() -> f32 {
+    <f32 as NumCast>::from(P::max_value()).unwrap()
+}
+
+#[inline]
+fn to_atom<P: Primitive>(value: f32) -> P {
+    <P as NumCast>::from(value.round().max(0.0).min(max_value::<P>())).unwrap()
+}
+
+// the weights of a normalized gaussian kernel, from the center outwards
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (sigma * 3.0).ceil() as usize;
+    let weights: Vec<f32> = (0..=radius)
+        .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+    weights.into_iter().map(|w| w / total).collect()
+}
+
+// blur rows of premultiplied pixels in place, where each row is `len` pixels `stride` apart
+fn blur_lines(
+    data: &mut [[f32; 4]],
+    lines: usize,
+    len: usize,
+    line_step: usize,
+    stride: usize,
+    kernel: &[f32],
+) {
+    let blurred: Vec<Vec<[f32; 4]>> = (0..lines)
+        .into_par_iter()
+        .map(|line| {
+            let at = |i: isize| {
+                let i = i.max(0).min(len as isize - 1) as usize;
+                data[line * line_step + i * stride]
+            };
+
+            (0..len as isize)
+                .map(|i| {
+                    let mut sum = [0.0; 4];
+                    kernel.iter().enumerate().for_each(|(k, w)| {
+                        let k = k as isize;
+                        let (before, after) = (at(i - k), at(i + k));
+                        let w = if k == 0 { *w / 2.0 } else { *w };
+                        (0..4).for_each(|c| sum[c] += (before[c] + after[c]) * w);
+                    });
+                    sum
+                })
+                .collect()
+        })
+        .collect();
+
+    blurred.into_iter().enumerate().for_each(|(line, pixels)| {
+        pixels
+            .into_iter()
+            .enumerate()
+            .for_each(|(i, px)| data[line * line_step + i * stride] = px);
+    });
+}
+
+/// Blur an image with a gaussian kernel. The blur is done as a horizontal pass and then a
+/// vertical one, with the colors weighted by their alpha so transparent pixels don't darken
+/// the edges. Pixels past the edge of the image are taken to be copies of the edge.
+pub fn gaussian_blur<P: Primitive + Send + Sync + 'static>(img: &mut RgbaBuffer<P>, sigma: f32) {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    if sigma.is_nan() || sigma <= 0.0 || width == 0 || height == 0 {
+        return;
+    }
+
+    let max = max_value::<P>();
+    let mut data: Vec<[f32; 4]> = img
+        .pixels()
+        .map(|Rgba(px)| {
+            let a = <f32 as NumCast>::from(px[3]).unwrap() / max;
+            let c = |i: usize| <f32 as NumCast>::from(px[i]).unwrap() * a;
+            [c(0), c(1), c(2), a * max]
+        })
+        .collect();
+
+    let kernel = gaussian_kernel(sigma);
+    blur_lines(&mut data, height, width, width, 1, &kernel);
+    blur_lines(&mut data, width, height, 1, width, &kernel);
+
+    img.pixels_mut().zip(data).for_each(|(px, [r, g, b, a])| {
+        let unmultiply = if a > 0.0 { max / a } else { 0.0 };
+        *px = Rgba([
+            to_atom(r * unmultiply),
+            to_atom(g * unmultiply),
+            to_atom(b * unmultiply),
+            to_atom(a),
+        ]);
+    });
+}
+
+/// Brighten or darken an image, by adding `amount` (from -1 to 1) of full brightness to every
+/// color channel. Alpha is left alone.
+pub fn brightness<P: Primitive + 'static>(img: &mut RgbaBuffer<P>, amount: f32) {
+    let shift = amount * max_value::<P>();
+    img.pixels_mut().for_each(|Rgba(px)| {
+        px.iter_mut()
+            .take(3)
+            .for_each(|c| *c = to_atom(<f32 as NumCast>::from(*c).unwrap() + shift));
+    });
+}
+
+/// Invert the colors of an image, leaving alpha alone.
+pub fn invert<P: Primitive + 'static>(img: &mut RgbaBuffer<P>) {
+    let max = <P as Bounded>::max_value();
+    img.pixels_mut().for_each(|Rgba(px)| {
+        px.iter_mut().take(3).for_each(|c| *c = max - *c);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    #[test]
+    fn blur_spreads_a_bright_pixel() {
+        let mut img = RgbaImage::from_pixel(9, 9, Rgba([0, 0, 0, 255]));
+        img.put_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        let energy = |img: &RgbaImage| img.pixels().map(|px| px[0] as u32).sum::<u32>();
+        let before = energy(&img);
+
+        gaussian_blur(&mut img, 1.0);
+        assert!(img.get_pixel(4, 4)[0] < 255);
+        assert!(img.get_pixel(3, 4)[0] > 0 && img.get_pixel(4, 5)[0] > 0);
+        // neighbors on a diagonal get less than those beside it
+        assert!(img.get_pixel(3, 3)[0] < img.get_pixel(3, 4)[0]);
+        // and the brightness is spread out rather than lost, give or take rounding
+        let after = energy(&img) as i32;
+        assert!((after - before as i32).abs() < 81);
+    }
+
+    #[test]
+    fn blur_keeps_transparent_pixels_from_darkening() {
+        let mut img = RgbaImage::from_pixel(5, 1, Rgba([0, 0, 0, 0]));
+        img.put_pixel(2, 0, Rgba([255, 255, 255, 255]));
+        gaussian_blur(&mut img, 1.0);
+        let px = img.get_pixel(1, 0);
+        assert!(px[3] > 0);
+        assert_eq!(px[0], 255);
+    }
+
+    #[test]
+    fn invert_twice_is_the_original() {
+        let img = RgbaImage::from_fn(4, 4, |x, y| Rgba([(x * 60) as u8, (y * 60) as u8, 7, 100]));
+        let mut inverted = img.clone();
+        invert(&mut inverted);
+        assert_eq!(inverted.get_pixel(1, 2).0, [195, 135, 248, 100]);
+        invert(&mut inverted);
+        assert_eq!(inverted, img);
+    }
+
+    #[test]
+    fn brightness_is_clamped() {
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([100, 200, 250, 80]));
+        brightness(&mut img, 0.1);
+        assert_eq!(img.get_pixel(0, 0).0, [126, 226, 255, 80]);
+        brightness(&mut img, -1.0);
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0, 80]);
+    }
+}
@@ -41,7 +41,7 @@ pub trait Point<T: Copy>: Copy {
     {
         let a = (self.x() - other.x())
             .pow(2)
-            .sub((self.y() - other.y()).pow(2));
+            .add((self.y() - other.y()).pow(2));
         a.sqrt()
     }
     #[inline]
@@ -534,3 +534,21 @@ impl<T: Copy> Line<T> for ((T, T), (T, T)) {
         ((x1, y1), (x2, y2))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances_add_both_offsets() {
+        assert_eq!((0.0f32, 0.0f32).distance_to(&(3.0f32, 4.0f32)), 5.0);
+        assert_eq!(
+            Vector2F::new(1.0, 1.0).distance_to(&Vector2F::new(-2.0, 5.0)),
+            5.0
+        );
+
+        // a longer vertical offset used to make this NaN
+        let origin: Point2D<f32, ()> = Point2D::new(0.0, 0.0);
+        assert_eq!(Point::distance_to(&origin, &(0.0f32, 2.0f32)), 2.0);
+    }
+}
@@ -0,0 +1,124 @@
+// GPLv3 License
+
+use cairo::Context;
+use pathfinder_geometry::vector::Vector2F;
+use serde::{Deserialize, Serialize};
+
+/// How close, in canvas pixels, a point has to be to a guide to snap onto it.
+pub const GUIDE_TOLERANCE: f32 = 4.0;
+
+/// A line across the whole canvas that things can be lined up against. Guides are never
+/// rendered.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Guide {
+    Horizontal(f32),
+    Vertical(f32),
+}
+
+impl Guide {
+    #[inline]
+    pub fn is_horizontal(self) -> bool {
+        match self {
+            Self::Horizontal(_) => true,
+            Self::Vertical(_) => false,
+        }
+    }
+
+    #[inline]
+    pub fn distance_to(self, pt: Vector2F) -> f32 {
+        match self {
+            Self::Horizontal(y) => (pt.y() - y).abs(),
+            Self::Vertical(x) => (pt.x() - x).abs(),
+        }
+    }
+
+    /// The same guide, moved so that it passes through a point.
+    #[inline]
+    pub fn moved_to(self, pt: Vector2F) -> Self {
+        match self {
+            Self::Horizontal(_) => Self::Horizontal(pt.y()),
+            Self::Vertical(_) => Self::Vertical(pt.x()),
+        }
+    }
+}
+
+/// Snap a point onto the closest horizontal and the closest vertical guide within a tolerance.
+pub fn snap_to_guides(guides: &[Guide], pt: Vector2F, tolerance: f32) -> Vector2F {
+    let closest = |horizontal: bool| {
+        guides
+            .iter()
+            .filter(|g| g.is_horizontal() == horizontal)
+            .map(|g| (g.distance_to(pt), *g))
+            .filter(|(dist, _)| *dist <= tolerance)
+            .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+    };
+
+    let x = match closest(false) {
+        Some((_, Guide::Vertical(x))) => x,
+        _ => pt.x(),
+    };
+    let y = match closest(true) {
+        Some((_, Guide::Horizontal(y))) => y,
+        _ => pt.y(),
+    };
+    Vector2F::new(x, y)
+}
+
+/// Draw guides across the canvas. The context should be in canvas coordinates.
+pub fn draw_guides(guides: &[Guide], width: u32, height: u32, zoom: f32, context: &Context) {
+    if guides.is_empty() {
+        return;
+    }
+
+    // keep the lines a single screen pixel wide, however far in we're zoomed
+    context.set_source_rgba(0.0, 0.6, 0.9, 0.8);
+    context.set_line_width(1.0 / zoom as f64);
+    guides.iter().for_each(|guide| {
+        match *guide {
+            Guide::Horizontal(y) => {
+                context.move_to(0.0, y as f64);
+                context.line_to(width as f64, y as f64);
+            }
+            Guide::Vertical(x) => {
+                context.move_to(x as f64, 0.0);
+                context.line_to(x as f64, height as f64);
+            }
+        }
+        context.stroke();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gui::{Project, ProjectFormat};
+
+    #[test]
+    fn snapping_picks_the_nearest_guide() {
+        let guides = [
+            Guide::Vertical(10.0),
+            Guide::Vertical(13.0),
+            Guide::Horizontal(50.0),
+        ];
+        let snapped = snap_to_guides(&guides, Vector2F::new(12.0, 52.0), GUIDE_TOLERANCE);
+        assert_eq!(snapped, Vector2F::new(13.0, 50.0));
+
+        // guides out of reach are left alone
+        let snapped = snap_to_guides(&guides, Vector2F::new(20.0, 40.0), GUIDE_TOLERANCE);
+        assert_eq!(snapped, Vector2F::new(20.0, 40.0));
+    }
+
+    #[test]
+    fn guides_are_saved_with_the_project() {
+        let mut project = Project::new(20, 10, 0.5);
+        project
+            .guides_mut()
+            .extend(&[Guide::Horizontal(4.0), Guide::Vertical(7.5)]);
+
+        for format in &[ProjectFormat::Bincode, ProjectFormat::Json] {
+            let bytes = project.to_bytes(*format).unwrap();
+            let loaded = Project::from_bytes(&bytes, *format).unwrap();
+            assert_eq!(loaded.guides(), project.guides());
+        }
+    }
+}
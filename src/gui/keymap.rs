@@ -0,0 +1,694 @@
+// GPLv3 License
+
+use super::GuiModeType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Something a key can be bound to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Command {
+    Save,
+    SaveAs,
+    NewProject,
+    OpenProject,
+    Export,
+    ResizeCanvas,
+    PlaceImage,
+    CycleSymmetry,
+    ToggleQuantized,
+    DecreaseOpacity,
+    IncreaseOpacity,
+    SwitchMode,
+    SearchCommands,
+    ClearGuides,
+    PlaceSymbol,
+    EditSymbol,
+    PreviousFrame,
+    NextFrame,
+    DuplicateFrame,
+    TweenFrames,
+    SetFrameDuration,
+    CycleLoopMode,
+    TogglePlayback,
+    StopPlayback,
+    ZoomToFit,
+    ZoomToSelection,
+    TogglePolarGrid,
+    ShowStats,
+    ToggleHardEdges,
+    CycleBlendMode,
+    SetJitter,
+    TogglePixelMode,
+    FlattenFrame,
+    UnflattenFrame,
+    BlurImages,
+    AdjustBrightness,
+    InvertImages,
+    TypePoint,
+    CycleRecentColor,
+    CycleBackground,
+    RunScript,
+
+    BufferedMode,
+    FreedrawMode,
+    RulerMode,
+    SelectMode,
+    GuideMode,
+
+    DropLines,
+    FitCurves,
+    CycleFitMethod,
+    ToggleFitPreview,
+    ConvertLines,
+    ConvertLinesTapered,
+    IncreaseSimplify,
+    DecreaseSimplify,
+    IncreaseError,
+    DecreaseError,
+
+    SelectHistory,
+    Unselect,
+    DeleteSelected,
+    ClosedPolygon,
+    OpenPolygon,
+    Union,
+    Intersection,
+    Difference,
+    ExplodePolygons,
+    OutlineStrokes,
+    MakeSymbol,
+    GroupSelected,
+    UngroupSelected,
+    ToggleGroupSelect,
+    MoveSelected,
+    RotateSelected,
+    AlignSelected,
+    DistributeSelected,
+    DuplicateSelected,
+    BringToFront,
+    NudgeLeft,
+    NudgeRight,
+    NudgeUp,
+    NudgeDown,
+    Undo,
+}
+
+/// Every command, along with the mode it works in (or `None` if it works anywhere), its default
+/// key and a description of what it does. A key is only used once in each mode, and keys that
+/// work anywhere aren't used by any mode.
+const COMMANDS: &[(Command, Option<GuiModeType>, char, &str)] = &[
+    (Command::Save, None, 's', "Save the project"),
+    (Command::NewProject, None, 'n', "Start a new project"),
+    (Command::OpenProject, None, 'O', "Open another project"),
+    (
+        Command::SaveAs,
+        None,
+        'S',
+        "Save the project under a new name",
+    ),
+    (Command::Export, None, 'e', "Export the project"),
+    (Command::ResizeCanvas, None, 'c', "Resize the canvas"),
+    (
+        Command::PlaceImage,
+        None,
+        'i',
+        "Place an image on the canvas",
+    ),
+    (
+        Command::CycleSymmetry,
+        None,
+        'y',
+        "Cycle through symmetry modes",
+    ),
+    (
+        Command::ToggleQuantized,
+        None,
+        'q',
+        "Toggle storing quantized coordinates",
+    ),
+    (
+        Command::DecreaseOpacity,
+        None,
+        '[',
+        "Decrease the brush opacity",
+    ),
+    (
+        Command::IncreaseOpacity,
+        None,
+        ']',
+        "Increase the brush opacity",
+    ),
+    (Command::SwitchMode, None, 'm', "Switch to another mode"),
+    (
+        Command::SearchCommands,
+        None,
+        '?',
+        "Search the list of commands",
+    ),
+    (Command::ClearGuides, None, 'G', "Remove all of the guides"),
+    (Command::PlaceSymbol, None, 'K', "Place a symbol"),
+    (
+        Command::EditSymbol,
+        None,
+        'Y',
+        "Start or finish editing a symbol",
+    ),
+    (
+        Command::PreviousFrame,
+        None,
+        '<',
+        "Go to the previous frame",
+    ),
+    (Command::NextFrame, None, '>', "Go to the next frame"),
+    (
+        Command::DuplicateFrame,
+        None,
+        'F',
+        "Add a copy of the current frame",
+    ),
+    (
+        Command::TweenFrames,
+        None,
+        'T',
+        "Fill in frames up to the next one",
+    ),
+    (
+        Command::SetFrameDuration,
+        None,
+        'N',
+        "Set how long the frame shows in the animation",
+    ),
+    (
+        Command::CycleLoopMode,
+        None,
+        'J',
+        "Cycle what the animation does after its last frame",
+    ),
+    (
+        Command::TogglePlayback,
+        None,
+        'Z',
+        "Play or pause the animation",
+    ),
+    (
+        Command::StopPlayback,
+        None,
+        '.',
+        "Stop the animation and go back to where it started",
+    ),
+    (
+        Command::ZoomToFit,
+        None,
+        '0',
+        "Zoom to fit the whole canvas in the window",
+    ),
+    (
+        Command::ZoomToSelection,
+        None,
+        '=',
+        "Zoom to fit the selection in the window",
+    ),
+    (
+        Command::TogglePolarGrid,
+        None,
+        'P',
+        "Snap to spokes and rings around a center",
+    ),
+    (
+        Command::ShowStats,
+        None,
+        '#',
+        "Show what's in the frame and how big it is",
+    ),
+    (
+        Command::ToggleHardEdges,
+        None,
+        'X',
+        "Toggle smooth edges on the brush's fills",
+    ),
+    (
+        Command::CycleBlendMode,
+        None,
+        'j',
+        "Cycle how the brush blends with what's under it",
+    ),
+    (
+        Command::SetJitter,
+        None,
+        '~',
+        "Scatter the brush's strokes for a chalky look",
+    ),
+    (Command::TogglePixelMode, None, 'Q', "Toggle pixel art mode"),
+    (
+        Command::FlattenFrame,
+        None,
+        'R',
+        "Flatten the frame into a single image",
+    ),
+    (
+        Command::UnflattenFrame,
+        None,
+        'U',
+        "Take back the last flatten",
+    ),
+    (Command::BlurImages, None, 'B', "Blur the selected images"),
+    (
+        Command::AdjustBrightness,
+        None,
+        'H',
+        "Brighten or darken the selected images",
+    ),
+    (
+        Command::InvertImages,
+        None,
+        'I',
+        "Invert the colors of the selected images",
+    ),
+    (
+        Command::TypePoint,
+        None,
+        ':',
+        "Type in the coordinates of the next point",
+    ),
+    (
+        Command::CycleRecentColor,
+        None,
+        'W',
+        "Cycle the brush through the recent colors",
+    ),
+    (
+        Command::CycleBackground,
+        None,
+        'V',
+        "Change what shows behind the canvas",
+    ),
+    (
+        Command::RunScript,
+        None,
+        '!',
+        "Run a script that draws into the frame",
+    ),
+    (
+        Command::BufferedMode,
+        Some(GuiModeType::Switching),
+        'b',
+        "Buffered lines",
+    ),
+    (
+        Command::FreedrawMode,
+        Some(GuiModeType::Switching),
+        'f',
+        "Freehand drawing",
+    ),
+    (
+        Command::RulerMode,
+        Some(GuiModeType::Switching),
+        'r',
+        "Ruler",
+    ),
+    (
+        Command::SelectMode,
+        Some(GuiModeType::Switching),
+        'v',
+        "Select",
+    ),
+    (
+        Command::GuideMode,
+        Some(GuiModeType::Switching),
+        'g',
+        "Guides",
+    ),
+    (
+        Command::DropLines,
+        Some(GuiModeType::Buffered),
+        'd',
+        "Drop the buffered lines",
+    ),
+    (
+        Command::FitCurves,
+        Some(GuiModeType::Buffered),
+        'b',
+        "Fit curves to the buffered lines",
+    ),
+    (
+        Command::CycleFitMethod,
+        Some(GuiModeType::Buffered),
+        'f',
+        "Cycle the curve fitting method",
+    ),
+    (
+        Command::ToggleFitPreview,
+        Some(GuiModeType::Buffered),
+        'v',
+        "Show or hide the last fit",
+    ),
+    (
+        Command::ConvertLines,
+        Some(GuiModeType::Buffered),
+        'l',
+        "Turn the buffered lines into lines",
+    ),
+    (
+        Command::ConvertLinesTapered,
+        Some(GuiModeType::Buffered),
+        'L',
+        "Turn the buffered lines into tapered lines",
+    ),
+    (
+        Command::IncreaseSimplify,
+        Some(GuiModeType::Buffered),
+        'x',
+        "Simplify strokes more",
+    ),
+    (
+        Command::DecreaseSimplify,
+        Some(GuiModeType::Buffered),
+        'z',
+        "Simplify strokes less",
+    ),
+    (
+        Command::IncreaseError,
+        Some(GuiModeType::Buffered),
+        't',
+        "Allow more error when fitting",
+    ),
+    (
+        Command::DecreaseError,
+        Some(GuiModeType::Buffered),
+        'r',
+        "Allow less error when fitting",
+    ),
+    (
+        Command::SelectHistory,
+        Some(GuiModeType::Select),
+        'h',
+        "Select from the history",
+    ),
+    (
+        Command::Unselect,
+        Some(GuiModeType::Select),
+        'u',
+        "Clear the selection",
+    ),
+    (
+        Command::DeleteSelected,
+        Some(GuiModeType::Select),
+        'd',
+        "Delete the selection",
+    ),
+    (
+        Command::ClosedPolygon,
+        Some(GuiModeType::Select),
+        'p',
+        "Join the selection into a polygon",
+    ),
+    (
+        Command::OpenPolygon,
+        Some(GuiModeType::Select),
+        'o',
+        "Join the selection into an open path",
+    ),
+    (
+        Command::Union,
+        Some(GuiModeType::Select),
+        '+',
+        "Union of the selected polygons",
+    ),
+    (
+        Command::Intersection,
+        Some(GuiModeType::Select),
+        '*',
+        "Intersection of the selected polygons",
+    ),
+    (
+        Command::Difference,
+        Some(GuiModeType::Select),
+        '-',
+        "Difference of the selected polygons",
+    ),
+    (
+        Command::ExplodePolygons,
+        Some(GuiModeType::Select),
+        'E',
+        "Break the selected polygons apart",
+    ),
+    (
+        Command::OutlineStrokes,
+        Some(GuiModeType::Select),
+        'x',
+        "Expand the selected strokes into shapes",
+    ),
+    (
+        Command::MakeSymbol,
+        Some(GuiModeType::Select),
+        'k',
+        "Turn the selection into a symbol",
+    ),
+    (
+        Command::GroupSelected,
+        Some(GuiModeType::Select),
+        'g',
+        "Group the selection",
+    ),
+    (
+        Command::UngroupSelected,
+        Some(GuiModeType::Select),
+        'a',
+        "Break apart the selected groups",
+    ),
+    (
+        Command::ToggleGroupSelect,
+        Some(GuiModeType::Select),
+        'w',
+        "Toggle selecting whole groups",
+    ),
+    (
+        Command::MoveSelected,
+        Some(GuiModeType::Select),
+        'M',
+        "Move the selection by an offset",
+    ),
+    (
+        Command::RotateSelected,
+        Some(GuiModeType::Select),
+        'r',
+        "Rotate the selection around its center",
+    ),
+    (
+        Command::AlignSelected,
+        Some(GuiModeType::Select),
+        'A',
+        "Line up the selected objects",
+    ),
+    (
+        Command::DistributeSelected,
+        Some(GuiModeType::Select),
+        'D',
+        "Space the selected objects out evenly",
+    ),
+    (
+        Command::DuplicateSelected,
+        Some(GuiModeType::Select),
+        'C',
+        "Duplicate the selection, a little way off",
+    ),
+    (
+        Command::BringToFront,
+        Some(GuiModeType::Select),
+        '^',
+        "Bring the selection in front of everything else",
+    ),
+    (
+        Command::NudgeLeft,
+        Some(GuiModeType::Select),
+        '4',
+        "Nudge the selection a pixel left",
+    ),
+    (
+        Command::NudgeRight,
+        Some(GuiModeType::Select),
+        '6',
+        "Nudge the selection a pixel right",
+    ),
+    (
+        Command::NudgeUp,
+        Some(GuiModeType::Select),
+        '8',
+        "Nudge the selection a pixel up",
+    ),
+    (
+        Command::NudgeDown,
+        Some(GuiModeType::Select),
+        '2',
+        "Nudge the selection a pixel down",
+    ),
+    (
+        Command::Undo,
+        Some(GuiModeType::Select),
+        'z',
+        "Undo the last addition or move",
+    ),
+];
+
+impl Command {
+    #[inline]
+    fn entry(self) -> &'static (Command, Option<GuiModeType>, char, &'static str) {
+        COMMANDS
+            .iter()
+            .find(|(cmd, ..)| *cmd == self)
+            .expect("Every command is in the table")
+    }
+
+    /// The mode this command works in, or `None` if it works in any mode.
+    #[inline]
+    pub fn mode(self) -> Option<GuiModeType> {
+        self.entry().1
+    }
+
+    #[inline]
+    pub fn description(self) -> &'static str {
+        self.entry().3
+    }
+}
+
+/// Which command each key runs.
+pub struct KeyMap {
+    keys: HashMap<Command, char>,
+    commands: HashMap<(Option<GuiModeType>, char), Command>,
+}
+
+impl KeyMap {
+    /// Create a key map from the default keys, with some of them replaced.
+    pub fn new(remapped: &HashMap<Command, char>) -> Self {
+        let mut keymap = Self {
+            keys: HashMap::new(),
+            commands: HashMap::new(),
+        };
+
+        // remapped keys go last, so that they win over any default they clash with
+        let defaults = COMMANDS
+            .iter()
+            .filter(|(cmd, ..)| !remapped.contains_key(cmd))
+            .map(|(cmd, _, key, _)| (*cmd, *key));
+        defaults
+            .chain(remapped.iter().map(|(cmd, key)| (*cmd, *key)))
+            .for_each(|(cmd, key)| {
+                keymap.keys.insert(cmd, key);
+                keymap.commands.insert((cmd.mode(), key), cmd);
+            });
+
+        keymap
+    }
+
+    /// The key bound to a command.
+    #[inline]
+    pub fn key(&self, cmd: Command) -> Option<char> {
+        self.keys
+            .get(&cmd)
+            .copied()
+            .filter(|key| self.commands.get(&(cmd.mode(), *key)) == Some(&cmd))
+    }
+
+    /// Find the command a key runs in the given mode. Keys that work anywhere win over the
+    /// mode's own, so remapped keys shouldn't reuse them.
+    pub fn command(&self, mode: GuiModeType, key: char) -> Option<Command> {
+        [None, Some(mode)]
+            .iter()
+            .find_map(|scope| self.commands.get(&(*scope, key)).copied())
+    }
+
+    /// Find the commands whose descriptions contain some text, ignoring case.
+    pub fn search<'a>(&'a self, query: &str) -> impl Iterator<Item = (Command, Option<char>)> + 'a {
+        let query = query.trim().to_lowercase();
+        COMMANDS
+            .iter()
+            .filter(move |(_, _, _, desc)| desc.to_lowercase().contains(&query))
+            .map(move |(cmd, ..)| (*cmd, self.key(*cmd)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn default_keys_are_unique() {
+        let mut seen = HashSet::new();
+        for (cmd, mode, key, _) in COMMANDS {
+            assert!(
+                seen.insert((*mode, *key)),
+                "{:?} reuses {:?} in {:?}",
+                cmd,
+                key,
+                mode
+            );
+        }
+    }
+
+    #[test]
+    fn mode_keys_are_not_shadowed() {
+        let global: HashSet<char> = COMMANDS
+            .iter()
+            .filter(|(_, mode, ..)| mode.is_none())
+            .map(|(_, _, key, _)| *key)
+            .collect();
+        for (cmd, mode, key, _) in COMMANDS.iter().filter(|(_, mode, ..)| mode.is_some()) {
+            assert!(
+                !global.contains(key),
+                "{:?} in {:?} is shadowed by a global {:?}",
+                cmd,
+                mode,
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn default_keys_run_their_commands() {
+        let keymap = KeyMap::new(&HashMap::new());
+        for (cmd, mode, key, _) in COMMANDS {
+            let mode = mode.unwrap_or(GuiModeType::Buffered);
+            assert_eq!(keymap.command(mode, *key), Some(*cmd));
+            assert_eq!(keymap.key(*cmd), Some(*key));
+        }
+    }
+
+    #[test]
+    fn remapped_keys_replace_defaults() {
+        let mut remapped = HashMap::new();
+        remapped.insert(Command::Save, 'c');
+        let keymap = KeyMap::new(&remapped);
+
+        assert_eq!(
+            keymap.command(GuiModeType::Select, 'c'),
+            Some(Command::Save)
+        );
+        assert_eq!(keymap.command(GuiModeType::Select, 's'), None);
+        // the command that had the key loses it
+        assert_eq!(keymap.key(Command::ResizeCanvas), None);
+    }
+
+    #[test]
+    fn remapped_mode_keys_stay_in_their_mode() {
+        let mut remapped = HashMap::new();
+        remapped.insert(Command::Undo, '%');
+        let keymap = KeyMap::new(&remapped);
+
+        assert_eq!(
+            keymap.command(GuiModeType::Select, '%'),
+            Some(Command::Undo)
+        );
+        assert_eq!(keymap.command(GuiModeType::Buffered, '%'), None);
+        assert_eq!(keymap.command(GuiModeType::Select, 'z'), None);
+    }
+
+    #[test]
+    fn commands_are_found_by_description() {
+        let keymap = KeyMap::new(&HashMap::new());
+        let found: Vec<_> = keymap.search("  SAVE ").map(|(cmd, _)| cmd).collect();
+        assert_eq!(found, vec![Command::Save, Command::SaveAs]);
+        assert_eq!(keymap.search("no such command").count(), 0);
+    }
+}
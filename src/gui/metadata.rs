@@ -0,0 +1,84 @@
+// GPLv3 License
+
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Descriptive information about a project. Timestamps are RFC 3339 strings in UTC.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub created: Option<String>,
+    pub modified: Option<String>,
+}
+
+impl ProjectMetadata {
+    /// Metadata for a project created just now.
+    #[inline]
+    pub fn created_now() -> Self {
+        Self {
+            created: Some(now_rfc3339()),
+            ..Default::default()
+        }
+    }
+
+    /// Mark the project as modified just now.
+    #[inline]
+    pub fn touch(&mut self) {
+        self.modified = Some(now_rfc3339());
+    }
+}
+
+/// The current time as an RFC 3339 timestamp, e.g. `2020-08-01T12:30:00Z`.
+pub fn now_rfc3339() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // convert days since the epoch to a civil date, after Howard Hinnant's algorithm
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_rfc3339() {
+        let now = now_rfc3339();
+        let bytes = now.as_bytes();
+        assert_eq!(bytes.len(), 20, "{}", now);
+        [4, 7].iter().for_each(|i| assert_eq!(bytes[*i], b'-'));
+        [13, 16].iter().for_each(|i| assert_eq!(bytes[*i], b':'));
+        assert_eq!((bytes[10], bytes[19]), (b'T', b'Z'));
+        assert!(now[..4].parse::<u32>().unwrap() >= 2020);
+    }
+
+    #[test]
+    fn touching_sets_the_modified_time() {
+        let mut metadata = ProjectMetadata::created_now();
+        assert!(metadata.modified.is_none());
+        metadata.touch();
+        assert!(metadata.modified >= metadata.created);
+    }
+}
@@ -1,30 +1,60 @@
 // GPL v3.0
 
 use super::{
-    colors, render, AlphaMaskTarget, Brush, Color, ColorAtom, DrawTarget, GraphicalState,
-    RenderTarget, SolidColor, TCImage,
+    colors, embedded_source, is_png, layout, png_bytes, render, since, since_or,
+    with_loading_version, Alignment, AlphaMaskTarget, Axis, Brush, BrushRef, ColorAtom, DataID,
+    DrawTarget, DynamicColor, Error, ExportOptions, GraphicalState, Jitter, LoopMode, Progress,
+    RenderTarget, SolidColor, Symbol, SymbolInstance, Symmetry, SymmetryConfig, TCImage,
+    DEFAULT_TOLERANCE, ENDPOINT_TOLERANCE, PREVIEW_TOLERANCE, PROJECT_VERSION, QUANTIZE_STEP,
 };
 use cairo::{Context, Format, ImageSurface};
+use euclid::default::{Point2D, Rect, Size2D, Vector2D};
 use gio::{prelude::*, ApplicationFlags};
-use gtk::{prelude::*, Application, ApplicationWindow, DrawingArea};
+use gtk::{
+    prelude::*, Application, ApplicationWindow, Box as GtkBox, ButtonsType, Dialog, DialogFlags,
+    DrawingArea, FileChooserAction, FileChooserDialog, MessageDialog, MessageType, ProgressBar,
+    ResponseType, SpinButton,
+};
 use image::Rgba;
 use once_cell::sync::OnceCell;
 use parking_lot::{Mutex, MutexGuard, RwLock, RwLockUpgradableReadGuard, RwLockWriteGuard};
+use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+use rand::{rngs::StdRng, SeedableRng};
 use rayon::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use smallvec::SmallVec;
 use std::{
+    cell::Cell,
+    collections::{hash_map::DefaultHasher, VecDeque},
     env,
     fs::File,
+    hash::Hasher,
     io::{self, prelude::*},
     mem,
+    ops::Range,
+    rc::Rc,
+    str,
     sync::Arc,
 };
 
+mod guide;
+mod keymap;
+mod metadata;
 mod mode;
+mod playback;
+mod polar;
+mod settings;
 mod ui;
+mod view;
 
+pub use guide::*;
+pub use keymap::*;
+pub use metadata::*;
 pub use mode::*;
+pub use playback::*;
+pub use polar::*;
+pub use settings::*;
+pub use view::*;
 
 #[inline]
 fn standard_brushes() -> SmallVec<[Brush; 10]> {
@@ -33,12 +63,61 @@ fn standard_brushes() -> SmallVec<[Brush; 10]> {
     sm
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
-enum ProjectSave {
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Copy, Clone)]
+enum ProjectFormat {
     Bincode,
     Json,
 }
 
+impl ProjectFormat {
+    /// Figure out which format a serialized project is stored in.
+    ///
+    /// JSON projects always begin with an object, while bincode projects are binary and
+    /// almost always contain zero bytes. Anything else is rejected.
+    #[inline]
+    fn detect(bytes: &[u8]) -> Result<ProjectFormat, Error> {
+        match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+            None => Err(Error::UnsupportedFormat("file is empty")),
+            Some(b'{') => Ok(Self::Json),
+            Some(_) if bytes.contains(&0) || str::from_utf8(bytes).is_err() => Ok(Self::Bincode),
+            Some(_) => Err(Error::UnsupportedFormat("unrecognized project format")),
+        }
+    }
+}
+
+/// A set of brushes stored in its own file, so that it can be shared between projects.
+#[derive(Serialize, Deserialize)]
+struct Palette {
+    brushes: SmallVec<[Brush; 10]>,
+}
+
+/// How an imported palette is combined with the project's existing brushes.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum PaletteImport {
+    /// Add the palette's brushes after the existing ones.
+    Merge,
+    /// Overwrite the existing brushes with the palette's brushes.
+    Replace,
+}
+
+/// Which part of the canvas stays put when it's resized.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum CanvasAnchor {
+    TopLeft,
+    Center,
+}
+
+// bincode projects start with this and then their layout version, since bincode can't tell
+// which fields a project has without knowing which version wrote it
+const BINCODE_HEADER: &[u8] = b"ARCHTYPE";
+
+// just the version of a JSON project, to know how to read the rest of it
+#[derive(Deserialize)]
+struct JsonVersion {
+    #[serde(default)]
+    version: u32,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Project {
     // the graphical frames contained within
@@ -49,387 +128,2094 @@ pub struct Project {
     current_frame: usize,
     current_brush: usize,
     filename: Option<String>,
-    filetype: Option<ProjectSave>,
+    filetype: Option<ProjectFormat>,
+    // projects saved before versioning was introduced are version 0
+    #[serde(default, deserialize_with = "since::<_, _, { layout::VERSIONED }>")]
+    version: u32,
+    // seed for random effects, so that they look the same every time the project is rendered
+    #[serde(default, deserialize_with = "since::<_, _, { layout::SEEDS }>")]
+    seed: Option<u64>,
+    // how close endpoints have to be, in canvas pixels, to be joined together
+    #[serde(
+        default = "default_endpoint_tolerance",
+        deserialize_with = "deserialize_endpoint_tolerance"
+    )]
+    endpoint_tolerance: f32,
+    #[serde(default, deserialize_with = "since::<_, _, { layout::METADATA }>")]
+    metadata: ProjectMetadata,
+    // whether coordinates are snapped to the quantization grid when saving, so they pack smaller
+    #[serde(default, deserialize_with = "since::<_, _, { layout::QUANTIZED }>")]
+    quantized: bool,
+    // crisp pixel art: no anti-aliasing, whole-pixel coordinates and no smoothing when zoomed
+    #[serde(default, deserialize_with = "since::<_, _, { layout::PIXEL_MODE }>")]
+    pixel_mode: bool,
+    // what an animation does after its last frame
+    #[serde(default, deserialize_with = "since::<_, _, { layout::LOOP_MODES }>")]
+    loop_mode: LoopMode,
+    #[serde(default, deserialize_with = "since::<_, _, { layout::GUIDES }>")]
+    guides: Vec<Guide>,
+    #[serde(default, deserialize_with = "since::<_, _, { layout::SYMBOLS }>")]
+    symbols: Vec<Symbol>,
+    // the mode that was in use when the project was saved, to carry on in when it's opened
+    #[serde(default, deserialize_with = "since::<_, _, { layout::SESSION_MODE }>")]
+    session_mode: Option<GuiModeType>,
+    // the symbol being edited in place of the current frame
+    #[serde(skip)]
+    editing_symbol: Option<usize>,
+    // a hash of the project as it was when last saved or loaded
+    #[serde(skip)]
+    saved_hash: Option<u64>,
+    // the objects removed by the last flatten, so that it can be taken back
+    #[serde(skip)]
+    flattened: Option<Flattened>,
+}
+
+// a frame's objects, from before they were flattened into an image
+struct Flattened {
+    frame: usize,
+    image: DataID,
+    objects: GraphicalState,
+}
+
+#[inline]
+fn default_endpoint_tolerance() -> f32 {
+    ENDPOINT_TOLERANCE
+}
+
+#[inline]
+fn deserialize_endpoint_tolerance<'de, D: Deserializer<'de>>(de: D) -> Result<f32, D::Error> {
+    since_or(de, layout::ENDPOINT_TOLERANCE, default_endpoint_tolerance)
 }
 
 impl Project {
-    #[inline]
-    pub fn current_frame(&self) -> &GraphicalState {
-        &self.frames[self.current_frame]
+    /// Create an empty project with the standard brushes.
+    pub fn new(width: u32, height: u32, endpoint_tolerance: f32) -> Project {
+        let mut project = Project {
+            width,
+            height,
+            frames: vec![GraphicalState::new()],
+            brushes: standard_brushes(),
+            current_frame: 0,
+            current_brush: 0,
+            filename: None,
+            filetype: None,
+            version: PROJECT_VERSION,
+            seed: None,
+            endpoint_tolerance,
+            metadata: ProjectMetadata::created_now(),
+            quantized: false,
+            pixel_mode: false,
+            loop_mode: LoopMode::default(),
+            guides: Vec::new(),
+            symbols: Vec::new(),
+            session_mode: None,
+            editing_symbol: None,
+            saved_hash: None,
+            flattened: None,
+        };
+        // there's nothing to lose in a project that hasn't been touched yet
+        project.mark_saved();
+        project
     }
 
-    #[inline]
-    pub fn current_frame_mut(&mut self) -> &mut GraphicalState {
-        &mut self.frames[self.current_frame]
+    /// Load a project from a file, stored either as bincode, as JSON, or embedded in an
+    /// exported PNG image.
+    pub fn load_from_path(path: &str) -> Result<Project, Error> {
+        let mut file = File::open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        mem::drop(file);
+
+        if is_png(&bytes) {
+            let source = embedded_source(&bytes)?;
+            let mut project = Project::from_bytes(&source, ProjectFormat::Json)?;
+
+            // saving should not overwrite the image
+            project.filename = None;
+            project.filetype = None;
+            project.mark_saved();
+            project.repair_if_invalid();
+
+            return Ok(project);
+        }
+
+        let filetype = ProjectFormat::detect(&bytes)?;
+        let mut project = Project::from_bytes(&bytes, filetype)?;
+
+        // re-saving should write back to the same place, in the same format
+        project.filename = Some(path.to_string());
+        project.filetype = Some(filetype);
+        project.mark_saved();
+        project.repair_if_invalid();
+
+        Ok(project)
+    }
+
+    // read a project in the given format, whichever layout version it was saved with
+    fn from_bytes(bytes: &[u8], format: ProjectFormat) -> Result<Project, Error> {
+        let (version, body) = match format {
+            ProjectFormat::Bincode if bytes.starts_with(BINCODE_HEADER) => {
+                let rest = &bytes[BINCODE_HEADER.len()..];
+                let version: u32 = bincode::deserialize(rest).map_err(Error::deserialize)?;
+                (version, &rest[mem::size_of::<u32>()..])
+            }
+            // bincode projects without a header are from before versioning
+            ProjectFormat::Bincode => (0, bytes),
+            ProjectFormat::Json => {
+                let JsonVersion { version } =
+                    serde_json::from_slice(bytes).map_err(Error::deserialize)?;
+                (version, bytes)
+            }
+        };
+        if version > PROJECT_VERSION {
+            return Err(Error::UnsupportedFormat(
+                "project was saved by a newer version of archetype",
+            ));
+        }
+
+        let mut project: Project = with_loading_version(version, || match format {
+            ProjectFormat::Bincode => bincode::deserialize(body).map_err(Error::deserialize),
+            ProjectFormat::Json => serde_json::from_slice(body).map_err(Error::deserialize),
+        })?;
+        project.version = version;
+        project.migrate()?;
+        Ok(project)
+    }
+
+    // write a project out in the given format
+    fn to_bytes(&self, format: ProjectFormat) -> Result<Vec<u8>, Error> {
+        match format {
+            ProjectFormat::Bincode => {
+                let mut bytes = BINCODE_HEADER.to_vec();
+                bincode::serialize_into(&mut bytes, &PROJECT_VERSION).map_err(Error::serialize)?;
+                bincode::serialize_into(&mut bytes, self).map_err(Error::serialize)?;
+                Ok(bytes)
+            }
+            ProjectFormat::Json => serde_json::to_vec(self).map_err(Error::serialize),
+        }
+    }
+
+    /// Check that everything in the project refers to things that exist, so that a damaged
+    /// file is caught before it causes trouble later on.
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut problems = Vec::new();
+        if self.frames.is_empty() {
+            problems.push("there are no frames".to_string());
+        } else if self.current_frame >= self.frames.len() {
+            problems.push(format!(
+                "the current frame is {}, but there are only {} frames",
+                self.current_frame + 1,
+                self.frames.len()
+            ));
+        }
+        if self.current_brush >= self.brushes.len() {
+            problems.push(format!(
+                "the current brush is {}, but there are only {} brushes",
+                self.current_brush + 1,
+                self.brushes.len()
+            ));
+        }
+
+        let (brush_count, symbol_count) = (self.brushes.len(), self.symbols.len());
+        self.frames.iter().enumerate().for_each(|(i, frame)| {
+            let name = format!("frame {}", i + 1);
+            problems.extend(frame.problems(&name, brush_count, symbol_count));
+        });
+        self.symbols.iter().for_each(|symbol| {
+            let name = format!("symbol \"{}\"", symbol.name);
+            problems.extend(symbol.state.problems(&name, brush_count, symbol_count));
+        });
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidProject(problems.join("; ")))
+        }
+    }
+
+    /// Fix anything `validate` complains about, by clamping indices into range and dropping
+    /// references to things that don't exist.
+    pub fn repair(&mut self) {
+        if self.brushes.is_empty() {
+            self.brushes = standard_brushes();
+        }
+        if self.frames.is_empty() {
+            self.frames.push(GraphicalState::new());
+        }
+        self.current_frame = self.current_frame.min(self.frames.len() - 1);
+        self.current_brush = self.current_brush.min(self.brushes.len() - 1);
+
+        let (brush_count, symbol_count) = (self.brushes.len(), self.symbols.len());
+        self.frames
+            .iter_mut()
+            .chain(self.symbols.iter_mut().map(|s| &mut s.state))
+            .for_each(|frame| frame.repair(brush_count, symbol_count));
     }
 
+    // repair a freshly loaded project if it needs it, saying what was wrong
     #[inline]
-    pub fn brush(&self, index: usize) -> Option<&Brush> {
-        self.brushes.get(index)
+    fn repair_if_invalid(&mut self) {
+        if let Err(e) = self.validate() {
+            eprintln!("{}\nRepairing the project, check it over before saving", e);
+            self.repair();
+        }
+    }
+
+    /// Upgrade a project saved by an older version to the current layout.
+    pub fn migrate(&mut self) -> Result<(), Error> {
+        if self.version > PROJECT_VERSION {
+            return Err(Error::UnsupportedFormat(
+                "project was saved by a newer version of archetype",
+            ));
+        }
+
+        // fields added since the project was saved were given their defaults while reading it,
+        // so far no version has needed more than that
+        self.version = PROJECT_VERSION;
+        Ok(())
     }
 
     #[inline]
-    pub fn current_brush(&self) -> &Brush {
-        &self.brushes[self.current_brush]
+    pub fn version(&self) -> u32 {
+        self.version
     }
 
     #[inline]
-    pub fn current_brush_index(&self) -> usize {
-        self.current_brush
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
     }
 
     #[inline]
-    pub fn width(&self) -> u32 {
-        self.width
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
     }
 
     #[inline]
-    pub fn height(&self) -> u32 {
-        self.height
+    pub fn metadata(&self) -> &ProjectMetadata {
+        &self.metadata
     }
-}
 
-struct GuiInternal {
-    current_project: RwLock<Project>,
-    image: DrawTarget,
-    application: Application,
+    #[inline]
+    pub fn metadata_mut(&mut self) -> &mut ProjectMetadata {
+        &mut self.metadata
+    }
 
-    canvas: OnceCell<DrawingArea>,
-    main_window: OnceCell<ApplicationWindow>,
-    surface: Mutex<Option<ImageSurface>>,
+    /// How close two endpoints have to be, in canvas pixels, to be snapped together.
+    #[inline]
+    pub fn endpoint_tolerance(&self) -> f32 {
+        self.endpoint_tolerance
+    }
 
-    gui_mode: Mutex<GuiModeStorage>,
-    past_gui_modes: Mutex<SmallVec<[GuiModeStorage; 5]>>,
-}
+    #[inline]
+    pub fn set_endpoint_tolerance(&mut self, tolerance: f32) {
+        self.endpoint_tolerance = tolerance.max(0.0);
+    }
 
-#[derive(Clone)]
-#[repr(transparent)]
-pub struct Gui(Arc<GuiInternal>);
+    #[inline]
+    pub fn quantized(&self) -> bool {
+        self.quantized
+    }
 
-impl Gui {
-    pub fn new(project: Project) -> Gui {
-        let application = Application::new(
-            Some("com.notaseagull.archetype"),
-            ApplicationFlags::HANDLES_COMMAND_LINE,
-        )
-        .expect("Unable to initialize GTK");
+    /// Choose whether coordinates are stored quantized. This rounds every point to the nearest
+    /// multiple of `QUANTIZE_STEP`, which can't be undone.
+    #[inline]
+    pub fn set_quantized(&mut self, quantized: bool) {
+        self.quantized = quantized;
+        if quantized {
+            self.frames
+                .iter_mut()
+                .for_each(|f| f.quantize_coordinates());
+        }
+    }
 
-        let img = TCImage::from_pixel(project.width, project.height, Rgba([0, 0, 0, 0]));
-        let mut gui = Self(Arc::new(GuiInternal {
-            current_project: RwLock::new(project),
-            application,
-            canvas: OnceCell::new(),
-            main_window: OnceCell::new(),
-            image: RwLock::new((img, true)),
-            surface: Mutex::new(None),
-            gui_mode: Mutex::new(GuiModeStorage::Buffered(BufferedGuiMode::new(
-                DEFAULT_ERROR,
-            ))),
-            past_gui_modes: Mutex::new(SmallVec::new()),
-        }));
+    #[inline]
+    pub fn pixel_mode(&self) -> bool {
+        self.pixel_mode
+    }
 
-        let cl = gui.clone();
-        gui.0.application.connect_command_line(|app, _cmd| {
-            app.activate();
-            0
-        });
-        gui.0.application.connect_activate(move |app| {
-            ui::build_ui(app, cl.clone());
-            cl.update_image();
-        });
+    #[inline]
+    pub fn set_pixel_mode(&mut self, pixel_mode: bool) {
+        self.pixel_mode = pixel_mode;
+    }
 
-        gui
+    #[inline]
+    pub fn loop_mode(&self) -> LoopMode {
+        self.loop_mode
     }
 
     #[inline]
-    pub fn new_project(width: u32, height: u32) -> Gui {
-        let project = Project {
-            width,
-            height,
-            frames: vec![GraphicalState::new()],
-            brushes: standard_brushes(),
-            current_frame: 0,
-            current_brush: 0,
-            filename: None,
-            filetype: None,
-        };
-        Self::new(project)
+    pub fn set_loop_mode(&mut self, loop_mode: LoopMode) {
+        self.loop_mode = loop_mode;
     }
 
+    // hash everything that gets saved, to tell whether anything changed
     #[inline]
-    pub fn gui_mode(&self) -> &Mutex<GuiModeStorage> {
-        &self.0.gui_mode
+    fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&bincode::serialize(self).unwrap_or_default());
+        hasher.finish()
     }
 
     #[inline]
-    pub fn take_matching_gui_mode(&self, ty: GuiModeType) -> Option<GuiModeStorage> {
-        let mut past_modes = self.0.past_gui_modes.lock();
-        let posn = (&*(&*past_modes))
-            .par_iter()
-            .position_any(|m| m.kind() == ty);
-        posn.map(|p| past_modes.remove(p))
+    fn mark_saved(&mut self) {
+        self.saved_hash = Some(self.content_hash());
     }
 
+    /// Tell whether the project has changed since it was last saved or loaded.
     #[inline]
-    pub fn store_gui_mode(&self) {
-        let mut switch_mode = GuiModeStorage::Switching;
-        let mut current_mode = self.0.gui_mode.lock();
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.saved_hash != Some(self.content_hash())
+    }
 
-        if let GuiModeStorage::Switching = &*current_mode {
-            // do nothing
-        } else {
-            mem::swap(&mut switch_mode, &mut current_mode);
-            mem::drop(current_mode);
-            switch_mode.switch_out(self);
-            let mut past_gui_modes = self.0.past_gui_modes.lock();
-            past_gui_modes.push(switch_mode);
+    // update the project right before it is written out
+    #[inline]
+    fn prepare_for_save(&mut self, mode: Option<GuiModeType>) {
+        self.metadata.touch();
+        if mode.is_some() {
+            self.session_mode = mode;
+        }
+        if self.quantized {
+            self.frames
+                .iter_mut()
+                .for_each(|f| f.quantize_coordinates());
         }
     }
 
+    /// The mode that was in use when the project was last saved.
     #[inline]
-    pub fn update_image(&self) {
-        // wipe the image
-        self.0
-            .image
-            .write()
-            .0
-            .as_flat_samples_mut()
-            .image_mut_slice()
-            .unwrap()
-            .par_iter_mut()
-            .for_each(|m| *m = 0);
+    pub fn session_mode(&self) -> Option<GuiModeType> {
+        self.session_mode
+    }
 
-        let pr = self.0.current_project.read();
-        let frame = &pr.frames[pr.current_frame];
-        frame.rasterize(&self.0.image, &*pr);
-        self.drawing_area().queue_draw();
+    #[inline]
+    pub fn guides(&self) -> &[Guide] {
+        &self.guides
     }
 
     #[inline]
-    pub fn project(&self) -> &RwLock<Project> {
-        &self.0.current_project
+    pub fn guides_mut(&mut self) -> &mut Vec<Guide> {
+        &mut self.guides
     }
 
+    /// Get a random number generator for stochastic effects. If the project has a seed, this
+    /// produces the same sequence every time.
     #[inline]
-    pub fn set_drawing_area(&self, dr: DrawingArea) {
-        self.0.canvas.set(dr).unwrap();
+    pub fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
     }
 
+    /// The frame being drawn on. While a symbol is being edited, this is the symbol.
     #[inline]
-    pub fn set_main_window(&self, mw: ApplicationWindow) {
-        self.0.main_window.set(mw).unwrap();
+    pub fn current_frame(&self) -> &GraphicalState {
+        match self.editing_symbol {
+            Some(i) => &self.symbols[i].state,
+            None => &self.frames[self.current_frame],
+        }
     }
 
     #[inline]
-    pub fn set_drawing_function<F>(&self, fnd: F)
-    where
-        F: Fn(&DrawingArea, &Context) -> Inhibit + 'static,
-    {
-        self.drawing_area().connect_draw(fnd);
+    pub fn current_frame_mut(&mut self) -> &mut GraphicalState {
+        match self.editing_symbol {
+            Some(i) => &mut self.symbols[i].state,
+            None => &mut self.frames[self.current_frame],
+        }
     }
 
+    /// One of the frames, by index.
     #[inline]
-    pub fn drawing_area(&self) -> &DrawingArea {
-        self.0.canvas.get().expect("Drawing area does not exist")
+    pub fn frame(&self, index: usize) -> Option<&GraphicalState> {
+        self.frames.get(index)
     }
 
     #[inline]
-    pub fn main_window(&self) -> &ApplicationWindow {
-        self.0
-            .main_window
-            .get()
-            .expect("Application window does not exist")
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
     }
 
     #[inline]
-    pub fn run(&self) {
-        self.0.application.run(&env::args().collect::<Vec<_>>());
+    pub fn current_frame_index(&self) -> usize {
+        self.current_frame
     }
 
+    /// Switch to another frame, if there is one at that index.
     #[inline]
-    pub fn dimensions(&self) -> (u32, u32) {
-        let pr = self.0.current_project.read();
-        (pr.width, pr.height)
+    pub fn set_current_frame(&mut self, index: usize) {
+        if index < self.frames.len() {
+            self.current_frame_mut().unselect();
+            self.current_frame = index;
+        }
     }
 
-    // rebuild the surface
+    /// Add an empty frame after the last one, and switch to it.
     #[inline]
-    pub fn recreate_surface(&self) {
-        let mut surface = self.0.surface.lock();
+    pub fn add_frame(&mut self) {
+        self.frames.push(GraphicalState::new());
+        self.set_current_frame(self.frames.len() - 1);
+    }
 
-        // clone the data from the image
-        let img = self.0.image.read();
-        let data: Box<[u8]> = img
-            .0
-            .as_flat_samples()
-            .image_slice()
-            .expect("Unable to get image data")
-            .iter()
-            .copied()
-            .map(|i| normalize(i))
+    /// Add a copy of the current frame right after it, and switch to the copy.
+    pub fn duplicate_frame(&mut self) {
+        let mut copy = self.frames[self.current_frame].placed_at(Vector2D::zero());
+        copy.set_duration_ms(self.current_frame().duration_ms());
+        self.frames.insert(self.current_frame + 1, copy);
+        self.set_current_frame(self.current_frame + 1);
+    }
+
+    /// Fill in `count` frames between two keyframes, by matching up their objects and moving
+    /// them from one to the other. The new frames go right before the second keyframe.
+    pub fn tween(&mut self, from: usize, to: usize, count: usize) -> Result<(), Error> {
+        if from >= to || to >= self.frames.len() {
+            return Err(Error::InvalidInput(
+                "the keyframes must be two different frames, in order",
+            ));
+        }
+
+        let (a, b) = (&self.frames[from], &self.frames[to]);
+        let in_betweens: Vec<GraphicalState> = (1..=count)
+            .map(|i| a.tween(b, i as f32 / (count + 1) as f32, &self.brushes))
             .collect();
 
-        *surface = Some(
-            ImageSurface::create_for_data(
-                data,
-                Format::Rgb24,
-                img.0.width() as i32,
-                img.0.height() as i32,
-                4i32 * img.0.width() as i32,
-            )
-            .expect("Unable to create surface"),
-        );
+        if self.current_frame >= to {
+            self.current_frame += count;
+        }
+        self.frames.splice(to..to, in_betweens);
+        Ok(())
     }
 
-    pub fn draw(&self, context: &Context) {
-        let surface = self.0.surface.lock();
-        if surface.is_none() {
-            mem::drop(surface); // free up the mutex
-            self.recreate_surface();
-            // do a recursive call on this function
-            self.draw(context);
-        } else {
-            let mut surface = MutexGuard::map(surface, |s| s.as_mut().unwrap());
-
-            surface.flush();
-            let img = RwLock::upgradable_read(&self.0.image);
-
-            // get the image width and height
-            let width = img.0.width();
-            let height = img.0.height();
-
-            if img.1 {
-                // the write flag is toggled to on
-                let mut img = RwLockUpgradableReadGuard::upgrade(img);
-                img.1 = false;
-                // downgrade to a read lock
-                let img = RwLockWriteGuard::downgrade(img);
-
-                let stride = surface.get_stride() as usize;
-                let mut data = surface.get_data().expect("Unable to borrow surface data");
-
-                // draw pixels into data
-                img.0.enumerate_rows().fold(&mut *data, |data, (y, row)| {
-                    row.for_each(|(x, _y, pixel)| {
-                        let pixel: [u8; 4] = match pixel.0 {
-                            [_, _, _, 0] => [219, 252, 255, 255],
-                            [r, g, b, a] => {
-                                [normalize(b), normalize(g), normalize(r), normalize(a)]
-                            }
-                        };
-
-                        data.iter_mut()
-                            .skip(x as usize * 4)
-                            .take(4)
-                            .zip(pixel.iter())
-                            .for_each(|(b, val)| *b = *val);
-                    });
-
-                    // move the data up by a stride
-                    &mut data[stride..]
-                });
-
-                mem::drop(data);
+    /// Replace every object in the current frame with a single image of them. The objects are
+    /// kept until the next flatten, so that `unflatten_frame` can bring them back.
+    pub fn flatten_frame(&mut self) -> Result<(), Error> {
+        if self.editing_symbol.is_some() {
+            return Err(Error::InvalidInput("symbols can't be flattened"));
+        }
 
-                // mark the surface as dirty
-                surface.mark_dirty();
-                mem::drop(img);
-            } else {
-                // don't hog the write lock
-                mem::drop(img);
-            }
+        let objects = self.current_frame_mut().take_objects();
+        if objects.creation_order().is_empty() {
+            return Err(Error::InvalidInput("there is nothing to flatten"));
+        }
 
-            context.set_source_surface(&*surface, 0.0f64, 0.0f64);
-            context.paint();
+        let img = RwLock::new((
+            TCImage::from_pixel(self.width, self.height, Rgba([0, 0, 0, 0])),
+            true,
+        ));
+        objects.rasterize(&img, self, DEFAULT_TOLERANCE);
+        let image = png_bytes(&img.into_inner().0).and_then(|bytes| {
+            self.current_frame_mut()
+                .import_image(bytes, Point2D::zero(), 1.0)
+        });
 
-            self.gui_mode().lock().draw(self, context);
+        match image {
+            Ok(image) => {
+                self.flattened = Some(Flattened {
+                    frame: self.current_frame,
+                    image,
+                    objects,
+                });
+                Ok(())
+            }
+            Err(e) => {
+                // put everything back the way it was
+                self.current_frame_mut().restore_objects(objects);
+                Err(e)
+            }
         }
     }
 
-    #[inline]
-    pub fn hide(&self) {
-        self.main_window().hide();
+    /// Take back the last flatten, replacing the image with the objects it was made from.
+    pub fn unflatten_frame(&mut self) -> Result<(), Error> {
+        let Flattened {
+            frame,
+            image,
+            objects,
+        } = self
+            .flattened
+            .take()
+            .ok_or(Error::InvalidInput("nothing has been flattened"))?;
+
+        let state = match self.frames.get_mut(frame) {
+            Some(state) if state.images().contains_key(&image) => state,
+            _ => return Err(Error::InvalidInput("the flattened image is gone")),
+        };
+        state.unselect();
+        state.images_mut().remove(&image);
+        state.restore_objects(objects);
+        self.set_current_frame(frame);
+        Ok(())
     }
 
     #[inline]
-    pub fn show(&self) {
-        self.main_window().show_all();
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
     }
 
     #[inline]
-    pub fn save_project(&self, force_rename: bool) -> Result<(), &'static str> {
-        let pr = RwLock::upgradable_read(&self.0.current_project);
-        let pr = if pr.filename.is_none() || force_rename {
-            let mut pr = RwLockUpgradableReadGuard::upgrade(pr);
-            self.hide();
+    pub fn symbol_index(&self, name: &str) -> Option<usize> {
+        self.symbols.iter().position(|s| s.name == name)
+    }
 
-            let si = io::stdin();
-            let so = io::stdout();
-            let mut stdin = si.lock();
-            let mut stdout = so.lock();
+    /// Turn the selected items in the current frame into a new symbol, and place it where they
+    /// were.
+    pub fn make_symbol_from_selection(&mut self, name: String) -> Result<usize, Error> {
+        if self.editing_symbol.is_some() {
+            return Err(Error::InvalidInput(
+                "symbols can't be made while editing a symbol",
+            ));
+        }
+        if self.symbol_index(&name).is_some() {
+            return Err(Error::InvalidInput(
+                "there is already a symbol with that name",
+            ));
+        }
 
-            let mut filename = String::new();
-            let mut save_type = String::new();
+        let index = self.symbols.len();
+        let frame = self.current_frame_mut();
+        if frame.selected().is_empty() {
+            return Err(Error::InvalidInput("nothing is selected"));
+        }
+        if frame.symbols_selected() {
+            return Err(Error::InvalidInput("symbols can't contain other symbols"));
+        }
 
-            stdout.write_all(b"Enter file name: ").unwrap();
-            stdout.flush().unwrap();
-            stdin.read_line(&mut filename).unwrap();
+        let state = frame.take_selected();
+        self.symbols.push(Symbol::new(name, state));
 
-            filename.pop();
-            pr.filename = Some(filename);
+        let instance = SymbolInstance::new(index, &self.symbols[index], Vector2D::zero());
+        self.current_frame_mut().add_symbol_instance(instance);
+        Ok(index)
+    }
 
-            'stype: loop {
-                stdout
-                    .write_all(b"Save as bincode (b) or JSON (j): ")
-                    .unwrap();
-                stdout.flush().unwrap();
+    /// Place a symbol in the current frame, with the top left corner of its bounds at a point.
+    pub fn place_symbol(&mut self, index: usize, position: Point2D<f32>) -> Result<(), Error> {
+        if self.editing_symbol.is_some() {
+            return Err(Error::InvalidInput("symbols can't contain other symbols"));
+        }
+        let symbol = self
+            .symbols
+            .get(index)
+            .ok_or(Error::InvalidInput("there is no such symbol"))?;
+
+        let offset = position - symbol.bounding_box().origin;
+        let instance = SymbolInstance::new(index, symbol, offset);
+        self.current_frame_mut().add_symbol_instance(instance);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn editing_symbol(&self) -> Option<usize> {
+        self.editing_symbol
+    }
+
+    /// Start editing a symbol in place of the current frame, or go back to the frame if `None`
+    /// is given.
+    pub fn edit_symbol(&mut self, index: Option<usize>) {
+        self.current_frame_mut().unselect();
+        self.editing_symbol = index.filter(|i| *i < self.symbols.len());
+
+        // the symbol may have changed size
+        if self.editing_symbol.is_none() {
+            let symbols = &self.symbols;
+            self.frames
+                .iter_mut()
+                .for_each(|f| f.refresh_symbol_instances(symbols));
+        }
+    }
+
+    #[inline]
+    pub fn brush(&self, index: usize) -> Option<&Brush> {
+        self.brushes.get(index)
+    }
+
+    /// The brushes in the project's palette.
+    #[inline]
+    pub fn brushes(&self) -> &[Brush] {
+        &self.brushes
+    }
+
+    #[inline]
+    pub fn current_brush(&self) -> &Brush {
+        &self.brushes[self.current_brush]
+    }
+
+    #[inline]
+    pub fn current_brush_index(&self) -> usize {
+        self.current_brush
+    }
+
+    /// Give the selected items their own brush, whose color is the average of their current
+    /// colors. Only solid colors are mixed, and the brush otherwise matches the first item's.
+    pub fn mix_selected_colors(&mut self) -> Result<(), Error> {
+        let used = self.current_frame().selected_brushes();
+        let colors: SmallVec<[SolidColor; 12]> = used
+            .iter()
+            .filter_map(|b| b.resolve(&self.brushes))
+            .filter_map(|b| b.color().as_solid().copied())
+            .collect();
+
+        let mixed = SolidColor::average(&colors)
+            .ok_or(Error::InvalidInput("no solid colors are selected"))?;
+        let mut brush = used
+            .first()
+            .and_then(|b| b.resolve(&self.brushes))
+            .copied()
+            .ok_or(Error::InvalidInput("no selected items have a brush"))?;
+        brush.set_color(mixed.into());
+
+        self.current_frame_mut()
+            .set_selected_brush(BrushRef::Inline(brush));
+        Ok(())
+    }
+
+    #[inline]
+    pub fn brush_mut(&mut self, index: usize) -> Option<&mut Brush> {
+        self.brushes.get_mut(index)
+    }
+
+    #[inline]
+    pub fn brush_count(&self) -> usize {
+        self.brushes.len()
+    }
+
+    /// Add a brush to the end of the brush list, returning its index.
+    #[inline]
+    pub fn add_brush(&mut self, brush: Brush) -> usize {
+        self.brushes.push(brush);
+        self.brushes.len() - 1
+    }
+
+    /// Move the current brush forwards or backwards through the brush list, wrapping around at
+    /// either end.
+    #[inline]
+    pub fn cycle_brush(&mut self, forward: bool) {
+        let count = self.brushes.len();
+        if count == 0 {
+            return;
+        }
+
+        self.current_brush = if forward {
+            (self.current_brush + 1) % count
+        } else {
+            (self.current_brush + count - 1) % count
+        };
+    }
+
+    /// Set the brush used for new items. Out of range indices are ignored.
+    #[inline]
+    pub fn set_current_brush(&mut self, index: usize) {
+        if index < self.brushes.len() {
+            self.current_brush = index;
+        }
+    }
+
+    /// Save this project's brushes to a JSON palette file.
+    pub fn export_palette(&self, path: &str) -> Result<(), Error> {
+        let palette = Palette {
+            brushes: self.brushes.clone(),
+        };
+        let json = serde_json::to_string_pretty(&palette).map_err(Error::serialize)?;
+
+        let mut f = File::create(path)?;
+        f.write_all(json.as_bytes())?;
+        Ok(())
+    }
+
+    /// Load brushes from a JSON palette file.
+    ///
+    /// Items in the project refer to brushes by index, so replacing with a smaller palette keeps
+    /// the existing brushes past the end of it.
+    pub fn import_palette(&mut self, path: &str, mode: PaletteImport) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        let palette: Palette = serde_json::from_slice(&bytes).map_err(Error::deserialize)?;
+
+        if palette.brushes.is_empty() {
+            return Err(Error::UnsupportedFormat(
+                "palette does not contain any brushes",
+            ));
+        }
+
+        match mode {
+            PaletteImport::Merge => self.brushes.extend(palette.brushes),
+            PaletteImport::Replace => {
+                palette
+                    .brushes
+                    .into_iter()
+                    .enumerate()
+                    .for_each(|(i, brush)| match self.brushes.get_mut(i) {
+                        Some(b) => *b = brush,
+                        None => self.brushes.push(brush),
+                    })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Change the dimensions of the project. If anchored at the center, every frame is moved so
+    /// that the drawing stays in the middle. Items may end up outside of the canvas.
+    pub fn resize_canvas(&mut self, new_width: u32, new_height: u32, anchor: CanvasAnchor) {
+        if let CanvasAnchor::Center = anchor {
+            let offset = Vector2F::new(
+                (new_width as f32 - self.width as f32) / 2.0,
+                (new_height as f32 - self.height as f32) / 2.0,
+            );
+            self.frames.iter_mut().for_each(|f| f.translate(offset));
+        }
+
+        self.width = new_width;
+        self.height = new_height;
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// How many recently used colors are kept.
+pub const RECENT_COLORS: usize = 8;
+
+struct GuiInternal {
+    current_project: RwLock<Project>,
+    image: DrawTarget,
+    application: Application,
+
+    canvas: OnceCell<DrawingArea>,
+    main_window: OnceCell<ApplicationWindow>,
+    palette: OnceCell<GtkBox>,
+    surface: Mutex<Option<ImageSurface>>,
+    // the rows of the image, from the first up to but not including the last, that have changed
+    // since they were last copied to the surface
+    dirty_rows: Mutex<Option<(u32, u32)>>,
+
+    gui_mode: Mutex<GuiModeStorage>,
+    view: Mutex<View>,
+    symmetry: Mutex<Option<SymmetryConfig>>,
+    polar: Mutex<Option<PolarGrid>>,
+    // the animation being played, and the timer that shows its next frame
+    playing: Mutex<Option<(Playback, glib::SourceId)>>,
+    // the colors used most recently, newest first
+    recent_colors: Mutex<VecDeque<SolidColor>>,
+    past_gui_modes: Mutex<StoredModes>,
+    settings: RwLock<Settings>,
+    keymap: RwLock<KeyMap>,
+}
+
+#[derive(Clone)]
+#[repr(transparent)]
+pub struct Gui(Arc<GuiInternal>);
+
+impl Gui {
+    #[inline]
+    pub fn new(project: Project) -> Gui {
+        Self::with_settings(project, Settings::load())
+    }
+
+    pub fn with_settings(project: Project, settings: Settings) -> Gui {
+        let application = Application::new(
+            Some("com.notaseagull.archetype"),
+            ApplicationFlags::HANDLES_COMMAND_LINE,
+        )
+        .expect("Unable to initialize GTK");
+
+        let img = TCImage::from_pixel(project.width, project.height, Rgba([0, 0, 0, 0]));
+        let mode = project.session_mode().unwrap_or(GuiModeType::Buffered);
+        let mut view = View::default();
+        view.set_natural_scrolling(settings.natural_scrolling);
+        let mut gui = Self(Arc::new(GuiInternal {
+            current_project: RwLock::new(project),
+            application,
+            canvas: OnceCell::new(),
+            main_window: OnceCell::new(),
+            palette: OnceCell::new(),
+            image: RwLock::new((img, true)),
+            surface: Mutex::new(None),
+            dirty_rows: Mutex::new(None),
+            gui_mode: Mutex::new(GuiModeStorage::new(mode, settings.fit_error)),
+            view: Mutex::new(view),
+            symmetry: Mutex::new(None),
+            polar: Mutex::new(None),
+            playing: Mutex::new(None),
+            recent_colors: Mutex::new(VecDeque::with_capacity(RECENT_COLORS)),
+            past_gui_modes: Mutex::new(StoredModes::default()),
+            keymap: RwLock::new(KeyMap::new(&settings.keys)),
+            settings: RwLock::new(settings),
+        }));
+
+        let cl = gui.clone();
+        gui.0.application.connect_command_line(|app, _cmd| {
+            app.activate();
+            0
+        });
+        gui.0.application.connect_activate(move |app| {
+            ui::build_ui(app, cl.clone());
+            cl.gui_mode().lock().switch_in(&cl);
+            cl.update_image();
+        });
+
+        gui
+    }
+
+    #[inline]
+    pub fn new_project(width: u32, height: u32) -> Gui {
+        let settings = Settings::load();
+        let project = Project::new(width, height, settings.endpoint_tolerance);
+        Self::with_settings(project, settings)
+    }
+
+    #[inline]
+    pub fn settings(&self) -> Settings {
+        self.0.settings.read().clone()
+    }
+
+    /// Switch to the next of the built in canvas backgrounds.
+    pub fn cycle_background(&self) -> Result<(), Error> {
+        self.update_settings(|settings| settings.background = settings.background.next())?;
+        println!(
+            "Showing a {:?} background",
+            self.0.settings.read().background
+        );
+        self.drawing_area().queue_draw();
+        Ok(())
+    }
+
+    /// Change the settings, saving them if anything changed.
+    pub fn update_settings<F: FnOnce(&mut Settings)>(&self, f: F) -> Result<(), Error> {
+        let mut settings = self.0.settings.write();
+        let old = settings.clone();
+        f(&mut settings);
+
+        if *settings != old {
+            *self.0.keymap.write() = KeyMap::new(&settings.keys);
+            settings.save()?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn keymap(&self) -> &RwLock<KeyMap> {
+        &self.0.keymap
+    }
+
+    #[inline]
+    pub fn gui_mode(&self) -> &Mutex<GuiModeStorage> {
+        &self.0.gui_mode
+    }
+
+    /// The symmetry that newly drawn objects are copied with, if any.
+    #[inline]
+    pub fn symmetry(&self) -> Option<SymmetryConfig> {
+        *self.0.symmetry.lock()
+    }
+
+    /// Switch between storing coordinates quantized and at full precision.
+    #[inline]
+    pub fn toggle_quantized(&self) {
+        let quantized = {
+            let mut pr = self.project().write();
+            let quantized = !pr.quantized();
+            pr.set_quantized(quantized);
+            quantized
+        };
+
+        if quantized {
+            println!("Storing coordinates quantized to {} pixels", QUANTIZE_STEP);
+        } else {
+            println!("Storing coordinates at full precision");
+        }
+        self.update_image();
+    }
+
+    /// Change what the animation does after its last frame.
+    #[inline]
+    pub fn cycle_loop_mode(&self) {
+        let mut pr = self.project().write();
+        let loop_mode = pr.loop_mode().next();
+        pr.set_loop_mode(loop_mode);
+        println!("Animation plays {:?}", loop_mode);
+    }
+
+    /// Switch pixel art mode on or off.
+    #[inline]
+    pub fn toggle_pixel_mode(&self) {
+        let mut pr = self.project().write();
+        let pixel_mode = !pr.pixel_mode();
+        pr.set_pixel_mode(pixel_mode);
+        mem::drop(pr);
+
+        if pixel_mode {
+            println!("Drawing crisp pixels");
+        } else {
+            println!("Drawing smooth edges");
+        }
+        self.update_image();
+    }
+
+    /// Round a point to whole pixels in pixel art mode, and leave it alone otherwise.
+    #[inline]
+    pub fn pixel_snap(&self, pt: Vector2F) -> Vector2F {
+        if self.project().read().pixel_mode() {
+            pt.round()
+        } else {
+            pt
+        }
+    }
+
+    /// Cycle between no symmetry, mirroring and a few radial symmetries around the center of
+    /// the canvas.
+    #[inline]
+    pub fn cycle_symmetry(&self) {
+        let (width, height) = self.dimensions();
+        let mut symmetry = self.0.symmetry.lock();
+
+        let next = match symmetry.map(|s| s.symmetry) {
+            None => Some(Symmetry::Mirror),
+            Some(Symmetry::Mirror) => Some(Symmetry::Radial(4)),
+            Some(Symmetry::Radial(n)) if n < 8 => Some(Symmetry::Radial(n + 2)),
+            Some(Symmetry::Radial(_)) => None,
+        };
+        *symmetry = next.map(|symmetry| SymmetryConfig {
+            symmetry,
+            center: Point2D::new(width as f32 / 2.0, height as f32 / 2.0),
+        });
+
+        match next {
+            Some(s) => println!("Drawing with {:?} symmetry", s),
+            None => println!("Drawing without symmetry"),
+        }
+    }
+
+    /// Turn the polar grid off, or prompt for its spokes and rings and turn it on. It goes
+    /// around the center of symmetry if there is one, or the center of the canvas otherwise.
+    pub fn toggle_polar_grid(&self) -> Result<(), Error> {
+        if self.0.polar.lock().take().is_some() {
+            println!("Polar grid off");
+        } else {
+            self.hide();
+            let numbers = crate::interactive_number("number of spokes").and_then(|spokes| {
+                let ring_spacing =
+                    crate::interactive_number("distance between rings, or 0 for none")?;
+                Ok((spokes, ring_spacing))
+            });
+            self.show();
+            let (spokes, ring_spacing) = numbers?;
+
+            let (width, height) = self.dimensions();
+            let center = self
+                .symmetry()
+                .map(|s| Vector2F::new(s.center.x, s.center.y))
+                .unwrap_or_else(|| Vector2F::new(width as f32 / 2.0, height as f32 / 2.0));
+            *self.0.polar.lock() = Some(PolarGrid {
+                center,
+                spokes,
+                ring_spacing: ring_spacing as f32,
+            });
+        }
+        self.drawing_area().queue_draw();
+        Ok(())
+    }
+
+    /// Snap a point in canvas coordinates onto the polar grid if it's on, or otherwise onto
+    /// any guides near it.
+    #[inline]
+    pub fn snap_to_guides(&self, pt: Vector2F) -> Vector2F {
+        let snapped = match *self.0.polar.lock() {
+            Some(ref polar) => polar.snap(pt),
+            None => snap_to_guides(self.project().read().guides(), pt, GUIDE_TOLERANCE),
+        };
+        self.pixel_snap(snapped)
+    }
+
+    #[inline]
+    pub fn clear_guides(&self) {
+        self.project().write().guides_mut().clear();
+        self.drawing_area().queue_draw();
+    }
+
+    #[inline]
+    pub fn view(&self) -> &Mutex<View> {
+        &self.0.view
+    }
+
+    /// Zoom the canvas around a point in the drawing area.
+    #[inline]
+    pub fn zoom_at(&self, factor: f32, anchor: Vector2F) {
+        self.0.view.lock().zoom_at(factor, anchor);
+        self.update_image();
+    }
+
+    /// Zoom so that the whole canvas, or just the selection, fits in the window.
+    pub fn zoom_to_fit(&self, selection_only: bool) -> Result<(), Error> {
+        let pr = self.project().read();
+        let rect = if selection_only {
+            pr.current_frame()
+                .selected_bounds()
+                .ok_or(Error::InvalidInput("nothing is selected"))?
+        } else {
+            Rect::new(
+                Point2D::zero(),
+                Size2D::new(pr.width() as f32, pr.height() as f32),
+            )
+        };
+        mem::drop(pr);
+
+        let da = self.drawing_area();
+        let (width, height) = (
+            da.get_allocated_width() as f32,
+            da.get_allocated_height() as f32,
+        );
+        self.0.view.lock().fit(rect, width, height);
+        self.update_image();
+        Ok(())
+    }
+
+    /// Pan the canvas by a scroll delta.
+    #[inline]
+    pub fn scroll_view(&self, delta: Vector2F) {
+        self.0.view.lock().scroll(delta);
+        self.update_image();
+    }
+
+    /// Convert a point in the drawing area to a point on the canvas.
+    #[inline]
+    pub fn to_canvas(&self, pt: Vector2F) -> Vector2F {
+        self.0.view.lock().to_canvas(pt)
+    }
+
+    /// Take a previously stored mode of the given type, if there is one, so that it can be
+    /// switched back into with its state intact.
+    #[inline]
+    pub fn take_matching_gui_mode(&self, ty: GuiModeType) -> Option<GuiModeStorage> {
+        self.0.past_gui_modes.lock().take(ty)
+    }
+
+    /// Switch the current mode out and store it, leaving the GUI waiting for a new mode to be
+    /// picked. Does nothing if a mode is already being picked.
+    #[inline]
+    pub fn store_gui_mode(&self) {
+        let mut switch_mode = GuiModeStorage::Switching;
+        let mut current_mode = self.0.gui_mode.lock();
+
+        if let GuiModeStorage::Switching = &*current_mode {
+            // do nothing
+        } else {
+            mem::swap(&mut switch_mode, &mut current_mode);
+            mem::drop(current_mode);
+            switch_mode.switch_out(self);
+            self.0.past_gui_modes.lock().store(switch_mode);
+        }
+    }
+
+    #[inline]
+    pub fn update_image(&self) {
+        // wipe the image
+        {
+            let mut img = self.0.image.write();
+            img.0
+                .as_flat_samples_mut()
+                .image_mut_slice()
+                .unwrap()
+                .par_iter_mut()
+                .for_each(|m| *m = 0);
+            img.1 = true;
+        }
+
+        let da = self.drawing_area();
+        let (da_width, da_height) = (da.get_allocated_width(), da.get_allocated_height());
+
+        let pr = self.0.current_project.read();
+        let frame = pr.current_frame();
+        self.mark_rows_dirty(0, pr.height);
+
+        // only draw what can be seen, the view redraws as it moves
+        // before the drawing area has been laid out, we don't know what that is
+        if da_width > 1 && da_height > 1 {
+            let viewport = self
+                .0
+                .view
+                .lock()
+                .visible_rect(da_width as f32, da_height as f32);
+            frame.rasterize_culled(&self.0.image, &pr, PREVIEW_TOLERANCE, viewport);
+        } else {
+            frame.rasterize(&self.0.image, &pr, PREVIEW_TOLERANCE);
+        }
+        da.queue_draw();
+    }
+
+    /// Redraw only the part of the canvas inside `region`, along with whatever overlaps it. If
+    /// the region covers too much of the canvas, everything is redrawn instead.
+    pub fn update_region(&self, region: Rect<f32>) {
+        // past this much of the canvas, a full redraw is just as fast
+        const MAX_DIRTY_FRACTION: f32 = 0.5;
+
+        let (width, height) = self.dimensions();
+        let region = match canvas_region(region, width, height) {
+            Some(region) => region,
+            None => return,
+        };
+        if region.area() > (width * height) as f32 * MAX_DIRTY_FRACTION {
+            return self.update_image();
+        }
+
+        redraw_region(&self.0.current_project.read(), &self.0.image, region);
+        self.mark_rows_dirty(region.min_y() as u32, region.max_y() as u32);
+        self.drawing_area().queue_draw();
+    }
+
+    // note that some rows need to be copied to the surface again
+    #[inline]
+    fn mark_rows_dirty(&self, start: u32, end: u32) {
+        let mut dirty = self.0.dirty_rows.lock();
+        *dirty = Some(match *dirty {
+            Some((s, e)) => (s.min(start), e.max(end)),
+            None => (start, end),
+        });
+    }
+
+    /// Make a change to the current frame that only affects the selected items, and redraw the
+    /// parts of the canvas they covered before and after the change.
+    pub fn update_selection<R, F: FnOnce(&mut GraphicalState) -> R>(&self, f: F) -> R {
+        let (res, region) = {
+            let mut pr = self.project().write();
+            selection_change(&mut pr, f)
+        };
+
+        match region {
+            Some(region) => self.update_region(region),
+            None => self.drawing_area().queue_draw(),
+        }
+        res
+    }
+
+    #[inline]
+    pub fn project(&self) -> &RwLock<Project> {
+        &self.0.current_project
+    }
+
+    #[inline]
+    pub fn set_drawing_area(&self, dr: DrawingArea) {
+        self.0.canvas.set(dr).unwrap();
+    }
+
+    #[inline]
+    pub fn set_main_window(&self, mw: ApplicationWindow) {
+        self.0.main_window.set(mw).unwrap();
+    }
+
+    #[inline]
+    pub fn set_palette(&self, palette: GtkBox) {
+        self.0.palette.set(palette).unwrap();
+    }
+
+    #[inline]
+    pub fn palette(&self) -> &GtkBox {
+        self.0.palette.get().expect("Palette does not exist")
+    }
+
+    /// Recreate the palette's swatches, after brushes have been added or removed.
+    #[inline]
+    pub fn rebuild_palette(&self) {
+        ui::build_palette(self.palette(), self);
+    }
+
+    /// Select the brush at the given index and highlight its swatch.
+    #[inline]
+    pub fn select_brush(&self, index: usize) {
+        let mut pr = self.project().write();
+        pr.set_current_brush(index);
+        let color = *pr.current_brush().color();
+        mem::drop(pr);
+
+        self.remember_color(&color);
+        self.palette().queue_draw();
+    }
+
+    /// The colors used most recently, newest first.
+    #[inline]
+    pub fn recent_colors(&self) -> Vec<SolidColor> {
+        self.0.recent_colors.lock().iter().copied().collect()
+    }
+
+    // move a color to the front of the recent colors, if it's a solid color
+    fn remember_color(&self, color: &DynamicColor) {
+        let color = match color.as_solid() {
+            Some(color) => *color,
+            None => return,
+        };
+
+        push_recent_color(&mut self.0.recent_colors.lock(), color);
+        self.palette().queue_draw();
+    }
+
+    // give the current brush a color, without changing the order of the recent colors
+    fn set_brush_color(&self, color: SolidColor) {
+        let mut pr = self.project().write();
+        let index = pr.current_brush_index();
+        if let Some(brush) = pr.brush_mut(index) {
+            brush.set_color(color.into());
+        }
+        mem::drop(pr);
+
+        self.palette().queue_draw();
+        self.update_image();
+    }
+
+    /// Give the current brush one of the recent colors, which moves it to the front.
+    #[inline]
+    pub fn use_recent_color(&self, index: usize) {
+        let color = match self.0.recent_colors.lock().get(index) {
+            Some(color) => *color,
+            None => return,
+        };
+        self.remember_color(&color.into());
+        self.set_brush_color(color);
+    }
+
+    /// Give the current brush the next of the recent colors, going round to the first one
+    /// after the last.
+    pub fn cycle_recent_color(&self) {
+        let mut recent = self.0.recent_colors.lock();
+        if recent.len() < 2 {
+            println!("No other colors have been used yet");
+            return;
+        }
+        recent.rotate_left(1);
+        let color = recent[0];
+        mem::drop(recent);
+
+        self.set_brush_color(color);
+    }
+
+    /// Raise or lower the opacity of the current brush.
+    #[inline]
+    pub fn adjust_brush_opacity(&self, delta: f32) {
+        let mut pr = self.project().write();
+        let index = pr.current_brush_index();
+        let brush = pr.brush_mut(index).expect("Brush ID Mismatch");
+        let opacity = brush.opacity() + delta;
+        brush.set_opacity(opacity);
+        println!("Brush opacity is {}", brush.opacity());
+        mem::drop(pr);
+
+        self.palette().queue_draw();
+    }
+
+    /// Switch the current brush between smooth and hard-edged fills.
+    #[inline]
+    pub fn toggle_hard_edges(&self) {
+        let mut pr = self.project().write();
+        let index = pr.current_brush_index();
+        let brush = pr.brush_mut(index).expect("Brush ID Mismatch");
+        brush.set_hard_edges(!brush.hard_edges());
+        if brush.hard_edges() {
+            println!("Brush fills with hard edges");
+        } else {
+            println!("Brush fills with smooth edges");
+        }
+        mem::drop(pr);
+
+        self.update_image();
+    }
+
+    /// Move the current brush on to the next blend mode.
+    #[inline]
+    pub fn cycle_blend_mode(&self) {
+        let mut pr = self.project().write();
+        let index = pr.current_brush_index();
+        let brush = pr.brush_mut(index).expect("Brush ID Mismatch");
+        brush.set_blend_mode(brush.blend_mode().next());
+        println!("Brush blends with {:?}", brush.blend_mode());
+        mem::drop(pr);
+
+        self.update_image();
+    }
+
+    /// Prompt for how far the current brush scatters its strokes, and how much of them it draws.
+    pub fn prompt_jitter(&self) -> Result<(), Error> {
+        let radius = self.prompt_float("Enter jitter radius in pixels, or 0 for none")?;
+        let jitter = if radius > 0.0 {
+            let density = self.prompt_float("Enter the fraction of points to draw, from 0 to 1")?;
+            Some(Jitter {
+                radius,
+                density: density.clamp(0.0, 1.0),
+                seed: 0,
+            })
+        } else {
+            None
+        };
+
+        let mut pr = self.project().write();
+        let index = pr.current_brush_index();
+        pr.brush_mut(index)
+            .expect("Brush ID Mismatch")
+            .set_jitter(jitter);
+        mem::drop(pr);
+
+        self.update_image();
+        Ok(())
+    }
+
+    /// Cycle through the brushes and highlight the newly selected one.
+    #[inline]
+    pub fn cycle_brush(&self, forward: bool) {
+        let mut pr = self.project().write();
+        pr.cycle_brush(forward);
+        println!("Selected brush {}", pr.current_brush_index() + 1);
+        mem::drop(pr);
+
+        self.palette().queue_draw();
+    }
+
+    /// Prompt for a new color for the brush at the given index.
+    #[inline]
+    pub fn edit_brush_color(&self, index: usize) {
+        self.hide();
+        let color = crate::interactive_color("brush color");
+        self.show();
+
+        if let Some(brush) = self.project().write().brush_mut(index) {
+            brush.set_color(color);
+        }
+        self.remember_color(&color);
+
+        self.palette().queue_draw();
+        self.update_image();
+    }
+
+    /// Prompt for a color to give the selected items, without changing the palette.
+    pub fn edit_selected_color(&self) {
+        self.hide();
+        let color = crate::interactive_color("selection color");
+        self.show();
+
+        let mut pr = self.project().write();
+        let palette = pr.brushes().to_vec();
+        pr.current_frame_mut().set_selected_color(&palette, color);
+        mem::drop(pr);
+        self.remember_color(&color);
+
+        self.update_image();
+    }
+
+    #[inline]
+    pub fn set_drawing_function<F>(&self, fnd: F)
+    where
+        F: Fn(&DrawingArea, &Context) -> Inhibit + 'static,
+    {
+        self.drawing_area().connect_draw(fnd);
+    }
+
+    #[inline]
+    pub fn drawing_area(&self) -> &DrawingArea {
+        self.0.canvas.get().expect("Drawing area does not exist")
+    }
+
+    /// Set the cursor shown over the canvas, by its CSS name. `None` restores the default.
+    #[inline]
+    pub fn set_cursor(&self, name: Option<&str>) {
+        use gdk::WindowExt as GdkWindowExt;
+
+        // the canvas has no GDK window until it is realized
+        if let Some(window) = self.drawing_area().get_window() {
+            let cursor = name.and_then(|name| gdk::Cursor::from_name(&window.get_display(), name));
+            window.set_cursor(cursor.as_ref());
+        }
+    }
+
+    #[inline]
+    pub fn main_window(&self) -> &ApplicationWindow {
+        self.0
+            .main_window
+            .get()
+            .expect("Application window does not exist")
+    }
+
+    #[inline]
+    pub fn run(&self) {
+        // the command line has already been handled by main, don't let GTK see it
+        self.0
+            .application
+            .run(&env::args().take(1).collect::<Vec<_>>());
+    }
+
+    #[inline]
+    pub fn dimensions(&self) -> (u32, u32) {
+        let pr = self.0.current_project.read();
+        (pr.width, pr.height)
+    }
+
+    // make sure there is a surface the same size as the image, creating a new one if there isn't
+    // returns whether a new surface was created
+    #[inline]
+    fn ensure_surface(&self, surface: &mut Option<ImageSurface>, width: u32, height: u32) -> bool {
+        let fits = surface
+            .as_ref()
+            .is_some_and(|s| s.get_width() == width as i32 && s.get_height() == height as i32);
+        if fits {
+            return false;
+        }
+
+        *surface = Some(
+            ImageSurface::create(Format::ARgb32, width as i32, height as i32)
+                .expect("Unable to create surface"),
+        );
+        true
+    }
+
+    pub fn draw(&self, context: &Context) {
+        let mut surface = self.0.surface.lock();
+        let img = RwLock::upgradable_read(&self.0.image);
+
+        // get the image width and height
+        let (width, height) = img.0.dimensions();
+
+        // a new surface starts out blank, so it needs the whole image whatever the flag says
+        let fresh = self.ensure_surface(&mut surface, width, height);
+        let mut surface = MutexGuard::map(surface, |s| s.as_mut().unwrap());
+        surface.flush();
+
+        // the flag says whether anything changed, and the dirty rows narrow down where; anything
+        // that changed the image without saying where means it all has to be copied
+        let dirty = self.0.dirty_rows.lock().take();
+        let rows = match (img.1, dirty) {
+            _ if fresh => Some((0, height)),
+            (true, Some((start, end))) => Some((start, end.min(height))),
+            (true, None) => Some((0, height)),
+            (false, _) => None,
+        };
+
+        if let Some((start, end)) = rows {
+            // the changes are on the screen now, so clear the flag
+            let mut img = RwLockUpgradableReadGuard::upgrade(img);
+            img.1 = false;
+            // downgrade to a read lock
+            let img = RwLockWriteGuard::downgrade(img);
+
+            let stride = surface.get_stride() as usize;
+            let mut data = surface.get_data().expect("Unable to borrow surface data");
+
+            // draw pixels into data, skipping the rows that haven't changed
+            copy_rows_to_surface(&img.0, &mut data, stride, start, end);
+
+            mem::drop(data);
+
+            // mark the surface as dirty
+            surface.mark_dirty();
+            mem::drop(img);
+        } else {
+            // don't hog the write lock
+            mem::drop(img);
+        }
+
+        // draw everything in canvas coordinates
+        let view = *self.0.view.lock();
+        context.save();
+        context.translate(view.pan().x() as f64, view.pan().y() as f64);
+        context.scale(view.zoom() as f64, view.zoom() as f64);
+
+        let background = self.0.settings.read().background;
+        draw_background(background, width, height, view.zoom(), context);
+
+        context.set_source_surface(&*surface, 0.0f64, 0.0f64);
+        if self.project().read().pixel_mode() {
+            // show each pixel as a sharp square when zoomed in
+            context.get_source().set_filter(cairo::Filter::Nearest);
+        }
+        context.paint();
+
+        draw_guides(
+            self.project().read().guides(),
+            width,
+            height,
+            view.zoom(),
+            context,
+        );
+        if let Some(ref polar) = *self.0.polar.lock() {
+            polar.draw(width, height, view.zoom(), context);
+        }
+
+        self.gui_mode().lock().draw(self, context);
+        context.restore();
+    }
+
+    #[inline]
+    pub fn hide(&self) {
+        self.main_window().hide();
+    }
+
+    #[inline]
+    pub fn show(&self) {
+        self.main_window().show_all();
+    }
+
+    #[inline]
+    pub fn save_project(&self, force_rename: bool) -> Result<(), Error> {
+        // the mode picker isn't worth coming back to, so keep whatever mode was there before it
+        let mode = Some(self.gui_mode().lock().kind()).filter(|m| *m != GuiModeType::Switching);
+        let pr = RwLock::upgradable_read(&self.0.current_project);
+        let pr = if pr.filename.is_none() || force_rename {
+            let mut pr = RwLockUpgradableReadGuard::upgrade(pr);
+            self.hide();
+
+            let si = io::stdin();
+            let so = io::stdout();
+            let mut stdin = si.lock();
+            let mut stdout = so.lock();
+
+            let mut filename = String::new();
+            let mut save_type = String::new();
+
+            stdout.write_all(b"Enter file name: ").unwrap();
+            stdout.flush().unwrap();
+            stdin.read_line(&mut filename).unwrap();
+
+            filename.pop();
+            pr.filename = Some(filename);
+
+            'stype: loop {
+                stdout
+                    .write_all(b"Save as bincode (b) or JSON (j): ")
+                    .unwrap();
+                stdout.flush().unwrap();
                 stdin.read_line(&mut save_type).unwrap();
 
-                match save_type.remove(0) {
-                    'b' => {
-                        pr.filetype = Some(ProjectSave::Bincode);
-                        break 'stype;
-                    }
-                    'j' => {
-                        pr.filetype = Some(ProjectSave::Json);
-                        break 'stype;
-                    }
-                    _ => (),
-                }
+                match save_type.remove(0) {
+                    'b' => {
+                        pr.filetype = Some(ProjectFormat::Bincode);
+                        break 'stype;
+                    }
+                    'j' => {
+                        pr.filetype = Some(ProjectFormat::Json);
+                        break 'stype;
+                    }
+                    _ => (),
+                }
+            }
+
+            self.show();
+            pr.prepare_for_save(mode);
+            RwLockWriteGuard::downgrade(pr)
+        } else {
+            let mut pr = RwLockUpgradableReadGuard::upgrade(pr);
+            pr.prepare_for_save(mode);
+            RwLockWriteGuard::downgrade(pr)
+        };
+
+        // open the file for writing
+        let mut f = File::create(pr.filename.as_ref().unwrap())?;
+
+        f.write_all(&pr.to_bytes(pr.filetype.unwrap())?)?;
+
+        mem::drop(pr);
+        self.project().write().mark_saved();
+        Ok(())
+    }
+
+    // ask whether to save the current project, if it has changed since it was last saved
+    fn offer_to_save(&self) -> Result<(), Error> {
+        if !self.project().read().has_unsaved_changes() {
+            return Ok(());
+        }
+
+        self.hide();
+        let save = crate::interactive_yn("Save changes to the current project?");
+        self.show();
+
+        if save {
+            self.save_project(false)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the current project with another one, starting over with a fresh canvas and a
+    /// fresh copy of the mode the project was saved in.
+    pub fn replace_project(&self, project: Project) {
+        let (width, height) = (project.width, project.height);
+
+        // modes may hold on to parts of the old project, so they go with it
+        let mode = project.session_mode().unwrap_or(GuiModeType::Buffered);
+        let fresh_mode = GuiModeStorage::new(mode, self.settings().fit_error);
+        let mut old_mode = mem::replace(&mut *self.0.gui_mode.lock(), fresh_mode);
+        old_mode.switch_out(self);
+        self.0.past_gui_modes.lock().clear();
+
+        swap_project(self.project(), &self.0.image, project);
+        let mut view = self.0.view.lock();
+        let natural_scrolling = view.natural_scrolling();
+        *view = View::default();
+        view.set_natural_scrolling(natural_scrolling);
+        mem::drop(view);
+        *self.0.symmetry.lock() = None;
+        *self.0.polar.lock() = None;
+        self.0.surface.lock().take();
+
+        self.drawing_area()
+            .set_size_request(width as i32, height as i32);
+        self.gui_mode().lock().switch_in(self);
+        self.update_image();
+    }
+
+    /// Prompt for dimensions and start a new project, offering to save the current one first.
+    pub fn prompt_new_project(&self) -> Result<(), Error> {
+        self.offer_to_save()?;
+
+        let current = self.dimensions();
+        let (width, height) = match self.dimensions_popup("New Project", current) {
+            Some(dimensions) => dimensions,
+            None => return Ok(()),
+        };
+        let tolerance = self.settings().endpoint_tolerance;
+        self.replace_project(Project::new(width, height, tolerance));
+        Ok(())
+    }
+
+    /// Pick a project file and open it in place of the current project, offering to save the
+    /// current one first. Nothing happens if no file is picked.
+    pub fn prompt_open_project(&self) -> Result<(), Error> {
+        self.offer_to_save()?;
+
+        let dialog = FileChooserDialog::with_buttons(
+            Some("Open Project"),
+            Some(self.main_window()),
+            FileChooserAction::Open,
+            &[
+                ("Cancel", ResponseType::Cancel),
+                ("Open", ResponseType::Accept),
+            ],
+        );
+        dialog.set_default_response(ResponseType::Accept);
+        let response = dialog.run();
+        let path = dialog.get_filename();
+        dialog.close();
+
+        let path = match path {
+            Some(path) if response == ResponseType::Accept => path,
+            _ => return Ok(()),
+        };
+        let project = Project::load_from_path(&path.to_string_lossy())?;
+        self.replace_project(project);
+        Ok(())
+    }
+
+    /// Report an error in a dialog over the window, as well as on the console.
+    pub fn show_error(&self, message: &str) {
+        eprintln!("{}", message);
+
+        let dialog = MessageDialog::new(
+            Some(self.main_window()),
+            DialogFlags::MODAL | DialogFlags::DESTROY_WITH_PARENT,
+            MessageType::Error,
+            ButtonsType::Ok,
+            message,
+        );
+        dialog.run();
+        dialog.close();
+    }
+
+    /// Move forwards or backwards through the frames.
+    pub fn step_frame(&self, forward: bool) {
+        let mut pr = self.project().write();
+        let index = pr.current_frame_index();
+        let next = if forward {
+            index + 1
+        } else {
+            index.saturating_sub(1)
+        };
+        pr.set_current_frame(next);
+        println!(
+            "Frame {} of {}",
+            pr.current_frame_index() + 1,
+            pr.frame_count()
+        );
+        mem::drop(pr);
+        self.update_image();
+    }
+
+    /// Tell whether an animation is playing, in which case the frames can't be edited.
+    #[inline]
+    pub fn is_playing(&self) -> bool {
+        self.0.playing.lock().is_some()
+    }
+
+    /// Start playing the animation from the current frame, or pause it where it is.
+    pub fn toggle_playback(&self) {
+        let playing = self.0.playing.lock().take();
+        if let Some((_, timer)) = playing {
+            glib::source_remove(timer);
+            println!("Paused");
+            return;
+        }
+
+        let pr = self.project().read();
+        let playback = Playback::new(pr.loop_mode(), pr.frame_count(), pr.current_frame_index());
+        mem::drop(pr);
+
+        println!("Playing");
+        self.show_playback_frame(playback);
+    }
+
+    /// Stop the animation, and go back to the frame it started playing from.
+    pub fn stop_playback(&self) {
+        let (playback, timer) = match self.0.playing.lock().take() {
+            Some(playing) => playing,
+            None => return,
+        };
+        glib::source_remove(timer);
+
+        self.project().write().set_current_frame(playback.start());
+        println!("Stopped");
+        self.update_image();
+    }
+
+    // show the playback's current frame, and set a timer to move on once its time is up
+    fn show_playback_frame(&self, playback: Playback) {
+        let mut pr = self.project().write();
+        pr.set_current_frame(playback.current());
+        let duration = pr.current_frame().duration_ms();
+        mem::drop(pr);
+        self.update_image();
+
+        let gui = self.clone();
+        let timer = gtk::timeout_add(duration, move || {
+            // the timer only runs once, so it's done with either way
+            let playing = gui.0.playing.lock().take();
+            if let Some((mut playback, _)) = playing {
+                match playback.advance() {
+                    Some(_) => gui.show_playback_frame(playback),
+                    None => println!("Finished playing"),
+                }
+            }
+            glib::Continue(false)
+        });
+        *self.0.playing.lock() = Some((playback, timer));
+    }
+
+    #[inline]
+    pub fn duplicate_frame(&self) {
+        let mut pr = self.project().write();
+        pr.duplicate_frame();
+        println!(
+            "Frame {} of {}",
+            pr.current_frame_index() + 1,
+            pr.frame_count()
+        );
+        mem::drop(pr);
+        self.update_image();
+    }
+
+    /// Flatten the current frame into an image, or take back the last flatten.
+    #[inline]
+    pub fn flatten_frame(&self, undo: bool) -> Result<(), Error> {
+        let mut pr = self.project().write();
+        if undo {
+            pr.unflatten_frame()?;
+        } else {
+            pr.flatten_frame()?;
+        }
+        mem::drop(pr);
+
+        self.update_image();
+        Ok(())
+    }
+
+    /// Prompt for a number that may be negative or fractional.
+    pub fn prompt_float(&self, prompt: &str) -> Result<f32, Error> {
+        self.hide();
+        let value = crate::interactive_line(prompt).trim().parse::<f32>();
+        self.show();
+        value.map_err(|_| Error::InvalidInput("expected a number"))
+    }
+
+    /// Prompt for a name and group the selected items under it.
+    pub fn group_selected(&self) -> Result<(), Error> {
+        self.hide();
+        let name = crate::interactive_line("Enter group name");
+        self.show();
+
+        self.project()
+            .write()
+            .current_frame_mut()
+            .group_selected(name)
+    }
+
+    /// Prompt for an offset and move the selected items, and their groups, by it.
+    pub fn move_selected(&self) -> Result<(), Error> {
+        let dx = self.prompt_float("Enter x offset")?;
+        let dy = self.prompt_float("Enter y offset")?;
+
+        self.project()
+            .write()
+            .current_frame_mut()
+            .translate_selected(Vector2F::new(dx, dy));
+        self.update_image();
+        Ok(())
+    }
+
+    /// Prompt for how to line up the selected items, and line them up.
+    pub fn align_selected(&self) -> Result<(), Error> {
+        self.hide();
+        let answer = crate::interactive_line(
+            "Align (l)eft, (r)ight, (t)op, (b)ottom, (h)orizontal centers or (v)ertical centers",
+        );
+        self.show();
+        let alignment = answer
+            .trim()
+            .chars()
+            .next()
+            .and_then(Alignment::from_char)
+            .ok_or(Error::InvalidInput("unknown alignment"))?;
+
+        self.project()
+            .write()
+            .current_frame_mut()
+            .align_selected(alignment);
+        self.update_image();
+        Ok(())
+    }
+
+    /// Prompt for a direction, and space the selected items out evenly in it.
+    pub fn distribute_selected(&self) -> Result<(), Error> {
+        self.hide();
+        let answer = crate::interactive_line("Distribute (h)orizontally or (v)ertically");
+        self.show();
+        let axis = answer
+            .trim()
+            .chars()
+            .next()
+            .and_then(Axis::from_char)
+            .ok_or(Error::InvalidInput("unknown direction"))?;
+
+        self.project()
+            .write()
+            .current_frame_mut()
+            .distribute_selected(axis);
+        self.update_image();
+        Ok(())
+    }
+
+    /// Prompt for an angle and rotate the selected items, and their groups, around the center of
+    /// the selection.
+    pub fn rotate_selected(&self) -> Result<(), Error> {
+        let degrees = self.prompt_float("Enter angle in degrees")?;
+
+        let mut pr = self.project().write();
+        let frame = pr.current_frame_mut();
+        frame.select_whole_groups();
+        let center = frame
+            .selected_bounds()
+            .ok_or(Error::InvalidInput("nothing is selected"))?
+            .center();
+        let center = Vector2F::new(center.x, center.y);
+        let rotation = Transform2F::from_translation(center)
+            * Transform2F::from_rotation(degrees.to_radians())
+            * Transform2F::from_translation(-center);
+        frame.transform_selected(&rotation);
+        mem::drop(pr);
+
+        self.update_image();
+        Ok(())
+    }
+
+    /// Run one of the raster filters over the selected images, or every image in the frame if
+    /// none are selected, prompting for how strong it should be.
+    pub fn filter_images(&self, cmd: Command) -> Result<(), Error> {
+        let prompt_float = |prompt| self.prompt_float(prompt);
+
+        let mut pr = self.project().write();
+        let frame = pr.current_frame_mut();
+        match cmd {
+            Command::BlurImages => {
+                let sigma = prompt_float("Enter blur radius")?;
+                frame.filter_images(|img| crate::gaussian_blur(img, sigma))?;
+            }
+            Command::AdjustBrightness => {
+                let amount = prompt_float("Enter brightness change, from -100 to 100")?;
+                frame.filter_images(|img| crate::brightness(img, amount / 100.0))?;
             }
+            _ => frame.filter_images(crate::invert)?,
+        }
+        mem::drop(pr);
+
+        self.update_image();
+        Ok(())
+    }
+
+    /// Prompt for a number of frames, and fill them in between the current frame and the next.
+    pub fn prompt_tween(&self) -> Result<(), Error> {
+        self.hide();
+        let count = crate::interactive_number("number of frames to fill in");
+        self.show();
+        let count = count?;
+
+        let mut pr = self.project().write();
+        let from = pr.current_frame_index();
+        pr.tween(from, from + 1, count as usize)?;
+        mem::drop(pr);
+
+        self.update_image();
+        Ok(())
+    }
+
+    /// Prompt for how long the current frame shows for in the animation.
+    pub fn prompt_frame_duration(&self) -> Result<(), Error> {
+        let current = self.project().read().current_frame().duration_ms();
+        println!("The frame shows for {} ms", current);
+
+        self.hide();
+        let duration = crate::interactive_number("milliseconds to show the frame for");
+        self.show();
+        let duration = duration?;
+
+        let mut pr = self.project().write();
+        pr.current_frame_mut().set_duration_ms(duration);
+        println!(
+            "The frame shows for {} ms",
+            pr.current_frame().duration_ms()
+        );
+        Ok(())
+    }
+
+    /// Prompt for a name and turn the selected items into a symbol by that name.
+    pub fn make_symbol(&self) -> Result<(), Error> {
+        self.hide();
+        let name = crate::interactive_line("Enter symbol name");
+        self.show();
+
+        self.project().write().make_symbol_from_selection(name)?;
+        self.update_image();
+        Ok(())
+    }
+
+    /// Prompt for a symbol and where to put it, and place it in the current frame.
+    pub fn place_symbol(&self) -> Result<(), Error> {
+        self.hide();
+        let name = crate::interactive_line("Enter symbol name");
+        let position = crate::interactive_number("x position")
+            .and_then(|x| Ok((x, crate::interactive_number("y position")?)));
+        self.show();
+        let (x, y) = position?;
+
+        let mut pr = self.project().write();
+        let index = pr
+            .symbol_index(&name)
+            .ok_or(Error::InvalidInput("there is no symbol with that name"))?;
+        pr.place_symbol(index, Point2D::new(x as f32, y as f32))?;
+        mem::drop(pr);
+
+        self.update_image();
+        Ok(())
+    }
 
+    /// Start editing a symbol by name in place of the current frame, or go back to the frame if
+    /// a symbol is already being edited.
+    pub fn toggle_symbol_editing(&self) -> Result<(), Error> {
+        if self.project().read().editing_symbol().is_some() {
+            self.project().write().edit_symbol(None);
+            println!("Finished editing symbol");
+        } else {
+            self.hide();
+            let name = crate::interactive_line("Enter symbol name");
             self.show();
-            RwLockWriteGuard::downgrade(pr)
+
+            let mut pr = self.project().write();
+            let index = pr
+                .symbol_index(&name)
+                .ok_or(Error::InvalidInput("there is no symbol with that name"))?;
+            pr.edit_symbol(Some(index));
+            println!("Editing symbol \"{}\"", name);
+        }
+
+        self.update_image();
+        Ok(())
+    }
+
+    /// Resize the project's canvas, along with the image and drawing area backing it.
+    pub fn resize_canvas(&self, new_width: u32, new_height: u32, anchor: CanvasAnchor) {
+        self.project()
+            .write()
+            .resize_canvas(new_width, new_height, anchor);
+
+        *self.0.image.write() = (
+            TCImage::from_pixel(new_width, new_height, Rgba([0, 0, 0, 0])),
+            true,
+        );
+        // the surface is recreated at the new size on the next draw
+        self.0.surface.lock().take();
+
+        self.drawing_area()
+            .set_size_request(new_width as i32, new_height as i32);
+        self.update_image();
+    }
+
+    /// Prompt for new canvas dimensions and resize the canvas.
+    pub fn prompt_resize_canvas(&self) -> Result<(), Error> {
+        self.hide();
+        let dimensions = crate::interactive_dimensions();
+        let anchor = if crate::interactive_yn("Keep the drawing centered?") {
+            CanvasAnchor::Center
         } else {
-            RwLockUpgradableReadGuard::downgrade(pr)
+            CanvasAnchor::TopLeft
         };
+        self.show();
 
-        // open the file for writing
-        let mut f =
-            File::create(pr.filename.as_ref().unwrap()).map_err(|_e| "Unable to open file")?;
-
-        match pr.filetype {
-            Some(ProjectSave::Bincode) => {
-                // use the bincode serializer to serialize the file to bytes
-                let bytes =
-                    bincode::serialize(&*pr).map_err(|_e| "Unable to serialize to bytes")?;
-                f.write_all(&bytes)
-                    .map_err(|_e| "Unable to write to file")?;
-            }
-            Some(ProjectSave::Json) => {
-                let json =
-                    serde_json::to_string(&*pr).map_err(|_e| "Unable to serialize to JSON")?;
-                f.write_all(json.as_bytes())
-                    .map_err(|_e| "Unable to write to file")?;
-            }
-            _ => unreachable!(),
-        }
+        let (width, height) = dimensions.map_err(Error::InvalidInput)?;
+        self.resize_canvas(width, height, anchor);
+        Ok(())
+    }
+
+    /// Prompt for a rhai script and let it draw into the current frame.
+    pub fn run_script(&self) -> Result<(), Error> {
+        self.hide();
+        let filename = crate::interactive_line("Enter script filename");
+        self.show();
+
+        let source = std::fs::read_to_string(filename.trim())?;
+        let result = crate::run_rhai(self, &source);
+
+        // whatever the script drew before any error stays
+        self.update_image();
+        result
+    }
 
+    /// Prompt for an image file and place it in the top-left corner of the current frame.
+    #[inline]
+    pub fn place_image(&self) -> Result<(), Error> {
+        let filename = crate::prompt_filename(
+            &mut io::stdin().lock(),
+            &mut io::stdout(),
+            "Enter image filename",
+        )?;
+
+        let mut bytes = Vec::new();
+        File::open(&filename)?.read_to_end(&mut bytes)?;
+
+        self.project().write().current_frame_mut().import_image(
+            bytes,
+            Point2D::new(0.0, 0.0),
+            1.0,
+        )?;
+        self.update_image();
         Ok(())
     }
 
     #[inline]
-    pub fn export_project(&self) -> Result<(), &'static str> {
+    pub fn export_project(&self) -> Result<(), Error> {
+        let pr = self.project().read();
+        if pr.frame_count() == 0 {
+            return Err(Error::Render("the project has no frames".to_string()));
+        }
+        if pr.current_frame().is_empty() {
+            println!("Nothing has been drawn in this frame, so the export will be blank");
+        }
+        mem::drop(pr);
+
         let si = io::stdin();
         let so = io::stdout();
         let mut stdin = si.lock();
@@ -449,7 +2235,9 @@ impl Gui {
         const PROMPT: &'static [u8] = b"
 The following export file types are supported:
  * (s)ingle image: Export the currently selected frame as a PNG image.
- * (m)p4 video
+ * (c)ropped selection: Export only the selected items, cropped to fit them.
+ * (b)uild steps: Export a numbered PNG for each step of drawing the current frame.
+ * (f)rames: Export a numbered PNG for each image of the animation, at a steady frame rate.
 
 Enter format: ";
 
@@ -474,24 +2262,1149 @@ Enter format: ";
             AlphaMaskTarget::Background(crate::interactive_color("background color"))
         };
 
-        render(
-            &*self.0.current_project.read(),
-            &filename,
-            outtype.unwrap(),
-            am,
-        )
+        let (whole_frame, selection_only) = match outtype.unwrap() {
+            RenderTarget::SingleImage => (true, false),
+            RenderTarget::Selection => (false, true),
+            _ => (false, false),
+        };
+        let padding = if selection_only {
+            crate::interactive_number("padding around the selection")?
+        } else {
+            0
+        };
+
+        let tile = if whole_frame
+            && crate::interactive_yn("Tile the frame, to check that it repeats seamlessly?")
+        {
+            Some((
+                crate::interactive_number("number of tiles across")?,
+                crate::interactive_number("number of tiles down")?,
+            ))
+        } else {
+            None
+        };
+        let step_size = match outtype.unwrap() {
+            RenderTarget::Steps => crate::interactive_number("number of objects to add each step")?,
+            _ => 1,
+        } as usize;
+        let fps = match outtype.unwrap() {
+            RenderTarget::Frames => crate::interactive_number("number of images per second")?,
+            _ => ExportOptions::default().fps,
+        };
+        let brick = tile.is_some() && crate::interactive_yn("Offset every other row like bricks?");
+        let trim = if whole_frame
+            && tile.is_none()
+            && crate::interactive_yn("Crop the image to what's drawn?")
+        {
+            Some(crate::interactive_number("margin around the drawing")?)
+        } else {
+            None
+        };
+
+        let options = ExportOptions {
+            padding,
+            tile,
+            brick,
+            step_size,
+            trim,
+            fps,
+            embed_source: whole_frame
+                && crate::interactive_yn(
+                    "Embed the project in the image so it can be edited later?",
+                ),
+            ..Default::default()
+        };
+
+        self.with_progress("Exporting", |progress| {
+            render(
+                &*self.0.current_project.read(),
+                &filename,
+                outtype.unwrap(),
+                am,
+                &options,
+                progress,
+            )
+        })
+    }
+
+    // run something long, showing its progress in a dialog that can cancel it
+    // the dialog is modal, so nothing else can touch the project while events are handled
+    fn with_progress<T, F: FnOnce(&mut Progress<'_>) -> T>(&self, title: &str, f: F) -> T {
+        let dialog = Dialog::with_buttons(
+            Some(title),
+            Some(self.main_window()),
+            DialogFlags::MODAL | DialogFlags::DESTROY_WITH_PARENT,
+            &[("Cancel", ResponseType::Cancel)],
+        );
+        let bar = ProgressBar::new();
+        bar.set_show_text(true);
+        dialog.get_content_area().pack_start(&bar, true, true, 8);
+
+        let cancelled = Rc::new(Cell::new(false));
+        let cancel = cancelled.clone();
+        dialog.connect_response(move |_, _| cancel.set(true));
+        dialog.show_all();
+
+        let result = f(&mut |done, total| {
+            bar.set_fraction(done as f64 / total.max(1) as f64);
+            bar.set_text(Some(&format!("{} of {}", done, total)));
+            while gtk::events_pending() {
+                gtk::main_iteration();
+            }
+            !cancelled.get()
+        });
+
+        dialog.close();
+        result
+    }
+
+    // ask for a width and a height in a dialog, starting from the given ones, or give `None` if
+    // it's cancelled
+    fn dimensions_popup(&self, title: &str, (width, height): (u32, u32)) -> Option<(u32, u32)> {
+        const MAX_DIMENSION: f64 = 16384.0;
+
+        let dialog = Dialog::with_buttons(
+            Some(title),
+            Some(self.main_window()),
+            DialogFlags::MODAL | DialogFlags::DESTROY_WITH_PARENT,
+            &[("Cancel", ResponseType::Cancel), ("OK", ResponseType::Ok)],
+        );
+        dialog.set_default_response(ResponseType::Ok);
+
+        let spin = |value: u32| {
+            let spin = SpinButton::with_range(1.0, MAX_DIMENSION, 1.0);
+            spin.set_value(value as f64);
+            spin.set_activates_default(true);
+            spin
+        };
+        let (width, height) = (spin(width), spin(height));
+        let content = dialog.get_content_area();
+        content.pack_start(&width, true, true, 8);
+        content.pack_start(&height, true, true, 8);
+        dialog.show_all();
+
+        let response = dialog.run();
+        let dimensions = (
+            width.get_value_as_int() as u32,
+            height.get_value_as_int() as u32,
+        );
+        dialog.close();
+        Some(dimensions).filter(|_| response == ResponseType::Ok)
+    }
+}
+
+// fill in what shows through the transparent parts of the canvas
+fn draw_background(background: Background, width: u32, height: u32, zoom: f32, context: &Context) {
+    // the size of a checker on the screen, whatever the zoom
+    const CHECKER_SIZE: f64 = 8.0;
+
+    let rgb = |[r, g, b]: [u8; 3]| {
+        context.set_source_rgb(r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0)
+    };
+    rgb(background.base_color());
+    context.rectangle(0.0, 0.0, width as f64, height as f64);
+    context.fill();
+
+    if background == Background::Checkerboard {
+        // gray squares over the white, clipped to the canvas where the last ones overhang it
+        // only the ones on screen are drawn, since zooming in makes them tiny on the canvas
+        let size = CHECKER_SIZE / zoom as f64;
+        let (columns, rows) = visible_checkers(context.clip_extents(), width, height, size);
+
+        context.save();
+        context.rectangle(0.0, 0.0, width as f64, height as f64);
+        context.clip();
+        rgb([204, 204, 204]);
+        rows.flat_map(|row| columns.clone().map(move |column| (column, row)))
+            .filter(|(column, row)| is_dark_checker(*column, *row))
+            .for_each(|(column, row)| {
+                context.rectangle(column as f64 * size, row as f64 * size, size, size);
+            });
+        context.fill();
+        context.restore();
     }
 }
 
-// normalize the truecolor u16's to u8's
-#[cfg(feature = "true_color")]
+// the columns and rows of checkers `size` canvas pixels across that lie within both the canvas
+// and the visible extents, given as the corners of a rectangle
+#[inline]
+fn visible_checkers(
+    (x0, y0, x1, y1): (f64, f64, f64, f64),
+    width: u32,
+    height: u32,
+    size: f64,
+) -> (Range<u32>, Range<u32>) {
+    let span = |from: f64, to: f64, extent: u32| {
+        let first = (from.max(0.0) / size).floor() as u32;
+        let last = (to.min(extent as f64) / size).ceil().max(0.0) as u32;
+        first..last.max(first)
+    };
+    (span(x0, x1, width), span(y0, y1, height))
+}
+
+// move a color to the front of a list of recent colors, dropping the oldest ones past the cap
+#[inline]
+fn push_recent_color(recent: &mut VecDeque<SolidColor>, color: SolidColor) {
+    recent.retain(|c| *c != color);
+    recent.push_front(color);
+    recent.truncate(RECENT_COLORS);
+}
+
+// put a project in place of the current one, along with a blank image its size, and give back
+// the project it replaced
+fn swap_project(current: &RwLock<Project>, image: &DrawTarget, project: Project) -> Project {
+    let blank = TCImage::from_pixel(project.width, project.height, Rgba([0, 0, 0, 0]));
+    *image.write() = (blank, true);
+    mem::replace(&mut *current.write(), project)
+}
+
+// checkers alternate along both rows and columns, starting with a light one in the corner
 #[inline]
-fn normalize(i: u16) -> u8 {
-    ((i as f32 / std::u16::MAX as f32) * std::u8::MAX as f32) as u8
+// make a change to the selected items of the current frame, returning the part of the canvas
+// they were drawn over before and after it
+fn selection_change<R, F: FnOnce(&mut GraphicalState) -> R>(
+    pr: &mut Project,
+    f: F,
+) -> (R, Option<Rect<f32>>) {
+    let selection_region = |pr: &Project| pr.current_frame().selected_drawn_bounds(pr.brushes());
+
+    let before = selection_region(pr);
+    let res = f(pr.current_frame_mut());
+    let after = selection_region(pr);
+
+    let region = match (before, after) {
+        (Some(before), Some(after)) => Some(before.union(&after)),
+        (before, after) => before.or(after),
+    };
+    (res, region)
+}
+
+// round a region out to whole pixels on the canvas, with a pixel around it for anti-aliasing
+fn canvas_region(region: Rect<f32>, width: u32, height: u32) -> Option<Rect<f32>> {
+    let canvas = Rect::new(Point2D::origin(), Size2D::new(width as f32, height as f32));
+    region.inflate(1.0, 1.0).round_out().intersection(&canvas)
+}
+
+// draw the objects touching a region of the canvas onto a blank image, then copy that region
+// over to the target
+fn redraw_region(pr: &Project, target: &DrawTarget, region: Rect<f32>) {
+    let scratch = RwLock::new((TCImage::new(pr.width, pr.height), true));
+    pr.current_frame()
+        .rasterize_culled(&scratch, pr, PREVIEW_TOLERANCE, region);
+    let scratch = scratch.into_inner().0;
+
+    let (x0, y0) = (region.min_x() as u32, region.min_y() as u32);
+    let (x1, y1) = (region.max_x() as u32, region.max_y() as u32);
+    let mut img = target.write();
+    (y0..y1).for_each(|y| {
+        (x0..x1).for_each(|x| img.0.put_pixel(x, y, *scratch.get_pixel(x, y)));
+    });
+    img.1 = true;
+}
+
+fn is_dark_checker(column: u32, row: u32) -> bool {
+    (column + row) % 2 == 1
+}
+
+// copy rows of the image into the data of an argb32 surface with the given stride
+fn copy_rows_to_surface(img: &TCImage, data: &mut [u8], stride: usize, start: u32, end: u32) {
+    let rows = img
+        .enumerate_rows()
+        .skip(start as usize)
+        .take(end.saturating_sub(start) as usize);
+    rows.fold(&mut data[start as usize * stride..], |data, (_y, row)| {
+        row.for_each(|(x, _y, pixel)| {
+            // cairo wants premultiplied alpha, packed into a native-endian word
+            let [r, g, b, a] = display_pixel(pixel.0);
+            let pixel = u32::from_be_bytes([a, r, g, b]).to_ne_bytes();
+
+            data.iter_mut()
+                .skip(x as usize * 4)
+                .take(4)
+                .zip(pixel.iter())
+                .for_each(|(b, val)| *b = *val);
+        });
+
+        // move the data up by a stride
+        &mut data[stride..]
+    });
 }
 
-#[cfg(not(feature = "true_color"))]
+// convert a premultiplied pixel to the 8 bits per channel the screen surface uses
+// this is the only place the image loses precision: exports keep the full color atom, and this
+// rounds to the nearest 8-bit value the same way image viewers do when they show a 16-bit png
 #[inline]
-fn normalize(i: u8) -> u8 {
-    i
+fn display_pixel(pixel: [ColorAtom; 4]) -> [u8; 4] {
+    let max = ColorAtom::MAX as u32;
+    let [r, g, b, a] = pixel;
+    let a = a as u32;
+
+    // premultiply before reducing the precision, so dark translucent pixels keep their color
+    let premultiply = |c: ColorAtom| (c as u32 * a + max / 2) / max;
+    let to_u8 = |c: u32| ((c * 255 + max / 2) / max) as u8;
+
+    [
+        to_u8(premultiply(r)),
+        to_u8(premultiply(g)),
+        to_u8(premultiply(b)),
+        to_u8(a),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        GradientColor, Jitter, RadialGradient, StateDataLoc, StateDataType, StrokeGradient,
+        DEFAULT_FRAME_DURATION,
+    };
+    use std::collections::HashMap;
+
+    // the layouts from before versioning, written out field by field
+    #[derive(Serialize)]
+    struct BrushV0(DynamicColor, u32);
+
+    // curves, buffered lines, lines, polygons, filled polygons, history, the next ID, the
+    // selection and the last selected item
+    #[derive(Serialize)]
+    struct FrameV0(
+        HashMap<DataID, ()>,
+        Vec<()>,
+        HashMap<DataID, ()>,
+        HashMap<DataID, ()>,
+        HashMap<DataID, ()>,
+        Vec<()>,
+        usize,
+        Vec<()>,
+        Option<DataID>,
+    );
+
+    #[derive(Serialize)]
+    struct ProjectV0(
+        u32,
+        u32,
+        Vec<BrushV0>,
+        Vec<FrameV0>,
+        usize,
+        usize,
+        Option<String>,
+        Option<ProjectFormat>,
+    );
+
+    fn empty_frame_v0() -> FrameV0 {
+        FrameV0(
+            HashMap::new(),
+            Vec::new(),
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            Vec::new(),
+            0,
+            Vec::new(),
+            None,
+        )
+    }
+
+    fn check_upgraded(project: &Project) {
+        assert_eq!(project.version(), PROJECT_VERSION);
+        assert_eq!((project.width, project.height), (20, 10));
+        assert_eq!(project.brushes.len(), 1);
+        assert_eq!(project.brushes[0].width(), 3);
+        assert_eq!(project.brushes[0].opacity().into_inner(), 1.0);
+        assert_eq!(project.endpoint_tolerance(), ENDPOINT_TOLERANCE);
+        assert_eq!(project.frames.len(), 2);
+        assert_eq!(project.frames[1].duration_ms(), DEFAULT_FRAME_DURATION);
+    }
+
+    #[test]
+    fn format_is_detected_from_contents() {
+        let project = Project::new(20, 10, 0.5);
+        let bincode = project.to_bytes(ProjectFormat::Bincode).unwrap();
+        let json = project.to_bytes(ProjectFormat::Json).unwrap();
+
+        assert_eq!(
+            ProjectFormat::detect(&bincode).ok(),
+            Some(ProjectFormat::Bincode)
+        );
+        assert_eq!(ProjectFormat::detect(&json).ok(), Some(ProjectFormat::Json));
+        assert_eq!(
+            ProjectFormat::detect(b"  \n{}").ok(),
+            Some(ProjectFormat::Json)
+        );
+
+        assert!(ProjectFormat::detect(b"").is_err());
+        assert!(ProjectFormat::detect(b" \t\n").is_err());
+        assert!(ProjectFormat::detect(b"just some text").is_err());
+    }
+
+    #[test]
+    fn garbage_is_not_a_project() {
+        assert!(Project::from_bytes(&[0xff, 0x00, 0x13, 0x37], ProjectFormat::Bincode).is_err());
+        assert!(Project::from_bytes(b"{\"width\": 10", ProjectFormat::Json).is_err());
+    }
+
+    #[test]
+    fn bincode_round_trip() {
+        let mut project = Project::new(20, 10, 0.5);
+        project.add_frame();
+        project.set_seed(Some(7));
+
+        let bytes = project.to_bytes(ProjectFormat::Bincode).unwrap();
+        assert!(bytes.starts_with(BINCODE_HEADER));
+        let loaded = Project::from_bytes(&bytes, ProjectFormat::Bincode).unwrap();
+
+        assert_eq!(loaded.version(), PROJECT_VERSION);
+        assert_eq!(loaded.frames.len(), 2);
+        assert_eq!(loaded.seed(), Some(7));
+        assert_eq!(loaded.endpoint_tolerance(), 0.5);
+        assert_eq!(loaded.content_hash(), project.content_hash());
+    }
+
+    #[test]
+    fn selected_colors_are_mixed() {
+        let mut project = Project::new(20, 10, 0.5);
+        let red = project.add_brush(Brush::new(colors::RED, 3));
+        let blue = project.add_brush(Brush::new(colors::BLUE, 1));
+        let frame = project.current_frame_mut();
+        let first = frame.test_line((0.0, 0.0), (10.0, 10.0));
+        let second = frame.test_line((0.0, 5.0), (10.0, 5.0));
+        frame.select_only(&[first]);
+        frame.set_selected_brush(BrushRef::Index(red));
+        frame.select_only(&[second]);
+        frame.set_selected_brush(BrushRef::Index(blue));
+        frame.select_all();
+
+        project.mix_selected_colors().unwrap();
+
+        let expected = colors::RED.mix_linear(&colors::BLUE, 0.5);
+        project
+            .current_frame()
+            .lines()
+            .values()
+            .for_each(|line| match line.brush {
+                BrushRef::Inline(ref brush) => {
+                    assert!(brush.color().as_solid() == Some(&expected));
+                    // the rest of the brush comes from the first item
+                    assert_eq!(brush.width(), 3);
+                }
+                BrushRef::Index(_) => panic!("the mixed color was not given to every item"),
+            });
+
+        project.current_frame_mut().unselect();
+        assert!(project.mix_selected_colors().is_err());
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        let mut project = Project::new(20, 10, 0.5);
+        assert!(project.metadata().created.is_some());
+        let metadata = project.metadata_mut();
+        metadata.title = Some("Sketch".to_string());
+        metadata.author = Some("Someone".to_string());
+        project.prepare_for_save(None);
+        assert!(project.metadata().modified.is_some());
+
+        for &format in &[ProjectFormat::Bincode, ProjectFormat::Json] {
+            let bytes = project.to_bytes(format).unwrap();
+            let loaded = Project::from_bytes(&bytes, format).unwrap();
+            assert_eq!(loaded.metadata(), project.metadata());
+        }
+    }
+
+    #[test]
+    fn unversioned_bincode_is_upgraded() {
+        let old = ProjectV0(
+            20,
+            10,
+            vec![BrushV0(DynamicColor::Solid(colors::RED), 3)],
+            vec![empty_frame_v0(), empty_frame_v0()],
+            1,
+            0,
+            None,
+            Some(ProjectFormat::Bincode),
+        );
+        let bytes = bincode::serialize(&old).unwrap();
+        assert_eq!(
+            ProjectFormat::detect(&bytes).ok(),
+            Some(ProjectFormat::Bincode)
+        );
+
+        let project = Project::from_bytes(&bytes, ProjectFormat::Bincode).unwrap();
+        check_upgraded(&project);
+        assert_eq!(project.current_frame_index(), 1);
+
+        // and it stays the same through a save at the new version
+        let resaved = project.to_bytes(ProjectFormat::Bincode).unwrap();
+        let reloaded = Project::from_bytes(&resaved, ProjectFormat::Bincode).unwrap();
+        check_upgraded(&reloaded);
+        assert_eq!(reloaded.content_hash(), project.content_hash());
+    }
+
+    #[test]
+    fn unversioned_json_is_upgraded() {
+        let frame = serde_json::json!({
+            "curves": {},
+            "buffered_lines": [],
+            "lines": {},
+            "polygons": {},
+            "filled_polygons": {},
+            "history": [],
+            "next_data_id": 0,
+            "selected": [],
+            "last_history_selected": null,
+        });
+        let mut drawn = frame.clone();
+        drawn["lines"] =
+            serde_json::json!({ "0": { "points": [[0.0, 0.0], [10.0, 5.0]], "brush": 0 } });
+        drawn["history"] = serde_json::json!([{ "Add": ["Line", 0] }]);
+        drawn["next_data_id"] = serde_json::json!(1);
+        let old = serde_json::json!({
+            "width": 20,
+            "height": 10,
+            "brushes": [{ "color": { "Solid": { "inner": [1.0, 0.0, 0.0] } }, "width": 3 }],
+            "frames": [drawn, frame],
+            "current_frame": 0,
+            "current_brush": 0,
+            "filename": null,
+            "filetype": null,
+        });
+
+        let bytes = serde_json::to_vec(&old).unwrap();
+        let project = Project::from_bytes(&bytes, ProjectFormat::Json).unwrap();
+        check_upgraded(&project);
+
+        // objects used to have a bare brush index
+        let line = &project.frames[0].lines()[&0];
+        assert!(matches!(line.brush, BrushRef::Index(0)));
+        assert_eq!(line.points[1], Point2D::new(10.0, 5.0));
+    }
+
+    fn temp_path(name: &str, extension: &str) -> String {
+        env::temp_dir()
+            .join(format!(
+                "archetype-{}-{}.{}",
+                name,
+                std::process::id(),
+                extension
+            ))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn gradient_palette() -> Project {
+        let stops = [
+            (0.0, colors::RED),
+            (0.5, colors::WHITE),
+            (1.0, colors::BLUE),
+        ];
+        let gradient = GradientColor::from_stops(&stops).unwrap();
+
+        let mut project = Project::new(20, 10, 0.5);
+        project.brushes.push(Brush::new(colors::RED, 4));
+        project
+            .brushes
+            .push(Brush::new(StrokeGradient::new(gradient), 6));
+        project.brushes.push(Brush::new(
+            RadialGradient::new(
+                GradientColor::new(colors::WHITE, colors::BLACK),
+                Point2D::new(5.0, 5.0),
+                3.0,
+            ),
+            1,
+        ));
+        project
+    }
+
+    #[test]
+    fn palette_round_trip() {
+        let path = temp_path("round-trip", "json");
+        let source = gradient_palette();
+        source.export_palette(&path).unwrap();
+
+        let mut project = Project::new(20, 10, 0.5);
+        project
+            .import_palette(&path, PaletteImport::Replace)
+            .unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(
+            serde_json::to_value(project.brushes()).unwrap(),
+            serde_json::to_value(source.brushes()).unwrap()
+        );
+        match project.brushes()[2].color() {
+            DynamicColor::Stroke(_) => (),
+            _ => panic!("the stroke gradient was not kept"),
+        }
+    }
+
+    #[test]
+    fn palettes_can_be_merged() {
+        let path = temp_path("merge", "json");
+        gradient_palette().export_palette(&path).unwrap();
+
+        let mut project = Project::new(20, 10, 0.5);
+        project.import_palette(&path, PaletteImport::Merge).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        // the standard black brush, then the whole palette after it
+        assert_eq!(project.brushes().len(), 5);
+        assert!(project.brushes()[0].color().as_solid() == Some(&colors::BLACK));
+        assert_eq!(project.brushes()[2].width(), 4);
+    }
+
+    // export the current frame and read the image back in
+    fn exported(project: &Project, name: &str) -> image::RgbaImage {
+        let path = temp_path(name, "png");
+        render::single_image(
+            project,
+            &path,
+            AlphaMaskTarget::Background(colors::WHITE.into()),
+            &ExportOptions::default(),
+            &mut render::no_progress,
+        )
+        .unwrap();
+        let img = image::open(&path).unwrap().to_rgba();
+        let _ = std::fs::remove_file(&path);
+        img
+    }
+
+    #[test]
+    fn resized_canvases_export_at_the_new_size() {
+        let mut project = Project::new(20, 10, 0.5);
+        project
+            .current_frame_mut()
+            .test_line((2.0, 2.0), (8.0, 2.0));
+
+        project.resize_canvas(30, 40, CanvasAnchor::TopLeft);
+        assert_eq!((project.width(), project.height()), (30, 40));
+        assert_eq!(exported(&project, "resize").dimensions(), (30, 40));
+
+        // shrinking is fine too, even though the line ends up off the canvas
+        project.resize_canvas(4, 4, CanvasAnchor::Center);
+        assert_eq!(exported(&project, "shrink").dimensions(), (4, 4));
+    }
+
+    #[test]
+    fn centered_resizes_move_the_drawing() {
+        let mut project = Project::new(20, 10, 0.5);
+        project
+            .current_frame_mut()
+            .test_line((2.0, 2.0), (8.0, 2.0));
+
+        project.resize_canvas(30, 40, CanvasAnchor::Center);
+        let line = project.current_frame().lines().values().next().unwrap();
+        assert_eq!(line.points[0], Point2D::new(7.0, 17.0));
+        assert_eq!(line.points[1], Point2D::new(13.0, 17.0));
+    }
+
+    // scattered strokes, drawn after seeding the project
+    fn jittered_project(seed: Option<u64>, radius: f32, density: f32) -> Project {
+        let mut project = Project::new(32, 32, 0.5);
+        project.set_seed(seed);
+        project.brushes[0].set_jitter(Some(Jitter {
+            radius,
+            density,
+            seed: 0,
+        }));
+        project
+            .current_frame_mut()
+            .test_line((4.0, 16.0), (28.0, 16.0));
+        project
+            .current_frame_mut()
+            .test_line((16.0, 4.0), (16.0, 28.0));
+        project
+    }
+
+    #[test]
+    fn seeded_exports_are_identical() {
+        let first = exported(&jittered_project(Some(7), 3.0, 0.5), "seed-a");
+        let second = exported(&jittered_project(Some(7), 3.0, 0.5), "seed-b");
+        assert_eq!(*first, *second);
+
+        let other = exported(&jittered_project(Some(8), 3.0, 0.5), "seed-c");
+        assert_ne!(*first, *other);
+    }
+
+    #[test]
+    fn zero_jitter_is_the_plain_line() {
+        let mut project = jittered_project(None, 0.0, 1.0);
+        let jittered = exported(&project, "no-jitter-a");
+
+        project.brushes[0].set_jitter(None);
+        let plain = exported(&project, "no-jitter-b");
+        assert_eq!(*jittered, *plain);
+    }
+
+    #[test]
+    fn unseeded_jitter_is_stable() {
+        let project = jittered_project(None, 3.0, 0.5);
+        let first = exported(&project, "stable-a");
+        assert_eq!(*first, *exported(&project, "stable-b"));
+
+        // the pattern is saved along with the frame
+        let bytes = project.to_bytes(ProjectFormat::Bincode).unwrap();
+        let loaded = Project::from_bytes(&bytes, ProjectFormat::Bincode).unwrap();
+        assert_eq!(*first, *exported(&loaded, "stable-c"));
+    }
+
+    #[test]
+    fn older_frames_are_given_a_jitter_seed() {
+        let old = ProjectV0(
+            20,
+            10,
+            vec![BrushV0(DynamicColor::Solid(colors::RED), 3)],
+            vec![empty_frame_v0()],
+            0,
+            0,
+            None,
+            Some(ProjectFormat::Bincode),
+        );
+        let bytes = bincode::serialize(&old).unwrap();
+        let project = Project::from_bytes(&bytes, ProjectFormat::Bincode).unwrap();
+
+        let resaved = project.to_bytes(ProjectFormat::Bincode).unwrap();
+        let reloaded = Project::from_bytes(&resaved, ProjectFormat::Bincode).unwrap();
+        assert_eq!(
+            reloaded.frames[0].jitter_seed(),
+            project.frames[0].jitter_seed()
+        );
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let mut bytes = BINCODE_HEADER.to_vec();
+        bincode::serialize_into(&mut bytes, &(PROJECT_VERSION + 1)).unwrap();
+        bincode::serialize_into(&mut bytes, &Project::new(20, 10, 0.5)).unwrap();
+        assert!(Project::from_bytes(&bytes, ProjectFormat::Bincode).is_err());
+    }
+
+    #[test]
+    fn checkers_alternate() {
+        // a light one in the corner, then dark ones beside and below it
+        assert!(!is_dark_checker(0, 0));
+        assert!(is_dark_checker(1, 0));
+        assert!(is_dark_checker(0, 1));
+        assert!(!is_dark_checker(1, 1));
+        assert!(!is_dark_checker(6, 4));
+        assert!(is_dark_checker(7, 4));
+    }
+
+    #[test]
+    fn only_visible_checkers_are_drawn() {
+        // zoomed all the way into a big canvas, a screenful of it is only a few checkers
+        let size = 8.0 / 32.0;
+        let (columns, rows) =
+            visible_checkers((1000.0, 2000.0, 1025.0, 2018.75), 10_000, 10_000, size);
+        assert_eq!((columns.start, columns.end), (4000, 4100));
+        assert_eq!((rows.start, rows.end), (8000, 8075));
+
+        // and they stop at the edge of the canvas
+        let (columns, rows) = visible_checkers((-50.0, -50.0, 500.0, 500.0), 100, 60, 8.0);
+        assert_eq!((columns.start, columns.end), (0, 13));
+        assert_eq!((rows.start, rows.end), (0, 8));
+
+        // or there are none at all
+        let (columns, _rows) = visible_checkers((200.0, 0.0, 300.0, 50.0), 100, 60, 8.0);
+        assert!(columns.is_empty());
+    }
+
+    #[test]
+    fn patterns_read_back_from_the_screen_surface() {
+        let max = ColorAtom::MAX;
+        let (width, height) = (5, 3);
+        let mut img = TCImage::new(width, height);
+        img.put_pixel(0, 0, Rgba([max, 0, 0, max]));
+        img.put_pixel(4, 1, Rgba([0, max, 0, max / 2]));
+        img.put_pixel(2, 2, Rgba([0, 0, max, max]));
+
+        // rows padded out past the pixels, like cairo's strides can be
+        let stride = 24;
+        let mut data = vec![0; stride * height as usize];
+        copy_rows_to_surface(&img, &mut data, stride, 0, height);
+        let read = |x: usize, y: usize| {
+            let at = y * stride + x * 4;
+            u32::from_ne_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+        };
+
+        assert_eq!(read(0, 0), 0xFF_FF_00_00);
+        // translucent pixels are premultiplied
+        assert_eq!(read(4, 1), 0x7F_00_7F_00);
+        assert_eq!(read(2, 2), 0xFF_00_00_FF);
+        assert_eq!(read(1, 0), 0);
+    }
+
+    #[test]
+    fn only_the_given_rows_are_copied() {
+        let max = ColorAtom::MAX;
+        let img = TCImage::from_pixel(4, 4, Rgba([max, max, max, max]));
+        let stride = 16;
+        let mut data = vec![0; stride * 4];
+        copy_rows_to_surface(&img, &mut data, stride, 1, 3);
+
+        let copied: Vec<bool> = data.chunks(stride).map(|row| row[0] != 0).collect();
+        assert_eq!(copied, vec![false, true, true, false]);
+    }
+
+    #[test]
+    fn mid_gray_looks_the_same_on_screen_and_exported() {
+        let mut project = Project::new(8, 8, 0.5);
+        let gray = SolidColor::new(0.5, 0.5, 0.5).unwrap();
+        let brush = project.add_brush(Brush::new(gray, 4));
+        let frame = project.current_frame_mut();
+        let line = frame.test_line((0.0, 4.0), (8.0, 4.0));
+        frame.select_only(&[line]);
+        frame.set_selected_brush(BrushRef::Index(brush));
+        frame.select_only(&[]);
+
+        let target = RwLock::new((TCImage::new(8, 8), false));
+        project.current_frame().rasterize(&target, &project, 0.5);
+        let on_screen = display_pixel(target.into_inner().0.get_pixel(4, 4).0);
+
+        let exported = exported(&project, "gray");
+
+        assert_eq!(on_screen, exported.get_pixel(4, 4).0);
+        assert!((127..=128).contains(&on_screen[0]));
+    }
+
+    #[test]
+    fn swapped_projects_bring_their_dimensions() {
+        let current = RwLock::new(Project::new(20, 10, 0.5));
+        let image = RwLock::new((TCImage::new(20, 10), false));
+        let old = swap_project(&current, &image, Project::new(64, 48, 0.5));
+
+        assert_eq!((old.width(), old.height()), (20, 10));
+        assert_eq!((current.read().width(), current.read().height()), (64, 48));
+        let image = image.into_inner();
+        assert_eq!(image.0.dimensions(), (64, 48));
+        // the image has to be drawn again
+        assert!(image.1);
+    }
+
+    #[test]
+    fn changes_are_unsaved_until_saved() {
+        let mut project = Project::new(20, 10, 0.5);
+        assert!(!project.has_unsaved_changes());
+        project
+            .current_frame_mut()
+            .test_line((0.0, 0.0), (5.0, 5.0));
+        assert!(project.has_unsaved_changes());
+        project.mark_saved();
+        assert!(!project.has_unsaved_changes());
+    }
+
+    #[test]
+    fn opened_projects_replace_the_current_one() {
+        let mut saved = Project::new(30, 25, 0.5);
+        saved.add_frame();
+        let path = temp_path("open", "arc");
+        std::fs::write(&path, saved.to_bytes(ProjectFormat::Bincode).unwrap()).unwrap();
+
+        let current = RwLock::new(Project::new(20, 10, 0.5));
+        let image = RwLock::new((TCImage::new(20, 10), false));
+        let opened = Project::load_from_path(&path);
+        let _ = std::fs::remove_file(&path);
+        swap_project(&current, &image, opened.unwrap());
+
+        let current = current.read();
+        assert_eq!((current.width(), current.height()), (30, 25));
+        assert_eq!(current.frame_count(), 2);
+        // saving goes back to where it was opened from
+        assert_eq!(current.filename.as_deref(), Some(&*path));
+        assert!(!current.has_unsaved_changes());
+        assert_eq!(image.read().0.dimensions(), (30, 25));
+    }
+
+    #[test]
+    fn missing_projects_fail_to_open() {
+        assert!(Project::load_from_path(&temp_path("missing", "arc")).is_err());
+    }
+
+    // whether anything is drawn in a column range of the current frame
+    fn inked_columns(project: &Project, columns: std::ops::Range<u32>) -> bool {
+        let target = RwLock::new((TCImage::new(project.width(), project.height()), false));
+        project.current_frame().rasterize(&target, project, 0.25);
+        let img = target.into_inner().0;
+        columns
+            .into_iter()
+            .any(|x| (0..img.height()).any(|y| img.get_pixel(x, y)[3] > 0))
+    }
+
+    #[test]
+    fn symbol_instances_render_where_they_are_placed() {
+        let mut project = Project::new(60, 20, 0.5);
+        let frame = project.current_frame_mut();
+        let dot = frame.test_polygon(&[(2.0, 2.0), (8.0, 2.0), (5.0, 8.0)]);
+        frame.select_only(&[dot]);
+        let symbol = project
+            .make_symbol_from_selection("dot".to_string())
+            .unwrap();
+        project
+            .place_symbol(symbol, Point2D::new(40.0, 10.0))
+            .unwrap();
+
+        assert!(inked_columns(&project, 0..10));
+        assert!(!inked_columns(&project, 10..40));
+        assert!(inked_columns(&project, 40..50));
+
+        // editing the symbol changes both instances
+        project.edit_symbol(Some(symbol));
+        project.current_frame_mut().select_all();
+        project.current_frame_mut().delete_selected();
+        project.edit_symbol(None);
+        assert!(!inked_columns(&project, 0..60));
+    }
+
+    #[test]
+    fn in_betweens_go_between_the_keyframes() {
+        let mut project = Project::new(60, 20, 0.5);
+        let line = project
+            .current_frame_mut()
+            .test_line((0.0, 0.0), (10.0, 0.0));
+        project.duplicate_frame();
+        project
+            .current_frame_mut()
+            .translate(Vector2F::new(40.0, 0.0));
+
+        project.tween(0, 1, 3).unwrap();
+        assert_eq!(project.frame_count(), 5);
+        // the second keyframe is still the one being drawn on
+        assert_eq!(project.current_frame_index(), 4);
+        let middle = project.frame(2).unwrap().lines()[&line.1].points[0];
+        assert_eq!(middle, Point2D::new(20.0, 0.0));
+
+        assert!(project.tween(1, 0, 1).is_err());
+        assert!(project.tween(0, 5, 1).is_err());
+    }
+
+    // the pixels of the current frame
+    fn rendered(project: &Project) -> TCImage {
+        let target = RwLock::new((TCImage::new(project.width(), project.height()), false));
+        project
+            .current_frame()
+            .rasterize(&target, project, DEFAULT_TOLERANCE);
+        target.into_inner().0
+    }
+
+    #[test]
+    fn flattened_frames_look_the_same() {
+        let mut project = Project::new(40, 30, 0.5);
+        let frame = project.current_frame_mut();
+        frame.test_line((5.0, 5.0), (35.0, 20.0));
+        frame.test_polygon(&[(20.0, 2.0), (30.0, 2.0), (25.0, 12.0)]);
+        let before = rendered(&project);
+
+        project.flatten_frame().unwrap();
+        let frame = project.current_frame();
+        assert!(frame.lines().is_empty() && frame.polygons().is_empty());
+        assert_eq!(frame.images().len(), 1);
+        // the image is kept at 8 bits, so it can be off by up to a step of that
+        let after = rendered(&project);
+        let step = (ColorAtom::MAX / 255) as i32;
+        let channels = |img: &TCImage| {
+            img.pixels()
+                .flat_map(|px| px.0.to_vec())
+                .collect::<Vec<_>>()
+        };
+        channels(&before)
+            .into_iter()
+            .zip(channels(&after))
+            .for_each(|(a, b)| assert!((a as i32 - b as i32).abs() <= step));
+
+        // and unflattening brings the objects back
+        project.unflatten_frame().unwrap();
+        let frame = project.current_frame();
+        assert_eq!((frame.lines().len(), frame.polygons().len()), (1, 1));
+        assert!(frame.images().is_empty());
+        assert!(project.unflatten_frame().is_err());
+    }
+
+    #[test]
+    fn empty_frames_are_not_flattened() {
+        let mut project = Project::new(40, 30, 0.5);
+        assert!(project.flatten_frame().is_err());
+    }
+
+    #[test]
+    fn missing_brushes_are_found_and_repaired() {
+        let mut project = Project::new(20, 10, 0.5);
+        let frame = project.current_frame_mut();
+        let line = frame.test_line((0.0, 0.0), (5.0, 5.0));
+        frame.select_only(&[line]);
+        frame.set_selected_brush(BrushRef::Index(99));
+        assert!(project.validate().is_err());
+
+        project.repair();
+        assert!(project.validate().is_ok());
+        let brush = project.current_frame().lines()[&line.1].brush;
+        assert!(matches!(brush, BrushRef::Index(0)));
+    }
+
+    #[test]
+    fn missing_frames_are_found_and_repaired() {
+        let mut project = Project::new(20, 10, 0.5);
+        project.add_frame();
+        project.current_frame = 5;
+        match project.validate() {
+            Err(Error::InvalidProject(problems)) => assert!(problems.contains("current frame")),
+            _ => panic!("the current frame should be out of range"),
+        }
+
+        project.repair();
+        assert!(project.validate().is_ok());
+        assert_eq!(project.current_frame_index(), 1);
+    }
+
+    #[test]
+    fn damaged_projects_are_repaired_on_load() {
+        let mut project = Project::new(20, 10, 0.5);
+        project.current_brush = 40;
+        let path = temp_path("damaged", "arc");
+        std::fs::write(&path, project.to_bytes(ProjectFormat::Json).unwrap()).unwrap();
+        let loaded = Project::load_from_path(&path);
+        let _ = std::fs::remove_file(&path);
+
+        let loaded = loaded.unwrap();
+        assert!(loaded.validate().is_ok());
+        assert_eq!(loaded.current_brush, loaded.brushes().len() - 1);
+    }
+
+    #[test]
+    fn used_colors_move_to_the_front() {
+        let mut recent = VecDeque::new();
+        [colors::BLACK, colors::RED, colors::BLUE, colors::RED]
+            .iter()
+            .for_each(|c| push_recent_color(&mut recent, *c));
+        assert!(recent == [colors::RED, colors::BLUE, colors::BLACK]);
+
+        // the oldest colors are forgotten
+        (0..RECENT_COLORS * 2).for_each(|i| {
+            let gray = i as f32 / (RECENT_COLORS * 2) as f32;
+            push_recent_color(&mut recent, SolidColor::new(gray, gray, gray).unwrap());
+        });
+        assert_eq!(recent.len(), RECENT_COLORS);
+        assert!(!recent.contains(&colors::BLACK));
+    }
+
+    #[test]
+    fn sessions_are_restored_with_the_project() {
+        let mut project = Project::new(40, 30, 0.5);
+        let frame = project.current_frame_mut();
+        frame.test_line((5.0, 5.0), (35.0, 20.0));
+        let polygon = frame.test_polygon(&[(20.0, 2.0), (30.0, 2.0), (25.0, 12.0)]);
+        let line = frame.test_line((2.0, 25.0), (38.0, 25.0));
+        frame.select_only(&[line, polygon]);
+        project.prepare_for_save(Some(GuiModeType::Select));
+
+        for &format in &[ProjectFormat::Bincode, ProjectFormat::Json] {
+            let bytes = project.to_bytes(format).unwrap();
+            let loaded = Project::from_bytes(&bytes, format).unwrap();
+            assert_eq!(loaded.current_frame().selected(), &[line, polygon]);
+            assert_eq!(loaded.session_mode(), Some(GuiModeType::Select));
+        }
+
+        // saving from the mode picker keeps the mode from before
+        project.prepare_for_save(None);
+        assert_eq!(project.session_mode(), Some(GuiModeType::Select));
+    }
+
+    // a grid of short plain lines, crossed by a tapered line and a scattered line that both
+    // reach much further than their brushes' widths
+    fn crossed_project(lines: usize) -> (Project, [StateDataLoc; 3]) {
+        let mut project = Project::new(240, 160, 0.5);
+        let mut scattered = Brush::new(colors::RED, 1);
+        scattered.set_jitter(Some(Jitter {
+            radius: 8.0,
+            density: 1.0,
+            seed: 0,
+        }));
+        let scattered = project.add_brush(scattered);
+
+        let frame = project.current_frame_mut();
+        let plain: Vec<StateDataLoc> = (0..lines)
+            .map(|i| {
+                let (x, y) = (
+                    (i % 22) as f32 * 10.0 + 10.0,
+                    (i / 22 % 15) as f32 * 10.0 + 5.0,
+                );
+                frame.test_line((x, y), (x + 8.0, y + 3.0))
+            })
+            .collect();
+        let mut stroke = |brush: Option<usize>, x: f32| {
+            frame.add_buffered_line(Point2D::new(x, 20.0), Point2D::new(x + 10.0, 140.0));
+            match brush {
+                Some(brush) => frame.convert_buffered_lines(brush),
+                None => frame.convert_buffered_lines_tapered(0, 2.0, 16.0),
+            }
+            StateDataLoc(StateDataType::Line, frame.current_data_id() - 1)
+        };
+        let items = [plain[0], stroke(None, 60.0), stroke(Some(scattered), 160.0)];
+        (project, items)
+    }
+
+    fn drawn_from_scratch(project: &Project) -> DrawTarget {
+        let target = RwLock::new((TCImage::new(project.width, project.height), false));
+        project
+            .current_frame()
+            .rasterize(&target, project, PREVIEW_TOLERANCE);
+        target
+    }
+
+    // move some items the way the GUI does, redrawing only the region they covered
+    fn moved_in_place(project: &mut Project, target: &DrawTarget, offset: Vector2F) {
+        let ((), region) = selection_change(project, |frame| frame.translate_selected(offset));
+        let region = canvas_region(region.unwrap(), project.width, project.height).unwrap();
+        redraw_region(project, target, region);
+    }
+
+    #[test]
+    fn selection_redraws_match_full_redraws() {
+        let (mut project, items) = crossed_project(40);
+        let target = drawn_from_scratch(&project);
+
+        // selecting, moving and deselecting all change how the items are drawn
+        let ((), region) = selection_change(&mut project, |frame| frame.select_only(&items));
+        redraw_region(
+            &project,
+            &target,
+            canvas_region(region.unwrap(), 240, 160).unwrap(),
+        );
+        assert!(*target.read().0 == *drawn_from_scratch(&project).read().0);
+
+        moved_in_place(&mut project, &target, Vector2F::new(25.0, 5.0));
+        assert!(*target.read().0 == *drawn_from_scratch(&project).read().0);
+
+        let ((), region) = selection_change(&mut project, |frame| frame.unselect());
+        redraw_region(
+            &project,
+            &target,
+            canvas_region(region.unwrap(), 240, 160).unwrap(),
+        );
+        assert!(*target.read().0 == *drawn_from_scratch(&project).read().0);
+    }
+
+    // run with `cargo test --release -- --ignored selection_redraw_speed` to see the numbers
+    #[test]
+    #[ignore]
+    fn selection_redraw_speed() {
+        use std::time::Instant;
+
+        let (mut project, items) = crossed_project(2000);
+        project.current_frame_mut().select_only(&items[..1]);
+        let target = drawn_from_scratch(&project);
+
+        let start = Instant::now();
+        moved_in_place(&mut project, &target, Vector2F::new(1.0, 0.0));
+        let partial = start.elapsed();
+
+        let start = Instant::now();
+        drawn_from_scratch(&project);
+        let full = start.elapsed();
+
+        println!(
+            "{} objects: {:?} to redraw one moved object, {:?} to redraw everything",
+            project.current_frame().stats().lines,
+            partial,
+            full
+        );
+    }
 }
@@ -1,18 +1,34 @@
 // GPLv3 License
 
-use super::GuiMode;
-use crate::Gui;
+use super::{Command, GuiMode, TypedPoint};
+use crate::{BezierCurve, BufferedLine, FitMethod, GraphicalState, Gui};
 use cairo::Context;
-use euclid::default::Point2D;
+use euclid::default::{Point2D, Rect};
+use gtk::WidgetExt;
 use pathfinder_geometry::vector::Vector2F;
-use std::mem;
+use smallvec::SmallVec;
+use std::{f64::consts::PI, mem};
 
 pub const DEFAULT_ERROR: f32 = 1.0;
+/// How far, in pixels, a point may be from the simplified stroke before it is kept.
+pub const DEFAULT_SIMPLIFY: f32 = 0.5;
 
 /// Use the buffered lines.
 pub struct BufferedGuiMode {
     drag_line: Option<(Vector2F, Vector2F)>,
+    // where the last buffered line ended, for typed points to carry on from
+    last_point: Option<Vector2F>,
     error: f32,
+    fit_method: FitMethod,
+    simplify: f32,
+    // lines buffered before switching out, put back when switching in again
+    stashed_lines: Vec<BufferedLine>,
+    // whether to draw the last fit over the canvas
+    show_fit: bool,
+    // the sample points and resulting curves of the last bezierification
+    last_fit: Option<(SmallVec<[Vector2F; 12]>, Vec<BezierCurve>)>,
+    // a short message drawn over the canvas, until the next command or click
+    status: Option<String>,
 }
 
 impl BufferedGuiMode {
@@ -20,61 +36,204 @@ impl BufferedGuiMode {
     pub fn new(error: f32) -> Self {
         Self {
             error,
+            fit_method: FitMethod::default(),
+            simplify: DEFAULT_SIMPLIFY,
             drag_line: None,
+            last_point: None,
+            stashed_lines: Vec::new(),
+            show_fit: false,
+            last_fit: None,
+            status: None,
         }
     }
 }
 
+impl BufferedGuiMode {
+    // keep the frame's buffered lines while this mode is switched out
+    #[inline]
+    fn stash_lines(&mut self, frame: &mut GraphicalState) {
+        self.stashed_lines = frame.take_buffered_lines().into_vec();
+    }
+
+    // put the kept lines back, telling whether there were any
+    #[inline]
+    fn restore_lines(&mut self, frame: &mut GraphicalState) -> bool {
+        if self.stashed_lines.is_empty() {
+            return false;
+        }
+        frame.restore_buffered_lines(mem::take(&mut self.stashed_lines).into());
+        true
+    }
+
+    // show a message over the canvas
+    #[inline]
+    fn set_status(&mut self, gui: &Gui, status: String) {
+        self.status = Some(status);
+        gui.drawing_area().queue_draw();
+    }
+
+    #[inline]
+    fn clear_status(&mut self, gui: &Gui) {
+        if self.status.take().is_some() {
+            gui.drawing_area().queue_draw();
+        }
+    }
+}
+
+// add a buffered line and redraw around it
+#[inline]
+fn add_line(gui: &Gui, pt1: Vector2F, pt2: Vector2F) {
+    let (pt1, pt2) = (
+        Point2D::new(pt1.x(), pt1.y()),
+        Point2D::new(pt2.x(), pt2.y()),
+    );
+    gui.project()
+        .write()
+        .current_frame_mut()
+        .add_buffered_line(pt1, pt2);
+
+    // buffered lines are drawn a few pixels wide
+    gui.update_region(Rect::from_points([pt1, pt2]).inflate(4.0, 4.0));
+}
+
+// draw the sample points and the control points of the curves fitted to them
+fn draw_fit(points: &[Vector2F], curves: &[BezierCurve], context: &Context) {
+    const DOT_RADIUS: f64 = 2.0;
+
+    // sample points in red
+    context.set_source_rgb(1.0, 0.0, 0.0);
+    points.iter().for_each(|pt| {
+        context.new_sub_path();
+        context.arc(pt.x().into(), pt.y().into(), DOT_RADIUS, 0.0, 2.0 * PI);
+    });
+    context.fill();
+
+    // control handles in blue, with the endpoints connected to their control points
+    context.set_source_rgb(0.0, 0.4, 1.0);
+    context.set_line_width(1.0);
+    curves.iter().for_each(|curve| {
+        let [p0, p1, p2, p3] = *curve.points();
+        context.move_to(p0.x().into(), p0.y().into());
+        context.line_to(p1.x().into(), p1.y().into());
+        context.move_to(p3.x().into(), p3.y().into());
+        context.line_to(p2.x().into(), p2.y().into());
+    });
+    context.stroke();
+
+    curves
+        .iter()
+        .flat_map(|c| c.points().iter())
+        .for_each(|pt| {
+            context.rectangle(
+                f64::from(pt.x()) - DOT_RADIUS,
+                f64::from(pt.y()) - DOT_RADIUS,
+                DOT_RADIUS * 2.0,
+                DOT_RADIUS * 2.0,
+            );
+        });
+    context.fill();
+}
+
+// add new objects to the current frame, then add any symmetric copies of them
+#[inline]
+pub(super) fn commit_with_symmetry<R, F: FnOnce(&mut GraphicalState, usize) -> R>(
+    gui: &Gui,
+    f: F,
+) -> R {
+    let symmetry = gui.symmetry();
+    let mut pr = gui.project().write();
+    let brush = pr.current_brush_index();
+    let frame = pr.current_frame_mut();
+
+    let first_id = frame.current_data_id();
+    let res = f(frame, brush);
+    if let Some(ref symmetry) = symmetry {
+        frame.add_symmetric_copies(first_id, symmetry);
+    }
+
+    mem::drop(pr);
+    gui.update_image();
+    res
+}
+
 impl GuiMode for BufferedGuiMode {
     #[inline]
-    fn switch_in(&mut self, _gui: &Gui) {}
+    fn switch_in(&mut self, gui: &Gui) {
+        gui.set_cursor(Some("crosshair"));
+
+        let restored = self.restore_lines(gui.project().write().current_frame_mut());
+        if restored {
+            gui.update_image();
+        }
+    }
 
     #[inline]
     fn switch_out(&mut self, gui: &Gui) {
+        gui.set_cursor(None);
         self.drag_line = None;
-        gui.project()
-            .write()
-            .current_frame_mut()
-            .drop_buffered_lines();
+        self.last_point = None;
+        self.stash_lines(gui.project().write().current_frame_mut());
         gui.update_image();
     }
 
     #[inline]
-    fn key_press(&mut self, c: char, gui: &Gui) {
-        match c {
-            'd' => {
+    fn command(&mut self, cmd: Command, gui: &Gui) {
+        self.clear_status(gui);
+
+        match cmd {
+            Command::DropLines => {
                 gui.project()
                     .write()
                     .current_frame_mut()
                     .drop_buffered_lines();
+                self.last_point = None;
                 gui.update_image();
             }
-            'b' => {
-                println!("Buffering lines...");
-                let mut pr = gui.project().write();
-                let brush = pr.current_brush_index();
-                pr.current_frame_mut()
-                    .bezierify_buffered_lines(brush, self.error);
-                mem::drop(pr);
-                gui.update_image();
+            Command::FitCurves => {
+                let (error, simplify, method) = (self.error, self.simplify, self.fit_method);
+                self.last_fit = Some(commit_with_symmetry(gui, |frame, brush| {
+                    frame.bezierify_buffered_lines(brush, error, simplify, method)
+                }));
             }
-            'l' => {
-                let mut pr = gui.project().write();
-                let brush = pr.current_brush_index();
-                pr.current_frame_mut().convert_buffered_lines(brush);
-                mem::drop(pr);
-                gui.update_image();
+            Command::CycleFitMethod => {
+                self.fit_method = match self.fit_method {
+                    FitMethod::LeastSquares => FitMethod::CatmullRom,
+                    FitMethod::CatmullRom => FitMethod::LeastSquares,
+                };
+                self.set_status(gui, format!("Fitting curves with {:?}", self.fit_method));
+            }
+            Command::ToggleFitPreview => {
+                self.show_fit = !self.show_fit;
+                gui.drawing_area().queue_draw();
+            }
+            Command::ConvertLines => {
+                commit_with_symmetry(gui, |frame, brush| frame.convert_buffered_lines(brush));
+            }
+            Command::ConvertLinesTapered => {
+                // taper from the brush's width down to a point
+                let width = gui.project().read().current_brush().width() as f32;
+                commit_with_symmetry(gui, |frame, brush| {
+                    frame.convert_buffered_lines_tapered(brush, width, 1.0)
+                });
+            }
+            Command::IncreaseSimplify => {
+                self.simplify += 0.25;
+                gui.drawing_area().queue_draw();
             }
-            't' => {
+            Command::DecreaseSimplify => {
+                self.simplify = (self.simplify - 0.25).max(0.0);
+                gui.drawing_area().queue_draw();
+            }
+            Command::IncreaseError => {
                 self.error += 0.1;
-                println!("Error is {}", self.error);
+                gui.drawing_area().queue_draw();
             }
-            'r' => {
+            Command::DecreaseError => {
                 self.error -= 0.1;
                 if self.error < 0.0f32 {
                     self.error = 0.1f32;
                 }
-                println!("Error is {}", self.error);
+                gui.drawing_area().queue_draw();
             }
             _ => (),
         }
@@ -82,9 +241,12 @@ impl GuiMode for BufferedGuiMode {
 
     #[inline]
     fn mouse_press(&mut self, btn: u32, pt: Vector2F, gui: &Gui) {
+        self.clear_status(gui);
+
         if btn == 1 {
             // check if there is currently a line drag (there should not be one)
             if self.drag_line.is_none() {
+                let pt = gui.snap_to_guides(pt);
                 self.drag_line = Some((pt, pt));
             }
         }
@@ -93,27 +255,61 @@ impl GuiMode for BufferedGuiMode {
     #[inline]
     fn mouse_release(&mut self, btn: u32, pt: Vector2F, gui: &Gui) {
         if let (1, Some((pt1, pt2))) = (btn, self.drag_line.take()) {
-            let (pt1, pt2) = (
-                Point2D::new(pt1.x(), pt1.y()),
-                Point2D::new(pt2.x(), pt2.y()),
-            );
-            gui.project()
-                .write()
-                .current_frame_mut()
-                .add_buffered_line(pt1, pt2);
-            gui.update_image();
+            add_line(gui, pt1, pt2);
+            self.last_point = Some(pt2);
+        }
+    }
+
+    #[inline]
+    fn typed_point(&mut self, pt: TypedPoint, gui: &Gui) {
+        self.clear_status(gui);
+
+        let pt = match pt.resolve(self.last_point) {
+            Some(pt) => pt,
+            None => {
+                self.set_status(gui, "There is no last point to go from".into());
+                return;
+            }
+        };
+
+        // the first point just sets where the line starts
+        match self.last_point {
+            Some(last) => add_line(gui, last, pt),
+            None => self.set_status(gui, format!("Starting at {},{}", pt.x(), pt.y())),
         }
+        self.last_point = Some(pt);
     }
 
     #[inline]
     fn mouse_move(&mut self, pt: Vector2F, gui: &Gui) {
         if let Some((_, ref mut pt2)) = self.drag_line.as_mut() {
-            *pt2 = pt;
+            *pt2 = gui.snap_to_guides(pt);
         }
     }
 
     #[inline]
     fn draw(&mut self, _gui: &Gui, context: &Context) {
+        if self.show_fit {
+            if let Some((ref points, ref curves)) = self.last_fit {
+                draw_fit(points, curves, context);
+            }
+
+            context.set_source_rgb(0.0, 0.4, 1.0);
+            context.set_font_size(12.0);
+            context.move_to(8.0, 20.0);
+            context.show_text(&format!(
+                "Fit error: {:.1}, simplify: {:.2} ({:?})",
+                self.error, self.simplify, self.fit_method
+            ));
+        }
+
+        if let Some(ref status) = self.status {
+            context.set_source_rgb(0.0, 0.4, 1.0);
+            context.set_font_size(12.0);
+            context.move_to(8.0, 36.0);
+            context.show_text(status);
+        }
+
         if let Some(ref drag_line) = &self.drag_line {
             context.set_source_rgb(1.0, 0.0, 0.0);
             context.set_line_width(100.0);
@@ -122,3 +318,36 @@ impl GuiMode for BufferedGuiMode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GuiModeStorage, GuiModeType, StoredModes};
+
+    #[test]
+    fn state_survives_switching_away_and_back() {
+        let mut frame = GraphicalState::new();
+        frame.add_buffered_line(Point2D::new(0.0, 0.0), Point2D::new(5.0, 5.0));
+        frame.add_buffered_line(Point2D::new(5.0, 5.0), Point2D::new(9.0, 2.0));
+
+        let mut buffered = BufferedGuiMode::new(2.5);
+        buffered.simplify = 1.5;
+        buffered.stash_lines(&mut frame);
+        assert!(frame.take_buffered_lines().is_empty());
+
+        // over to select mode and back
+        let mut modes = StoredModes::default();
+        modes.store(GuiModeStorage::Buffered(buffered));
+        modes.store(GuiModeStorage::new(GuiModeType::Select, DEFAULT_ERROR));
+        let mut buffered = match modes.take(GuiModeType::Buffered) {
+            Some(GuiModeStorage::Buffered(b)) => b,
+            _ => panic!("the buffered mode was not kept"),
+        };
+
+        assert_eq!(buffered.error, 2.5);
+        assert_eq!(buffered.simplify, 1.5);
+        assert!(buffered.restore_lines(&mut frame));
+        assert!(!buffered.restore_lines(&mut frame));
+        assert_eq!(frame.take_buffered_lines().len(), 2);
+    }
+}
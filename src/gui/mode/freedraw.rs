@@ -1,34 +1,79 @@
 // GPLv3 License
 
-use super::GuiMode;
-use crate::Gui;
+use super::{buffered::commit_with_symmetry, GuiMode};
+use crate::{Gui, IncrementalFit};
+use cairo::Context;
+use gtk::WidgetExt;
 use pathfinder_geometry::vector::Vector2F;
-use std::collections::HashSet;
 
 pub struct FreedrawGuiMode {
-    is_drawing: bool,
-    point_set: HashSet<Vector2F>,
+    // the stroke being drawn, fitted as it goes
+    stroke: Option<IncrementalFit>,
 }
 
 impl FreedrawGuiMode {
     #[inline]
     pub fn new() -> Self {
-        Self {
-            is_drawing: false,
-            point_set: HashSet::new(),
-        }
+        Self { stroke: None }
     }
 }
 
 impl GuiMode for FreedrawGuiMode {
     #[inline]
-    fn switch_in(&mut self, _gui: &Gui) {}
+    fn switch_in(&mut self, gui: &Gui) {
+        gui.set_cursor(Some("crosshair"));
+    }
+
+    #[inline]
+    fn switch_out(&mut self, gui: &Gui) {
+        gui.set_cursor(None);
+        self.stroke = None;
+    }
+
+    #[inline]
+    fn mouse_press(&mut self, btn: u32, pt: Vector2F, gui: &Gui) {
+        if btn == 1 {
+            let mut stroke = IncrementalFit::new(gui.settings().fit_error);
+            stroke.push(gui.pixel_snap(pt));
+            self.stroke = Some(stroke);
+        }
+    }
+
+    #[inline]
+    fn mouse_move(&mut self, pt: Vector2F, gui: &Gui) {
+        if let Some(ref mut stroke) = self.stroke {
+            stroke.push(gui.pixel_snap(pt));
+            gui.drawing_area().queue_draw();
+        }
+    }
 
     #[inline]
-    fn switch_out(&mut self, _gui: &Gui) {
-        self.is_drawing = false;
+    fn mouse_release(&mut self, btn: u32, pt: Vector2F, gui: &Gui) {
+        if let (1, Some(mut stroke)) = (btn, self.stroke.take()) {
+            stroke.push(gui.pixel_snap(pt));
+            let curves = stroke.finish();
+            commit_with_symmetry(gui, |frame, brush| frame.add_curves(brush, curves));
+        }
     }
 
     #[inline]
-    fn key_press(&mut self, _c: char, _gui: &Gui) {}
+    fn draw(&mut self, _gui: &Gui, context: &Context) {
+        if let Some(ref stroke) = self.stroke {
+            context.set_source_rgb(0.0, 0.0, 0.0);
+            context.set_line_width(1.0);
+            stroke.curves().for_each(|curve| {
+                let [p0, p1, p2, p3] = *curve.points();
+                context.move_to(p0.x().into(), p0.y().into());
+                context.curve_to(
+                    p1.x().into(),
+                    p1.y().into(),
+                    p2.x().into(),
+                    p2.y().into(),
+                    p3.x().into(),
+                    p3.y().into(),
+                );
+            });
+            context.stroke();
+        }
+    }
 }
@@ -0,0 +1,107 @@
+// GPLv3 License
+
+use super::GuiMode;
+use crate::{draw_guides, Gui, Guide, GUIDE_TOLERANCE};
+use cairo::Context;
+use gtk::WidgetExt;
+use pathfinder_geometry::vector::Vector2F;
+
+/// Drag guides out from the top and left edges of the canvas, or move existing ones around.
+/// Dragging a guide off the canvas removes it.
+pub struct GuideGuiMode {
+    dragging: Option<Guide>,
+}
+
+impl GuideGuiMode {
+    #[inline]
+    pub fn new() -> Self {
+        Self { dragging: None }
+    }
+}
+
+impl Default for GuideGuiMode {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GuiMode for GuideGuiMode {
+    #[inline]
+    fn switch_in(&mut self, gui: &Gui) {
+        gui.set_cursor(Some("move"));
+    }
+
+    #[inline]
+    fn switch_out(&mut self, gui: &Gui) {
+        gui.set_cursor(None);
+
+        // put back whatever guide was being dragged
+        if let Some(guide) = self.dragging.take() {
+            gui.project().write().guides_mut().push(guide);
+        }
+    }
+
+    #[inline]
+    fn mouse_press(&mut self, btn: u32, pt: Vector2F, gui: &Gui) {
+        if btn != 1 {
+            return;
+        }
+
+        let mut pr = gui.project().write();
+        let guides = pr.guides_mut();
+        let nearest = guides
+            .iter()
+            .enumerate()
+            .map(|(i, g)| (g.distance_to(pt), i))
+            .filter(|(dist, _)| *dist <= GUIDE_TOLERANCE)
+            .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
+
+        // pick up an existing guide, or pull a new one out of an edge
+        self.dragging = match nearest {
+            Some((_, i)) => Some(guides.remove(i)),
+            None if pt.y() <= GUIDE_TOLERANCE => Some(Guide::Horizontal(pt.y())),
+            None if pt.x() <= GUIDE_TOLERANCE => Some(Guide::Vertical(pt.x())),
+            None => None,
+        };
+        gui.drawing_area().queue_draw();
+    }
+
+    #[inline]
+    fn mouse_move(&mut self, pt: Vector2F, gui: &Gui) {
+        if let Some(ref mut guide) = self.dragging {
+            *guide = guide.moved_to(pt);
+            gui.drawing_area().queue_draw();
+        }
+    }
+
+    #[inline]
+    fn mouse_release(&mut self, btn: u32, pt: Vector2F, gui: &Gui) {
+        if btn != 1 {
+            return;
+        }
+
+        if let Some(guide) = self.dragging.take() {
+            let guide = guide.moved_to(pt);
+            let mut pr = gui.project().write();
+            let (width, height) = (pr.width() as f32, pr.height() as f32);
+            let on_canvas = match guide {
+                Guide::Horizontal(y) => y >= 0.0 && y <= height,
+                Guide::Vertical(x) => x >= 0.0 && x <= width,
+            };
+            if on_canvas {
+                pr.guides_mut().push(guide);
+            }
+            gui.drawing_area().queue_draw();
+        }
+    }
+
+    #[inline]
+    fn draw(&mut self, gui: &Gui, context: &Context) {
+        if let Some(guide) = self.dragging {
+            let (width, height) = gui.dimensions();
+            let zoom = gui.view().lock().zoom();
+            draw_guides(&[guide], width, height, zoom, context);
+        }
+    }
+}
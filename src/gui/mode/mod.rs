@@ -1,20 +1,74 @@
 // GPLv3 License
 
-use super::Gui;
-use cairo::Context;
+use super::{Command, Gui};
+use cairo::{Context, FontSlant, FontWeight};
+use gtk::WidgetExt;
 use pathfinder_geometry::vector::Vector2F;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::str::FromStr;
 
 mod buffered;
-pub use buffered::{BufferedGuiMode, DEFAULT_ERROR};
+pub use buffered::{BufferedGuiMode, DEFAULT_ERROR, DEFAULT_SIMPLIFY};
 mod freedraw;
 pub use freedraw::*;
+mod guide;
+pub use guide::*;
+mod ruler;
+pub use ruler::*;
 mod select;
 pub use select::*;
 
+/// A point typed in by hand, either as `x,y` on the canvas or as `@dx,dy` from the last point.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TypedPoint {
+    Absolute(Vector2F),
+    Relative(Vector2F),
+}
+
+impl TypedPoint {
+    /// Find where the point is on the canvas, given the last point a mode placed. Relative
+    /// points have nothing to go from if there is no last point.
+    #[inline]
+    pub fn resolve(self, last: Option<Vector2F>) -> Option<Vector2F> {
+        match self {
+            TypedPoint::Absolute(pt) => Some(pt),
+            TypedPoint::Relative(delta) => last.map(|last| last + delta),
+        }
+    }
+}
+
+impl FromStr for TypedPoint {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (relative, s) = match s.strip_prefix('@') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+
+        let mut parts = s.split(',').map(|part| part.trim().parse::<f32>());
+        let pt = match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(x)), Some(Ok(y)), None) if x.is_finite() && y.is_finite() => {
+                Vector2F::new(x, y)
+            }
+            _ => return Err("Expected a point like 10,20 or @5,-5"),
+        };
+
+        Ok(if relative {
+            TypedPoint::Relative(pt)
+        } else {
+            TypedPoint::Absolute(pt)
+        })
+    }
+}
+
 /// Various modes of GUI
 pub trait GuiMode {
-    /// Handle a key press.
-    fn key_press(&mut self, c: char, gui: &Gui);
+    /// Run a command bound to this mode.
+    #[inline]
+    fn command(&mut self, _cmd: Command, _gui: &Gui) {}
     /// Handle mouse press.
     #[inline]
     fn mouse_press(&mut self, _btn: u32, _pt: Vector2F, _gui: &Gui) {}
@@ -24,6 +78,11 @@ pub trait GuiMode {
     /// Handle mouse motion.
     #[inline]
     fn mouse_move(&mut self, _pt: Vector2F, _gui: &Gui) {}
+    /// Handle a point typed in by hand.
+    #[inline]
+    fn typed_point(&mut self, _pt: TypedPoint, _gui: &Gui) {
+        println!("This mode doesn't take typed points");
+    }
     /// Handle being switched in.
     fn switch_in(&mut self, gui: &Gui);
     /// Handle being switched out.
@@ -33,12 +92,58 @@ pub trait GuiMode {
     fn draw(&mut self, _gui: &Gui, _context: &Context) {}
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// The commands that pick a mode while switching.
+const MODE_COMMANDS: &[Command] = &[
+    Command::BufferedMode,
+    Command::FreedrawMode,
+    Command::RulerMode,
+    Command::SelectMode,
+    Command::GuideMode,
+];
+
+// list the available modes over the canvas
+fn draw_switching_overlay(gui: &Gui, context: &Context) {
+    const LINE_HEIGHT: f64 = 20.0;
+    const PADDING: f64 = 10.0;
+
+    // the overlay stays the same size and place, no matter the zoom or pan
+    let view = *gui.view().lock();
+    context.save();
+    context.scale(1.0 / view.zoom() as f64, 1.0 / view.zoom() as f64);
+    context.translate(-view.pan().x() as f64, -view.pan().y() as f64);
+
+    let height = LINE_HEIGHT * (MODE_COMMANDS.len() + 1) as f64 + PADDING * 2.0;
+    context.set_source_rgba(0.0, 0.0, 0.0, 0.6);
+    context.rectangle(PADDING, PADDING, 220.0, height);
+    context.fill();
+
+    context.set_source_rgb(1.0, 1.0, 1.0);
+    context.select_font_face("Sans", FontSlant::Normal, FontWeight::Normal);
+    context.set_font_size(14.0);
+
+    let keymap = gui.keymap().read();
+    let lines = std::iter::once("Switch to which mode?".to_string()).chain(
+        MODE_COMMANDS.iter().map(|cmd| {
+            let key = keymap.key(*cmd).unwrap_or(' ');
+            format!("{}  {}", key, cmd.description())
+        }),
+    );
+    lines.enumerate().for_each(|(i, line)| {
+        context.move_to(PADDING * 2.0, PADDING + LINE_HEIGHT * (i + 1) as f64);
+        context.show_text(&line);
+    });
+
+    context.restore();
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum GuiModeType {
     Switching,
     Buffered,
     Freedraw,
     Select,
+    Ruler,
+    Guide,
 }
 
 pub enum GuiModeStorage {
@@ -46,6 +151,8 @@ pub enum GuiModeStorage {
     Buffered(BufferedGuiMode),
     Freedraw(FreedrawGuiMode),
     Select(SelectGuiMode),
+    Ruler(RulerGuiMode),
+    Guide(GuideGuiMode),
 }
 
 impl GuiModeStorage {
@@ -56,9 +163,24 @@ impl GuiModeStorage {
             Self::Buffered(ref mut b) => b,
             Self::Freedraw(ref mut f) => f,
             Self::Select(ref mut s) => s,
+            Self::Ruler(ref mut r) => r,
+            Self::Guide(ref mut g) => g,
         })
     }
 
+    /// Start a mode of the given type from scratch.
+    #[inline]
+    pub fn new(ty: GuiModeType, fit_error: f32) -> Self {
+        match ty {
+            GuiModeType::Switching => Self::Switching,
+            GuiModeType::Buffered => Self::Buffered(BufferedGuiMode::new(fit_error)),
+            GuiModeType::Freedraw => Self::Freedraw(FreedrawGuiMode::new()),
+            GuiModeType::Select => Self::Select(SelectGuiMode::new()),
+            GuiModeType::Ruler => Self::Ruler(RulerGuiMode::new()),
+            GuiModeType::Guide => Self::Guide(GuideGuiMode::new()),
+        }
+    }
+
     #[inline]
     pub fn kind(&self) -> GuiModeType {
         match self {
@@ -66,10 +188,57 @@ impl GuiModeStorage {
             Self::Buffered(_) => GuiModeType::Buffered,
             Self::Freedraw(_) => GuiModeType::Freedraw,
             Self::Select(_) => GuiModeType::Select,
+            Self::Ruler(_) => GuiModeType::Ruler,
+            Self::Guide(_) => GuiModeType::Guide,
         }
     }
 }
 
+/// How many modes that were switched out of are kept.
+const STORED_MODES: usize = 5;
+
+/// Modes that were switched out of, kept so that switching back into one picks up where it
+/// left off.
+#[derive(Default)]
+pub struct StoredModes(SmallVec<[GuiModeStorage; STORED_MODES]>);
+
+impl StoredModes {
+    /// Keep a mode, forgetting the oldest one rather than spilling onto the heap.
+    #[inline]
+    pub fn store(&mut self, mode: GuiModeStorage) {
+        if let GuiModeStorage::Switching = mode {
+            return;
+        }
+
+        if self.0.len() >= STORED_MODES {
+            self.0.remove(0);
+        }
+        self.0.push(mode);
+    }
+
+    /// Take the most recently stored mode of the given type, if there is one.
+    #[inline]
+    pub fn take(&mut self, ty: GuiModeType) -> Option<GuiModeStorage> {
+        let posn = self.0.iter().rposition(|m| m.kind() == ty);
+        posn.map(|p| self.0.remove(p))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
 impl GuiMode for GuiModeStorage {
     #[inline]
     fn switch_in(&mut self, gui: &Gui) {
@@ -82,27 +251,29 @@ impl GuiMode for GuiModeStorage {
     }
 
     #[inline]
-    fn key_press(&mut self, c: char, gui: &Gui) {
+    fn command(&mut self, cmd: Command, gui: &Gui) {
         match self.generic_mut() {
-            Some(m) => m.key_press(c, gui),
+            Some(m) => m.command(cmd, gui),
             None => {
                 // see which mode to switch into
-                let mut new_mode: GuiModeStorage = match c {
-                    'b' => gui
-                        .take_matching_gui_mode(GuiModeType::Buffered)
-                        .unwrap_or_else(|| Self::Buffered(BufferedGuiMode::new(DEFAULT_ERROR))),
-                    'f' => gui
-                        .take_matching_gui_mode(GuiModeType::Freedraw)
-                        .unwrap_or_else(|| Self::Freedraw(FreedrawGuiMode::new())),
-                    's' => gui
-                        .take_matching_gui_mode(GuiModeType::Select)
-                        .unwrap_or_else(|| Self::Select(SelectGuiMode::new())),
-                    _ => GuiModeStorage::Switching,
+                let ty = match cmd {
+                    Command::BufferedMode => GuiModeType::Buffered,
+                    Command::FreedrawMode => GuiModeType::Freedraw,
+                    Command::SelectMode => GuiModeType::Select,
+                    Command::RulerMode => GuiModeType::Ruler,
+                    Command::GuideMode => GuiModeType::Guide,
+                    _ => GuiModeType::Switching,
                 };
+                let mut new_mode = gui
+                    .take_matching_gui_mode(ty)
+                    .unwrap_or_else(|| Self::new(ty, gui.settings().fit_error));
 
                 new_mode.switch_in(gui);
                 *self = new_mode;
                 println!("Switching to {:?} mode", self.kind());
+
+                // get rid of the overlay
+                gui.drawing_area().queue_draw();
             }
         }
     }
@@ -122,8 +293,90 @@ impl GuiMode for GuiModeStorage {
         self.generic_mut().map(|m| m.mouse_move(pt, gui));
     }
 
+    #[inline]
+    fn typed_point(&mut self, pt: TypedPoint, gui: &Gui) {
+        match self.generic_mut() {
+            Some(m) => m.typed_point(pt, gui),
+            None => println!("Pick a mode before typing a point"),
+        }
+    }
+
     #[inline]
     fn draw(&mut self, gui: &Gui, context: &Context) {
-        self.generic_mut().map(|m| m.draw(gui, context));
+        match self.generic_mut() {
+            Some(m) => m.draw(gui, context),
+            None => draw_switching_overlay(gui, context),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(types: &[GuiModeType]) -> StoredModes {
+        let mut modes = StoredModes::default();
+        types
+            .iter()
+            .for_each(|ty| modes.store(GuiModeStorage::new(*ty, DEFAULT_ERROR)));
+        modes
+    }
+
+    #[test]
+    fn stored_modes_are_taken_by_type() {
+        let mut modes = stored(&[GuiModeType::Select, GuiModeType::Ruler]);
+        assert!(modes.take(GuiModeType::Buffered).is_none());
+
+        let select = modes.take(GuiModeType::Select).unwrap();
+        assert_eq!(select.kind(), GuiModeType::Select);
+        assert!(modes.take(GuiModeType::Select).is_none());
+        assert_eq!(modes.len(), 1);
+    }
+
+    #[test]
+    fn switching_is_not_stored() {
+        let mut modes = stored(&[GuiModeType::Switching]);
+        assert!(modes.is_empty());
+        assert!(modes.take(GuiModeType::Switching).is_none());
+    }
+
+    #[test]
+    fn oldest_modes_are_forgotten() {
+        let mut modes = stored(&[
+            GuiModeType::Buffered,
+            GuiModeType::Freedraw,
+            GuiModeType::Select,
+            GuiModeType::Ruler,
+            GuiModeType::Guide,
+            GuiModeType::Select,
+        ]);
+        assert_eq!(modes.len(), STORED_MODES);
+        assert!(modes.take(GuiModeType::Buffered).is_none());
+        assert!(modes.take(GuiModeType::Guide).is_some());
+    }
+
+    #[test]
+    fn typed_points_are_parsed() {
+        let v = Vector2F::new;
+        assert_eq!("10,20".parse(), Ok(TypedPoint::Absolute(v(10.0, 20.0))));
+        assert_eq!(" 1.5 , -3 ".parse(), Ok(TypedPoint::Absolute(v(1.5, -3.0))));
+        assert_eq!("@5,-5".parse(), Ok(TypedPoint::Relative(v(5.0, -5.0))));
+        assert_eq!("@ -2,0.25".parse(), Ok(TypedPoint::Relative(v(-2.0, 0.25))));
+
+        ["", "10", "10,20,30", "a,b", "@", "10;20", "inf,0", "NaN,1"]
+            .iter()
+            .for_each(|s| assert!(s.parse::<TypedPoint>().is_err(), "{:?}", s));
+    }
+
+    #[test]
+    fn relative_points_need_a_last_point() {
+        let last = Some(Vector2F::new(10.0, 10.0));
+        let relative = TypedPoint::Relative(Vector2F::new(5.0, -5.0));
+        assert_eq!(relative.resolve(last), Some(Vector2F::new(15.0, 5.0)));
+        assert_eq!(relative.resolve(None), None);
+
+        let absolute = TypedPoint::Absolute(Vector2F::new(1.0, 2.0));
+        assert_eq!(absolute.resolve(last), Some(Vector2F::new(1.0, 2.0)));
+        assert_eq!(absolute.resolve(None), Some(Vector2F::new(1.0, 2.0)));
     }
 }
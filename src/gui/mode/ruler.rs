@@ -0,0 +1,118 @@
+// GPLv3 License
+
+use super::{GuiMode, TypedPoint};
+use crate::{snap_to_guides, Gui, Line, GUIDE_TOLERANCE};
+use cairo::Context;
+use gtk::WidgetExt;
+use pathfinder_geometry::{line_segment::LineSegment2F, vector::Vector2F};
+
+/// Measure the distance and angle between two points, without drawing anything.
+pub struct RulerGuiMode {
+    measurement: Option<(Vector2F, Vector2F)>,
+    dragging: bool,
+}
+
+impl RulerGuiMode {
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            measurement: None,
+            dragging: false,
+        }
+    }
+}
+
+impl Default for RulerGuiMode {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// snap a point onto a nearby endpoint, or failing that onto any nearby guides
+#[inline]
+fn snap(pt: Vector2F, gui: &Gui) -> Vector2F {
+    let pr = gui.project().read();
+    pr.current_frame()
+        .nearest_endpoint(pt, pr.endpoint_tolerance())
+        .unwrap_or_else(|| snap_to_guides(pr.guides(), pt, GUIDE_TOLERANCE))
+}
+
+impl GuiMode for RulerGuiMode {
+    #[inline]
+    fn switch_in(&mut self, gui: &Gui) {
+        gui.set_cursor(Some("crosshair"));
+    }
+
+    #[inline]
+    fn switch_out(&mut self, gui: &Gui) {
+        gui.set_cursor(None);
+        self.measurement = None;
+        self.dragging = false;
+    }
+
+    #[inline]
+    fn mouse_press(&mut self, btn: u32, pt: Vector2F, gui: &Gui) {
+        if btn == 1 {
+            let pt = snap(pt, gui);
+            self.measurement = Some((pt, pt));
+            self.dragging = true;
+            gui.drawing_area().queue_draw();
+        }
+    }
+
+    #[inline]
+    fn mouse_move(&mut self, pt: Vector2F, gui: &Gui) {
+        if let (true, Some((_, ref mut end))) = (self.dragging, self.measurement.as_mut()) {
+            *end = snap(pt, gui);
+            gui.drawing_area().queue_draw();
+        }
+    }
+
+    #[inline]
+    fn mouse_release(&mut self, btn: u32, pt: Vector2F, gui: &Gui) {
+        if btn == 1 {
+            self.mouse_move(pt, gui);
+            self.dragging = false;
+        }
+    }
+
+    #[inline]
+    fn typed_point(&mut self, pt: TypedPoint, gui: &Gui) {
+        // each typed point measures on from the end of the last measurement
+        let last = self.measurement.map(|(_, end)| end);
+        match pt.resolve(last) {
+            Some(pt) => {
+                self.measurement = Some((last.unwrap_or(pt), pt));
+                self.dragging = false;
+                gui.drawing_area().queue_draw();
+            }
+            None => println!("There is no last point to go from"),
+        }
+    }
+
+    #[inline]
+    fn draw(&mut self, _gui: &Gui, context: &Context) {
+        let (start, end) = match self.measurement {
+            Some(m) => m,
+            None => return,
+        };
+
+        let line = LineSegment2F::new(start, end);
+        let length = line.length();
+        // y goes down on the canvas, so flip it to get a conventional angle
+        let angle = (-(end.y() - start.y()))
+            .atan2(end.x() - start.x())
+            .to_degrees();
+
+        context.set_source_rgb(0.0, 0.6, 0.0);
+        context.set_line_width(1.0);
+        context.move_to(start.x().into(), start.y().into());
+        context.line_to(end.x().into(), end.y().into());
+        context.stroke();
+
+        context.set_font_size(12.0);
+        context.move_to((end.x() + 8.0).into(), (end.y() - 8.0).into());
+        context.show_text(&format!("{:.1} px, {:.1}\u{b0}", length, angle));
+    }
+}
@@ -1,9 +1,19 @@
 // GPLv3 License
 
-use super::GuiMode;
-use crate::Gui;
+use super::{Command, GuiMode};
+use crate::{BooleanOp, Gui};
 use pathfinder_geometry::vector::Vector2F;
-use std::mem;
+use std::{
+    mem,
+    time::{Duration, Instant},
+};
+
+/// How far each duplicate is moved from what it was copied from.
+pub const DUPLICATE_OFFSET: f32 = 10.0;
+/// How far a nudge moves the selection.
+pub const NUDGE_STEP: f32 = 1.0;
+// nudges closer together than this are a single gesture, undone all at once
+const NUDGE_WINDOW: Duration = Duration::from_millis(800);
 
 enum SelectionMode {
     NoSelection,
@@ -14,6 +24,10 @@ enum SelectionMode {
 pub struct SelectGuiMode {
     mode: SelectionMode,
     mouse_click_alternator: bool,
+    // whether clicking on a grouped item selects the rest of its group
+    group_select: bool,
+    // when the last of a burst of nudges was
+    last_nudge: Option<Instant>,
 }
 
 impl SelectGuiMode {
@@ -22,63 +36,208 @@ impl SelectGuiMode {
         Self {
             mode: SelectionMode::NoSelection,
             mouse_click_alternator: false,
+            group_select: true,
+            last_nudge: None,
+        }
+    }
+
+    // move the selection, folding nudges that quickly follow each other into one gesture
+    fn nudge(&mut self, offset: Vector2F, gui: &Gui) {
+        let now = Instant::now();
+        let in_burst = self
+            .last_nudge
+            .is_some_and(|last| now.duration_since(last) < NUDGE_WINDOW);
+        self.last_nudge = Some(now);
+
+        gui.update_selection(|frame| {
+            if !in_burst {
+                frame.end_gesture();
+                frame.begin_gesture();
+            }
+            frame.translate_selected(offset);
+        });
+    }
+
+    // finish any burst of nudges, so that whatever comes next is undone on its own
+    #[inline]
+    fn end_nudges(&mut self, gui: &Gui) {
+        if self.last_nudge.take().is_some() {
+            gui.project().write().current_frame_mut().end_gesture();
         }
     }
 }
 
 impl GuiMode for SelectGuiMode {
     #[inline]
-    fn key_press(&mut self, c: char, gui: &Gui) {
-        match c {
-            'h' => {
-                gui.project()
+    fn command(&mut self, cmd: Command, gui: &Gui) {
+        let nudge = match cmd {
+            Command::NudgeLeft => Some(Vector2F::new(-NUDGE_STEP, 0.0)),
+            Command::NudgeRight => Some(Vector2F::new(NUDGE_STEP, 0.0)),
+            Command::NudgeUp => Some(Vector2F::new(0.0, -NUDGE_STEP)),
+            Command::NudgeDown => Some(Vector2F::new(0.0, NUDGE_STEP)),
+            _ => None,
+        };
+        if let Some(offset) = nudge {
+            self.nudge(offset, gui);
+            return;
+        }
+        self.end_nudges(gui);
+
+        match cmd {
+            Command::SelectHistory => {
+                gui.update_selection(|frame| frame.select_from_history());
+                self.mode = SelectionMode::History;
+            }
+            Command::Unselect => {
+                self.mode = SelectionMode::NoSelection;
+                gui.update_selection(|frame| frame.unselect());
+            }
+            Command::DeleteSelected => {
+                self.mode = SelectionMode::NoSelection;
+                gui.update_selection(|frame| frame.delete_selected());
+            }
+            Command::ClosedPolygon | Command::OpenPolygon => {
+                self.mode = SelectionMode::NoSelection;
+                let mut pr = gui.project().write();
+                let (brush, tolerance) = (pr.current_brush_index(), pr.endpoint_tolerance());
+                pr.current_frame_mut().polygonify_selected_items(
+                    brush,
+                    false,
+                    false,
+                    cmd == Command::ClosedPolygon,
+                    tolerance,
+                );
+                mem::drop(pr);
+                gui.update_image();
+            }
+            Command::Union | Command::Intersection | Command::Difference => {
+                let op = match cmd {
+                    Command::Union => BooleanOp::Union,
+                    Command::Intersection => BooleanOp::Intersection,
+                    _ => BooleanOp::Difference,
+                };
+                self.mode = SelectionMode::NoSelection;
+                let res = gui
+                    .project()
                     .write()
                     .current_frame_mut()
-                    .select_from_history();
-                self.mode = SelectionMode::History;
+                    .boolean_selected(op);
+                if let Err(e) = res {
+                    eprintln!("Unable to combine polygons: {}", e);
+                }
                 gui.update_image();
             }
-            'u' => {
+            Command::ExplodePolygons => {
                 self.mode = SelectionMode::NoSelection;
-                gui.project().write().current_frame_mut().unselect();
+                gui.project()
+                    .write()
+                    .current_frame_mut()
+                    .explode_selected_polygons();
                 gui.update_image();
             }
-            'd' => {
+            Command::MakeSymbol => {
                 self.mode = SelectionMode::NoSelection;
-                gui.project().write().current_frame_mut().delete_selected();
+                if let Err(e) = gui.make_symbol() {
+                    eprintln!("Unable to make a symbol: {}", e);
+                }
+            }
+            Command::GroupSelected => {
+                if let Err(e) = gui.group_selected() {
+                    eprintln!("Unable to group: {}", e);
+                }
+            }
+            Command::UngroupSelected => {
+                gui.update_selection(|frame| frame.ungroup_selected());
+            }
+            Command::ToggleGroupSelect => {
+                self.group_select = !self.group_select;
+                println!(
+                    "Selecting {}",
+                    if self.group_select {
+                        "whole groups"
+                    } else {
+                        "single items"
+                    }
+                );
+            }
+            Command::MoveSelected => {
+                if let Err(e) = gui.move_selected() {
+                    eprintln!("Unable to move: {}", e);
+                }
+            }
+            Command::DuplicateSelected => {
+                gui.project()
+                    .write()
+                    .current_frame_mut()
+                    .duplicate_selected(Vector2F::splat(DUPLICATE_OFFSET));
                 gui.update_image();
             }
-            'p' => {
+            Command::BringToFront => {
+                gui.project()
+                    .write()
+                    .current_frame_mut()
+                    .bring_selected_to_front();
+                gui.update_image();
+            }
+            Command::AlignSelected => {
+                if let Err(e) = gui.align_selected() {
+                    eprintln!("Unable to align: {}", e);
+                }
+            }
+            Command::DistributeSelected => {
+                if let Err(e) = gui.distribute_selected() {
+                    eprintln!("Unable to distribute: {}", e);
+                }
+            }
+            Command::RotateSelected => {
+                if let Err(e) = gui.rotate_selected() {
+                    eprintln!("Unable to rotate: {}", e);
+                }
+            }
+            Command::OutlineStrokes => {
+                // expand the selected strokes into filled shapes
                 self.mode = SelectionMode::NoSelection;
                 let mut pr = gui.project().write();
-                let brush = pr.current_brush_index();
-                pr.current_frame_mut()
-                    .polygonify_selected_items(brush, false, false);
+                let palette = pr.brushes().to_vec();
+                pr.current_frame_mut().outline_selected_items(&palette);
                 mem::drop(pr);
                 gui.update_image();
             }
+            Command::Undo => {
+                if !gui.project().write().current_frame_mut().undo() {
+                    println!("Nothing to undo");
+                }
+                gui.update_image();
+            }
             _ => (),
         }
     }
 
     #[inline]
-    fn switch_in(&mut self, _gui: &Gui) {}
+    fn switch_in(&mut self, gui: &Gui) {
+        gui.set_cursor(Some("pointer"));
+    }
 
     #[inline]
-    fn switch_out(&mut self, _gui: &Gui) {
+    fn switch_out(&mut self, gui: &Gui) {
+        gui.set_cursor(None);
         self.mode = SelectionMode::NoSelection;
+        self.end_nudges(gui);
     }
 
     #[inline]
     fn mouse_press(&mut self, btn: u32, pt: Vector2F, gui: &Gui) {
+        self.end_nudges(gui);
         if btn == 1 {
             if self.mouse_click_alternator {
                 self.mode = SelectionMode::NearestPt;
-                gui.project()
-                    .write()
-                    .current_frame_mut()
-                    .select_closest_element(pt);
-                gui.update_image();
+                let group_select = self.group_select;
+                gui.update_selection(|frame| {
+                    frame.select_closest_element(pt);
+                    if group_select {
+                        frame.select_whole_groups();
+                    }
+                });
                 self.mouse_click_alternator = false;
             } else {
                 self.mouse_click_alternator = true;
@@ -0,0 +1,99 @@
+// GPLv3 License
+
+use crate::LoopMode;
+
+/// Steps through the frames of an animation, in the order its loop mode plays them.
+#[derive(Debug, Clone)]
+pub struct Playback {
+    sequence: Vec<usize>,
+    step: usize,
+    loop_mode: LoopMode,
+    // the frame playback started on, to go back to when it's stopped
+    start: usize,
+}
+
+impl Playback {
+    /// Start playing `frame_count` frames from the given frame.
+    #[inline]
+    pub fn new(loop_mode: LoopMode, frame_count: usize, start: usize) -> Self {
+        let sequence = loop_mode.sequence(frame_count.max(1));
+        let step = sequence.iter().position(|i| *i == start).unwrap_or(0);
+        Self {
+            sequence,
+            step,
+            loop_mode,
+            start,
+        }
+    }
+
+    /// The frame that's showing.
+    #[inline]
+    pub fn current(&self) -> usize {
+        self.sequence[self.step]
+    }
+
+    /// The frame playback started on.
+    #[inline]
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Move on to the next frame and return it, or return `None` if the animation only plays
+    /// once and this was the last frame.
+    pub fn advance(&mut self) -> Option<usize> {
+        if self.step + 1 < self.sequence.len() {
+            self.step += 1;
+        } else if self.loop_mode == LoopMode::Once {
+            return None;
+        } else {
+            self.step = 0;
+        }
+        Some(self.current())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the frames shown over the given number of steps, starting with the first one
+    fn played(mut playback: Playback, steps: usize) -> Vec<Option<usize>> {
+        let first = Some(playback.current());
+        std::iter::once(first)
+            .chain((0..steps).map(|_| playback.advance()))
+            .collect()
+    }
+
+    #[test]
+    fn looping_playback_goes_round() {
+        let playback = Playback::new(LoopMode::Loop, 3, 1);
+        assert_eq!(playback.start(), 1);
+        assert_eq!(
+            played(playback, 4),
+            [Some(1), Some(2), Some(0), Some(1), Some(2)]
+        );
+    }
+
+    #[test]
+    fn playing_once_stops_at_the_end() {
+        let playback = Playback::new(LoopMode::Once, 3, 0);
+        assert_eq!(played(playback, 3), [Some(0), Some(1), Some(2), None]);
+    }
+
+    #[test]
+    fn ping_pong_playback_bounces() {
+        let playback = Playback::new(LoopMode::PingPong, 3, 0);
+        assert_eq!(
+            played(playback, 5),
+            [Some(0), Some(1), Some(2), Some(1), Some(0), Some(1)]
+        );
+    }
+
+    #[test]
+    fn single_frames_play_in_place() {
+        let playback = Playback::new(LoopMode::Loop, 1, 0);
+        assert_eq!(played(playback, 2), [Some(0), Some(0), Some(0)]);
+        // a frame past the end starts from the beginning
+        assert_eq!(Playback::new(LoopMode::Loop, 2, 5).current(), 0);
+    }
+}
@@ -0,0 +1,127 @@
+// GPLv3 License
+
+use cairo::Context;
+use pathfinder_geometry::vector::Vector2F;
+use std::f32::consts::PI;
+
+/// Spokes and rings around a center that drawn points snap onto, for radial designs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PolarGrid {
+    pub center: Vector2F,
+    /// How many spokes go around the center, evenly spaced.
+    pub spokes: u32,
+    /// The distance between rings, or zero to snap to the spokes only.
+    pub ring_spacing: f32,
+}
+
+impl PolarGrid {
+    /// The distance of a point from the center, and its angle in radians clockwise from the
+    /// right of the center, since the canvas's y axis points down.
+    #[inline]
+    pub fn to_polar(&self, pt: Vector2F) -> (f32, f32) {
+        let rel = pt - self.center;
+        (rel.length(), rel.y().atan2(rel.x()))
+    }
+
+    /// The point a distance and angle from the center, as given by `to_polar`.
+    #[inline]
+    pub fn from_polar(&self, radius: f32, angle: f32) -> Vector2F {
+        self.center + Vector2F::new(angle.cos(), angle.sin()) * radius
+    }
+
+    /// Move a point onto the closest spoke, and the closest ring along it.
+    pub fn snap(&self, pt: Vector2F) -> Vector2F {
+        let (radius, angle) = self.to_polar(pt);
+        let angle = if self.spokes > 0 {
+            let step = 2.0 * PI / self.spokes as f32;
+            (angle / step).round() * step
+        } else {
+            angle
+        };
+        let radius = if self.ring_spacing > 0.0 {
+            (radius / self.ring_spacing).round() * self.ring_spacing
+        } else {
+            radius
+        };
+        self.from_polar(radius, angle)
+    }
+
+    /// Draw the spokes and rings out to the edges of the canvas. The context should be in
+    /// canvas coordinates.
+    pub fn draw(&self, width: u32, height: u32, zoom: f32, context: &Context) {
+        // far enough to reach every corner from anywhere on the canvas
+        let reach = (width as f32).hypot(height as f32) * 2.0;
+        let (cx, cy) = (self.center.x() as f64, self.center.y() as f64);
+
+        context.set_source_rgba(0.9, 0.4, 0.0, 0.5);
+        context.set_line_width(1.0 / zoom as f64);
+        (0..self.spokes).for_each(|i| {
+            let end = self.from_polar(reach, 2.0 * PI * i as f32 / self.spokes as f32);
+            context.move_to(cx, cy);
+            context.line_to(end.x() as f64, end.y() as f64);
+            context.stroke();
+        });
+
+        if self.ring_spacing > 0.0 {
+            let rings = (reach / self.ring_spacing) as u32;
+            (1..=rings).for_each(|i| {
+                let radius = (i as f32 * self.ring_spacing) as f64;
+                context.new_sub_path();
+                context.arc(cx, cy, radius, 0.0, 2.0 * std::f64::consts::PI);
+                context.stroke();
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> PolarGrid {
+        PolarGrid {
+            center: Vector2F::new(100.0, 50.0),
+            spokes: 8,
+            ring_spacing: 10.0,
+        }
+    }
+
+    fn close(a: Vector2F, b: Vector2F) -> bool {
+        (a - b).length() < 1e-3
+    }
+
+    #[test]
+    fn polar_points_land_on_the_canvas() {
+        let grid = grid();
+        // a quarter turn goes down the canvas, since y points down
+        assert!(close(
+            grid.from_polar(20.0, PI / 2.0),
+            Vector2F::new(100.0, 70.0)
+        ));
+        assert!(close(grid.from_polar(10.0, PI), Vector2F::new(90.0, 50.0)));
+
+        let (radius, angle) = grid.to_polar(Vector2F::new(103.0, 54.0));
+        assert!((radius - 5.0).abs() < 1e-4);
+        assert!(close(
+            grid.from_polar(radius, angle),
+            Vector2F::new(103.0, 54.0)
+        ));
+    }
+
+    #[test]
+    fn points_snap_to_spokes_and_rings() {
+        let grid = grid();
+        // just off the spoke pointing right, between two rings
+        let snapped = grid.snap(Vector2F::new(122.0, 51.0));
+        assert!(close(snapped, Vector2F::new(120.0, 50.0)));
+
+        // without rings, only the angle changes
+        let spokes_only = PolarGrid {
+            ring_spacing: 0.0,
+            ..grid
+        };
+        let snapped = spokes_only.snap(Vector2F::new(117.0, 51.0));
+        assert!((snapped.x() - 100.0 - 17.0f32.hypot(1.0)).abs() < 1e-3);
+        assert!((snapped.y() - 50.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,208 @@
+// GPLv3 License
+
+use super::{Command, Error, DEFAULT_ERROR, ENDPOINT_TOLERANCE};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// What shows through the transparent parts of the canvas. This is only for the screen, and
+/// exports stay transparent. The checkerboard is the default, since it can't be mistaken for
+/// white or any other color in the image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Background {
+    LightBlue,
+    White,
+    Checkerboard,
+    Custom([u8; 3]),
+}
+
+impl Default for Background {
+    #[inline]
+    fn default() -> Self {
+        Background::Checkerboard
+    }
+}
+
+impl Background {
+    /// The color filling the whole of the background, under the dark squares of a checkerboard.
+    #[inline]
+    pub fn base_color(self) -> [u8; 3] {
+        match self {
+            Background::LightBlue => [219, 252, 255],
+            Background::White | Background::Checkerboard => [255, 255, 255],
+            Background::Custom(color) => color,
+        }
+    }
+
+    /// The next of the built in backgrounds, for cycling through them.
+    #[inline]
+    pub fn next(self) -> Self {
+        match self {
+            Background::Checkerboard => Background::White,
+            Background::White => Background::LightBlue,
+            Background::LightBlue | Background::Custom(_) => Background::Checkerboard,
+        }
+    }
+}
+
+/// Preferences that apply to the application as a whole, rather than to any one project. Any
+/// setting missing from the file takes its default value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The error allowed when fitting curves to drawn points.
+    pub fit_error: f32,
+    /// How close two endpoints have to be to count as joined, in new projects.
+    pub endpoint_tolerance: f32,
+    /// Commands bound to something other than their default key.
+    pub keys: HashMap<Command, char>,
+    /// What to show behind the canvas.
+    pub background: Background,
+    /// Scroll the canvas along with the fingers on a touchpad, rather than the other way.
+    pub natural_scrolling: bool,
+}
+
+impl Default for Settings {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            fit_error: DEFAULT_ERROR,
+            endpoint_tolerance: ENDPOINT_TOLERANCE,
+            keys: HashMap::new(),
+            background: Background::default(),
+            natural_scrolling: false,
+        }
+    }
+}
+
+impl Settings {
+    /// The file the settings are kept in, if the platform has a place for it.
+    #[inline]
+    pub fn path() -> Option<PathBuf> {
+        ProjectDirs::from("com", "notaseagull", "archetype")
+            .map(|dirs| dirs.config_dir().join(SETTINGS_FILE))
+    }
+
+    /// Load the settings, falling back to the defaults if there aren't any yet or they can't
+    /// be read.
+    #[inline]
+    pub fn load() -> Self {
+        Self::path().map_or_else(Self::default, |path| Self::load_from(&path))
+    }
+
+    /// Load the settings from a particular file, falling back to the defaults like `load`.
+    pub fn load_from(path: &Path) -> Self {
+        if !path.exists() {
+            return Self::default();
+        }
+
+        let loaded = File::open(path)
+            .map_err(Error::from)
+            .and_then(|f| serde_json::from_reader(BufReader::new(f)).map_err(Error::deserialize));
+        match loaded {
+            Ok(settings) => settings,
+            Err(e) => {
+                eprintln!("Unable to load settings from {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Write the settings out to the settings file.
+    #[inline]
+    pub fn save(&self) -> Result<(), Error> {
+        match Self::path() {
+            Some(path) => self.save_to(&path),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the settings out to a particular file.
+    pub fn save_to(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let data = serde_json::to_vec_pretty(self).map_err(Error::serialize)?;
+        fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "archetype-settings-{}-{}.json",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = Settings {
+            fit_error: 2.5,
+            keys: vec![(Command::Save, 'w')].into_iter().collect(),
+            background: Background::Custom([10, 20, 30]),
+            natural_scrolling: true,
+            ..Default::default()
+        };
+
+        let path = temp_path("round-trip");
+        settings.save_to(&path).unwrap();
+        let loaded = Settings::load_from(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded, settings);
+    }
+
+    #[test]
+    fn missing_settings_are_defaults() {
+        assert_eq!(
+            Settings::load_from(&temp_path("missing")),
+            Settings::default()
+        );
+
+        // as are any left out of the file
+        let path = temp_path("partial");
+        fs::write(&path, r#"{ "fit_error": 3.0 }"#).unwrap();
+        let loaded = Settings::load_from(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded.fit_error, 3.0);
+        assert_eq!(loaded.background, Background::Checkerboard);
+    }
+
+    #[test]
+    fn backgrounds_pick_their_colors() {
+        assert_eq!(Background::LightBlue.base_color(), [219, 252, 255]);
+        assert_eq!(Background::White.base_color(), [255, 255, 255]);
+        assert_eq!(Background::Checkerboard.base_color(), [255, 255, 255]);
+        assert_eq!(Background::Custom([1, 2, 3]).base_color(), [1, 2, 3]);
+
+        // cycling goes through the built in ones, and leaves a custom color behind
+        let cycle: Vec<Background> = (0..3)
+            .scan(Background::Custom([1, 2, 3]), |bg, _| {
+                *bg = bg.next();
+                Some(*bg)
+            })
+            .collect();
+        assert_eq!(
+            cycle,
+            [
+                Background::Checkerboard,
+                Background::White,
+                Background::LightBlue
+            ]
+        );
+    }
+}
@@ -1,15 +1,397 @@
 // GPL v3.0
 
-use super::{Gui, GuiMode};
+use super::{Command, Gui, GuiMode, GuiModeType, TypedPoint, DUPLICATE_OFFSET, RECENT_COLORS};
+use crate::{Color, LocationInfo, StateDataLoc, StateDataType};
 
+use cairo::Context;
+use gdk::EventMask;
 use gtk::{
-    prelude::*, Application, ApplicationWindow, Box as GtkBox, DrawingArea, Inhibit, Orientation,
+    prelude::*, Application, ApplicationWindow, Box as GtkBox, Dialog, DialogFlags, DrawingArea,
+    Entry, Inhibit, Menu, MenuItem, Orientation, ResponseType, Separator, SeparatorMenuItem,
 };
 use pathfinder_geometry::vector::Vector2F;
+use std::mem;
+
+const SWATCH_SIZE: i32 = 24;
+const RECENT_SWATCH_SIZE: i32 = 16;
+
+const SWATCH_LOC: LocationInfo = LocationInfo {
+    x: 0,
+    y: 0,
+    width: 1,
+    height: 1,
+    stroke: 0.0,
+};
+
+// fill a swatch with its brush's color, outlining it if it's the current brush
+fn draw_swatch(gui: &Gui, index: usize, da: &DrawingArea, c: &Context) {
+    let pr = gui.project().read();
+    let brush = match pr.brush(index) {
+        Some(brush) => brush,
+        None => return,
+    };
+
+    let [r, g, b, a] = brush.color().parts(&SWATCH_LOC);
+    let a = a * brush.opacity();
+    let (width, height) = (
+        da.get_allocated_width() as f64,
+        da.get_allocated_height() as f64,
+    );
+
+    c.set_source_rgba(
+        r.into_inner() as f64,
+        g.into_inner() as f64,
+        b.into_inner() as f64,
+        a.into_inner() as f64,
+    );
+    c.rectangle(0.0, 0.0, width, height);
+    c.fill();
+
+    if pr.current_brush_index() == index {
+        c.set_source_rgb(0.0, 0.0, 1.0);
+        c.set_line_width(4.0);
+        c.rectangle(0.0, 0.0, width, height);
+        c.stroke();
+    }
+}
+
+// fill a swatch with one of the recent colors, or leave it empty if there aren't that many
+fn draw_recent_swatch(gui: &Gui, index: usize, da: &DrawingArea, c: &Context) {
+    let color = match gui.recent_colors().get(index) {
+        Some(color) => *color,
+        None => return,
+    };
+
+    let [r, g, b, _] = color.parts(&SWATCH_LOC);
+    c.set_source_rgb(
+        r.into_inner() as f64,
+        g.into_inner() as f64,
+        b.into_inner() as f64,
+    );
+    c.rectangle(
+        0.0,
+        0.0,
+        da.get_allocated_width() as f64,
+        da.get_allocated_height() as f64,
+    );
+    c.fill();
+}
+
+// add an item to a context menu that runs an action and then redraws
+fn menu_item<F: Fn(&Gui) + 'static>(menu: &Menu, label: &str, sensitive: bool, gui: &Gui, f: F) {
+    let item = MenuItem::with_label(label);
+    item.set_sensitive(sensitive);
+
+    let g = gui.clone();
+    item.connect_activate(move |_item| {
+        f(&g);
+        g.update_image();
+    });
+    menu.append(&item);
+}
+
+/// Show a menu of the actions that apply to the current mode and selection.
+fn show_context_menu(gui: &Gui, button: u32, time: u32) {
+    let menu = Menu::new();
+
+    let pr = gui.project().read();
+    let selection = pr.current_frame().selected();
+    let has_selection = !selection.is_empty();
+    let all_lines = has_selection
+        && selection
+            .iter()
+            .all(|StateDataLoc(ty, _)| matches!(ty, StateDataType::Line | StateDataType::Curve));
+    let any_polygons = selection
+        .iter()
+        .any(|StateDataLoc(ty, _)| *ty == StateDataType::Polygon);
+    let current_brush = pr.current_brush_index();
+    mem::drop(pr);
+
+    if gui.gui_mode().lock().kind() == GuiModeType::Buffered {
+        menu_item(&menu, "Convert buffered lines", true, gui, |g| {
+            let mut pr = g.project().write();
+            let brush = pr.current_brush_index();
+            pr.current_frame_mut().convert_buffered_lines(brush);
+        });
+        menu_item(&menu, "Drop buffered lines", true, gui, |g| {
+            g.project()
+                .write()
+                .current_frame_mut()
+                .drop_buffered_lines();
+        });
+        menu.append(&SeparatorMenuItem::new());
+    }
+
+    menu_item(&menu, "Delete", has_selection, gui, |g| {
+        g.project().write().current_frame_mut().delete_selected();
+    });
+    menu_item(&menu, "Duplicate", has_selection, gui, |g| {
+        g.project()
+            .write()
+            .current_frame_mut()
+            .duplicate_selected(Vector2F::splat(DUPLICATE_OFFSET));
+    });
+    menu_item(&menu, "Bring to front", has_selection, gui, |g| {
+        g.project()
+            .write()
+            .current_frame_mut()
+            .bring_selected_to_front();
+    });
+    menu_item(&menu, "Convert to polygon", all_lines, gui, |g| {
+        let mut pr = g.project().write();
+        let (brush, tolerance) = (pr.current_brush_index(), pr.endpoint_tolerance());
+        pr.current_frame_mut()
+            .polygonify_selected_items(brush, false, false, true, tolerance);
+    });
+    menu_item(&menu, "Convert to open path", all_lines, gui, |g| {
+        let mut pr = g.project().write();
+        let (brush, tolerance) = (pr.current_brush_index(), pr.endpoint_tolerance());
+        pr.current_frame_mut()
+            .polygonify_selected_items(brush, false, false, false, tolerance);
+    });
+    menu_item(&menu, "Convert to edges", any_polygons, gui, |g| {
+        g.project()
+            .write()
+            .current_frame_mut()
+            .explode_selected_polygons();
+    });
+    menu_item(&menu, "Mix colors", has_selection, gui, |g| {
+        if let Err(e) = g.project().write().mix_selected_colors() {
+            eprintln!("Unable to mix colors: {}", e);
+        }
+    });
+    menu_item(&menu, "Set color", has_selection, gui, |g| {
+        g.edit_selected_color();
+    });
+    menu_item(&menu, "Unselect", has_selection, gui, |g| {
+        g.project().write().current_frame_mut().unselect();
+    });
+    menu.append(&SeparatorMenuItem::new());
+    menu_item(&menu, "Edit brush color", true, gui, move |g| {
+        g.edit_brush_color(current_brush);
+    });
+
+    menu.show_all();
+    menu.popup_easy(button, time);
+}
+
+/// Fill the palette panel with one swatch per brush.
+pub fn build_palette(palette: &GtkBox, gui: &Gui) {
+    palette
+        .get_children()
+        .iter()
+        .for_each(|w| palette.remove(w));
+
+    let brush_count = gui.project().read().brush_count();
+    for i in 0..brush_count {
+        let swatch = DrawingArea::new();
+        swatch.set_size_request(SWATCH_SIZE, SWATCH_SIZE);
+        swatch.add_events(EventMask::BUTTON_PRESS_MASK);
+
+        let g = gui.clone();
+        swatch.connect_draw(move |da, c| {
+            draw_swatch(&g, i, da, c);
+            Inhibit(false)
+        });
+
+        // left click selects the brush, right click edits its color
+        let g = gui.clone();
+        swatch.connect_button_press_event(move |_da, evb| {
+            match evb.get_button() {
+                1 => g.select_brush(i),
+                3 => g.edit_brush_color(i),
+                _ => (),
+            }
+            Inhibit(true)
+        });
+
+        palette.pack_start(&swatch, false, false, 1);
+    }
+
+    // the recent colors go under the brushes, and clicking one gives it to the current brush
+    palette.pack_start(&Separator::new(Orientation::Horizontal), false, false, 4);
+    for i in 0..RECENT_COLORS {
+        let swatch = DrawingArea::new();
+        swatch.set_size_request(RECENT_SWATCH_SIZE, RECENT_SWATCH_SIZE);
+        swatch.add_events(EventMask::BUTTON_PRESS_MASK);
+
+        let g = gui.clone();
+        swatch.connect_draw(move |da, c| {
+            draw_recent_swatch(&g, i, da, c);
+            Inhibit(false)
+        });
+
+        let g = gui.clone();
+        swatch.connect_button_press_event(move |_da, evb| {
+            if evb.get_button() == 1 {
+                g.use_recent_color(i);
+            }
+            Inhibit(true)
+        });
+
+        palette.pack_start(&swatch, false, false, 1);
+    }
+
+    palette.show_all();
+}
+
+// ask for a line of text in a small popup over the window, or `None` if it's cancelled
+fn entry_popup(gui: &Gui, title: &str) -> Option<String> {
+    let dialog = Dialog::with_buttons(
+        Some(title),
+        Some(gui.main_window()),
+        DialogFlags::MODAL | DialogFlags::DESTROY_WITH_PARENT,
+        &[("Cancel", ResponseType::Cancel), ("OK", ResponseType::Ok)],
+    );
+    dialog.set_default_response(ResponseType::Ok);
+
+    // pressing enter in the entry is the same as OK
+    let entry = Entry::new();
+    entry.set_activates_default(true);
+    dialog.get_content_area().pack_start(&entry, true, true, 8);
+    dialog.show_all();
+
+    let response = dialog.run();
+    let text = entry.get_text().to_string();
+    dialog.close();
+    Some(text).filter(|_| response == ResponseType::Ok)
+}
+
+// run a command, either one that works anywhere or one for the current mode
+fn run_command(gui: &Gui, cmd: Command) {
+    match cmd {
+        Command::Save | Command::SaveAs => {
+            if let Err(e) = gui.save_project(cmd == Command::SaveAs) {
+                eprintln!("Unable to save file: {}", e);
+            }
+        }
+        Command::NewProject => {
+            if let Err(e) = gui.prompt_new_project() {
+                eprintln!("Unable to create project: {}", e);
+            }
+        }
+        Command::OpenProject => {
+            if let Err(e) = gui.prompt_open_project() {
+                gui.show_error(&format!("Unable to open project: {}", e));
+            }
+        }
+        Command::Export => {
+            if let Err(e) = gui.export_project() {
+                eprintln!("Unable to export file: {}", e);
+            }
+        }
+        Command::ResizeCanvas => {
+            if let Err(e) = gui.prompt_resize_canvas() {
+                eprintln!("Unable to resize canvas: {}", e);
+            }
+        }
+        Command::PlaceImage => {
+            if let Err(e) = gui.place_image() {
+                eprintln!("Unable to place image: {}", e);
+            }
+        }
+        Command::CycleSymmetry => gui.cycle_symmetry(),
+        Command::ClearGuides => gui.clear_guides(),
+        Command::TogglePolarGrid => {
+            if let Err(e) = gui.toggle_polar_grid() {
+                eprintln!("Unable to set up the polar grid: {}", e);
+            }
+        }
+        Command::ShowStats => println!("{}", gui.project().read().current_frame().stats()),
+        Command::PreviousFrame => gui.step_frame(false),
+        Command::NextFrame => gui.step_frame(true),
+        Command::DuplicateFrame => gui.duplicate_frame(),
+        Command::TweenFrames => {
+            if let Err(e) = gui.prompt_tween() {
+                eprintln!("Unable to fill in frames: {}", e);
+            }
+        }
+        Command::SetFrameDuration => {
+            if let Err(e) = gui.prompt_frame_duration() {
+                eprintln!("Unable to set the frame duration: {}", e);
+            }
+        }
+        Command::CycleLoopMode => gui.cycle_loop_mode(),
+        Command::ZoomToFit | Command::ZoomToSelection => {
+            if let Err(e) = gui.zoom_to_fit(cmd == Command::ZoomToSelection) {
+                eprintln!("Unable to zoom: {}", e);
+            }
+        }
+        Command::TogglePlayback => gui.toggle_playback(),
+        Command::StopPlayback => gui.stop_playback(),
+        Command::FlattenFrame | Command::UnflattenFrame => {
+            if let Err(e) = gui.flatten_frame(cmd == Command::UnflattenFrame) {
+                eprintln!("Unable to flatten frame: {}", e);
+            }
+        }
+        Command::CycleRecentColor => gui.cycle_recent_color(),
+        Command::RunScript => {
+            if let Err(e) = gui.run_script() {
+                eprintln!("Unable to run script: {}", e);
+            }
+        }
+        Command::CycleBackground => {
+            if let Err(e) = gui.cycle_background() {
+                eprintln!("Unable to save settings: {}", e);
+            }
+        }
+        Command::BlurImages | Command::AdjustBrightness | Command::InvertImages => {
+            if let Err(e) = gui.filter_images(cmd) {
+                eprintln!("Unable to filter images: {}", e);
+            }
+        }
+        Command::PlaceSymbol => {
+            if let Err(e) = gui.place_symbol() {
+                eprintln!("Unable to place symbol: {}", e);
+            }
+        }
+        Command::EditSymbol => {
+            if let Err(e) = gui.toggle_symbol_editing() {
+                eprintln!("Unable to edit symbol: {}", e);
+            }
+        }
+        Command::ToggleQuantized => gui.toggle_quantized(),
+        Command::DecreaseOpacity => gui.adjust_brush_opacity(-0.1),
+        Command::IncreaseOpacity => gui.adjust_brush_opacity(0.1),
+        Command::ToggleHardEdges => gui.toggle_hard_edges(),
+        Command::CycleBlendMode => gui.cycle_blend_mode(),
+        Command::SetJitter => {
+            if let Err(e) = gui.prompt_jitter() {
+                eprintln!("Unable to set jitter: {}", e);
+            }
+        }
+        Command::TogglePixelMode => gui.toggle_pixel_mode(),
+        Command::SwitchMode => {
+            // switch into switch mode
+            gui.store_gui_mode();
+            gui.drawing_area().queue_draw();
+            println!("Activated switch mode");
+        }
+        Command::SearchCommands => {
+            let query = crate::interactive_line("Search commands");
+            gui.keymap()
+                .read()
+                .search(&query)
+                .for_each(|(cmd, key)| println!("{}  {}", key.unwrap_or(' '), cmd.description()));
+        }
+        Command::TypePoint => {
+            let text = match entry_popup(gui, "Next point (x,y or @dx,dy)") {
+                Some(text) => text,
+                None => return,
+            };
+            match text.parse::<TypedPoint>() {
+                Ok(pt) => gui.gui_mode().lock().typed_point(pt, gui),
+                Err(e) => eprintln!("Unable to read point: {}", e),
+            }
+        }
+        cmd => gui.gui_mode().lock().command(cmd, gui),
+    }
+}
 
 pub fn build_ui(application: &Application, gui: Gui) {
     let window = ApplicationWindow::new(application);
-    let gtk_box = GtkBox::new(Orientation::Vertical, 1);
+    let gtk_box = GtkBox::new(Orientation::Horizontal, 1);
+    let palette = GtkBox::new(Orientation::Vertical, 1);
     let (width, height) = gui.dimensions();
     //    let drawing_area = DrawingAreaBuilder::new()
     //        .halign(Align::Start)
@@ -33,13 +415,22 @@ pub fn build_ui(application: &Application, gui: Gui) {
     let g2 = gui.clone();
     window.connect_button_press_event(move |_da, evb| {
         let gc = g2.clone();
+        if gc.is_playing() {
+            return Inhibit(true);
+        }
+
         let (x, y) = match evb.get_position() {
             (a, b) => (a as f32, b as f32),
         };
+        let pt = gc.to_canvas(Vector2F::new(x, y));
 
-        gc.gui_mode()
-            .lock()
-            .mouse_press(evb.get_button(), Vector2F::new(x, y), &gc);
+        // right click opens the context menu
+        if evb.get_button() == 3 {
+            show_context_menu(&gc, evb.get_button(), evb.get_time());
+            return Inhibit(true);
+        }
+
+        gc.gui_mode().lock().mouse_press(evb.get_button(), pt, &gc);
 
         Inhibit(false)
     });
@@ -51,7 +442,8 @@ pub fn build_ui(application: &Application, gui: Gui) {
         let (x, y) = match evm.get_position() {
             (a, b) => (a as f32, b as f32),
         };
-        gc.gui_mode().lock().mouse_move(Vector2F::new(x, y), &gc);
+        let pt = gc.to_canvas(Vector2F::new(x, y));
+        gc.gui_mode().lock().mouse_move(pt, &gc);
 
         Inhibit(false)
     });
@@ -61,37 +453,76 @@ pub fn build_ui(application: &Application, gui: Gui) {
         let (x, y) = match evb.get_position() {
             (a, b) => (a as f32, b as f32),
         };
+        let pt = gc.to_canvas(Vector2F::new(x, y));
         gc.gui_mode()
             .lock()
-            .mouse_release(evb.get_button(), Vector2F::new(x, y), &gc);
+            .mouse_release(evb.get_button(), pt, &gc);
 
         Inhibit(false)
     });
+    let g6 = gui.clone();
+    window.add_events(EventMask::SCROLL_MASK | EventMask::SMOOTH_SCROLL_MASK);
+    window.connect_scroll_event(move |_w, evs| {
+        use gdk::{ModifierType, ScrollDirection};
+
+        // how far one notch of the wheel moves the canvas
+        const SCROLL_STEP: f32 = 32.0;
+        const ZOOM_STEP: f32 = 1.1;
+
+        let gc = g6.clone();
+        let (dx, dy) = match evs.get_direction() {
+            ScrollDirection::Up => (0.0, -1.0),
+            ScrollDirection::Down => (0.0, 1.0),
+            ScrollDirection::Left => (-1.0, 0.0),
+            ScrollDirection::Right => (1.0, 0.0),
+            ScrollDirection::Smooth => {
+                let (dx, dy) = evs.get_delta();
+                (dx as f32, dy as f32)
+            }
+            _ => (0.0, 0.0),
+        };
+        let (x, y) = evs.get_position();
+        let (x, y) = (x as f32, y as f32);
+        let state = evs.get_state();
+
+        if state.contains(ModifierType::CONTROL_MASK) {
+            // scrolling up zooms in
+            gc.zoom_at(ZOOM_STEP.powf(-dy), Vector2F::new(x, y));
+        } else if state.contains(ModifierType::SHIFT_MASK) {
+            gc.scroll_view(Vector2F::new(dy, dx) * SCROLL_STEP);
+        } else {
+            gc.scroll_view(Vector2F::new(dx, dy) * SCROLL_STEP);
+        }
+
+        Inhibit(true)
+    });
+
     let g5 = gui.clone();
     window.connect_key_press_event(move |_w, evk| {
-        use gdk::ModifierType;
+        use gdk::{keys, ModifierType};
 
         let gc = g5.clone();
-        match evk.get_keyval().to_unicode() {
-            Some('s') if gc.gui_mode().lock().kind() != super::GuiModeType::Switching => {
-                if let Err(e) = gc.save_project(
-                    evk.get_state() & ModifierType::SHIFT_MASK != ModifierType::empty(),
-                ) {
-                    eprintln!("Unable to save file: {}", e);
-                }
-            }
-            Some('e') => {
-                if let Err(e) = gc.export_project() {
-                    eprintln!("Unable to export file: {}", e);
-                }
-            }
-            Some('m') => {
-                // switch into switch mode
-                gc.store_gui_mode();
-                println!("Activated switch mode");
-            }
-            Some(c) => gc.gui_mode().lock().key_press(c, &gc),
-            _ => (),
+
+        // tab cycles through the brushes, instead of moving the focus
+        let keyval = evk.get_keyval();
+        if keyval == keys::constants::Tab || keyval == keys::constants::ISO_Left_Tab {
+            let shift = evk.get_state().contains(ModifierType::SHIFT_MASK);
+            gc.cycle_brush(keyval == keys::constants::Tab && !shift);
+            return Inhibit(true);
+        }
+
+        let mode = gc.gui_mode().lock().kind();
+        let cmd = keyval
+            .to_unicode()
+            .and_then(|c| gc.keymap().read().command(mode, c));
+        if let Some(cmd) = cmd {
+            // the frames can't be touched while they're playing
+            let controls_playback = cmd == Command::TogglePlayback || cmd == Command::StopPlayback;
+            if gc.is_playing() && !controls_playback {
+                println!("Pause the animation to do that");
+            } else {
+                run_command(&gc, cmd);
+            }
         }
         Inhibit(false)
     });
@@ -99,6 +530,9 @@ pub fn build_ui(application: &Application, gui: Gui) {
     window.set_default_size(width as i32, height as i32);
     window.set_resizable(false);
     gtk_box.pack_start(&*dr, true, true, 1);
+    build_palette(&palette, &gui);
+    gtk_box.pack_start(&palette, false, false, 1);
+    gui.set_palette(palette);
     window.add(&gtk_box);
     window.show_all();
     gui.set_main_window(window);
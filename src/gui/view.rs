@@ -0,0 +1,154 @@
+// GPL v3.0
+
+use euclid::default::{Point2D, Rect, Size2D};
+use pathfinder_geometry::vector::Vector2F;
+
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 32.0;
+// space left around whatever is zoomed to fit, in drawing area pixels
+const FIT_MARGIN: f32 = 16.0;
+
+/// How the canvas is zoomed and panned within the drawing area.
+#[derive(Copy, Clone)]
+pub struct View {
+    zoom: f32,
+    pan: Vector2F,
+    // scroll the content along with the fingers, rather than moving the viewport
+    natural_scrolling: bool,
+}
+
+impl Default for View {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            pan: Vector2F::zero(),
+            natural_scrolling: false,
+        }
+    }
+}
+
+impl View {
+    #[inline]
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    #[inline]
+    pub fn pan(&self) -> Vector2F {
+        self.pan
+    }
+
+    #[inline]
+    pub fn natural_scrolling(&self) -> bool {
+        self.natural_scrolling
+    }
+
+    #[inline]
+    pub fn set_natural_scrolling(&mut self, natural: bool) {
+        self.natural_scrolling = natural;
+    }
+
+    /// Convert a point in the drawing area to a point on the canvas.
+    #[inline]
+    pub fn to_canvas(&self, pt: Vector2F) -> Vector2F {
+        (pt - self.pan) * (1.0 / self.zoom)
+    }
+
+    /// The part of the canvas that shows up in a drawing area of the given size.
+    #[inline]
+    pub fn visible_rect(&self, width: f32, height: f32) -> Rect<f32> {
+        let origin = self.to_canvas(Vector2F::zero());
+        Rect::new(
+            Point2D::new(origin.x(), origin.y()),
+            Size2D::new(width / self.zoom, height / self.zoom),
+        )
+    }
+
+    /// Multiply the zoom by a factor, keeping the canvas point under `anchor` in place.
+    #[inline]
+    pub fn zoom_at(&mut self, factor: f32, anchor: Vector2F) {
+        let canvas_pt = self.to_canvas(anchor);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan = anchor - (canvas_pt * self.zoom);
+    }
+
+    /// Zoom and pan so that a part of the canvas fills a drawing area of the given size, with a
+    /// margin around it, and sits in the middle.
+    pub fn fit(&mut self, rect: Rect<f32>, width: f32, height: f32) {
+        let (room_x, room_y) = (
+            (width - 2.0 * FIT_MARGIN).max(1.0),
+            (height - 2.0 * FIT_MARGIN).max(1.0),
+        );
+        let zoom = (room_x / rect.size.width.max(1.0)).min(room_y / rect.size.height.max(1.0));
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+
+        let center = rect.center();
+        self.pan =
+            Vector2F::new(width, height) * 0.5 - Vector2F::new(center.x, center.y) * self.zoom;
+    }
+
+    /// Move the canvas by a scroll delta, in drawing area pixels.
+    #[inline]
+    pub fn scroll(&mut self, delta: Vector2F) {
+        if self.natural_scrolling {
+            self.pan += delta;
+        } else {
+            self.pan -= delta;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrolling_follows_the_setting() {
+        let mut view = View::default();
+        view.scroll(Vector2F::new(3.0, -2.0));
+        assert_eq!(view.pan(), Vector2F::new(-3.0, 2.0));
+
+        let mut view = View::default();
+        view.set_natural_scrolling(true);
+        view.scroll(Vector2F::new(3.0, -2.0));
+        assert_eq!(view.pan(), Vector2F::new(3.0, -2.0));
+    }
+
+    #[test]
+    fn zooming_keeps_the_anchor_in_place() {
+        let mut view = View::default();
+        let anchor = Vector2F::new(40.0, 30.0);
+        let before = view.to_canvas(anchor);
+        view.zoom_at(2.0, anchor);
+        assert_eq!(view.zoom(), 2.0);
+        assert_eq!(view.to_canvas(anchor), before);
+
+        view.zoom_at(1000.0, anchor);
+        assert_eq!(view.zoom(), MAX_ZOOM);
+        view.zoom_at(0.0, anchor);
+        assert_eq!(view.zoom(), MIN_ZOOM);
+    }
+
+    #[test]
+    fn fitting_fills_the_area_around_the_content() {
+        let mut view = View::default();
+        let content = Rect::new(Point2D::new(10.0, 20.0), Size2D::new(100.0, 50.0));
+        let size = 200.0 + 2.0 * FIT_MARGIN;
+
+        // the wider side sets the zoom, and the middle of the content is in the middle
+        view.fit(content, size, size);
+        assert_eq!(view.zoom(), 2.0);
+        let middle = Vector2F::new(size / 2.0, size / 2.0);
+        assert_eq!(view.to_canvas(middle), Vector2F::new(60.0, 45.0));
+        assert_eq!(
+            view.to_canvas(Vector2F::new(FIT_MARGIN, FIT_MARGIN)).x(),
+            10.0
+        );
+
+        // a single point doesn't zoom in forever
+        let point = Rect::new(Point2D::new(5.0, 5.0), Size2D::zero());
+        view.fit(point, size, size);
+        assert_eq!(view.zoom(), MAX_ZOOM);
+    }
+}
@@ -1,6 +1,6 @@
 // MIT License
 
-use super::{DynamicColor, SolidColor};
+use super::{DynamicColor, Error, SolidColor};
 use smallvec::SmallVec;
 use std::io::{self, prelude::*};
 
@@ -70,3 +70,152 @@ pub fn interactive_color(color_name: &str) -> DynamicColor {
         }
     }
 }
+
+/// Prompt the user for the dimensions of a project.
+pub fn interactive_dimensions() -> Result<(u32, u32), &'static str> {
+    let mut width = String::new();
+    let mut height = String::new();
+
+    let so = io::stdout();
+    let mut stdout = so.lock();
+    let si = io::stdin();
+    let mut stdin = si.lock();
+
+    stdout
+        .write_all(b"Enter the width of the project: ")
+        .map_err(|_e| "Unable to write to stdout")?;
+    stdout.flush().map_err(|_e| "Unable to write to stdout")?;
+    stdin
+        .read_line(&mut width)
+        .map_err(|_e| "Unable to get width")?;
+    stdout
+        .write_all(b"Enter the height of the project: ")
+        .map_err(|_e| "Unable to write to stdout")?;
+    stdout.flush().map_err(|_e| "Unable to write to stdout")?;
+    stdin
+        .read_line(&mut height)
+        .map_err(|_e| "Unable to get height")?;
+
+    Ok((
+        width.trim().parse().map_err(|_e| "Width is not a number")?,
+        height
+            .trim()
+            .parse()
+            .map_err(|_e| "Height is not a number")?,
+    ))
+}
+
+/// Prompt for the name of a file. Running out of input cancels, and a blank name is an error.
+pub fn prompt_filename<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    prompt: &str,
+) -> Result<String, Error> {
+    output.write_fmt(format_args!("{}: ", prompt))?;
+    output.flush()?;
+
+    let mut filename = String::new();
+    if input.read_line(&mut filename)? == 0 {
+        return Err(Error::Cancelled);
+    }
+
+    match filename.trim_end_matches(&['\r', '\n'][..]) {
+        "" => Err(Error::InvalidInput("no file name was given")),
+        name => Ok(name.to_string()),
+    }
+}
+
+/// Prompt the user for a line of text.
+pub fn interactive_line(prompt: &str) -> String {
+    let mut stdout = io::stdout();
+    stdout.write_fmt(format_args!("{}: ", prompt)).unwrap();
+    stdout.flush().unwrap();
+
+    let mut res = String::new();
+    io::stdin().read_line(&mut res).unwrap();
+    res.trim().to_string()
+}
+
+/// Prompt for a whole number, asking again until one is entered. Running out of input cancels.
+pub fn prompt_number<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    prompt: &str,
+) -> Result<u32, Error> {
+    loop {
+        output.write_fmt(format_args!("Enter {}: ", prompt))?;
+        output.flush()?;
+
+        let mut res = String::new();
+        if input.read_line(&mut res)? == 0 {
+            return Err(Error::Cancelled);
+        }
+        if let Ok(n) = res.trim().parse() {
+            return Ok(n);
+        }
+    }
+}
+
+/// Prompt the user for a whole number, asking again until they enter one.
+#[inline]
+pub fn interactive_number(prompt: &str) -> Result<u32, Error> {
+    prompt_number(&mut io::stdin().lock(), &mut io::stdout().lock(), prompt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt(input: &str) -> Result<String, Error> {
+        let mut output = Vec::new();
+        let res = prompt_filename(&mut input.as_bytes(), &mut output, "Enter image filename");
+        assert_eq!(output, b"Enter image filename: ");
+        res
+    }
+
+    #[test]
+    fn filenames_are_read() {
+        assert_eq!(prompt("cat.png\n").unwrap(), "cat.png");
+        assert_eq!(prompt("dog.jpg\r\n").unwrap(), "dog.jpg");
+        // a name without a newline at the end of the input is kept whole
+        assert_eq!(prompt("bird.png").unwrap(), "bird.png");
+        assert_eq!(prompt(" spaced name.png\n").unwrap(), " spaced name.png");
+    }
+
+    #[test]
+    fn missing_filenames_are_errors() {
+        assert!(matches!(prompt(""), Err(Error::Cancelled)));
+        assert!(matches!(prompt("\n"), Err(Error::InvalidInput(_))));
+    }
+
+    #[test]
+    fn numbers_are_asked_for_again() {
+        let mut output = Vec::new();
+        let mut input: &[u8] = b"ten\n-3\n 12 \n";
+        assert_eq!(
+            prompt_number(&mut input, &mut output, "a number").unwrap(),
+            12
+        );
+        assert_eq!(output, b"Enter a number: ".repeat(3));
+    }
+
+    #[test]
+    fn numbers_are_cancelled_at_the_end_of_input() {
+        let mut output = Vec::new();
+        let mut input: &[u8] = b"ten\n";
+        assert!(matches!(
+            prompt_number(&mut input, &mut output, "a number"),
+            Err(Error::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn unreadable_input_is_an_error() {
+        let mut output = Vec::new();
+        let mut input: &[u8] = &[0xff, 0xfe, b'\n'];
+        assert!(matches!(
+            prompt_filename(&mut input, &mut output, "Enter image filename"),
+            Err(Error::Io(_))
+        ));
+    }
+}
@@ -8,32 +8,40 @@
 mod bezier;
 mod brush;
 mod drawing;
+mod error;
+mod filters;
 mod geometry;
 mod gui;
 mod interactive;
+mod parallel;
 mod polygon;
+mod quantize;
 mod render;
+mod script;
+#[cfg(test)]
+mod snapshots;
 mod state;
+mod version;
 
 pub use bezier::*;
 pub use brush::*;
 pub use drawing::*;
+pub use error::*;
+pub use filters::*;
 pub use geometry::*;
 pub use gui::*;
 pub use interactive::*;
+pub use parallel::*;
 pub use polygon::*;
+pub use quantize::*;
 pub use render::*;
+pub use script::*;
 pub use state::*;
+pub use version::*;
 
 use image::{ImageBuffer, Rgba};
 use parking_lot::RwLock;
-use std::{
-    env,
-    fs::File,
-    io::{self, prelude::*},
-    mem, thread,
-    time::Duration,
-};
+use std::{env, process};
 
 #[cfg(feature = "true_color")]
 pub type ColorAtom = u16;
@@ -42,11 +50,15 @@ pub type ColorAtom = u8;
 
 /// A true-color RGBA image.
 pub type TCImage = ImageBuffer<Rgba<ColorAtom>, Vec<ColorAtom>>;
-/// The locked image buffer.
+/// The locked image buffer. The flag is set by anything that draws on the image, and cleared
+/// once the changes have been copied to the screen, so it must be set along with every write.
 pub type DrawTarget = RwLock<(TCImage, bool)>;
 
 // spawns a quick deadlock detector
+#[cfg(feature = "deadlock_detection")]
 fn deadlock_detector() {
+    use std::{thread, time::Duration};
+
     thread::spawn(move || loop {
         thread::sleep(Duration::from_secs(10));
 
@@ -66,63 +78,287 @@ fn deadlock_detector() {
     });
 }
 
+const USAGE: &str = "Usage:
+    archetype                               Create a new project, prompting for its size
+    archetype PROJECT                       Open an existing project, or a PNG exported with it
+    archetype new WIDTHxHEIGHT              Create a new project of the given size
+    archetype --width WIDTH --height HEIGHT Create a new project of the given size
+    archetype export PROJECT IMAGE          Render a project to a PNG image without opening it
+              [--frame N]                   Render frame N, counting from 0, instead of the
+                                            frame the project was saved on
+              [--seed N]                    Seed random effects like jitter with N, so that
+                                            every export comes out the same
+    archetype run SCRIPT                    Draw into a new project from a script of commands,
+                                            one per line:
+                                              canvas WIDTH HEIGHT
+                                              brush #RRGGBB WIDTH
+                                              line X1 Y1 X2 Y2
+                                              curve X0 Y0 X1 Y1 X2 Y2 X3 Y3
+                                              frame
+                                              export IMAGE
+    archetype script SCRIPT                 Draw into a new project with a rhai script, then
+                                            open it
+
+Environment:
+    ARCHETYPE_THREADS=N                     Draw with N threads, or 1 to draw on one thread
+    ARCHETYPE_PARALLEL_THRESHOLD=N          Only split drawing across threads when there are at
+                                            least N objects of a kind to draw (default 32)";
+
+/// What the command line asked us to do.
+#[derive(Debug, PartialEq)]
+enum Invocation {
+    Prompt,
+    New(u32, u32),
+    Open(String),
+    Export {
+        project: String,
+        image: String,
+        frame: Option<usize>,
+        seed: Option<u64>,
+    },
+    Run(String),
+    Script(String),
+}
+
+// parse a "WIDTHxHEIGHT" pair
+#[inline]
+fn parse_dimensions(dims: &str) -> Result<(u32, u32), &'static str> {
+    let mut parts = dims.splitn(2, ['x', 'X']);
+    let width = parts
+        .next()
+        .ok_or("Dimensions must be of the form WIDTHxHEIGHT")?;
+    let height = parts
+        .next()
+        .ok_or("Dimensions must be of the form WIDTHxHEIGHT")?;
+
+    Ok((
+        width.parse().map_err(|_e| "Width is not a number")?,
+        height.parse().map_err(|_e| "Height is not a number")?,
+    ))
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Invocation, &'static str> {
+    let first = match args.next() {
+        None => return Ok(Invocation::Prompt),
+        Some(first) => first,
+    };
+
+    let command = match first.as_str() {
+        "new" => {
+            let dims = args.next().ok_or("Expected dimensions after \"new\"")?;
+            let (width, height) = parse_dimensions(&dims)?;
+            Invocation::New(width, height)
+        }
+        "--width" | "--height" => {
+            let mut width = None;
+            let mut height = None;
+            let mut flag = Some(first);
+
+            while let Some(f) = flag.take() {
+                let value = args
+                    .next()
+                    .ok_or("Expected a number after the dimension flag")?;
+                match f.as_str() {
+                    "--width" => width = Some(value.parse().map_err(|_e| "Width is not a number")?),
+                    "--height" => {
+                        height = Some(value.parse().map_err(|_e| "Height is not a number")?)
+                    }
+                    _ => return Err("Unrecognized argument"),
+                }
+                flag = args.next();
+            }
+
+            match (width, height) {
+                (Some(width), Some(height)) => Invocation::New(width, height),
+                _ => return Err("Both --width and --height must be specified"),
+            }
+        }
+        "export" => {
+            let project = args.next().ok_or("Expected a project to export")?;
+            let image = args.next().ok_or("Expected an image to export to")?;
+            let mut frame = None;
+            let mut seed = None;
+            while let Some(flag) = args.next() {
+                match flag.as_str() {
+                    "--frame" => {
+                        let value = args.next().ok_or("Expected a frame after --frame")?;
+                        frame = Some(value.parse().map_err(|_e| "Frame is not a number")?);
+                    }
+                    "--seed" => {
+                        let value = args.next().ok_or("Expected a seed after --seed")?;
+                        seed = Some(value.parse().map_err(|_e| "Seed is not a number")?);
+                    }
+                    _ => return Err("Unrecognized argument"),
+                }
+            }
+            Invocation::Export {
+                project,
+                image,
+                frame,
+                seed,
+            }
+        }
+        "run" => Invocation::Run(args.next().ok_or("Expected a script to run")?),
+        "script" => Invocation::Script(args.next().ok_or("Expected a script to run")?),
+        _ => Invocation::Open(first),
+    };
+
+    if args.next().is_some() {
+        Err("Too many arguments")
+    } else {
+        Ok(command)
+    }
+}
+
+// render a project straight to an image, without the gui
+fn export_headless(
+    project: &str,
+    image: &str,
+    frame: Option<usize>,
+    seed: Option<u64>,
+) -> Result<(), Error> {
+    let mut project = gui::Project::load_from_path(project)?;
+    if seed.is_some() {
+        project.set_seed(seed);
+    }
+    let options = ExportOptions {
+        frame,
+        ..Default::default()
+    };
+    render(
+        &project,
+        image,
+        RenderTarget::SingleImage,
+        AlphaMaskTarget::Background(colors::BLACK.into()),
+        &options,
+        &mut no_progress,
+    )
+}
+
 fn main() {
+    #[cfg(feature = "deadlock_detection")]
     deadlock_detector();
+    configure_threads();
 
-    let gui = match env::args().nth(1) {
-        None => {
-            let mut width = String::new();
-            let mut height = String::new();
-
-            let so = io::stdout();
-            let mut stdout = so.lock();
-            let si = io::stdin();
-            let mut stdin = si.lock();
-
-            stdout
-                .write_all(b"Enter the width of the project: ")
-                .unwrap();
-            stdout.flush().unwrap();
-            stdin.read_line(&mut width).expect("Unable to get width");
-            stdout
-                .write_all(b"Enter the height of the project: ")
-                .unwrap();
-            stdout.flush().unwrap();
-            stdin.read_line(&mut height).expect("Unable to get height");
-
-            mem::drop(stdin);
-            mem::drop(stdout);
-
-            width.pop();
-            height.pop();
-
-            let width = width.parse::<u32>().expect("Width is not a number");
-            let height = height.parse::<u32>().expect("Height is not a number");
-
-            gui::Gui::new_project(width, height)
+    let command = match parse_args(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(1);
         }
-        Some(prj_name) => {
-            // try to deserialize with bincode
-            let mut file = File::open(&prj_name)
-                .unwrap_or_else(|_| panic!("Unable to open file \"{}\"", prj_name));
-            let mut bytes = Vec::new();
-            file.read_to_end(&mut bytes)
-                .expect("Unable to read from file");
-            mem::drop(file);
-
-            let project: gui::Project = match bincode::deserialize(&bytes) {
-                Ok(prj) => prj,
-                Err(_e) => {
-                    // if it error'd out, try deserializing from JSON
-                    let string =
-                        String::from_utf8(bytes).expect("Unable to convert bytes to string");
-                    serde_json::from_str(&string)
-                        .expect("Unable to deserialize from bincode or json")
-                }
-            };
+    };
 
-            gui::Gui::new(project)
+    let gui = match command {
+        Invocation::Export {
+            project,
+            image,
+            frame,
+            seed,
+        } => {
+            if let Err(e) = export_headless(&project, &image, frame, seed) {
+                eprintln!("Unable to export \"{}\": {}", project, e);
+                process::exit(1);
+            }
+            return;
+        }
+        Invocation::Run(script) => {
+            if let Err(e) = run_script_file(&script) {
+                eprintln!("Unable to run \"{}\": {}", script, e);
+                process::exit(1);
+            }
+            return;
         }
+        Invocation::Script(script) => match run_rhai_file(&script) {
+            Ok(project) => gui::Gui::new(project),
+            Err(e) => {
+                eprintln!("Unable to run \"{}\": {}", script, e);
+                process::exit(1);
+            }
+        },
+        Invocation::New(width, height) => gui::Gui::new_project(width, height),
+        Invocation::Prompt => match interactive_dimensions() {
+            Ok((width, height)) => gui::Gui::new_project(width, height),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        Invocation::Open(prj_name) => match gui::Project::load_from_path(&prj_name) {
+            Ok(project) => gui::Gui::new(project),
+            Err(e) => {
+                eprintln!("Unable to open \"{}\": {}", prj_name, e);
+                process::exit(1);
+            }
+        },
     };
     gui.run();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Invocation, &'static str> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn dimensions_skip_the_prompt() {
+        assert_eq!(parse(&[]), Ok(Invocation::Prompt));
+        assert_eq!(parse(&["new", "640x480"]), Ok(Invocation::New(640, 480)));
+        assert_eq!(parse(&["new", "32X16"]), Ok(Invocation::New(32, 16)));
+        assert_eq!(
+            parse(&["--width", "640", "--height", "480"]),
+            Ok(Invocation::New(640, 480))
+        );
+        assert_eq!(
+            parse(&["--height", "480", "--width", "640"]),
+            Ok(Invocation::New(640, 480))
+        );
+    }
+
+    #[test]
+    fn other_invocations() {
+        assert_eq!(
+            parse(&["art.arch"]),
+            Ok(Invocation::Open("art.arch".to_string()))
+        );
+        assert_eq!(
+            parse(&["export", "art.arch", "art.png", "--frame", "2"]),
+            Ok(Invocation::Export {
+                project: "art.arch".to_string(),
+                image: "art.png".to_string(),
+                frame: Some(2),
+                seed: None,
+            })
+        );
+        assert_eq!(
+            parse(&["export", "art.arch", "art.png", "--seed", "7", "--frame", "0"]),
+            Ok(Invocation::Export {
+                project: "art.arch".to_string(),
+                image: "art.png".to_string(),
+                frame: Some(0),
+                seed: Some(7),
+            })
+        );
+    }
+
+    #[test]
+    fn bad_arguments_are_errors() {
+        assert!(parse_dimensions("640").is_err());
+        assert!(parse_dimensions("640xtall").is_err());
+        assert!(parse_dimensions("-1x480").is_err());
+        assert_eq!(parse_dimensions("640x480"), Ok((640, 480)));
+
+        assert!(parse(&["new"]).is_err());
+        assert!(parse(&["new", "big"]).is_err());
+        assert!(parse(&["--width", "640"]).is_err());
+        assert!(parse(&["--width", "wide", "--height", "480"]).is_err());
+        assert!(parse(&["--width", "640", "--depth", "480"]).is_err());
+        assert!(parse(&["export", "art.arch"]).is_err());
+        assert!(parse(&["export", "art.arch", "art.png", "--frame", "last"]).is_err());
+        assert!(parse(&["export", "art.arch", "art.png", "--seed", "-1"]).is_err());
+        assert!(parse(&["export", "art.arch", "art.png", "--seed"]).is_err());
+        assert!(parse(&["art.arch", "more.arch"]).is_err());
+    }
+}
@@ -0,0 +1,55 @@
+// GPLv3 License
+
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use std::env;
+
+/// The fewest items that are worth spreading across threads. Short jobs spend more time
+/// handing out work than doing it, so they run on the calling thread instead.
+pub const DEFAULT_PARALLEL_THRESHOLD: usize = 32;
+
+// set ARCHETYPE_PARALLEL_THRESHOLD to change where the cutoff is
+static PARALLEL_THRESHOLD: Lazy<usize> =
+    Lazy::new(|| env_number("ARCHETYPE_PARALLEL_THRESHOLD").unwrap_or(DEFAULT_PARALLEL_THRESHOLD));
+
+#[inline]
+fn env_number(name: &str) -> Option<usize> {
+    env::var(name).ok()?.trim().parse().ok()
+}
+
+/// Size the thread pool from `ARCHETYPE_THREADS`, if it's set. Setting it to 1 runs everything
+/// on a single thread, which makes the order things happen in the same on every run. This has
+/// to be called before anything else uses the pool.
+pub fn configure_threads() {
+    if let Some(threads) = env_number("ARCHETYPE_THREADS") {
+        if let Err(e) = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+        {
+            eprintln!("Unable to set the number of threads: {}", e);
+        }
+    }
+}
+
+/// Tell whether a job with this many items should be spread across threads.
+#[inline]
+pub fn worth_parallelizing(items: usize) -> bool {
+    items >= *PARALLEL_THRESHOLD && rayon::current_num_threads() > 1
+}
+
+/// Run a function on every item in a collection, across threads only if there are enough of
+/// them to be worth it.
+#[inline]
+pub fn for_each_item<'a, C, T, F>(items: &'a C, count: usize, f: F)
+where
+    C: ?Sized,
+    &'a C: IntoIterator<Item = T> + IntoParallelIterator<Item = T>,
+    T: Send,
+    F: Fn(T) + Send + Sync,
+{
+    if worth_parallelizing(count) {
+        items.into_par_iter().for_each(f);
+    } else {
+        items.into_iter().for_each(f);
+    }
+}
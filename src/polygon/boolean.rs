@@ -0,0 +1,337 @@
+// GPLv3 License
+
+//! Boolean operations between polygons, using the Greiner-Hormann clipping algorithm on their
+//! flattened outlines.
+
+use pathfinder_geometry::vector::Vector2F;
+
+/// A boolean operation between two polygons.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BooleanOp {
+    /// The area covered by either polygon.
+    Union,
+    /// The area covered by both polygons.
+    Intersection,
+    /// The area covered by the first polygon but not the second.
+    Difference,
+}
+
+// points closer than this are treated as touching
+const EPSILON: f32 = 1.0e-4;
+// how far a point is nudged when it lies right on the other outline
+const NUDGE: f32 = 1.0e-2;
+
+/// A closed outline, as a list of its corners.
+pub type Ring = Vec<Vector2F>;
+
+struct Node {
+    point: Vector2F,
+    // the index of the intersection this node is, if it is one
+    intersection: Option<usize>,
+    entry: bool,
+}
+
+// even-odd test for whether a point is inside a ring
+fn contains(ring: &[Vector2F], pt: Vector2F) -> bool {
+    let mut inside = false;
+    let mut j = ring.len() - 1;
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[j]);
+        if (a.y() > pt.y()) != (b.y() > pt.y())
+            && pt.x() < (b.x() - a.x()) * (pt.y() - a.y()) / (b.y() - a.y()) + a.x()
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+#[inline]
+fn distance_to_segment(pt: Vector2F, a: Vector2F, b: Vector2F) -> f32 {
+    let seg = b - a;
+    let len2 = seg.square_length();
+    let t = if len2 == 0.0 {
+        0.0
+    } else {
+        ((pt - a).dot(seg) / len2).clamp(0.0, 1.0)
+    };
+    (pt - (a + seg * t)).length()
+}
+
+// move any corner lying on the other outline slightly off of it, so that every intersection is
+// a proper crossing
+fn perturb(ring: &mut [Vector2F], other: &[Vector2F]) {
+    let len = other.len();
+    ring.iter_mut().for_each(|pt| {
+        while (0..len).any(|i| distance_to_segment(*pt, other[i], other[(i + 1) % len]) < EPSILON) {
+            *pt += Vector2F::new(NUDGE, NUDGE * 0.7);
+        }
+    });
+}
+
+// the parameters along both segments where they cross, if they do
+#[inline]
+fn crossing(p1: Vector2F, p2: Vector2F, q1: Vector2F, q2: Vector2F) -> Option<(f32, f32)> {
+    let (r, s) = (p2 - p1, q2 - q1);
+    let denom = r.det(s);
+    if denom.abs() < EPSILON * EPSILON {
+        return None;
+    }
+
+    let diff = q1 - p1;
+    let (t, u) = (diff.det(s) / denom, diff.det(r) / denom);
+    if t > 0.0 && t < 1.0 && u > 0.0 && u < 1.0 {
+        Some((t, u))
+    } else {
+        None
+    }
+}
+
+// build the list of nodes for one ring, with the intersections inserted along each edge
+fn build_nodes(
+    ring: &[Vector2F],
+    crossings: &[(usize, f32, Vector2F)],
+    index_of: &mut [usize],
+) -> Vec<Node> {
+    let mut nodes = Vec::with_capacity(ring.len() + crossings.len());
+    for (i, pt) in ring.iter().enumerate() {
+        nodes.push(Node {
+            point: *pt,
+            intersection: None,
+            entry: false,
+        });
+
+        let mut on_edge: Vec<(usize, f32, Vector2F)> = crossings
+            .iter()
+            .enumerate()
+            .filter(|(_k, (edge, _t, _pt))| *edge == i)
+            .map(|(k, (_edge, t, pt))| (k, *t, *pt))
+            .collect();
+        on_edge.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        on_edge.into_iter().for_each(|(k, _t, point)| {
+            index_of[k] = nodes.len();
+            nodes.push(Node {
+                point,
+                intersection: Some(k),
+                entry: false,
+            });
+        });
+    }
+    nodes
+}
+
+// mark each intersection by whether following the ring forward enters the other ring there
+fn mark_entries(nodes: &mut [Node], other: &[Vector2F], invert: bool) {
+    let mut inside = contains(other, nodes[0].point);
+    nodes
+        .iter_mut()
+        .filter(|n| n.intersection.is_some())
+        .for_each(|n| {
+            n.entry = inside == invert;
+            inside = !inside;
+        });
+}
+
+/// Combine two outlines, returning the outlines of the result. The outlines are filled with
+/// the even-odd rule, so holes come out as separate rings.
+pub fn combine(subject: &[Vector2F], clip: &[Vector2F], op: BooleanOp) -> Vec<Ring> {
+    if subject.len() < 3 || clip.len() < 3 {
+        return match op {
+            BooleanOp::Union if subject.len() >= 3 => vec![subject.to_vec()],
+            BooleanOp::Union if clip.len() >= 3 => vec![clip.to_vec()],
+            BooleanOp::Difference if subject.len() >= 3 => vec![subject.to_vec()],
+            _ => vec![],
+        };
+    }
+
+    let mut subject = subject.to_vec();
+    let mut clip = clip.to_vec();
+    perturb(&mut subject, &clip);
+    perturb(&mut clip, &subject);
+
+    // find every crossing, remembering which edge of each ring it lies on
+    let (slen, clen) = (subject.len(), clip.len());
+    let mut s_crossings = vec![];
+    let mut c_crossings = vec![];
+    for i in 0..slen {
+        for j in 0..clen {
+            let (p1, p2) = (subject[i], subject[(i + 1) % slen]);
+            let (q1, q2) = (clip[j], clip[(j + 1) % clen]);
+            if let Some((t, u)) = crossing(p1, p2, q1, q2) {
+                let point = p1 + (p2 - p1) * t;
+                s_crossings.push((i, t, point));
+                c_crossings.push((j, u, point));
+            }
+        }
+    }
+
+    // with no crossings, the rings are either nested or apart
+    if s_crossings.is_empty() {
+        let subject_in_clip = contains(&clip, subject[0]);
+        let clip_in_subject = contains(&subject, clip[0]);
+
+        return match op {
+            BooleanOp::Union if subject_in_clip => vec![clip],
+            BooleanOp::Union if clip_in_subject => vec![subject],
+            BooleanOp::Union => vec![subject, clip],
+            BooleanOp::Intersection if subject_in_clip => vec![subject],
+            BooleanOp::Intersection if clip_in_subject => vec![clip],
+            BooleanOp::Intersection => vec![],
+            BooleanOp::Difference if subject_in_clip => vec![],
+            BooleanOp::Difference if clip_in_subject => vec![subject, clip],
+            BooleanOp::Difference => vec![subject],
+        };
+    }
+
+    let count = s_crossings.len();
+    let mut s_index = vec![0; count];
+    let mut c_index = vec![0; count];
+    let mut s_nodes = build_nodes(&subject, &s_crossings, &mut s_index);
+    let mut c_nodes = build_nodes(&clip, &c_crossings, &mut c_index);
+
+    // flipping the markings makes the walk follow the outside of the other ring instead
+    let (invert_subject, invert_clip) = match op {
+        BooleanOp::Union => (true, true),
+        BooleanOp::Intersection => (false, false),
+        BooleanOp::Difference => (true, false),
+    };
+    mark_entries(&mut s_nodes, &clip, invert_subject);
+    mark_entries(&mut c_nodes, &subject, invert_clip);
+
+    // walk from each unvisited intersection, switching rings at every intersection
+    let mut visited = vec![false; count];
+    let mut rings = vec![];
+    for start in 0..count {
+        if visited[start] {
+            continue;
+        }
+
+        let mut ring = vec![];
+        let (mut on_subject, mut idx) = (true, s_index[start]);
+        loop {
+            let nodes = if on_subject { &s_nodes } else { &c_nodes };
+            let k = nodes[idx].intersection.unwrap();
+            if visited[k] {
+                break;
+            }
+            visited[k] = true;
+            ring.push(nodes[idx].point);
+
+            let (forward, len) = (nodes[idx].entry, nodes.len());
+            loop {
+                idx = if forward {
+                    (idx + 1) % len
+                } else {
+                    (idx + len - 1) % len
+                };
+                match nodes[idx].intersection {
+                    Some(_) => break,
+                    None => ring.push(nodes[idx].point),
+                }
+            }
+
+            let k = nodes[idx].intersection.unwrap();
+            on_subject = !on_subject;
+            idx = if on_subject { s_index[k] } else { c_index[k] };
+        }
+
+        if ring.len() >= 3 {
+            rings.push(ring);
+        }
+    }
+
+    rings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[inline]
+    fn rect(x0: f32, y0: f32, x1: f32, y1: f32) -> Ring {
+        vec![
+            Vector2F::new(x0, y0),
+            Vector2F::new(x1, y0),
+            Vector2F::new(x1, y1),
+            Vector2F::new(x0, y1),
+        ]
+    }
+
+    // the corners of a ring, rounded and sorted so that rings can be compared wherever they
+    // start and whichever way they wind
+    fn corners(ring: &[Vector2F]) -> Vec<(i32, i32)> {
+        let mut corners: Vec<(i32, i32)> = ring
+            .iter()
+            .map(|p| (p.x().round() as i32, p.y().round() as i32))
+            .collect();
+        corners.sort_unstable();
+        corners
+    }
+
+    fn overlapping(op: BooleanOp) -> Vec<Ring> {
+        combine(&rect(0.0, 0.0, 10.0, 10.0), &rect(5.0, 5.0, 15.0, 15.0), op)
+    }
+
+    #[test]
+    fn intersection_of_overlapping_rectangles() {
+        let rings = overlapping(BooleanOp::Intersection);
+        assert_eq!(rings.len(), 1);
+        assert_eq!(corners(&rings[0]), vec![(5, 5), (5, 10), (10, 5), (10, 10)]);
+    }
+
+    #[test]
+    fn union_of_overlapping_rectangles() {
+        let rings = overlapping(BooleanOp::Union);
+        assert_eq!(rings.len(), 1);
+        assert_eq!(
+            corners(&rings[0]),
+            vec![
+                (0, 0),
+                (0, 10),
+                (5, 10),
+                (5, 15),
+                (10, 0),
+                (10, 5),
+                (15, 5),
+                (15, 15)
+            ]
+        );
+    }
+
+    #[test]
+    fn difference_of_overlapping_rectangles() {
+        let rings = overlapping(BooleanOp::Difference);
+        assert_eq!(rings.len(), 1);
+        assert_eq!(
+            corners(&rings[0]),
+            vec![(0, 0), (0, 10), (5, 5), (5, 10), (10, 0), (10, 5)]
+        );
+    }
+
+    #[test]
+    fn disjoint_rectangles() {
+        let (a, b) = (rect(0.0, 0.0, 5.0, 5.0), rect(10.0, 10.0, 15.0, 15.0));
+        assert!(combine(&a, &b, BooleanOp::Intersection).is_empty());
+        assert_eq!(combine(&a, &b, BooleanOp::Union).len(), 2);
+
+        let difference = combine(&a, &b, BooleanOp::Difference);
+        assert_eq!(difference.len(), 1);
+        assert_eq!(corners(&difference[0]), corners(&a));
+    }
+
+    #[test]
+    fn nested_difference_leaves_a_hole() {
+        let (outer, inner) = (rect(0.0, 0.0, 20.0, 20.0), rect(5.0, 5.0, 15.0, 15.0));
+        let rings = combine(&outer, &inner, BooleanOp::Difference);
+        assert_eq!(rings.len(), 2);
+        assert_eq!(
+            combine(&outer, &inner, BooleanOp::Intersection)
+                .iter()
+                .map(|r| corners(r))
+                .collect::<Vec<_>>(),
+            vec![corners(&inner)]
+        );
+    }
+}
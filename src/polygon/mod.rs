@@ -0,0 +1,618 @@
+// GPLv3 License
+
+use super::{
+    draw_batched, rasterize_polyline_into, rasterize_thin_line_into, BezierCurve, Brush, ColorAtom,
+    DrawTarget, Error, Line, LocationInfo, PixelBatch, PixelSink, Point, Rasterizable,
+};
+use crate::quantize::PackedCoords;
+use euclid::default::Point2D;
+use pathfinder_geometry::{
+    line_segment::LineSegment2F, transform2d::Transform2F, vector::Vector2F,
+};
+use rayon::prelude::*;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use smallvec::SmallVec;
+
+mod boolean;
+pub use boolean::{BooleanOp, Ring};
+
+/// Serializable form of polygon edge.
+#[derive(Deserialize, Serialize)]
+enum PolygonEdgeSerde {
+    Straight(Point2D<f32>, Point2D<f32>),
+    Curved(BezierCurve),
+    // straight edges in binary formats, added last so older files still load
+    PackedStraight(PackedCoords),
+}
+
+/// A polygon's edge.
+#[derive(Clone)]
+pub enum PolygonEdge {
+    Straight(LineSegment2F),
+    Curved(BezierCurve),
+}
+
+impl PolygonEdge {
+    /// The points where this edge starts and ends.
+    #[inline]
+    pub fn endpoints(&self) -> [Vector2F; 2] {
+        match self {
+            Self::Straight(ref l) => [l.from(), l.to()],
+            Self::Curved(ref bz) => [bz.points()[0], bz.points()[3]],
+        }
+    }
+
+    #[inline]
+    pub fn is_straight(&self) -> bool {
+        if let Self::Straight(_) = self {
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline]
+    fn into_serde(&self, human_readable: bool) -> PolygonEdgeSerde {
+        match self {
+            Self::Straight(ref l) if !human_readable => {
+                PolygonEdgeSerde::PackedStraight(PackedCoords::pack(&[
+                    l.from_x(),
+                    l.from_y(),
+                    l.to_x(),
+                    l.to_y(),
+                ]))
+            }
+            Self::Straight(ref l) => PolygonEdgeSerde::Straight(
+                Point2D::new(l.from_x(), l.from_y()),
+                Point2D::new(l.to_x(), l.to_y()),
+            ),
+            Self::Curved(ref c) => PolygonEdgeSerde::Curved(c.clone()),
+        }
+    }
+}
+
+impl Serialize for PolygonEdge {
+    #[inline]
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        Serialize::serialize(&self.into_serde(ser.is_human_readable()), ser)
+    }
+}
+
+impl<'de> Deserialize<'de> for PolygonEdge {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        let repr: PolygonEdgeSerde = Deserialize::deserialize(de)?;
+        Ok(match repr {
+            PolygonEdgeSerde::Straight(
+                Point2D { x: x1, y: y1, .. },
+                Point2D { x: x2, y: y2, .. },
+            ) => Self::Straight(LineSegment2F::new(
+                Vector2F::new(x1, y1),
+                Vector2F::new(x2, y2),
+            )),
+            PolygonEdgeSerde::Curved(c) => Self::Curved(c),
+            PolygonEdgeSerde::PackedStraight(packed) => match packed.unpack()[..] {
+                [x1, y1, x2, y2] => Self::Straight(LineSegment2F::new(
+                    Vector2F::new(x1, y1),
+                    Vector2F::new(x2, y2),
+                )),
+                ref coords => return Err(D::Error::invalid_length(coords.len(), &"4 coordinates")),
+            },
+        })
+    }
+}
+
+impl From<LineSegment2F> for PolygonEdge {
+    #[inline]
+    fn from(ls: LineSegment2F) -> PolygonEdge {
+        Self::Straight(ls)
+    }
+}
+
+impl<Dim> From<[euclid::Point2D<f32, Dim>; 2]> for PolygonEdge {
+    #[inline]
+    fn from(pts: [euclid::Point2D<f32, Dim>; 2]) -> Self {
+        Self::Straight(LineSegment2F::new(
+            pts.from::<Vector2F>(),
+            pts.to::<Vector2F>(),
+        ))
+    }
+}
+
+impl From<BezierCurve> for PolygonEdge {
+    #[inline]
+    fn from(bz: BezierCurve) -> PolygonEdge {
+        Self::Curved(bz)
+    }
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub enum PolygonType {
+    Fill,
+    Outline,
+    /// An outline whose last edge is not connected back to the first.
+    Open,
+}
+
+/// A filled polygon.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Polygon {
+    edges: SmallVec<[PolygonEdge; 6]>,
+    mode: PolygonType,
+}
+
+impl Polygon {
+    #[inline]
+    pub fn new<PE, I: IntoIterator<Item = PE>>(iter: I, mode: PolygonType) -> Self
+    where
+        PE: Into<PolygonEdge>,
+    {
+        Self {
+            edges: iter.into_iter().map(|pe| pe.into()).collect(),
+            mode,
+        }
+    }
+
+    /// Build a filled polygon covering a path stroked `width` wide, with round caps at either
+    /// end. Returns `None` if there are no points.
+    pub fn from_stroke(points: &[Vector2F], width: f32) -> Option<Self> {
+        const CAP_SEGMENTS: usize = 8;
+        // don't let sharp corners shoot the miter out too far
+        const MITER_LIMIT: f32 = 4.0;
+
+        let mut points: SmallVec<[Vector2F; 12]> = points.iter().copied().collect();
+        points.dedup();
+        let last = points.len().checked_sub(1)?;
+        let half = width / 2.0;
+
+        // the direction of each segment, and the normal pointing to its left
+        let dirs: SmallVec<[Vector2F; 12]> = if last == 0 {
+            SmallVec::from_elem(Vector2F::new(1.0, 0.0), 1)
+        } else {
+            points
+                .windows(2)
+                .map(|w| (w[1] - w[0]).normalize())
+                .collect()
+        };
+        let normal = |d: Vector2F| Vector2F::new(-d.y(), d.x());
+
+        // offset each point along the average of the normals of the segments meeting there
+        let offsets: SmallVec<[Vector2F; 12]> = (0..=last)
+            .map(|i| {
+                let before = normal(dirs[i.saturating_sub(1)]);
+                let after = normal(dirs[i.min(dirs.len() - 1)]);
+                let avg = (before + after) * 0.5;
+                let len = avg.length();
+
+                if len < 1.0e-6 {
+                    before * half
+                } else {
+                    avg * (half / (len * len)).min(half * MITER_LIMIT / len)
+                }
+            })
+            .collect();
+
+        // half-circle around an end of the stroke, leaving from `n` and sweeping through `d`
+        let cap = |center: Vector2F, n: Vector2F, d: Vector2F| {
+            (1..CAP_SEGMENTS).map(move |i| {
+                let theta = std::f32::consts::PI * i as f32 / CAP_SEGMENTS as f32;
+                center + (n * theta.cos() + d * theta.sin()) * half
+            })
+        };
+
+        let (first_dir, last_dir) = (dirs[0], dirs[dirs.len() - 1]);
+        let ring: Vec<Vector2F> = points
+            .iter()
+            .zip(offsets.iter())
+            .map(|(pt, off)| *pt + *off)
+            .chain(cap(points[last], normal(last_dir), last_dir))
+            .chain(
+                points
+                    .iter()
+                    .zip(offsets.iter())
+                    .rev()
+                    .map(|(pt, off)| *pt - *off),
+            )
+            .chain(cap(points[0], normal(first_dir) * -1.0, first_dir * -1.0))
+            .collect();
+
+        Some(Self::new(
+            ring.iter()
+                .zip(ring.iter().cycle().skip(1))
+                .map(|(from, to)| LineSegment2F::new(*from, *to)),
+            PolygonType::Fill,
+        ))
+    }
+
+    #[inline]
+    pub fn edges(&self) -> &[PolygonEdge] {
+        &self.edges
+    }
+
+    /// The area inside this polygon, flattening curves to within `tolerance`. Outlines that
+    /// wind the other way to the rest, like holes, take away from it.
+    pub fn area(&self, tolerance: f32) -> f32 {
+        let signed: f32 = self
+            .rings(tolerance)
+            .iter()
+            .map(|ring| {
+                ring.iter()
+                    .zip(ring.iter().cycle().skip(1))
+                    .map(|(a, b)| a.x() * b.y() - b.x() * a.y())
+                    .sum::<f32>()
+                    / 2.0
+            })
+            .sum();
+        signed.abs()
+    }
+
+    /// Split the flattened edges of this polygon into closed outlines, starting a new outline
+    /// wherever an edge doesn't start where the last one ended.
+    pub fn rings(&self, tolerance: f32) -> SmallVec<[Ring; 1]> {
+        const GAP: f32 = 1.0e-3;
+
+        let mut rings: SmallVec<[Ring; 1]> = SmallVec::new();
+        let mut last_end: Option<Vector2F> = None;
+        self.as_straight_edges(tolerance).for_each(|l| {
+            let (from, to): (Vector2F, Vector2F) = (l.from(), l.to());
+            match (last_end, rings.last_mut()) {
+                (Some(end), Some(ring)) if (end - from).length() < GAP => ring.push(from),
+                _ => rings.push(vec![from]),
+            }
+            last_end = Some(to);
+        });
+
+        rings.retain(|r| r.len() >= 3);
+        rings
+    }
+
+    /// Combine this polygon with another one. Returns `Ok(None)` if nothing is left over, and an
+    /// error if either polygon has more than one outline.
+    pub fn boolean(
+        &self,
+        other: &Polygon,
+        op: BooleanOp,
+        tolerance: f32,
+    ) -> Result<Option<Polygon>, Error> {
+        let (subject, clip) = (self.rings(tolerance), other.rings(tolerance));
+        if subject.len() > 1 || clip.len() > 1 {
+            return Err(Error::InvalidInput(
+                "boolean operations only support polygons with a single outline",
+            ));
+        }
+
+        let empty = Ring::new();
+        let rings = boolean::combine(
+            subject.first().unwrap_or(&empty),
+            clip.first().unwrap_or(&empty),
+            op,
+        );
+        if rings.is_empty() {
+            return Ok(None);
+        }
+
+        // every ring goes into the one polygon, the even-odd fill takes care of holes
+        let edges = rings.iter().flat_map(|ring| {
+            ring.iter()
+                .zip(ring.iter().cycle().skip(1))
+                .map(|(from, to)| LineSegment2F::new(*from, *to))
+        });
+        Ok(Some(Self::new(edges, PolygonType::Fill)))
+    }
+
+    #[inline]
+    pub fn union(&self, other: &Polygon, tolerance: f32) -> Result<Option<Polygon>, Error> {
+        self.boolean(other, BooleanOp::Union, tolerance)
+    }
+
+    #[inline]
+    pub fn intersection(&self, other: &Polygon, tolerance: f32) -> Result<Option<Polygon>, Error> {
+        self.boolean(other, BooleanOp::Intersection, tolerance)
+    }
+
+    #[inline]
+    pub fn difference(&self, other: &Polygon, tolerance: f32) -> Result<Option<Polygon>, Error> {
+        self.boolean(other, BooleanOp::Difference, tolerance)
+    }
+
+    #[inline]
+    pub fn mode(&self) -> PolygonType {
+        self.mode
+    }
+
+    /// Move every edge of this polygon by an offset.
+    #[inline]
+    pub fn translate(&mut self, offset: Vector2F) {
+        self.edges.iter_mut().for_each(|e| match e {
+            PolygonEdge::Straight(ref mut l) => {
+                *l = LineSegment2F::new(l.from() + offset, l.to() + offset)
+            }
+            PolygonEdge::Curved(ref mut bz) => bz.translate(offset),
+        });
+    }
+
+    /// Apply an affine transform to every edge of this polygon.
+    #[inline]
+    pub fn transform(&mut self, transform: &Transform2F) {
+        self.edges.iter_mut().for_each(|e| match e {
+            PolygonEdge::Straight(ref mut l) => *l = *transform * *l,
+            PolygonEdge::Curved(ref mut bz) => bz.transform(transform),
+        });
+    }
+
+    /// Move each of the points defining this polygon's edges.
+    #[inline]
+    pub fn map_points<F: FnMut(Vector2F) -> Vector2F>(&mut self, mut f: F) {
+        self.edges.iter_mut().for_each(|e| match e {
+            PolygonEdge::Straight(ref mut l) => *l = LineSegment2F::new(f(l.from()), f(l.to())),
+            PolygonEdge::Curved(ref mut bz) => bz.points_mut().iter_mut().for_each(|p| *p = f(*p)),
+        });
+    }
+
+    // the flattened edges in the order they are joined, so strokes run along the outline
+    #[inline]
+    fn outline_segments(&self, tolerance: f32) -> Vec<LineSegment2F> {
+        let mut segments = Vec::with_capacity(self.edges.len());
+        self.edges.iter().for_each(|e| match e {
+            PolygonEdge::Straight(l) => segments.push(*l),
+            PolygonEdge::Curved(bz) => segments.extend(bz.edges_with_tolerance(tolerance)),
+        });
+        segments
+    }
+
+    #[inline]
+    pub fn as_straight_edges(&self, tolerance: f32) -> impl Iterator<Item = LineSegment2F> + '_ {
+        self.edges
+            .iter()
+            .filter(|s| s.is_straight())
+            .map(|s| {
+                if let PolygonEdge::Straight(ref l) = s {
+                    l.clone()
+                } else {
+                    unreachable!()
+                }
+            })
+            .chain(
+                self.edges
+                    .iter()
+                    .filter(|s| !s.is_straight())
+                    .map(|s| {
+                        if let PolygonEdge::Curved(ref bz) = s {
+                            bz
+                        } else {
+                            unreachable!()
+                        }
+                    })
+                    .flat_map(move |bz| bz.edges_with_tolerance(tolerance)),
+            )
+    }
+
+    #[inline]
+    fn fill(&self, target: &DrawTarget, brush: &Brush, tolerance: f32) {
+        // each task draws its share of the spans into a batch of its own, and then the batches
+        // are copied onto the target all at once, in order
+        let (width, height) = target.read().0.dimensions();
+        if !brush.hard_edges() {
+            let batches: Vec<PixelBatch> = self
+                .coverage_rows(tolerance, width, height)
+                .par_iter()
+                .fold(
+                    || PixelBatch::new(width, height),
+                    |mut batch, row| {
+                        composite_coverage_row(&mut batch, row, brush);
+                        batch
+                    },
+                )
+                .collect();
+
+            let mut img = target.write();
+            batches.iter().for_each(|batch| batch.apply(&mut img.0));
+            img.1 = true;
+            return;
+        }
+
+        let batches: Vec<PixelBatch> = self
+            .scanline_spans(tolerance)
+            .par_iter()
+            .fold(
+                || PixelBatch::new(width, height),
+                |mut batch, span| {
+                    rasterize_thin_line_into(&mut batch, span, brush);
+                    batch
+                },
+            )
+            .collect();
+
+        let mut img = target.write();
+        batches.iter().for_each(|batch| batch.apply(&mut img.0));
+        img.1 = true;
+    }
+
+    #[inline]
+    fn fill_into(&self, img: &mut dyn PixelSink, brush: &Brush, tolerance: f32) {
+        if !brush.hard_edges() {
+            let (width, height) = img.dimensions();
+            self.coverage_rows(tolerance, width, height)
+                .iter()
+                .for_each(|row| composite_coverage_row(img, row, brush));
+            return;
+        }
+
+        self.scanline_spans(tolerance)
+            .iter()
+            .for_each(|span| rasterize_thin_line_into(img, span, brush));
+    }
+
+    // how much of each pixel the filled polygon covers, for drawing it with smooth edges
+    // each row of pixels is sampled along a few lines, and each line adds the exact fraction of
+    // every pixel its spans cross, so edges get partial coverage both across and down
+    fn coverage_rows(&self, tolerance: f32, width: u32, height: u32) -> Vec<CoverageRow> {
+        const SUBSAMPLES: usize = 4;
+
+        let edges: Vec<LineSegment2F> = self.as_straight_edges(tolerance).collect();
+        let (min, max) = edges.iter().fold(
+            (
+                Vector2F::splat(f32::INFINITY),
+                Vector2F::splat(f32::NEG_INFINITY),
+            ),
+            |(min, max), l| (min.min(l.from()).min(l.to()), max.max(l.from()).max(l.to())),
+        );
+        if edges.is_empty() || !(max.x() > 0.0 && max.y() > 0.0) {
+            return Vec::new();
+        }
+
+        let first_col = min.x().floor().max(0.0) as u32;
+        let last_col = (max.x().ceil() as u32).min(width);
+        let first_row = min.y().floor().max(0.0) as u32;
+        let last_row = (max.y().ceil() as u32).min(height);
+        if first_col >= last_col {
+            return Vec::new();
+        }
+
+        (first_row..last_row)
+            .into_par_iter()
+            .filter_map(|y| {
+                let mut coverage = vec![0.0f32; (last_col - first_col) as usize];
+                (0..SUBSAMPLES).for_each(|s| {
+                    let line_y = y as f32 + (s as f32 + 0.5) / SUBSAMPLES as f32;
+                    crossings(&edges, line_y).chunks_exact(2).for_each(|span| {
+                        add_span_coverage(
+                            &mut coverage,
+                            span[0] - first_col as f32,
+                            span[1] - first_col as f32,
+                            1.0 / SUBSAMPLES as f32,
+                        )
+                    });
+                });
+
+                if coverage.iter().any(|c| *c > 0.0) {
+                    Some(CoverageRow {
+                        y,
+                        first_col,
+                        coverage,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    // the horizontal spans that make up the filled polygon, one through the center of each row
+    // of pixels
+    fn scanline_spans(&self, tolerance: f32) -> Vec<((f32, f32), (f32, f32))> {
+        let edges: Vec<LineSegment2F> = self.as_straight_edges(tolerance).collect();
+        let (min_y, max_y) =
+            edges
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), l| {
+                    (
+                        min.min(l.from_y()).min(l.to_y()),
+                        max.max(l.from_y()).max(l.to_y()),
+                    )
+                });
+        if edges.is_empty() || max_y < 0.0 {
+            return Vec::new();
+        }
+
+        (min_y.floor().max(0.0) as u32..max_y.ceil() as u32)
+            .into_par_iter()
+            .flat_map(|y| {
+                let row = y as f32;
+                crossings(&edges, row + 0.5)
+                    .chunks_exact(2)
+                    .map(|span| ((span[0], row), (span[1], row)))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+// where a horizontal line crosses the edges of a polygon, from left to right
+#[inline]
+fn crossings(edges: &[LineSegment2F], line_y: f32) -> SmallVec<[f32; 8]> {
+    let mut crossings: SmallVec<[f32; 8]> = edges
+        .iter()
+        .filter(|l| (l.from_y() <= line_y) != (l.to_y() <= line_y))
+        .map(|l| {
+            let t = (line_y - l.from_y()) / (l.to_y() - l.from_y());
+            l.from_x() + t * (l.to_x() - l.from_x())
+        })
+        .collect();
+    crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    crossings
+}
+
+// how much of each pixel in a row, starting from a column, a filled polygon covers
+struct CoverageRow {
+    y: u32,
+    first_col: u32,
+    coverage: Vec<f32>,
+}
+
+// add how much of each pixel a span from x0 to x1 covers, along a line of the row
+#[inline]
+fn add_span_coverage(row: &mut [f32], x0: f32, x1: f32, weight: f32) {
+    let (x0, x1) = (x0.max(0.0), x1.min(row.len() as f32));
+    if x1 <= x0 {
+        return;
+    }
+
+    let last = (x1.ceil() as usize).min(row.len());
+    (x0.floor() as usize..last).for_each(|px| {
+        let covered = (px as f32 + 1.0).min(x1) - (px as f32).max(x0);
+        row[px] += covered.max(0.0) * weight;
+    });
+}
+
+// draw a row of a filled polygon, fading out the pixels it only partly covers
+fn composite_coverage_row<S: PixelSink + ?Sized>(img: &mut S, row: &CoverageRow, brush: &Brush) {
+    let (width, height) = img.dimensions();
+    let columns = row.coverage.len() as f32;
+    row.coverage
+        .iter()
+        .enumerate()
+        .filter(|(_i, c)| **c > 0.0)
+        .for_each(|(i, c)| {
+            let x = row.first_col + i as u32;
+            let mut src = brush.as_rgba::<ColorAtom>(&LocationInfo {
+                x,
+                y: row.y,
+                width,
+                height,
+                // there's no single stroke across a fill, so go across the polygon instead
+                stroke: i as f32 / columns,
+            });
+            src[3] = (src[3] as f32 * c.min(1.0)).round() as ColorAtom;
+            img.composite(x, row.y, src, brush.blend_mode());
+        });
+}
+
+impl Rasterizable for Polygon {
+    // the fill is drawn in parallel, so it needs the lock
+    #[inline]
+    fn rasterize(&self, target: &DrawTarget, brush: &Brush, tolerance: f32) {
+        match self.mode {
+            PolygonType::Outline | PolygonType::Open => {
+                draw_batched(target, |batch| {
+                    rasterize_polyline_into(batch, self.outline_segments(tolerance), brush)
+                });
+            }
+            PolygonType::Fill => {
+                self.fill(target, brush, tolerance);
+            }
+        }
+    }
+
+    #[inline]
+    fn rasterize_into(&self, img: &mut dyn PixelSink, brush: &Brush, tolerance: f32) {
+        match self.mode {
+            PolygonType::Outline | PolygonType::Open => {
+                rasterize_polyline_into(img, self.outline_segments(tolerance), brush);
+            }
+            PolygonType::Fill => {
+                self.fill_into(img, brush, tolerance);
+            }
+        }
+    }
+}
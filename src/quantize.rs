@@ -0,0 +1,143 @@
+// GPLv3 License
+
+use crate::{layout, loading_version};
+use euclid::default::Point2D;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The size, in canvas pixels, of the grid that quantized coordinates lie on. Snapping to it
+/// moves a point by at most an eighth of a pixel along each axis, and only coordinates between
+/// -8192 and 8192 pixels fit.
+pub const QUANTIZE_STEP: f32 = 0.25;
+
+/// Get the quantized form of a coordinate, if it lies exactly on the grid.
+#[inline]
+pub fn quantize(coord: f32) -> Option<i16> {
+    let steps = coord / QUANTIZE_STEP;
+    if steps.fract() == 0.0 && steps >= i16::MIN as f32 && steps <= i16::MAX as f32 {
+        Some(steps as i16)
+    } else {
+        None
+    }
+}
+
+#[inline]
+pub fn dequantize(steps: i16) -> f32 {
+    steps as f32 * QUANTIZE_STEP
+}
+
+/// Move a coordinate to the closest point on the grid.
+#[inline]
+pub fn snap_to_grid(coord: f32) -> f32 {
+    let steps = (coord / QUANTIZE_STEP)
+        .round()
+        .max(i16::MIN as f32)
+        .min(i16::MAX as f32);
+    steps * QUANTIZE_STEP
+}
+
+/// Coordinates as they are written to binary formats. When every coordinate is on the grid,
+/// they take up half the space.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum PackedCoords {
+    Full(Vec<f32>),
+    Quantized(Vec<i16>),
+}
+
+impl PackedCoords {
+    #[inline]
+    pub(crate) fn pack(coords: &[f32]) -> Self {
+        match coords.iter().map(|c| quantize(*c)).collect() {
+            Some(steps) => Self::Quantized(steps),
+            None => Self::Full(coords.to_vec()),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn unpack(self) -> Vec<f32> {
+        match self {
+            Self::Full(coords) => coords,
+            Self::Quantized(steps) => steps.into_iter().map(dequantize).collect(),
+        }
+    }
+}
+
+/// Serde functions for the endpoints of a line, packing them in binary formats.
+pub(crate) mod packed_line {
+    use super::*;
+
+    #[inline]
+    pub fn serialize<S: Serializer>(pts: &[Point2D<f32>; 2], ser: S) -> Result<S::Ok, S::Error> {
+        if ser.is_human_readable() {
+            pts.serialize(ser)
+        } else {
+            PackedCoords::pack(&[pts[0].x, pts[0].y, pts[1].x, pts[1].y]).serialize(ser)
+        }
+    }
+
+    #[inline]
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<[Point2D<f32>; 2], D::Error> {
+        if de.is_human_readable() || loading_version() < layout::QUANTIZED {
+            return Deserialize::deserialize(de);
+        }
+
+        match PackedCoords::deserialize(de)?.unpack()[..] {
+            [x1, y1, x2, y2] => Ok([Point2D::new(x1, y1), Point2D::new(x2, y2)]),
+            ref coords => Err(D::Error::invalid_length(coords.len(), &"4 coordinates")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{with_loading_version, BezierCurve, PROJECT_VERSION};
+    use pathfinder_geometry::vector::Vector2F;
+
+    #[test]
+    fn snapping_stays_within_half_a_step() {
+        for &coord in &[0.0, 0.1, 0.125, 3.3, -17.61, 1023.99, -8000.05] {
+            let snapped = snap_to_grid(coord);
+            assert!((snapped - coord).abs() <= QUANTIZE_STEP / 2.0, "{}", coord);
+
+            // and what is snapped packs exactly
+            let steps = quantize(snapped).unwrap();
+            assert_eq!(dequantize(steps), snapped);
+        }
+
+        assert_eq!(quantize(0.1), None);
+        assert_eq!(quantize(10_000.0), None);
+    }
+
+    #[test]
+    fn packed_coordinates_round_trip() {
+        let coords = [snap_to_grid(12.3), snap_to_grid(-4.6), 0.5, 8191.75];
+        let packed = bincode::serialize(&PackedCoords::pack(&coords)).unwrap();
+        let full = bincode::serialize(&PackedCoords::Full(coords.to_vec())).unwrap();
+        assert!(packed.len() < full.len());
+
+        let unpacked: PackedCoords = bincode::deserialize(&packed).unwrap();
+        assert_eq!(unpacked.unpack(), coords);
+
+        // coordinates off the grid are kept in full
+        let coords = [0.1, 2.0];
+        let packed = bincode::serialize(&PackedCoords::pack(&coords)).unwrap();
+        let unpacked: PackedCoords = bincode::deserialize(&packed).unwrap();
+        assert_eq!(unpacked.unpack(), coords);
+    }
+
+    #[test]
+    fn unpacked_curves_still_load() {
+        // before coordinates were packed, binary curves were a plain list of them
+        let coords: Vec<f32> = vec![0.0, 0.0, 1.5, 2.0, 3.0, 2.0, 4.25, 0.0];
+        let bytes = bincode::serialize(&coords).unwrap();
+        let curve: BezierCurve = with_loading_version(layout::QUANTIZED - 1, || {
+            bincode::deserialize(&bytes).unwrap()
+        });
+        assert_eq!(curve.points()[3], Vector2F::new(4.25, 0.0));
+
+        let bytes = bincode::serialize(&curve).unwrap();
+        let curve: BezierCurve =
+            with_loading_version(PROJECT_VERSION, || bincode::deserialize(&bytes).unwrap());
+        assert_eq!(curve.points()[1], Vector2F::new(1.5, 2.0));
+    }
+}
@@ -0,0 +1,153 @@
+// MIT License
+
+use crate::{ColorAtom, Error, TCImage};
+use miniz_oxide::{deflate::compress_to_vec_zlib, inflate::decompress_to_vec_zlib};
+use png::{BitDepth, ColorType, Encoder};
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// The keyword of the zTXt chunk that holds an embedded project.
+const SOURCE_KEYWORD: &[u8] = b"archetype";
+
+/// Tell whether or not a set of bytes is a PNG image.
+#[inline]
+pub fn is_png(bytes: &[u8]) -> bool {
+    bytes.starts_with(&PNG_SIGNATURE)
+}
+
+/// Write an image out as a PNG, optionally embedding the serialized project it was rendered from.
+#[inline]
+pub fn write_png(img: &TCImage, filename: &str, source: Option<&[u8]>) -> Result<(), Error> {
+    let file = File::create(filename)?;
+    encode_png(img, BufWriter::new(file), source)
+}
+
+/// Encode an image as a PNG in memory.
+#[inline]
+pub fn png_bytes(img: &TCImage) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    encode_png(img, &mut bytes, None)?;
+    Ok(bytes)
+}
+
+fn encode_png<W: Write>(img: &TCImage, out: W, source: Option<&[u8]>) -> Result<(), Error> {
+    let mut encoder = Encoder::new(out, img.width(), img.height());
+    encoder.set_color(ColorType::RGBA);
+
+    let raw: &[ColorAtom] = img;
+
+    // PNG stores 16-bit samples in big endian order
+    #[cfg(feature = "true_color")]
+    let data: Cow<'_, [u8]> = {
+        encoder.set_depth(BitDepth::Sixteen);
+        let mut data = Vec::with_capacity(raw.len() * 2);
+        raw.iter()
+            .for_each(|c| data.extend_from_slice(&c.to_be_bytes()));
+        Cow::Owned(data)
+    };
+    #[cfg(not(feature = "true_color"))]
+    let data: Cow<'_, [u8]> = {
+        encoder.set_depth(BitDepth::Eight);
+        Cow::Borrowed(raw)
+    };
+
+    let mut writer = encoder.write_header()?;
+
+    if let Some(source) = source {
+        // keyword, null separator, compression method, then the compressed text
+        let mut chunk = SOURCE_KEYWORD.to_vec();
+        chunk.push(0);
+        chunk.push(0);
+        chunk.extend(compress_to_vec_zlib(source, 6));
+
+        writer.write_chunk(*b"zTXt", &chunk)?;
+    }
+
+    writer.write_image_data(&data)?;
+    Ok(())
+}
+
+/// Extract the serialized project embedded in a PNG image.
+pub fn embedded_source(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    if !is_png(bytes) {
+        return Err(Error::UnsupportedFormat("file is not a PNG image"));
+    }
+
+    // walk the chunks, each of which is a length, a type, the data and a CRC
+    let mut rest = &bytes[PNG_SIGNATURE.len()..];
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        if rest.len() < len + 12 {
+            break;
+        }
+
+        let (ty, data) = (&rest[4..8], &rest[8..8 + len]);
+        if ty == b"zTXt"
+            && data.starts_with(SOURCE_KEYWORD)
+            && data.get(SOURCE_KEYWORD.len()) == Some(&0)
+        {
+            // skip the separator and the compression method, which a damaged chunk may not have
+            let compressed = data
+                .get(SOURCE_KEYWORD.len() + 2..)
+                .ok_or_else(|| Error::deserialize("embedded project is truncated"))?;
+            return decompress_to_vec_zlib(compressed)
+                .map_err(|e| Error::deserialize(format!("{:?}", e)));
+        } else if ty == b"IEND" {
+            break;
+        }
+
+        rest = &rest[len + 12..];
+    }
+
+    Err(Error::UnsupportedFormat(
+        "PNG image does not contain an embedded project",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a PNG with nothing after the signature but one chunk, whose CRC is never checked
+    fn png_with_chunk(ty: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut bytes = PNG_SIGNATURE.to_vec();
+        bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(ty);
+        bytes.extend_from_slice(data);
+        bytes.extend_from_slice(&[0; 4]);
+        bytes
+    }
+
+    #[test]
+    fn embedded_source_round_trip() {
+        let img = TCImage::new(4, 3);
+        let source = br#"{"width":4,"height":3}"#;
+
+        let mut bytes = Vec::new();
+        encode_png(&img, &mut bytes, Some(source)).unwrap();
+        assert!(is_png(&bytes));
+        assert_eq!(embedded_source(&bytes).unwrap(), &source[..]);
+
+        // and the image itself is still readable
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgba();
+        assert_eq!(decoded.dimensions(), (4, 3));
+    }
+
+    #[test]
+    fn missing_source_is_an_error() {
+        let bytes = png_bytes(&TCImage::new(4, 3)).unwrap();
+        assert!(embedded_source(&bytes).is_err());
+        assert!(embedded_source(b"not a png").is_err());
+    }
+
+    #[test]
+    fn truncated_source_is_an_error() {
+        assert!(embedded_source(&png_with_chunk(b"zTXt", b"archetype\0")).is_err());
+        assert!(embedded_source(&png_with_chunk(b"zTXt", b"archetype\0\0garbage")).is_err());
+    }
+}
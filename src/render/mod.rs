@@ -1,13 +1,82 @@
 // MIT License
 
-use super::{DynamicColor, Project, TCImage};
-use image::{DynamicImage, ImageFormat, Rgba};
+use super::{DynamicColor, Error, GraphicalState, Project, TCImage, DEFAULT_TOLERANCE};
+use image::Rgba;
 use parking_lot::RwLock;
+use pathfinder_geometry::vector::Vector2F;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+
+mod embed;
+pub use embed::*;
 
 #[derive(Copy, Clone)]
 pub enum RenderTarget {
     SingleImage,
+    Selection,
     Mp4,
+    Steps,
+    Frames,
+}
+
+/// Options controlling how a project is exported.
+#[derive(Copy, Clone)]
+pub struct ExportOptions {
+    /// Embed the project source into exported PNG images, so that they can be edited later.
+    pub embed_source: bool,
+    /// How far, in pixels, flattened curves may stray from the true curve.
+    pub tolerance: f32,
+    /// Extra space, in pixels, left around the items when exporting only the selection.
+    pub padding: u32,
+    /// Repeat the frame this many times across and down, to check that a pattern is seamless.
+    pub tile: Option<(u32, u32)>,
+    /// Shift every other row of tiles by half a tile, like bricks in a wall.
+    pub brick: bool,
+    /// How many objects are added in each image when exporting the steps of a drawing.
+    pub step_size: usize,
+    /// The index of the frame to export, or `None` for the current frame.
+    pub frame: Option<usize>,
+    /// Crop the image to what was drawn, leaving this many pixels around it.
+    pub trim: Option<u32>,
+    /// How many images make up each second of an exported animation.
+    pub fps: u32,
+}
+
+impl Default for ExportOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            embed_source: false,
+            tolerance: DEFAULT_TOLERANCE,
+            padding: 0,
+            tile: None,
+            brick: false,
+            step_size: 1,
+            frame: None,
+            trim: None,
+            fps: 24,
+        }
+    }
+}
+
+/// Called as an export goes along with how many of its steps are done, out of the total. The
+/// export stops with `Error::Cancelled` if this returns `false`.
+pub type Progress<'a> = dyn FnMut(usize, usize) -> bool + 'a;
+
+/// Progress callback for exports that nobody is watching.
+#[inline]
+pub fn no_progress(_done: usize, _total: usize) -> bool {
+    true
+}
+
+// report progress, turning a request to stop into an error
+#[inline]
+fn report(progress: &mut Progress<'_>, done: usize, total: usize) -> Result<(), Error> {
+    if progress(done, total) {
+        Ok(())
+    } else {
+        Err(Error::Cancelled)
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -21,7 +90,10 @@ impl RenderTarget {
     pub fn from_char(c: char) -> Option<RenderTarget> {
         Some(match c {
             's' => Self::SingleImage,
+            'c' => Self::Selection,
             'm' => Self::Mp4,
+            'b' => Self::Steps,
+            'f' => Self::Frames,
             _ => return None,
         })
     }
@@ -29,37 +101,331 @@ impl RenderTarget {
     #[inline]
     pub fn is_single_image(&self) -> bool {
         match self {
-            Self::SingleImage => true,
+            Self::SingleImage | Self::Selection | Self::Steps | Self::Frames => true,
             _ => false,
         }
     }
 }
 
+// make sure there is a frame to export, rather than panicking on a project without any
+#[inline]
+fn check_frames(project: &Project) -> Result<(), Error> {
+    if project.frame_count() == 0 {
+        Err(Error::Render("the project has no frames".to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+// the frame an export is of
+#[inline]
+fn export_frame<'a>(
+    project: &'a Project,
+    options: &ExportOptions,
+) -> Result<&'a GraphicalState, Error> {
+    check_frames(project)?;
+    match options.frame {
+        None => Ok(project.current_frame()),
+        Some(index) => project.frame(index).ok_or_else(|| {
+            Error::Render(format!(
+                "there is no frame {}, the project has {} frames",
+                index,
+                project.frame_count()
+            ))
+        }),
+    }
+}
+
 #[inline]
 pub fn single_image<'a>(
     project: &Project,
     filename: &str,
     alpha: AlphaMaskTarget<'a>,
-) -> Result<(), &'static str> {
+    options: &ExportOptions,
+    progress: &mut Progress<'_>,
+) -> Result<(), Error> {
+    report(progress, 0, 1)?;
+
     // rasterize onto an image
     let img = RwLock::new((
         TCImage::from_pixel(project.width(), project.height(), Rgba([0, 0, 0, 0])),
         true,
     ));
-    project.current_frame().rasterize(&img, project);
+    export_frame(project, options)?.rasterize(&img, project, options.tolerance);
+
+    let source = if options.embed_source {
+        Some(serde_json::to_vec(project).map_err(Error::serialize)?)
+    } else {
+        None
+    };
+
+    let img = img.into_inner().0;
+    let img = match options.trim {
+        // an empty frame has nothing to trim to, so it's left as it is
+        Some(margin) => trim_to_content(&img, margin).unwrap_or(img),
+        None => img,
+    };
+    let img = match options.tile {
+        Some((across, down)) => tile_image(&img, across, down, options.brick),
+        None => img,
+    };
+
+    write_png(&img, filename, source.as_deref())?;
+    report(progress, 1, 1)
+}
+
+/// Crop an image to the smallest rectangle holding all of its pixels that aren't completely
+/// transparent, with a transparent margin around it. Returns `None` if every pixel is
+/// transparent.
+pub fn trim_to_content(img: &TCImage, margin: u32) -> Option<TCImage> {
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+    img.enumerate_pixels()
+        .filter(|(_x, _y, px)| px[3] != 0)
+        .for_each(|(x, y, _px)| {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        });
+    if min_x > max_x {
+        return None;
+    }
+
+    let width = max_x - min_x + 1 + 2 * margin;
+    let height = max_y - min_y + 1 + 2 * margin;
+    Some(TCImage::from_fn(width, height, |x, y| {
+        let (src_x, src_y) = (
+            (x + min_x).wrapping_sub(margin),
+            (y + min_y).wrapping_sub(margin),
+        );
+        if src_x < img.width() && src_y < img.height() {
+            *img.get_pixel(src_x, src_y)
+        } else {
+            Rgba([0, 0, 0, 0])
+        }
+    }))
+}
+
+/// Repeat an image in a grid of `across` by `down` copies, optionally shifting alternate rows
+/// over by half the image's width.
+pub fn tile_image(img: &TCImage, across: u32, down: u32, brick: bool) -> TCImage {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        return img.clone();
+    }
+
+    TCImage::from_fn(width * across.max(1), height * down.max(1), |x, y| {
+        let shift = if brick && (y / height) % 2 == 1 {
+            width / 2
+        } else {
+            0
+        };
+        *img.get_pixel((x + shift) % width, y % height)
+    })
+}
+
+/// Export only the selected items of the current frame, cropped to their bounds. The project
+/// source is never embedded, since the image doesn't show the whole project.
+pub fn selection_image(
+    project: &Project,
+    filename: &str,
+    options: &ExportOptions,
+    progress: &mut Progress<'_>,
+) -> Result<(), Error> {
+    report(progress, 0, 1)?;
+    let frame = export_frame(project, options)?;
+    let bounds = frame
+        .selected_bounds()
+        .ok_or_else(|| Error::Render("nothing is selected".to_string()))?;
+
+    // brushes are drawn as circles around each point, so leave room for the widest one
+    let brush_room = frame
+        .selected_brushes()
+        .iter()
+        .filter_map(|b| b.resolve(project.brushes()))
+        .map(|b| b.width())
+        .max()
+        .unwrap_or(0);
+    let padding = (options.padding + brush_room) as f32;
+
+    let mut selection = frame.selection_only();
+    selection.translate(Vector2F::new(
+        padding - bounds.origin.x.floor(),
+        padding - bounds.origin.y.floor(),
+    ));
+
+    let width = (bounds.max_x().ceil() - bounds.origin.x.floor() + 2.0 * padding).max(1.0);
+    let height = (bounds.max_y().ceil() - bounds.origin.y.floor() + 2.0 * padding).max(1.0);
+    let img = RwLock::new((
+        TCImage::from_pixel(width as u32, height as u32, Rgba([0, 0, 0, 0])),
+        true,
+    ));
+    selection.rasterize(&img, project, options.tolerance);
+
+    write_png(&img.into_inner().0, filename, None)?;
+    report(progress, 1, 1)
+}
+
+// the name of one image in a numbered sequence, e.g. "drawing.png" becomes "drawing_0003.png"
+#[inline]
+fn numbered_filename(filename: &str, number: usize) -> String {
+    let path = Path::new(filename);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let numbered = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}_{:04}.{}", stem, number, ext),
+        None => format!("{}_{:04}", stem, number),
+    };
+    path.with_file_name(numbered).to_string_lossy().into_owned()
+}
+
+/// Export the current frame as a sequence of images that show it being drawn, adding
+/// `step_size` objects in the order they were created to each image. Returns how many images
+/// were written.
+pub fn construction_steps(
+    project: &Project,
+    filename: &str,
+    options: &ExportOptions,
+    progress: &mut Progress<'_>,
+) -> Result<usize, Error> {
+    let frame = export_frame(project, options)?;
+    let order = frame.creation_order();
+    if order.is_empty() {
+        return Err(Error::Render("the frame is empty".to_string()));
+    }
+
+    let step_size = options.step_size.max(1);
+    let steps = order.len().div_ceil(step_size);
+    (1..=steps).try_for_each(|step| {
+        report(progress, step - 1, steps)?;
+        let shown = (step * step_size).min(order.len());
+        let img = RwLock::new((
+            TCImage::from_pixel(project.width(), project.height(), Rgba([0, 0, 0, 0])),
+            true,
+        ));
+        frame
+            .subset(&order[..shown])
+            .rasterize(&img, project, options.tolerance);
+
+        write_png(
+            &img.into_inner().0,
+            &numbered_filename(filename, step),
+            None,
+        )
+    })?;
+
+    report(progress, steps, steps)?;
+    Ok(steps)
+}
+
+/// How an animation carries on once it reaches its last frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoopMode {
+    /// Stop on the last frame.
+    Once,
+    /// Go back to the first frame.
+    Loop,
+    /// Play backwards to the first frame, then forwards again.
+    PingPong,
+}
+
+impl Default for LoopMode {
+    #[inline]
+    fn default() -> Self {
+        LoopMode::Loop
+    }
+}
+
+impl LoopMode {
+    /// The next loop mode, for cycling through them.
+    #[inline]
+    pub fn next(self) -> Self {
+        match self {
+            LoopMode::Once => LoopMode::Loop,
+            LoopMode::Loop => LoopMode::PingPong,
+            LoopMode::PingPong => LoopMode::Once,
+        }
+    }
+
+    /// The order one pass of the animation plays `count` frames in. A ping-pong goes back down
+    /// without repeating the last frame, and stops short of the first, so that looping the
+    /// pass doesn't show either end twice in a row: 3 frames play as 0, 1, 2, 1.
+    pub fn sequence(self, count: usize) -> Vec<usize> {
+        let forwards = 0..count;
+        match self {
+            LoopMode::PingPong if count > 2 => forwards.chain((1..count - 1).rev()).collect(),
+            _ => forwards.collect(),
+        }
+    }
+}
+
+/// Work out which frame each image of an animation shows, when frames last for the given
+/// number of milliseconds and there are `fps` images a second. Each image shows the frame that
+/// is up at the moment the image starts, so a frame that lasts twice as long gets twice as many
+/// images, give or take one for rounding.
+pub fn frame_schedule(durations: &[u32], fps: u32) -> Vec<usize> {
+    let total: u64 = durations.iter().map(|d| *d as u64).sum();
+    let fps = fps.max(1) as u64;
+    let count = (total * fps).div_ceil(1000);
 
-    #[cfg(feature = "true_color")]
-    let img = DynamicImage::ImageRgba16(img.into_inner().0);
-    #[cfg(not(feature = "true_color"))]
-    let img = DynamicImage::ImageRgba8(img.into_inner().0);
+    let mut frame = 0;
+    let mut frame_end = durations.first().copied().unwrap_or(0) as u64;
+    (0..count)
+        .map(|image| {
+            let time = image * 1000 / fps;
+            while time >= frame_end && frame + 1 < durations.len() {
+                frame += 1;
+                frame_end += durations[frame] as u64;
+            }
+            frame
+        })
+        .collect()
+}
+
+/// Export every frame as a numbered PNG, one for each image of the animation at `fps` images
+/// a second. Frames that last longer are repeated across more images, and a ping-pong
+/// animation includes the frames going back. Returns how many images were written.
+pub fn animation_frames(
+    project: &Project,
+    filename: &str,
+    options: &ExportOptions,
+    progress: &mut Progress<'_>,
+) -> Result<usize, Error> {
+    check_frames(project)?;
+    let sequence = project.loop_mode().sequence(project.frame_count());
+    let durations: Vec<u32> = sequence
+        .iter()
+        .filter_map(|i| project.frame(*i))
+        .map(|frame| frame.duration_ms())
+        .collect();
+    let schedule: Vec<usize> = frame_schedule(&durations, options.fps)
+        .into_iter()
+        .map(|step| sequence[step])
+        .collect();
 
-    img.save_with_format(filename, ImageFormat::Png)
-        .map_err(|e| {
-            eprintln!("{:?}", e);
-            "Unable to write image to file"
+    // each frame only needs to be drawn once, however many images it's in
+    let mut encoded: HashMap<usize, Vec<u8>> = HashMap::new();
+    schedule
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, index)| -> Result<(), Error> {
+            report(progress, i, schedule.len())?;
+            if !encoded.contains_key(index) {
+                let img = RwLock::new((
+                    TCImage::from_pixel(project.width(), project.height(), Rgba([0, 0, 0, 0])),
+                    true,
+                ));
+                if let Some(frame) = project.frame(*index) {
+                    frame.rasterize(&img, project, options.tolerance);
+                }
+                encoded.insert(*index, png_bytes(&img.into_inner().0)?);
+            }
+
+            fs::write(numbered_filename(filename, i + 1), &encoded[index])?;
+            Ok(())
         })?;
 
-    Ok(())
+    report(progress, schedule.len(), schedule.len())?;
+    Ok(schedule.len())
 }
 
 #[inline]
@@ -68,9 +434,397 @@ pub fn render<'a>(
     filename: &str,
     target: RenderTarget,
     alpha: AlphaMaskTarget<'a>,
-) -> Result<(), &'static str> {
+    options: &ExportOptions,
+    progress: &mut Progress<'_>,
+) -> Result<(), Error> {
     match target {
-        RenderTarget::SingleImage => single_image(project, filename, alpha),
-        _ => todo!(),
+        RenderTarget::SingleImage => single_image(project, filename, alpha, options, progress),
+        RenderTarget::Selection => selection_image(project, filename, options, progress),
+        RenderTarget::Steps => construction_steps(project, filename, options, progress).map(|_| ()),
+        RenderTarget::Frames => animation_frames(project, filename, options, progress).map(|_| ()),
+        RenderTarget::Mp4 => Err(Error::UnsupportedFormat("mp4 export is not supported")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{colors, Brush, BrushRef};
+    use std::env;
+
+    fn temp_path(name: &str) -> String {
+        env::temp_dir()
+            .join(format!(
+                "archetype-render-{}-{}.png",
+                name,
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    // export with some options, and read back what was written
+    fn exported<F>(name: &str, export: F) -> Result<image::RgbaImage, Error>
+    where
+        F: FnOnce(&str) -> Result<(), Error>,
+    {
+        let path = temp_path(name);
+        let result = export(&path).map(|()| image::open(&path).unwrap().to_rgba());
+        let _ = fs::remove_file(&path);
+        result
+    }
+
+    // a project with a diagonal line, drawn with a brush 2 pixels wide
+    fn line_project() -> Project {
+        let mut project = Project::new(40, 30, 0.5);
+        let brush = project.add_brush(Brush::new(colors::BLACK, 2));
+        let frame = project.current_frame_mut();
+        let line = frame.test_line((5.0, 5.0), (25.0, 15.0));
+        frame.select_only(&[line]);
+        frame.set_selected_brush(BrushRef::Index(brush));
+        project
+    }
+
+    #[test]
+    fn selection_is_cropped_to_its_bounds() {
+        let project = line_project();
+        let options = ExportOptions {
+            padding: 3,
+            ..Default::default()
+        };
+        let img = exported("selection", |path| {
+            selection_image(&project, path, &options, &mut no_progress)
+        })
+        .unwrap();
+
+        // the line's bounds, with room for the brush and the padding on every side
+        assert_eq!(img.dimensions(), (20 + 2 * 5, 10 + 2 * 5));
+        assert!(img.pixels().any(|p| p[3] > 0));
+    }
+
+    #[test]
+    fn empty_selections_are_errors() {
+        let mut project = line_project();
+        project.current_frame_mut().unselect();
+        let result = exported("empty-selection", |path| {
+            selection_image(&project, path, &ExportOptions::default(), &mut no_progress)
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn tiles_repeat_the_frame() {
+        let project = line_project();
+        let background = AlphaMaskTarget::Background(colors::WHITE.into());
+        let single = exported("single", |path| {
+            single_image(
+                &project,
+                path,
+                background,
+                &ExportOptions::default(),
+                &mut no_progress,
+            )
+        })
+        .unwrap();
+        let options = ExportOptions {
+            tile: Some((2, 2)),
+            ..Default::default()
+        };
+        let tiled = exported("tiled", |path| {
+            single_image(&project, path, background, &options, &mut no_progress)
+        })
+        .unwrap();
+
+        assert_eq!(tiled.dimensions(), (80, 60));
+        for &(tile_x, tile_y) in &[(0, 0), (40, 0), (0, 30), (40, 30)] {
+            single
+                .enumerate_pixels()
+                .for_each(|(x, y, px)| assert_eq!(tiled.get_pixel(x + tile_x, y + tile_y), px));
+        }
+    }
+
+    #[test]
+    fn bricks_shift_alternate_rows() {
+        let img = TCImage::from_fn(4, 2, |x, _y| Rgba([x as _, 0, 0, 255]));
+        let tiled = tile_image(&img, 2, 2, true);
+        assert_eq!(tiled.dimensions(), (8, 4));
+        assert_eq!(tiled.get_pixel(0, 0)[0], 0);
+        assert_eq!(tiled.get_pixel(0, 2)[0], 2);
+        assert_eq!(tiled.get_pixel(7, 3)[0], 1);
+    }
+
+    #[test]
+    fn each_object_gets_a_step() {
+        let mut project = line_project();
+        let frame = project.current_frame_mut();
+        frame.test_line((5.0, 20.0), (30.0, 20.0));
+        frame.test_polygon(&[(30.0, 2.0), (38.0, 2.0), (34.0, 8.0)]);
+
+        let path = temp_path("steps");
+        let steps = |step_size: usize| {
+            let options = ExportOptions {
+                step_size,
+                ..Default::default()
+            };
+            let steps = construction_steps(&project, &path, &options, &mut no_progress).unwrap();
+            let written = (1..=steps)
+                .filter(|i| fs::remove_file(numbered_filename(&path, *i)).is_ok())
+                .count();
+            assert_eq!(written, steps);
+            steps
+        };
+
+        assert_eq!(steps(1), 3);
+        // a last step with fewer objects in it still counts
+        assert_eq!(steps(2), 2);
+        assert_eq!(steps(5), 1);
+    }
+
+    #[test]
+    fn empty_frames_have_no_steps() {
+        let project = Project::new(10, 10, 0.5);
+        let path = temp_path("no-steps");
+        let result = construction_steps(&project, &path, &Default::default(), &mut no_progress);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn step_images_are_numbered() {
+        assert_eq!(
+            numbered_filename("out/drawing.png", 3),
+            "out/drawing_0003.png"
+        );
+        assert_eq!(numbered_filename("drawing", 12), "drawing_0012");
+    }
+
+    // a frame with three objects, so that it exports in three steps
+    fn three_step_project() -> Project {
+        let mut project = line_project();
+        let frame = project.current_frame_mut();
+        frame.test_line((5.0, 20.0), (30.0, 20.0));
+        frame.test_line((5.0, 25.0), (30.0, 25.0));
+        project
+    }
+
+    #[test]
+    fn progress_is_reported_for_each_image() {
+        let project = three_step_project();
+        let path = temp_path("progress");
+        let mut calls = Vec::new();
+        let steps = construction_steps(
+            &project,
+            &path,
+            &ExportOptions::default(),
+            &mut |done, total| {
+                calls.push((done, total));
+                true
+            },
+        )
+        .unwrap();
+        (1..=steps).for_each(|i| {
+            let _ = fs::remove_file(numbered_filename(&path, i));
+        });
+
+        assert_eq!(calls, vec![(0, 3), (1, 3), (2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn cancelling_stops_the_export() {
+        let project = three_step_project();
+        let path = temp_path("cancel");
+        let result = construction_steps(
+            &project,
+            &path,
+            &ExportOptions::default(),
+            &mut |done, _total| done < 1,
+        );
+        let written = (1..=3)
+            .filter(|i| fs::remove_file(numbered_filename(&path, *i)).is_ok())
+            .count();
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(written, 1);
+    }
+
+    #[test]
+    fn exports_can_pick_a_frame() {
+        let mut project = line_project();
+        project.add_frame();
+        project
+            .current_frame_mut()
+            .test_polygon(&[(30.0, 2.0), (38.0, 2.0), (34.0, 8.0)]);
+        // the second frame is the one being drawn on, so it's exported unless told otherwise
+        assert_eq!(project.current_frame_index(), 1);
+
+        let frame = |index: Option<usize>| {
+            let options = ExportOptions {
+                frame: index,
+                ..Default::default()
+            };
+            let background = AlphaMaskTarget::Background(colors::WHITE.into());
+            exported("frame", |path| {
+                single_image(&project, path, background, &options, &mut no_progress)
+            })
+        };
+
+        let (first, second) = (frame(Some(0)).unwrap(), frame(Some(1)).unwrap());
+        assert!(first != second);
+        assert!(frame(None).unwrap() == second);
+        assert!(frame(Some(2)).is_err());
+    }
+
+    #[test]
+    fn trimmed_exports_hug_the_drawing() {
+        let mut project = Project::new(200, 150, 0.5);
+        project.current_frame_mut().test_polygon(&[
+            (50.0, 60.0),
+            (60.0, 60.0),
+            (60.0, 70.0),
+            (50.0, 70.0),
+        ]);
+        let options = ExportOptions {
+            trim: Some(2),
+            ..Default::default()
+        };
+        let background = AlphaMaskTarget::Background(colors::WHITE.into());
+        let export = |project: &Project, name| {
+            exported(name, |path| {
+                single_image(project, path, background, &options, &mut no_progress)
+            })
+            .unwrap()
+        };
+
+        // the square, give or take its edge pixels, with the margin on every side
+        let img = export(&project, "trimmed");
+        let (width, height) = img.dimensions();
+        assert!((13..=16).contains(&width), "{}", width);
+        assert!((13..=16).contains(&height), "{}", height);
+        assert_eq!(img.get_pixel(0, 0)[3], 0);
+        assert_eq!(img.get_pixel(width / 2, height / 2)[3], 255);
+
+        // there's nothing to trim to in an empty frame
+        let empty = export(&Project::new(200, 150, 0.5), "trimmed-empty");
+        assert_eq!(empty.dimensions(), (200, 150));
+    }
+
+    // read back and remove a numbered sequence of exported files
+    fn take_numbered(path: &str, count: usize) -> Vec<Vec<u8>> {
+        (1..=count)
+            .map(|i| {
+                let name = numbered_filename(path, i);
+                let bytes = fs::read(&name).unwrap();
+                let _ = fs::remove_file(&name);
+                bytes
+            })
+            .collect()
+    }
+
+    #[test]
+    fn longer_frames_get_more_images() {
+        assert_eq!(
+            frame_schedule(&[100, 200, 100], 20),
+            [0, 0, 1, 1, 1, 1, 2, 2]
+        );
+        // a frame shorter than an image still starts on the image it's up for
+        assert_eq!(frame_schedule(&[200, 10, 250], 4), [0, 2]);
+        assert!(frame_schedule(&[], 10).is_empty());
+
+        let mut project = line_project();
+        project.current_frame_mut().set_duration_ms(100);
+        project.add_frame();
+        project.current_frame_mut().set_duration_ms(200);
+        project.set_loop_mode(LoopMode::Loop);
+
+        let path = temp_path("durations");
+        let options = ExportOptions {
+            fps: 10,
+            ..Default::default()
+        };
+        let images = animation_frames(&project, &path, &options, &mut no_progress).unwrap();
+        let written = take_numbered(&path, images);
+
+        assert_eq!(images, 3);
+        assert!(written[0] != written[1]);
+        assert!(written[1] == written[2]);
+    }
+
+    #[test]
+    fn ping_pong_goes_back_without_repeating_the_ends() {
+        assert_eq!(LoopMode::PingPong.sequence(3), [0, 1, 2, 1]);
+        assert_eq!(LoopMode::PingPong.sequence(4), [0, 1, 2, 3, 2, 1]);
+        assert_eq!(LoopMode::PingPong.sequence(2), [0, 1]);
+        assert_eq!(LoopMode::Loop.sequence(3), [0, 1, 2]);
+        assert_eq!(LoopMode::Once.sequence(3), [0, 1, 2]);
+        assert!(LoopMode::PingPong.sequence(0).is_empty());
+
+        // exporting three frames gives four images, with the middle one twice
+        let mut project = Project::new(20, 20, 0.5);
+        (0..3).for_each(|i| {
+            if i > 0 {
+                project.add_frame();
+            }
+            let x = 2.0 + i as f32 * 6.0;
+            let frame = project.current_frame_mut();
+            frame.set_duration_ms(100);
+            frame.test_line((x, 2.0), (x, 18.0));
+        });
+        project.set_loop_mode(LoopMode::PingPong);
+
+        let path = temp_path("ping-pong");
+        let options = ExportOptions {
+            fps: 10,
+            ..Default::default()
+        };
+        let images = animation_frames(&project, &path, &options, &mut no_progress).unwrap();
+        let written = take_numbered(&path, images);
+
+        assert_eq!(images, 4);
+        assert!(written[1] == written[3]);
+        assert!(written[0] != written[1] && written[1] != written[2] && written[0] != written[2]);
+    }
+
+    #[test]
+    fn projects_without_frames_are_errors() {
+        let mut value = serde_json::to_value(Project::new(20, 10, 0.5)).unwrap();
+        value["frames"] = serde_json::json!([]);
+        let project: Project = serde_json::from_value(value).unwrap();
+        assert_eq!(project.frame_count(), 0);
+
+        let background = AlphaMaskTarget::Background(colors::WHITE.into());
+        [
+            RenderTarget::SingleImage,
+            RenderTarget::Selection,
+            RenderTarget::Steps,
+            RenderTarget::Frames,
+        ]
+        .iter()
+        .for_each(|target| {
+            let path = temp_path("no-frames");
+            let result = render(
+                &project,
+                &path,
+                *target,
+                background,
+                &ExportOptions::default(),
+                &mut no_progress,
+            );
+            assert!(matches!(result, Err(Error::Render(_))));
+            assert!(!Path::new(&path).exists());
+        });
+    }
+
+    #[test]
+    fn mp4_exports_are_unsupported() {
+        let path = temp_path("video");
+        let result = render(
+            &line_project(),
+            &path,
+            RenderTarget::Mp4,
+            AlphaMaskTarget::Background(colors::WHITE.into()),
+            &ExportOptions::default(),
+            &mut no_progress,
+        );
+        assert!(matches!(result, Err(Error::UnsupportedFormat(_))));
+        assert!(!Path::new(&path).exists());
     }
 }
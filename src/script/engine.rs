@@ -0,0 +1,132 @@
+// GPLv3 License
+
+use super::ScriptTarget;
+use crate::{Error, FitMethod, DEFAULT_ERROR, DEFAULT_SIMPLIFY};
+use euclid::default::Point2D;
+use rhai::{Dynamic, Engine, EvalAltResult, RegisterFn, RegisterResultFn, FLOAT, INT};
+
+// how many operations a script may run, so that one stuck in a loop can't hang the program
+const MAX_OPERATIONS: u64 = 50_000_000;
+
+// fit curves to the buffered lines, with the current brush
+#[inline]
+fn fit_curves<T: ScriptTarget>(target: &T, error: f32) {
+    target.with_project(|pr| {
+        let brush = pr.current_brush_index();
+        pr.current_frame_mut().bezierify_buffered_lines(
+            brush,
+            error,
+            DEFAULT_SIMPLIFY,
+            FitMethod::default(),
+        );
+    });
+}
+
+/// Run a procedural drawing script written in Rhai. Scripts can only reach the current frame
+/// and the choice of brush, through these functions:
+///
+/// * `line(x1, y1, x2, y2)` adds a buffered line
+/// * `fit_curves()` or `fit_curves(error)` fits curves to the buffered lines
+/// * `convert_lines()` turns the buffered lines into lines
+/// * `brush(index)` picks the brush to draw with, counting from 0
+/// * `width()` and `height()` give the size of the canvas
+pub fn run_rhai<T: ScriptTarget>(target: &T, source: &str) -> Result<(), Error> {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let t = target.clone();
+    engine.register_fn("line", move |x1: FLOAT, y1: FLOAT, x2: FLOAT, y2: FLOAT| {
+        let (pt1, pt2) = (
+            Point2D::new(x1 as f32, y1 as f32),
+            Point2D::new(x2 as f32, y2 as f32),
+        );
+        t.with_project(|pr| pr.current_frame_mut().add_buffered_line(pt1, pt2));
+    });
+
+    let t = target.clone();
+    engine.register_fn("fit_curves", move || fit_curves(&t, DEFAULT_ERROR));
+    let t = target.clone();
+    engine.register_fn("fit_curves", move |error: FLOAT| {
+        fit_curves(&t, error as f32)
+    });
+
+    let t = target.clone();
+    engine.register_fn("convert_lines", move || {
+        t.with_project(|pr| {
+            let brush = pr.current_brush_index();
+            pr.current_frame_mut().convert_buffered_lines(brush);
+        });
+    });
+
+    let t = target.clone();
+    engine.register_result_fn(
+        "brush",
+        move |index: INT| -> Result<Dynamic, Box<EvalAltResult>> {
+            t.with_project(|pr| {
+                if index < 0 || index as usize >= pr.brush_count() {
+                    return Err(format!("There is no brush {}", index).into());
+                }
+                pr.set_current_brush(index as usize);
+                Ok(().into())
+            })
+        },
+    );
+
+    let t = target.clone();
+    engine.register_fn("width", move || t.with_project(|pr| pr.width() as INT));
+    let t = target.clone();
+    engine.register_fn("height", move || t.with_project(|pr| pr.height() as INT));
+
+    engine.consume(source).map_err(|e| Error::Script {
+        line: e.position().line().unwrap_or(0),
+        message: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gui::Project, ENDPOINT_TOLERANCE};
+    use std::{cell::RefCell, rc::Rc};
+
+    fn drawn(source: &str) -> Result<Project, Error> {
+        let project = Rc::new(RefCell::new(Project::new(200, 200, ENDPOINT_TOLERANCE)));
+        run_rhai(&project, source)?;
+        Ok(Rc::try_unwrap(project).ok().unwrap().into_inner())
+    }
+
+    #[test]
+    fn spirals_are_fitted_with_curves() {
+        let project = drawn(
+            r#"
+            let cx = width().to_float() / 2.0;
+            let cy = height().to_float() / 2.0;
+            let x = cx;
+            let y = cy;
+            for i in range(1, 120) {
+                let t = i.to_float() * 0.1;
+                let nx = cx + 6.0 * t * cos(t);
+                let ny = cy + 6.0 * t * sin(t);
+                line(x, y, nx, ny);
+                x = nx;
+                y = ny;
+            }
+            fit_curves();
+            "#,
+        )
+        .unwrap();
+
+        let stats = project.current_frame().stats();
+        assert!(stats.curves > 0);
+        assert_eq!(stats.buffered_lines, 0);
+    }
+
+    #[test]
+    fn scripts_only_pick_brushes_that_exist() {
+        assert!(drawn("brush(0); line(0.0, 0.0, 5.0, 5.0); convert_lines();").is_ok());
+        assert!(matches!(
+            drawn("\n\nbrush(7);"),
+            Err(Error::Script { line: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,275 @@
+// GPLv3 License
+
+use crate::{
+    colors, gui::Project, no_progress, render, AlphaMaskTarget, BezierCurve, Brush, CanvasAnchor,
+    Error, ExportOptions, RenderTarget, SolidColor, ENDPOINT_TOLERANCE,
+};
+use euclid::default::Point2D;
+use pathfinder_geometry::vector::Vector2F;
+use std::{cell::RefCell, fs, rc::Rc};
+
+#[cfg(feature = "scripting")]
+mod engine;
+#[cfg(feature = "scripting")]
+pub use engine::*;
+
+/// The size of the canvas a script starts with, until it asks for another.
+pub const SCRIPT_CANVAS: (u32, u32) = (800, 600);
+
+/// One line of a script.
+#[derive(Clone, PartialEq)]
+pub enum ScriptCommand {
+    /// `canvas WIDTH HEIGHT`
+    Canvas(u32, u32),
+    /// `brush #RRGGBB WIDTH`
+    Brush(SolidColor, u32),
+    /// `line X1 Y1 X2 Y2`
+    Line([f32; 4]),
+    /// `curve X0 Y0 X1 Y1 X2 Y2 X3 Y3`
+    Curve([f32; 8]),
+    /// `frame`
+    Frame,
+    /// `export FILENAME`
+    Export(String),
+}
+
+// read exactly N numbers from the rest of a line
+fn numbers<'a, T, I, const N: usize>(words: I) -> Result<[T; N], String>
+where
+    T: std::str::FromStr + Default + Copy,
+    I: Iterator<Item = &'a str>,
+{
+    let mut values = [T::default(); N];
+    let mut count = 0;
+    for word in words {
+        if count == N {
+            return Err(format!("expected {} numbers", N));
+        }
+        values[count] = word
+            .parse()
+            .map_err(|_e| format!("\"{}\" is not a number", word))?;
+        count += 1;
+    }
+
+    if count == N {
+        Ok(values)
+    } else {
+        Err(format!("expected {} numbers", N))
+    }
+}
+
+impl ScriptCommand {
+    /// Parse a line of a script. Blank lines and comments starting with `#` give `None`.
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let mut words = line.split_whitespace();
+        let command = match words.next().unwrap() {
+            "canvas" => {
+                let [width, height]: [u32; 2] = numbers(words)?;
+                ScriptCommand::Canvas(width, height)
+            }
+            "brush" => {
+                let color = words.next().ok_or("expected a color")?;
+                let color = SolidColor::from_hex(color)
+                    .ok_or_else(|| format!("\"{}\" is not a color like #ff8000", color))?;
+                let [width]: [u32; 1] = numbers(words)?;
+                ScriptCommand::Brush(color, width)
+            }
+            "line" => ScriptCommand::Line(numbers(words)?),
+            "curve" => ScriptCommand::Curve(numbers(words)?),
+            "frame" => match words.next() {
+                None => ScriptCommand::Frame,
+                Some(_) => return Err("frame doesn't take anything".to_string()),
+            },
+            "export" => {
+                // filenames may have spaces, so take the rest of the line
+                let filename = line["export".len()..].trim();
+                if filename.is_empty() {
+                    return Err("expected a file to export to".to_string());
+                }
+                ScriptCommand::Export(filename.to_string())
+            }
+            other => return Err(format!("unknown command \"{}\"", other)),
+        };
+
+        Ok(Some(command))
+    }
+
+    /// Carry out the command on a project.
+    pub fn run(&self, project: &mut Project) -> Result<(), Error> {
+        match self {
+            ScriptCommand::Canvas(width, height) => {
+                project.resize_canvas(*width, *height, CanvasAnchor::TopLeft)
+            }
+            ScriptCommand::Brush(color, width) => {
+                let index = project.add_brush(Brush::new(*color, *width));
+                project.set_current_brush(index);
+            }
+            ScriptCommand::Line([x1, y1, x2, y2]) => {
+                let brush = project.current_brush_index();
+                let frame = project.current_frame_mut();
+                frame.add_buffered_line(Point2D::new(*x1, *y1), Point2D::new(*x2, *y2));
+                frame.convert_buffered_lines(brush);
+            }
+            ScriptCommand::Curve(coords) => {
+                let mut points = [Vector2F::zero(); 4];
+                points
+                    .iter_mut()
+                    .zip(coords.chunks(2))
+                    .for_each(|(pt, xy)| *pt = Vector2F::new(xy[0], xy[1]));
+
+                let brush = project.current_brush_index();
+                project
+                    .current_frame_mut()
+                    .add_curves(brush, Some(BezierCurve::from_points(points)));
+            }
+            ScriptCommand::Frame => project.add_frame(),
+            ScriptCommand::Export(filename) => render(
+                project,
+                filename,
+                RenderTarget::SingleImage,
+                AlphaMaskTarget::Background(colors::BLACK.into()),
+                &ExportOptions::default(),
+                &mut no_progress,
+            )?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Run a script against a new project, stopping at the first line that can't be read or
+/// carried out. Returns the project the script made.
+pub fn run_script(source: &str) -> Result<Project, Error> {
+    let (width, height) = SCRIPT_CANVAS;
+    let mut project = Project::new(width, height, ENDPOINT_TOLERANCE);
+
+    source.lines().enumerate().try_for_each(|(i, line)| {
+        let line_error = |message: String| Error::Script {
+            line: i + 1,
+            message,
+        };
+        match ScriptCommand::parse(line).map_err(line_error)? {
+            Some(command) => command
+                .run(&mut project)
+                .map_err(|e| line_error(e.to_string())),
+            None => Ok(()),
+        }
+    })?;
+
+    Ok(project)
+}
+
+/// Run the script in a file.
+#[inline]
+pub fn run_script_file(path: &str) -> Result<Project, Error> {
+    run_script(&fs::read_to_string(path)?)
+}
+
+/// Something a procedural drawing script can draw on.
+pub trait ScriptTarget: Clone + 'static {
+    /// Run a function with the project the script is drawing into.
+    fn with_project<R, F: FnOnce(&mut Project) -> R>(&self, f: F) -> R;
+}
+
+impl ScriptTarget for Rc<RefCell<Project>> {
+    #[inline]
+    fn with_project<R, F: FnOnce(&mut Project) -> R>(&self, f: F) -> R {
+        f(&mut self.borrow_mut())
+    }
+}
+
+impl ScriptTarget for crate::Gui {
+    #[inline]
+    fn with_project<R, F: FnOnce(&mut Project) -> R>(&self, f: F) -> R {
+        f(&mut self.project().write())
+    }
+}
+
+/// Run a procedural drawing script. This build has no script engine, so it always fails.
+#[cfg(not(feature = "scripting"))]
+#[inline]
+pub fn run_rhai<T: ScriptTarget>(_target: &T, _source: &str) -> Result<(), Error> {
+    Err(Error::UnsupportedFormat(
+        "scripts need archetype to be built with the \"scripting\" feature",
+    ))
+}
+
+/// Run a procedural drawing script from a file, against a new project.
+#[inline]
+pub fn run_rhai_file(path: &str) -> Result<Project, Error> {
+    let source = fs::read_to_string(path)?;
+    let (width, height) = SCRIPT_CANVAS;
+    let project = Rc::new(RefCell::new(Project::new(
+        width,
+        height,
+        ENDPOINT_TOLERANCE,
+    )));
+
+    run_rhai(&project, &source)?;
+    let project = Rc::try_unwrap(project)
+        .ok()
+        .expect("Scripts don't keep the project after they finish");
+    Ok(project.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn scripts_draw_and_export() {
+        let path = env::temp_dir().join(format!("archetype-script-{}.png", std::process::id()));
+        let source = format!(
+            "# two lines and a curve, then a frame with one more line\n\
+             canvas 60 40\n\
+             brush #ff8000 3\n\
+             line 5 5 55 5\n\
+             line 5 10 55 30\n\
+             \n\
+             curve 5 35 20 20 40 20 55 35\n\
+             frame\n\
+             line 10 10 20 20\n\
+             export {}\n",
+            path.display()
+        );
+        let project = run_script(&source).unwrap();
+        let exported = image::open(&path).map(|img| img.to_rgba());
+        let _ = fs::remove_file(&path);
+
+        assert_eq!((project.width(), project.height()), (60, 40));
+        assert_eq!(project.frame_count(), 2);
+        let first = project.frame(0).unwrap();
+        assert_eq!((first.lines().len(), first.curves().len()), (2, 1));
+        assert_eq!(project.frame(1).unwrap().lines().len(), 1);
+        assert_eq!(exported.unwrap().dimensions(), (60, 40));
+    }
+
+    #[test]
+    fn script_errors_name_their_line() {
+        let error = |source: &str| match run_script(source) {
+            Err(Error::Script { line, message }) => (line, message),
+            _ => panic!("{:?} should have failed", source),
+        };
+
+        assert_eq!(error("canvas 60 40\n\nline 1 2 3\n").0, 3);
+        assert_eq!(error("# nothing yet\ndraw 1 2\n").0, 2);
+        assert_eq!(error("brush orange 2").0, 1);
+        assert_eq!(error("line 1 2 3 4\nframe 2").0, 2);
+        assert_eq!(error("export").0, 1);
+        assert!(error("line 1 2 x 4").1.contains("\"x\""));
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    #[test]
+    fn rhai_needs_the_scripting_feature() {
+        let project = Rc::new(RefCell::new(Project::new(10, 10, ENDPOINT_TOLERANCE)));
+        assert!(run_rhai(&project, "line(0.0, 0.0, 5.0, 5.0);").is_err());
+        assert!(project.borrow().current_frame().is_empty());
+    }
+}
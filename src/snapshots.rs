@@ -0,0 +1,151 @@
+// GPLv3 License
+
+//! Rendering regression tests. Each case is drawn onto a small image and compared against a
+//! reference PNG in `tests/snapshots`. Run the tests with `BLESS=1` to write new references.
+
+use crate::{
+    colors, rasterize_tapered_line_into, BezierCurve, Brush, ColorAtom, Polygon, PolygonType,
+    Rasterizable, TCImage, DEFAULT_TOLERANCE,
+};
+use image::RgbaImage;
+use pathfinder_geometry::{line_segment::LineSegment2F, vector::Vector2F};
+use std::{env, path::PathBuf};
+
+const SIZE: u32 = 32;
+/// How far apart each channel can be, out of 255, before a pixel counts as different.
+const CHANNEL_TOLERANCE: u8 = 3;
+
+#[inline]
+fn reference_path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "snapshots"]
+        .iter()
+        .collect::<PathBuf>()
+        .join(format!("{}.png", name))
+}
+
+// references are stored with 8 bits per channel, whatever the color depth
+#[inline]
+fn to_rgba8(img: &TCImage) -> RgbaImage {
+    let scale = |c: ColorAtom| (u32::from(c) * 255 / u32::from(ColorAtom::MAX)) as u8;
+    RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+        let px = img.get_pixel(x, y);
+        image::Rgba([scale(px[0]), scale(px[1]), scale(px[2]), scale(px[3])])
+    })
+}
+
+/// Draw a case onto a blank image, and compare it with its reference.
+fn check_snapshot<F: FnOnce(&mut TCImage)>(name: &str, draw: F) {
+    let mut img = TCImage::new(SIZE, SIZE);
+    draw(&mut img);
+    let rendered = to_rgba8(&img);
+    let path = reference_path(name);
+
+    if env::var_os("BLESS").is_some() {
+        rendered.save(&path).expect("Unable to write the reference");
+        return;
+    }
+
+    let reference = match image::open(&path) {
+        Ok(reference) => reference.to_rgba(),
+        Err(e) => panic!(
+            "No reference for {} ({}), run with BLESS=1 to make one",
+            name, e
+        ),
+    };
+    assert_eq!(reference.dimensions(), rendered.dimensions());
+
+    let different: Vec<(u32, u32)> = rendered
+        .enumerate_pixels()
+        .filter(|(x, y, px)| {
+            let expected = reference.get_pixel(*x, *y);
+            px.0.iter()
+                .zip(expected.0.iter())
+                .any(|(a, b)| (i16::from(*a) - i16::from(*b)).abs() > i16::from(CHANNEL_TOLERANCE))
+        })
+        .map(|(x, y, _)| (x, y))
+        .collect();
+    assert!(
+        different.is_empty(),
+        "{} pixels of {} differ from the reference, starting at {:?}",
+        different.len(),
+        name,
+        different.first()
+    );
+}
+
+#[inline]
+fn triangle(mode: PolygonType) -> Polygon {
+    let (a, b, c) = (
+        Vector2F::new(4.0, 28.0),
+        Vector2F::new(16.0, 3.0),
+        Vector2F::new(29.0, 25.0),
+    );
+    Polygon::new(
+        vec![
+            LineSegment2F::new(a, b),
+            LineSegment2F::new(b, c),
+            LineSegment2F::new(c, a),
+        ],
+        mode,
+    )
+}
+
+#[test]
+fn thick_line() {
+    check_snapshot("thick_line", |img| {
+        let line = LineSegment2F::new(Vector2F::new(4.0, 6.0), Vector2F::new(27.0, 24.0));
+        line.rasterize_into(img, &Brush::new(colors::BLACK, 2), DEFAULT_TOLERANCE);
+    });
+}
+
+#[test]
+fn tapered_line() {
+    check_snapshot("tapered_line", |img| {
+        let line = LineSegment2F::new(Vector2F::new(5.0, 16.0), Vector2F::new(27.0, 16.0));
+        rasterize_tapered_line_into(img, &line, &Brush::new(colors::BLUE, 1), 4.0, 1.0);
+    });
+}
+
+#[test]
+fn curve() {
+    check_snapshot("curve", |img| {
+        let curve = BezierCurve::from_points([
+            Vector2F::new(3.0, 28.0),
+            Vector2F::new(6.0, 0.0),
+            Vector2F::new(26.0, 32.0),
+            Vector2F::new(29.0, 4.0),
+        ]);
+        curve.rasterize_into(img, &Brush::new(colors::RED, 1), DEFAULT_TOLERANCE);
+    });
+}
+
+#[test]
+fn filled_triangle() {
+    check_snapshot("filled_triangle", |img| {
+        triangle(PolygonType::Fill).rasterize_into(
+            img,
+            &Brush::new(colors::BLACK, 1),
+            DEFAULT_TOLERANCE,
+        );
+    });
+}
+
+#[test]
+fn hard_edged_triangle() {
+    check_snapshot("hard_edged_triangle", |img| {
+        let mut brush = Brush::new(colors::BLACK, 1);
+        brush.set_hard_edges(true);
+        triangle(PolygonType::Fill).rasterize_into(img, &brush, DEFAULT_TOLERANCE);
+    });
+}
+
+#[test]
+fn outlined_triangle() {
+    check_snapshot("outlined_triangle", |img| {
+        triangle(PolygonType::Outline).rasterize_into(
+            img,
+            &Brush::new(colors::BLUE, 1),
+            DEFAULT_TOLERANCE,
+        );
+    });
+}
@@ -1,12 +1,12 @@
 // GPLv3 License
 
-use super::GraphicalState as State;
-use crate::{BezierCurve, Line, Polygon};
-use euclid::default::Point2D;
+use super::{GraphicalState as State, ImageObject, SymbolInstance};
+use crate::{layout, since, BezierCurve, Brush, BrushRef, Line, Point, Polygon, DEFAULT_TOLERANCE};
+use euclid::default::{Point2D, Rect};
 use pathfinder_geometry::vector::Vector2F;
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
-use std::{any::Any, boxed::Box, collections::HashMap};
+use std::{any::Any, boxed::Box, collections::HashMap, iter};
 
 pub type DataID = usize;
 
@@ -15,6 +15,8 @@ pub enum StateDataType {
     Curve,
     Line,
     Polygon,
+    Image,
+    Symbol,
 }
 
 impl StateDataType {
@@ -24,6 +26,8 @@ impl StateDataType {
             Self::Curve => state.curves(),
             Self::Line => state.lines(),
             Self::Polygon => state.polygons(),
+            Self::Image => state.images(),
+            Self::Symbol => state.instances(),
         }
     }
 
@@ -33,6 +37,8 @@ impl StateDataType {
             Self::Curve => state.curves_mut(),
             Self::Line => state.lines_mut(),
             Self::Polygon => state.polygons_mut(),
+            Self::Image => state.images_mut(),
+            Self::Symbol => state.instances_mut(),
         }
     }
 }
@@ -66,8 +72,12 @@ impl StateDataLoc {
 // repr of a line
 #[derive(Clone, Serialize, Deserialize)]
 pub struct StateLine {
+    #[serde(with = "crate::quantize::packed_line")]
     pub points: [Point2D<f32>; 2],
-    pub brush: usize,
+    pub brush: BrushRef,
+    // the widths at the start and end of a tapered line, otherwise the brush width is used
+    #[serde(default, deserialize_with = "since::<_, _, { layout::TAPERED_LINES }>")]
+    pub widths: Option<[f32; 2]>,
 }
 
 // repr of a buffered line
@@ -79,18 +89,43 @@ pub struct BufferedLine(pub [Point2D<f32>; 2]);
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Curve {
     pub curve: BezierCurve,
-    pub brush: usize,
+    pub brush: BrushRef,
 }
 
 // repr of a polygon
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Polyshape {
     pub polygon: Polygon,
-    pub brush: usize,
+    pub brush: BrushRef,
+}
+
+impl StateLine {
+    /// Get the filled outline of this line when it is stroked `width` wide.
+    #[inline]
+    pub fn to_outline(&self, width: f32) -> Polyshape {
+        let [from, to] = self.points;
+        let points = [Vector2F::new(from.x, from.y), Vector2F::new(to.x, to.y)];
+
+        Polyshape {
+            polygon: Polygon::from_stroke(&points, width).expect("Line outline has no points"),
+            brush: self.brush,
+        }
+    }
+}
+
+impl Curve {
+    /// Get the filled outline of this curve when it is stroked `width` wide.
+    #[inline]
+    pub fn to_outline(&self, width: f32) -> Polyshape {
+        Polyshape {
+            polygon: self.curve.to_outline(width),
+            brush: self.brush,
+        }
+    }
 }
 
 #[inline]
-fn line_to_points<Ln: Line<f32>>(line: &Ln) -> SmallVec<[Vector2F; 4]> {
+pub(crate) fn line_to_points<Ln: Line<f32>>(line: &Ln) -> SmallVec<[Vector2F; 4]> {
     const POINT_SKIP: usize = 8;
     let line_iter = imageproc::drawing::BresenhamLineIter::new(
         (line.from_x(), line.from_y()),
@@ -103,11 +138,31 @@ fn line_to_points<Ln: Line<f32>>(line: &Ln) -> SmallVec<[Vector2F; 4]> {
 }
 
 pub const HISTORY_LIMIT: usize = 45;
+/// How close two endpoints have to be, in canvas pixels, to count as the same point by default.
+pub const ENDPOINT_TOLERANCE: f32 = 2.0;
 
 /// A trait unifying every object that can be selected.
 pub trait DataObject {
     fn data_type(&self) -> StateDataType;
     fn points(&self) -> SmallVec<[Vector2F; 4]>;
+    /// The distance from a point to the closest part of the object.
+    #[inline]
+    fn distance_to(&self, pt: Vector2F) -> f32 {
+        self.points()
+            .into_iter()
+            .map(|p| p.distance_to(&pt))
+            .fold(f32::INFINITY, f32::min)
+    }
+    /// The axis-aligned bounds of the object.
+    #[inline]
+    fn bounding_box(&self) -> Rect<f32> {
+        Rect::from_points(self.points().into_iter().map(|pt| pt.into_euclid()))
+    }
+    /// How far past its bounds the object is drawn when it uses `brush`.
+    #[inline]
+    fn reach(&self, brush: &Brush) -> f32 {
+        brush.reach()
+    }
     fn into_container(self) -> DataObjectContainer;
     fn clone_into_container(&self) -> DataObjectContainer;
 }
@@ -123,6 +178,24 @@ impl DataObject for StateLine {
         line_to_points(&self.points)
     }
 
+    // the sampled points skip the far end, so go by the endpoints instead
+    #[inline]
+    fn bounding_box(&self) -> Rect<f32> {
+        Rect::from_points(self.points.iter())
+    }
+
+    // tapered lines can be wider than their brush
+    #[inline]
+    fn reach(&self, brush: &Brush) -> f32 {
+        match self.widths {
+            Some([start, end]) => {
+                let widest = start.max(end).round() - brush.width() as f32;
+                brush.reach() + widest.max(0.0)
+            }
+            None => brush.reach(),
+        }
+    }
+
     #[inline]
     fn into_container(self) -> DataObjectContainer {
         DataObjectContainer::StateLine(self)
@@ -148,6 +221,16 @@ impl DataObject for Curve {
             .collect()
     }
 
+    #[inline]
+    fn distance_to(&self, pt: Vector2F) -> f32 {
+        self.curve.closest_point(pt).1
+    }
+
+    #[inline]
+    fn bounding_box(&self) -> Rect<f32> {
+        self.curve.bounding_box()
+    }
+
     #[inline]
     fn into_container(self) -> DataObjectContainer {
         DataObjectContainer::Curve(self)
@@ -168,11 +251,21 @@ impl DataObject for Polyshape {
     #[inline]
     fn points(&self) -> SmallVec<[Vector2F; 4]> {
         self.polygon
-            .as_straight_edges()
+            .as_straight_edges(DEFAULT_TOLERANCE)
             .flat_map(|l| line_to_points(&l))
             .collect()
     }
 
+    #[inline]
+    fn bounding_box(&self) -> Rect<f32> {
+        Rect::from_points(
+            self.polygon
+                .as_straight_edges(DEFAULT_TOLERANCE)
+                .flat_map(|l| iter::once(l.from()).chain(iter::once(l.to())))
+                .map(|pt| pt.into_euclid()),
+        )
+    }
+
     #[inline]
     fn into_container(self) -> DataObjectContainer {
         DataObjectContainer::Polyshape(self)
@@ -190,6 +283,8 @@ pub enum DataObjectContainer {
     Curve(Curve),
     StateLine(StateLine),
     Polyshape(Polyshape),
+    Image(ImageObject),
+    Symbol(SymbolInstance),
 }
 
 impl DataObjectContainer {
@@ -199,6 +294,8 @@ impl DataObjectContainer {
             Self::Curve(c) => Box::new(c),
             Self::StateLine(s) => Box::new(s),
             Self::Polyshape(p) => Box::new(p),
+            Self::Image(i) => Box::new(i),
+            Self::Symbol(s) => Box::new(s),
         }
     }
 
@@ -208,6 +305,8 @@ impl DataObjectContainer {
             Self::Curve(ref c) => c as _,
             Self::StateLine(ref s) => s as _,
             Self::Polyshape(ref p) => p as _,
+            Self::Image(ref i) => i as _,
+            Self::Symbol(ref s) => s as _,
         }
     }
 }
@@ -223,6 +322,16 @@ impl DataObject for DataObjectContainer {
         self.as_data_object_ref().points()
     }
 
+    #[inline]
+    fn bounding_box(&self) -> Rect<f32> {
+        self.as_data_object_ref().bounding_box()
+    }
+
+    #[inline]
+    fn reach(&self, brush: &Brush) -> f32 {
+        self.as_data_object_ref().reach(brush)
+    }
+
     #[inline]
     fn into_container(self) -> Self {
         self
@@ -287,7 +396,123 @@ impl<T: DataObject + 'static> DataObjectCollection for HashMap<usize, T> {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum StateOperation {
     Add(StateDataLoc),
+    // objects moved together by an offset, added last so older files still load
+    Move(Vec<StateDataLoc>, [f32; 2]),
+}
+
+impl StateOperation {
+    /// The object this operation added, if it added one.
+    #[inline]
+    pub fn added(&self) -> Option<StateDataLoc> {
+        match self {
+            Self::Add(loc) => Some(*loc),
+            Self::Move(..) => None,
+        }
+    }
+
+    /// Point the operation at an object's new location.
+    #[inline]
+    pub fn relocate(&mut self, old: StateDataLoc, new: StateDataLoc) {
+        match self {
+            Self::Add(loc) => {
+                if *loc == old {
+                    *loc = new;
+                }
+            }
+            Self::Move(items, _) => items
+                .iter_mut()
+                .filter(|loc| **loc == old)
+                .for_each(|loc| *loc = new),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use euclid::default::Size2D;
+    use std::f32::consts::PI;
+
+    // a stroke covers its length times its width, plus a round cap at either end
+    #[inline]
+    fn assert_stroke_area(outline: &Polyshape, length: f32, width: f32) {
+        let body = length * width;
+        let caps = PI * (width / 2.0) * (width / 2.0);
+        let area = outline.polygon.area(DEFAULT_TOLERANCE);
+        // the caps are polygons, so they come up a little short
+        assert!(
+            area > body + caps * 0.95 && area <= body + caps,
+            "area {} for a body of {} and caps of {}",
+            area,
+            body,
+            caps
+        );
+    }
+
+    #[test]
+    fn line_outlines_cover_the_stroke() {
+        let line = StateLine {
+            points: [Point2D::new(10.0, 10.0), Point2D::new(70.0, 90.0)],
+            brush: 0.into(),
+            widths: None,
+        };
+        assert_stroke_area(&line.to_outline(8.0), 100.0, 8.0);
+    }
+
+    #[test]
+    fn curve_outlines_cover_the_stroke() {
+        // a straight curve, so its length is known
+        let curve = Curve {
+            curve: BezierCurve::from_points([
+                Vector2F::new(0.0, 20.0),
+                Vector2F::new(30.0, 20.0),
+                Vector2F::new(70.0, 20.0),
+                Vector2F::new(100.0, 20.0),
+            ]),
+            brush: 0.into(),
+        };
+        assert_stroke_area(&curve.to_outline(6.0), 100.0, 6.0);
+    }
+
+    #[test]
+    fn bent_outlines_are_close_to_length_times_width() {
+        let curve = BezierCurve::from_points([
+            Vector2F::new(0.0, 0.0),
+            Vector2F::new(0.0, 100.0),
+            Vector2F::new(100.0, 100.0),
+            Vector2F::new(100.0, 0.0),
+        ]);
+        let length: f32 = curve.edges().map(|l| l.vector().length()).sum();
+        let area = curve.to_outline(4.0).area(DEFAULT_TOLERANCE);
+        let expected = length * 4.0 + PI * 4.0;
+        assert!(
+            (area - expected).abs() < expected * 0.02,
+            "area {} is not close to {}",
+            area,
+            expected
+        );
+    }
+
+    #[test]
+    fn bounds_reach_the_ends_of_lines() {
+        let line = StateLine {
+            points: [Point2D::new(5.0, 5.0), Point2D::new(25.0, 15.0)],
+            brush: 0.into(),
+            widths: None,
+        };
+        let bounds = line.bounding_box();
+        assert_eq!(
+            bounds,
+            Rect::new(Point2D::new(5.0, 5.0), Size2D::new(20.0, 10.0))
+        );
+        assert_eq!(line.clone_into_container().bounding_box(), bounds);
+
+        let outline = line.to_outline(4.0);
+        let bounds = outline.bounding_box();
+        assert!(bounds.min_x() < 5.0 && bounds.max_x() > 25.0);
+        assert!(bounds.min_y() < 5.0 && bounds.max_y() > 15.0);
+    }
 }
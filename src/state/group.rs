@@ -0,0 +1,24 @@
+// GPLv3 License
+
+use super::StateDataLoc;
+use serde::{Deserialize, Serialize};
+
+/// A named set of objects in a frame that are selected and moved together. An object is in at
+/// most one group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    pub name: String,
+    pub members: Vec<StateDataLoc>,
+}
+
+impl Group {
+    #[inline]
+    pub fn new(name: String, members: Vec<StateDataLoc>) -> Self {
+        Self { name, members }
+    }
+
+    #[inline]
+    pub fn contains(&self, loc: &StateDataLoc) -> bool {
+        self.members.contains(loc)
+    }
+}
@@ -0,0 +1,334 @@
+// GPLv3 License
+
+use super::{line_to_points, DataObject, DataObjectContainer, StateDataType};
+use crate::{BlendMode, ColorAtom, DrawTarget, Error};
+use euclid::default::Point2D;
+use image::{png::PNGEncoder, ColorType, Rgba, RgbaImage};
+use pathfinder_geometry::vector::Vector2F;
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use smallvec::SmallVec;
+use std::fmt;
+
+/// The bytes of an embedded image file. These are written as base64 in human-readable formats
+/// like JSON, and as raw bytes otherwise.
+#[derive(Clone)]
+struct EmbeddedBytes(Vec<u8>);
+
+impl Serialize for EmbeddedBytes {
+    #[inline]
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        if ser.is_human_readable() {
+            ser.serialize_str(&base64::encode(&self.0))
+        } else {
+            ser.serialize_bytes(&self.0)
+        }
+    }
+}
+
+struct EmbeddedBytesVisitor;
+
+impl<'de> Visitor<'de> for EmbeddedBytesVisitor {
+    type Value = EmbeddedBytes;
+
+    #[inline]
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("image bytes or a base64 string")
+    }
+
+    #[inline]
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<EmbeddedBytes, E> {
+        base64::decode(s).map(EmbeddedBytes).map_err(E::custom)
+    }
+
+    #[inline]
+    fn visit_bytes<E: de::Error>(self, b: &[u8]) -> Result<EmbeddedBytes, E> {
+        Ok(EmbeddedBytes(b.to_vec()))
+    }
+
+    #[inline]
+    fn visit_byte_buf<E: de::Error>(self, b: Vec<u8>) -> Result<EmbeddedBytes, E> {
+        Ok(EmbeddedBytes(b))
+    }
+}
+
+impl<'de> Deserialize<'de> for EmbeddedBytes {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        if de.is_human_readable() {
+            de.deserialize_str(EmbeddedBytesVisitor)
+        } else {
+            de.deserialize_byte_buf(EmbeddedBytesVisitor)
+        }
+    }
+}
+
+/// Serializable form of an image object.
+#[derive(Serialize, Deserialize)]
+struct ImageObjectSerde {
+    source: EmbeddedBytes,
+    position: Point2D<f32>,
+    scale: f32,
+}
+
+/// A raster image placed into a frame.
+///
+/// Both the original file and its decoded pixels are kept in memory, so a large image costs its
+/// file size plus four bytes per pixel for every copy of the frame. The file is also written out
+/// in full whenever the project is saved.
+#[derive(Clone)]
+pub struct ImageObject {
+    source: Vec<u8>,
+    image: RgbaImage,
+    position: Point2D<f32>,
+    scale: f32,
+}
+
+impl ImageObject {
+    /// Decode a PNG or JPEG image, placing its top-left corner at the given position.
+    #[inline]
+    pub fn new(source: Vec<u8>, position: Point2D<f32>, scale: f32) -> Result<Self, Error> {
+        let image = image::load_from_memory(&source)
+            .map_err(Error::deserialize)?
+            .into_rgba();
+
+        Ok(Self {
+            source,
+            image,
+            position,
+            scale,
+        })
+    }
+
+    #[inline]
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    #[inline]
+    pub fn position(&self) -> Point2D<f32> {
+        self.position
+    }
+
+    #[inline]
+    pub fn set_position(&mut self, position: Point2D<f32>) {
+        self.position = position;
+    }
+
+    #[inline]
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    #[inline]
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    /// Change the pixels of the image. The source file is replaced with a PNG of the result, so
+    /// the change is kept when the project is saved.
+    pub fn edit_pixels<F: FnOnce(&mut RgbaImage)>(&mut self, f: F) -> Result<(), Error> {
+        f(&mut self.image);
+
+        let mut source = Vec::new();
+        PNGEncoder::new(&mut source)
+            .encode(
+                &self.image,
+                self.image.width(),
+                self.image.height(),
+                ColorType::Rgba8,
+            )
+            .map_err(Error::serialize)?;
+        self.source = source;
+        Ok(())
+    }
+
+    /// The corners of the image on the canvas, clockwise from the top left.
+    #[inline]
+    pub fn corners(&self) -> [Point2D<f32>; 4] {
+        let (x, y) = (self.position.x, self.position.y);
+        let (width, height) = (
+            self.image.width() as f32 * self.scale,
+            self.image.height() as f32 * self.scale,
+        );
+
+        [
+            Point2D::new(x, y),
+            Point2D::new(x + width, y),
+            Point2D::new(x + width, y + height),
+            Point2D::new(x, y + height),
+        ]
+    }
+
+    /// Blit this image onto the target, scaling it with nearest-neighbor sampling.
+    pub fn rasterize(&self, target: &DrawTarget) {
+        // this also skips NaN scales
+        if self.scale.is_nan() || self.scale <= 0.0 {
+            return;
+        }
+
+        let mut img = target.write();
+        img.1 = true;
+        let (width, height) = img.0.dimensions();
+        let [top_left, _, bottom_right, _] = self.corners();
+
+        let clamp = |v: f32, max: u32| v.max(0.0).min(max as f32) as u32;
+        let (min_x, max_x) = (
+            clamp(top_left.x, width),
+            clamp(bottom_right.x.ceil(), width),
+        );
+        let (min_y, max_y) = (
+            clamp(top_left.y, height),
+            clamp(bottom_right.y.ceil(), height),
+        );
+
+        for y in min_y..max_y {
+            let src_y = ((y as f32 - self.position.y) / self.scale) as u32;
+            if src_y >= self.image.height() {
+                continue;
+            }
+
+            for x in min_x..max_x {
+                let src_x = ((x as f32 - self.position.x) / self.scale) as u32;
+                if src_x >= self.image.width() {
+                    continue;
+                }
+
+                let Rgba(src) = *self.image.get_pixel(src_x, src_y);
+                let src = Rgba([widen(src[0]), widen(src[1]), widen(src[2]), widen(src[3])]);
+                let dst = *img.0.get_pixel(x, y);
+                img.0.put_pixel(x, y, BlendMode::Normal.composite(src, dst));
+            }
+        }
+    }
+}
+
+// convert an 8-bit channel to the canvas's color depth
+#[inline]
+fn widen(c: u8) -> ColorAtom {
+    (c as f32 / u8::MAX as f32 * ColorAtom::MAX as f32) as ColorAtom
+}
+
+impl Serialize for ImageObject {
+    #[inline]
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        Serialize::serialize(
+            &ImageObjectSerde {
+                source: EmbeddedBytes(self.source.clone()),
+                position: self.position,
+                scale: self.scale,
+            },
+            ser,
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for ImageObject {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        let repr: ImageObjectSerde = Deserialize::deserialize(de)?;
+        ImageObject::new(repr.source.0, repr.position, repr.scale).map_err(de::Error::custom)
+    }
+}
+
+impl DataObject for ImageObject {
+    #[inline]
+    fn data_type(&self) -> StateDataType {
+        StateDataType::Image
+    }
+
+    // images are selected by their bounding box
+    #[inline]
+    fn points(&self) -> SmallVec<[Vector2F; 4]> {
+        let [a, b, c, d] = self.corners();
+        [[a, b], [b, c], [c, d], [d, a]]
+            .iter()
+            .flat_map(line_to_points)
+            .collect()
+    }
+
+    #[inline]
+    fn into_container(self) -> DataObjectContainer {
+        DataObjectContainer::Image(self)
+    }
+
+    #[inline]
+    fn clone_into_container(&self) -> DataObjectContainer {
+        self.clone().into_container()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TCImage;
+    use parking_lot::RwLock;
+
+    // a 2x2 opaque red PNG
+    fn red_png() -> Vec<u8> {
+        let img = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        let mut bytes = Vec::new();
+        PNGEncoder::new(&mut bytes)
+            .encode(&img, 2, 2, ColorType::Rgba8)
+            .unwrap();
+        bytes
+    }
+
+    fn drawn_pixels(image: &ImageObject) -> usize {
+        let target: DrawTarget = RwLock::new((TCImage::new(8, 8), false));
+        image.rasterize(&target);
+        let img = target.read();
+        img.0.pixels().filter(|p| p[3] > 0).count()
+    }
+
+    #[test]
+    fn images_are_decoded() {
+        let image = ImageObject::new(red_png(), Point2D::new(1.0, 1.0), 1.0).unwrap();
+        assert_eq!(image.image().dimensions(), (2, 2));
+        assert_eq!(image.corners()[2], Point2D::new(3.0, 3.0));
+    }
+
+    #[test]
+    fn garbage_is_not_an_image() {
+        assert!(ImageObject::new(b"not an image".to_vec(), Point2D::zero(), 1.0).is_err());
+    }
+
+    #[test]
+    fn images_round_trip() {
+        let image = ImageObject::new(red_png(), Point2D::new(3.0, 4.0), 2.0).unwrap();
+
+        let json = serde_json::to_string(&image).unwrap();
+        assert!(json.contains(&base64::encode(red_png())));
+        let from_json: ImageObject = serde_json::from_str(&json).unwrap();
+
+        let bytes = bincode::serialize(&image).unwrap();
+        let from_bincode: ImageObject = bincode::deserialize(&bytes).unwrap();
+
+        [from_json, from_bincode].iter().for_each(|loaded| {
+            assert_eq!(loaded.position(), image.position());
+            assert_eq!(loaded.scale(), 2.0);
+            assert_eq!(**loaded.image(), **image.image());
+        });
+    }
+
+    #[test]
+    fn images_are_scaled_when_drawn() {
+        let image = ImageObject::new(red_png(), Point2D::new(1.0, 1.0), 1.0).unwrap();
+        assert_eq!(drawn_pixels(&image), 4);
+
+        let mut image = image;
+        image.set_scale(2.0);
+        assert_eq!(drawn_pixels(&image), 16);
+
+        // past the edge of the canvas, only the part inside is drawn
+        image.set_position(Point2D::new(6.0, 6.0));
+        assert_eq!(drawn_pixels(&image), 4);
+
+        image.set_scale(0.0);
+        assert_eq!(drawn_pixels(&image), 0);
+        image.set_scale(f32::NAN);
+        assert_eq!(drawn_pixels(&image), 0);
+    }
+}
@@ -1,18 +1,47 @@
 // GPL v3.0
 
 mod data;
+mod group;
+mod image_object;
 mod operations;
+mod spatial;
+mod symbol;
 
+use crate::{layout, since, since_or};
 use data::*;
 use rayon::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use smallvec::SmallVec;
+use spatial::SpatialIndex;
 use std::{
     collections::HashMap,
     sync::atomic::{AtomicUsize, Ordering},
 };
 
 pub use data::*;
+pub use group::*;
+pub use image_object::*;
+pub use operations::{Alignment, Axis, FrameStats, Symmetry, SymmetryConfig};
+pub use symbol::*;
+
+/// How long a frame shows for in an animation, in milliseconds, unless it's been given a time.
+pub const DEFAULT_FRAME_DURATION: u32 = 100;
+
+#[inline]
+fn default_duration() -> u32 {
+    DEFAULT_FRAME_DURATION
+}
+
+#[inline]
+fn deserialize_duration<'de, D: Deserializer<'de>>(de: D) -> Result<u32, D::Error> {
+    since_or(de, layout::FRAME_DURATIONS, default_duration)
+}
+
+// frames saved before they had a seed get one when they're loaded, which is then kept
+#[inline]
+fn deserialize_jitter_seed<'de, D: Deserializer<'de>>(de: D) -> Result<u64, D::Error> {
+    since_or(de, layout::JITTER_SEEDS, rand::random)
+}
 
 /// The current graphical state.
 #[derive(Serialize, Deserialize)]
@@ -22,11 +51,34 @@ pub struct GraphicalState {
     lines: HashMap<DataID, StateLine>,
     polygons: HashMap<DataID, Polyshape>,
     filled_polygons: HashMap<DataID, Polyshape>,
+    #[serde(default, deserialize_with = "since::<_, _, { layout::IMAGES }>")]
+    images: HashMap<DataID, ImageObject>,
+    #[serde(default, deserialize_with = "since::<_, _, { layout::SYMBOLS }>")]
+    instances: HashMap<DataID, SymbolInstance>,
+    #[serde(default, deserialize_with = "since::<_, _, { layout::GROUPS }>")]
+    groups: Vec<Group>,
+    // how long this frame shows for when it's part of an animation
+    #[serde(
+        default = "default_duration",
+        deserialize_with = "deserialize_duration"
+    )]
+    duration_ms: u32,
+    // picks how jittered strokes are scattered, mixed with each object's ID so that every
+    // object keeps the same pattern from when it's drawn in
+    #[serde(default = "rand::random", deserialize_with = "deserialize_jitter_seed")]
+    jitter_seed: u64,
     history: Vec<StateOperation>,
 
     next_data_id: AtomicUsize,
     selected: Vec<StateDataLoc>,
     last_history_selected: Option<DataID>,
+    // built when selecting, and thrown away whenever the objects change
+    #[serde(skip)]
+    spatial_index: Option<SpatialIndex>,
+    // whether a gesture is under way, and if so whether it has moved anything yet, so that all
+    // of its moves are undone together
+    #[serde(skip)]
+    gesture: Option<bool>,
 }
 
 impl GraphicalState {
@@ -38,10 +90,17 @@ impl GraphicalState {
             lines: HashMap::new(),
             polygons: HashMap::new(),
             filled_polygons: HashMap::new(),
+            images: HashMap::new(),
+            instances: HashMap::new(),
+            groups: Vec::new(),
+            duration_ms: DEFAULT_FRAME_DURATION,
+            jitter_seed: rand::random(),
             history: Vec::new(),
             selected: Vec::new(),
             last_history_selected: None,
             next_data_id: AtomicUsize::new(0),
+            spatial_index: None,
+            gesture: None,
         }
     }
 
@@ -52,6 +111,7 @@ impl GraphicalState {
 
     #[inline]
     pub fn curves_mut(&mut self) -> &mut HashMap<DataID, Curve> {
+        self.invalidate_spatial_index();
         &mut self.curves
     }
 
@@ -62,6 +122,7 @@ impl GraphicalState {
 
     #[inline]
     pub fn lines_mut(&mut self) -> &mut HashMap<DataID, StateLine> {
+        self.invalidate_spatial_index();
         &mut self.lines
     }
 
@@ -72,9 +133,40 @@ impl GraphicalState {
 
     #[inline]
     pub fn polygons_mut(&mut self) -> &mut HashMap<DataID, Polyshape> {
+        self.invalidate_spatial_index();
         &mut self.polygons
     }
 
+    #[inline]
+    pub fn images(&self) -> &HashMap<DataID, ImageObject> {
+        &self.images
+    }
+
+    #[inline]
+    pub fn images_mut(&mut self) -> &mut HashMap<DataID, ImageObject> {
+        self.invalidate_spatial_index();
+        &mut self.images
+    }
+
+    /// The symbols placed in this frame.
+    #[inline]
+    pub fn instances(&self) -> &HashMap<DataID, SymbolInstance> {
+        &self.instances
+    }
+
+    #[inline]
+    pub fn instances_mut(&mut self) -> &mut HashMap<DataID, SymbolInstance> {
+        self.invalidate_spatial_index();
+        &mut self.instances
+    }
+
+    /// Forget the index used to find objects near a point. Anything that adds, removes or moves
+    /// an object has to call this.
+    #[inline]
+    pub(crate) fn invalidate_spatial_index(&mut self) {
+        self.spatial_index = None;
+    }
+
     #[inline]
     pub fn history(&self) -> &[StateOperation] {
         &self.history
@@ -83,6 +175,7 @@ impl GraphicalState {
     /// Update the history to go below the history limit.
     #[inline]
     pub fn update_history_add(&mut self, kind: StateDataType, last_id: usize, item_num: usize) {
+        self.invalidate_spatial_index();
         if item_num == 0 {
             return;
         }
@@ -108,6 +201,14 @@ impl GraphicalState {
             .map(|(i, d)| (*i, d as _))
             .chain(self.curves.iter().map(|(i, d)| (*i, d as _)))
             .chain(self.lines.iter().map(|(i, d)| (*i, d as _)))
+            .chain(self.images.iter().map(|(i, d)| (*i, d as _)))
+            .chain(self.instances.iter().map(|(i, d)| (*i, d as _)))
+    }
+
+    /// Tell whether there's nothing in this frame, not counting buffered lines.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.iter_data_objects().next().is_none()
     }
 
     /// Get the next iteration of the data ID.
@@ -123,8 +224,78 @@ impl GraphicalState {
             .collect()
     }
 
+    /// How long this frame shows for in an animation, in milliseconds.
+    #[inline]
+    pub fn duration_ms(&self) -> u32 {
+        self.duration_ms
+    }
+
+    /// Set how long this frame shows for. Every frame shows for at least a millisecond.
+    #[inline]
+    pub fn set_duration_ms(&mut self, duration_ms: u32) {
+        self.duration_ms = duration_ms.max(1);
+    }
+
+    /// The seed jittered strokes in this frame are scattered with, unless the project has one.
+    #[inline]
+    pub fn jitter_seed(&self) -> u64 {
+        self.jitter_seed
+    }
+
     /// Get the current iteration of the Data ID.
     pub fn current_data_id(&self) -> DataID {
         self.next_data_id.load(Ordering::SeqCst)
     }
 }
+
+// ways of building up frames for tests
+#[cfg(test)]
+impl GraphicalState {
+    pub(crate) fn test_line(&mut self, from: (f32, f32), to: (f32, f32)) -> StateDataLoc {
+        use euclid::default::Point2D;
+
+        self.add_buffered_line(Point2D::new(from.0, from.1), Point2D::new(to.0, to.1));
+        self.convert_buffered_lines(0);
+        StateDataLoc(StateDataType::Line, self.current_data_id() - 1)
+    }
+
+    pub(crate) fn test_curve(&mut self, points: [(f32, f32); 4]) -> StateDataLoc {
+        use crate::BezierCurve;
+        use pathfinder_geometry::vector::Vector2F;
+
+        let [a, b, c, d] = points;
+        let v = |(x, y): (f32, f32)| Vector2F::new(x, y);
+        self.add_curves(0, Some(BezierCurve::from_points([v(a), v(b), v(c), v(d)])));
+        StateDataLoc(StateDataType::Curve, self.current_data_id() - 1)
+    }
+
+    pub(crate) fn test_polygon(&mut self, corners: &[(f32, f32)]) -> StateDataLoc {
+        use crate::{Polygon, PolygonType};
+        use pathfinder_geometry::{line_segment::LineSegment2F, vector::Vector2F};
+
+        let v = |(x, y): (f32, f32)| Vector2F::new(x, y);
+        let edges = corners
+            .iter()
+            .zip(corners.iter().cycle().skip(1))
+            .map(|(a, b)| LineSegment2F::new(v(*a), v(*b)));
+        let loc = StateDataLoc(StateDataType::Polygon, self.next_data_id());
+        self.invalidate_spatial_index();
+        self.polygons.insert(
+            loc.1,
+            Polyshape {
+                polygon: Polygon::new(edges, PolygonType::Fill),
+                brush: 0.into(),
+            },
+        );
+        self.history.push(StateOperation::Add(loc));
+        loc
+    }
+
+    pub(crate) fn select_all(&mut self) {
+        self.selected = self.creation_order();
+    }
+
+    pub(crate) fn select_only(&mut self, items: &[StateDataLoc]) {
+        self.selected = items.to_vec();
+    }
+}
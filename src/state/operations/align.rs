@@ -0,0 +1,212 @@
+// GPLv3 License
+
+use super::{group::translate_item, GraphicalState, StateDataLoc};
+use euclid::default::Rect;
+use itertools::Itertools;
+use pathfinder_geometry::vector::Vector2F;
+
+/// Which edge or center of the selection to line objects up with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// Line up the centers of the objects on a vertical line.
+    CenterHorizontal,
+    /// Line up the centers of the objects on a horizontal line.
+    CenterVertical,
+}
+
+impl Alignment {
+    #[inline]
+    pub fn from_char(c: char) -> Option<Alignment> {
+        Some(match c {
+            'l' => Self::Left,
+            'r' => Self::Right,
+            't' => Self::Top,
+            'b' => Self::Bottom,
+            'h' => Self::CenterHorizontal,
+            'v' => Self::CenterVertical,
+            _ => return None,
+        })
+    }
+}
+
+/// A direction to spread objects out in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+impl Axis {
+    #[inline]
+    pub fn from_char(c: char) -> Option<Axis> {
+        Some(match c {
+            'h' => Self::Horizontal,
+            'v' => Self::Vertical,
+            _ => return None,
+        })
+    }
+}
+
+// a set of items that move together, along with their combined bounds
+struct Unit {
+    items: Vec<StateDataLoc>,
+    bounds: Rect<f32>,
+}
+
+impl GraphicalState {
+    // the selected items, with each group kept together as one unit
+    fn selected_units(&self) -> Vec<Unit> {
+        let units: Vec<Vec<StateDataLoc>> = self
+            .selected
+            .iter()
+            .map(|loc| match self.group_of(loc) {
+                Some(group) => group.members.clone(),
+                None => vec![*loc],
+            })
+            .unique()
+            .collect();
+
+        units
+            .into_iter()
+            .map(|items| {
+                let bounds = items
+                    .iter()
+                    .map(|loc| loc.item(self).bounding_box())
+                    .fold1(|a, b| a.union(&b))
+                    .unwrap_or_else(Rect::zero);
+                Unit { items, bounds }
+            })
+            .collect()
+    }
+
+    // move every item in a unit
+    fn translate_unit(&mut self, unit: &Unit, offset: Vector2F) {
+        self.invalidate_spatial_index();
+        unit.items.iter().for_each(|loc| {
+            let mut item = loc.take_item(self);
+            translate_item(&mut item, offset);
+            loc.0.assoc_collection_mut(self).insert(loc.1, item);
+        });
+    }
+
+    /// Line up the selected objects by their bounding boxes, against the bounds of the whole
+    /// selection. Grouped objects move as one.
+    pub fn align_selected(&mut self, alignment: Alignment) {
+        let units = self.selected_units();
+        let whole = match units.iter().map(|u| u.bounds).fold1(|a, b| a.union(&b)) {
+            Some(whole) => whole,
+            None => return,
+        };
+
+        units.iter().for_each(|unit| {
+            let b = unit.bounds;
+            let offset = match alignment {
+                Alignment::Left => Vector2F::new(whole.min_x() - b.min_x(), 0.0),
+                Alignment::Right => Vector2F::new(whole.max_x() - b.max_x(), 0.0),
+                Alignment::Top => Vector2F::new(0.0, whole.min_y() - b.min_y()),
+                Alignment::Bottom => Vector2F::new(0.0, whole.max_y() - b.max_y()),
+                Alignment::CenterHorizontal => Vector2F::new(whole.center().x - b.center().x, 0.0),
+                Alignment::CenterVertical => Vector2F::new(0.0, whole.center().y - b.center().y),
+            };
+            self.translate_unit(unit, offset);
+        });
+    }
+
+    /// Space the selected objects out so the gaps between them are even, keeping the first and
+    /// last ones where they are. Grouped objects move as one.
+    pub fn distribute_selected(&mut self, axis: Axis) {
+        type Measure = fn(&Rect<f32>) -> f32;
+        let (start, size): (Measure, Measure) = match axis {
+            Axis::Horizontal => (|r| r.min_x(), |r| r.size.width),
+            Axis::Vertical => (|r| r.min_y(), |r| r.size.height),
+        };
+
+        let mut units = self.selected_units();
+        if units.len() < 3 {
+            return;
+        }
+        units.sort_by(|a, b| start(&a.bounds).partial_cmp(&start(&b.bounds)).unwrap());
+
+        let (first, last) = (&units[0].bounds, &units[units.len() - 1].bounds);
+        let span = start(last) + size(last) - start(first);
+        let taken: f32 = units.iter().map(|u| size(&u.bounds)).sum();
+        let gap = (span - taken) / (units.len() - 1) as f32;
+
+        let mut position = start(first);
+        units.iter().for_each(|unit| {
+            let shift = position - start(&unit.bounds);
+            let offset = match axis {
+                Axis::Horizontal => Vector2F::new(shift, 0.0),
+                Axis::Vertical => Vector2F::new(0.0, shift),
+            };
+            self.translate_unit(unit, offset);
+            position += size(&unit.bounds) + gap;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // where the bounds of each item start
+    fn min_xs(state: &GraphicalState, items: &[StateDataLoc]) -> Vec<f32> {
+        items
+            .iter()
+            .map(|loc| loc.item(state).bounding_box().min_x())
+            .collect()
+    }
+
+    #[test]
+    fn left_edges_line_up() {
+        let mut state = GraphicalState::new();
+        let items = [
+            state.test_line((5.0, 0.0), (20.0, 0.0)),
+            state.test_polygon(&[(12.0, 10.0), (20.0, 10.0), (16.0, 18.0)]),
+            state.test_curve([(8.0, 30.0), (10.0, 40.0), (14.0, 40.0), (16.0, 30.0)]),
+        ];
+        state.select_only(&items);
+        state.align_selected(Alignment::Left);
+        assert_eq!(min_xs(&state, &items), vec![5.0, 5.0, 5.0]);
+
+        state.align_selected(Alignment::Right);
+        let max_xs: Vec<f32> = items
+            .iter()
+            .map(|loc| loc.item(&state).bounding_box().max_x())
+            .collect();
+        assert_eq!(max_xs, vec![20.0, 20.0, 20.0]);
+    }
+
+    #[test]
+    fn gaps_are_evened_out() {
+        let mut state = GraphicalState::new();
+        let items = [
+            state.test_line((0.0, 0.0), (10.0, 0.0)),
+            state.test_line((50.0, 5.0), (60.0, 5.0)),
+            state.test_line((13.0, 10.0), (23.0, 10.0)),
+        ];
+        state.select_only(&items);
+        state.distribute_selected(Axis::Horizontal);
+        // the ends stay put, and the gaps are (60 - 30) / 2 wide
+        assert_eq!(min_xs(&state, &items), vec![0.0, 50.0, 25.0]);
+    }
+
+    #[test]
+    fn groups_are_aligned_as_one() {
+        let mut state = GraphicalState::new();
+        let a = state.test_line((10.0, 0.0), (20.0, 0.0));
+        let b = state.test_line((30.0, 5.0), (40.0, 5.0));
+        let c = state.test_line((0.0, 10.0), (5.0, 10.0));
+        state.select_only(&[a, b]);
+        state.group_selected("pair".to_string()).unwrap();
+
+        state.select_only(&[a, c]);
+        state.align_selected(Alignment::Left);
+        // the group keeps its shape
+        assert_eq!(min_xs(&state, &[a, b, c]), vec![0.0, 20.0, 0.0]);
+    }
+}
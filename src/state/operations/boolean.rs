@@ -0,0 +1,109 @@
+// GPLv3 License
+
+use super::{GraphicalState, Polyshape, StateDataLoc, StateDataType, StateOperation};
+use crate::{BooleanOp, Error, DEFAULT_TOLERANCE};
+
+impl GraphicalState {
+    /// Combine the two selected polygons, replacing them with the result. The first one selected
+    /// is the one the second is subtracted from, and its brush is kept.
+    pub fn boolean_selected(&mut self, op: BooleanOp) -> Result<(), Error> {
+        let (first, second) = match self.selected[..] {
+            [first, second] => (first, second),
+            _ => {
+                return Err(Error::InvalidInput(
+                    "select exactly two polygons to combine",
+                ))
+            }
+        };
+
+        let (subject, clip) = match (self.polygons.get(&first.1), self.polygons.get(&second.1)) {
+            (Some(subject), Some(clip))
+                if first.0 == StateDataType::Polygon && second.0 == StateDataType::Polygon =>
+            {
+                (subject, clip)
+            }
+            _ => {
+                return Err(Error::InvalidInput(
+                    "boolean operations can only be applied to polygons",
+                ))
+            }
+        };
+
+        let brush = subject.brush;
+        let result = subject
+            .polygon
+            .boolean(&clip.polygon, op, DEFAULT_TOLERANCE)?;
+
+        self.selected.clear();
+        self.invalidate_spatial_index();
+        self.polygons.remove(&first.1);
+        self.polygons.remove(&second.1);
+        self.history
+            .retain(|op| op.added().is_none_or(|loc| loc != first && loc != second));
+        self.last_history_selected.take();
+
+        if let Some(polygon) = result {
+            let did = self.next_data_id();
+            self.history.push(StateOperation::Add(StateDataLoc(
+                StateDataType::Polygon,
+                did,
+            )));
+            self.polygons.insert(did, Polyshape { polygon, brush });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[inline]
+    fn overlapping_squares() -> GraphicalState {
+        let mut state = GraphicalState::new();
+        state.test_polygon(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+        state.test_polygon(&[(5.0, 5.0), (15.0, 5.0), (15.0, 15.0), (5.0, 15.0)]);
+        state.select_all();
+        state
+    }
+
+    #[test]
+    fn selected_polygons_are_replaced() {
+        let mut state = overlapping_squares();
+        state.boolean_selected(BooleanOp::Intersection).unwrap();
+
+        assert_eq!(state.polygons().len(), 1);
+        assert!(state.selected.is_empty());
+        assert_eq!(state.history().len(), 1);
+        let loc = state.history()[0].added().unwrap();
+        let area = state.polygons()[&loc.1].polygon.area(DEFAULT_TOLERANCE);
+        assert!((area - 25.0).abs() < 0.1, "area is {}", area);
+    }
+
+    #[test]
+    fn nothing_left_over_removes_both() {
+        let mut state = GraphicalState::new();
+        state.test_polygon(&[(0.0, 0.0), (5.0, 0.0), (5.0, 5.0)]);
+        state.test_polygon(&[(10.0, 10.0), (15.0, 10.0), (15.0, 15.0)]);
+        state.select_all();
+        state.boolean_selected(BooleanOp::Intersection).unwrap();
+
+        assert!(state.polygons().is_empty());
+        assert!(state.history().is_empty());
+    }
+
+    #[test]
+    fn only_pairs_of_polygons_combine() {
+        let mut state = overlapping_squares();
+        state.selected.pop();
+        assert!(state.boolean_selected(BooleanOp::Union).is_err());
+
+        let mut state = GraphicalState::new();
+        state.test_polygon(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        state.test_line((0.0, 0.0), (10.0, 10.0));
+        state.select_all();
+        assert!(state.boolean_selected(BooleanOp::Union).is_err());
+        assert_eq!(state.polygons().len(), 1);
+    }
+}
@@ -1,7 +1,7 @@
 // GPLv3 License
 
 use super::{BufferedLine, Curve, GraphicalState, StateDataType, StateLine};
-use crate::BezierCurve;
+use crate::{simplify_points, BezierCurve, FitMethod};
 use euclid::default::Point2D;
 use pathfinder_geometry::vector::Vector2F;
 use smallvec::SmallVec;
@@ -13,6 +13,18 @@ impl GraphicalState {
         self.buffered_lines.push(BufferedLine([pt1, pt2]));
     }
 
+    /// Remove the buffered lines, so they can be put back later.
+    #[inline]
+    pub fn take_buffered_lines(&mut self) -> SmallVec<[BufferedLine; 12]> {
+        self.buffered_lines.drain(..).collect()
+    }
+
+    /// Put back buffered lines that were taken out.
+    #[inline]
+    pub fn restore_buffered_lines(&mut self, lines: SmallVec<[BufferedLine; 12]>) {
+        self.buffered_lines.extend(lines);
+    }
+
     /// Drop the buffered lines.
     #[inline]
     pub fn drop_buffered_lines(&mut self) {
@@ -32,7 +44,16 @@ impl GraphicalState {
             .buffered_lines
             .drain(..)
             .enumerate()
-            .map(|(i, f)| (data_ids[i], StateLine { points: f.0, brush }))
+            .map(|(i, f)| {
+                (
+                    data_ids[i],
+                    StateLine {
+                        points: f.0,
+                        brush: brush.into(),
+                        widths: None,
+                    },
+                )
+            })
             .collect::<SmallVec<[(usize, StateLine); 10]>>();
 
         let len = lines.len();
@@ -40,8 +61,60 @@ impl GraphicalState {
         self.lines.extend(lines);
     }
 
-    /// Convert the buffered items into a bezier curve.
-    pub fn bezierify_buffered_lines(&mut self, brush: usize, error: f32) {
+    /// Convert the buffered items into lines that taper from one width to another, spread over
+    /// the length of the whole stroke.
+    pub fn convert_buffered_lines_tapered(&mut self, brush: usize, start: f32, end: f32) {
+        let data_id = self.current_data_id();
+        let data_ids = self.next_data_ids(self.buffered_lines.len());
+
+        let lengths: SmallVec<[f32; 12]> = self
+            .buffered_lines
+            .iter()
+            .map(|BufferedLine([pt1, pt2])| pt1.distance_to(*pt2))
+            .collect();
+        let total: f32 = lengths.iter().sum();
+
+        // figure out how far along the stroke each line starts and ends
+        let mut travelled = 0.0;
+        let lines = self
+            .buffered_lines
+            .drain(..)
+            .zip(lengths)
+            .enumerate()
+            .map(|(i, (f, len))| {
+                let width_at = |dist: f32| {
+                    let t = if total > 0.0 { dist / total } else { 0.0 };
+                    start + (end - start) * t
+                };
+                let widths = [width_at(travelled), width_at(travelled + len)];
+                travelled += len;
+
+                (
+                    data_ids[i],
+                    StateLine {
+                        points: f.0,
+                        brush: brush.into(),
+                        widths: Some(widths),
+                    },
+                )
+            })
+            .collect::<SmallVec<[(usize, StateLine); 10]>>();
+
+        let len = lines.len();
+        self.update_history_add(StateDataType::Line, data_id, len);
+        self.lines.extend(lines);
+    }
+
+    /// Convert the buffered items into a bezier curve. Points within `simplify` pixels of the
+    /// simplified path are dropped before fitting. Returns the points that were fitted and the
+    /// curves that were fitted to them.
+    pub fn bezierify_buffered_lines(
+        &mut self,
+        brush: usize,
+        error: f32,
+        simplify: f32,
+        method: FitMethod,
+    ) -> (SmallVec<[Vector2F; 12]>, Vec<BezierCurve>) {
         let pts: SmallVec<[Vector2F; 12]> = self
             .buffered_lines
             .drain(..)
@@ -49,10 +122,27 @@ impl GraphicalState {
             .map(|pt| Vector2F::new(pt.x, pt.y))
             .collect();
 
+        let pts = simplify_points(&pts, simplify);
+        let fitted = BezierCurve::fit_with(pts.clone(), error, method);
+        self.add_curves(brush, fitted.iter().cloned());
+
+        (pts, fitted)
+    }
+
+    /// Add a set of curves, all drawn with the same brush.
+    pub fn add_curves<I: IntoIterator<Item = BezierCurve>>(&mut self, brush: usize, curves: I) {
         let data_id = self.current_data_id();
-        let curves = BezierCurve::fit_to(pts, error)
+        let curves = curves
             .into_iter()
-            .map(|v| (self.next_data_id(), Curve { curve: v, brush }))
+            .map(|v| {
+                (
+                    self.next_data_id(),
+                    Curve {
+                        curve: v,
+                        brush: brush.into(),
+                    },
+                )
+            })
             .collect::<SmallVec<[(usize, Curve); 10]>>();
         let len = curves.len();
 
@@ -0,0 +1,57 @@
+// GPLv3 License
+
+use super::{group::translate_item, GraphicalState, StateDataLoc};
+use pathfinder_geometry::vector::Vector2F;
+
+impl GraphicalState {
+    /// Copy the selected items, moved by an offset, and select the copies instead. Doing this
+    /// again copies the copies, so each one steps further along.
+    pub fn duplicate_selected(&mut self, offset: Vector2F) {
+        self.invalidate_spatial_index();
+        let copies: Vec<StateDataLoc> = self
+            .selected
+            .clone()
+            .into_iter()
+            .map(|loc| {
+                let StateDataLoc(ty, _) = loc;
+                let mut item = loc.item(self).clone_into_container();
+                translate_item(&mut item, offset);
+
+                let did = self.next_data_id();
+                ty.assoc_collection_mut(self).insert(did, item);
+                self.update_history_add(ty, did, 1);
+                StateDataLoc(ty, did)
+            })
+            .collect();
+
+        self.unselect();
+        self.selected = copies;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicates_are_selected_and_offset() {
+        let mut state = GraphicalState::new();
+        let line = state.test_line((0.0, 0.0), (10.0, 0.0));
+        state.select_only(&[line]);
+        let step = Vector2F::new(10.0, 10.0);
+
+        state.duplicate_selected(step);
+        assert_eq!(state.lines().len(), 2);
+        let copy = state.selected()[0];
+        assert_ne!(copy, line);
+        assert_eq!(state.selected().len(), 1);
+        assert_eq!(state.lines()[&copy.1].points[0].y, 10.0);
+        // the original stays where it was
+        assert_eq!(state.lines()[&line.1].points[0].y, 0.0);
+
+        // and duplicating again steps further along
+        state.duplicate_selected(step);
+        let again = state.selected()[0];
+        assert_eq!(state.lines()[&again.1].points[0].y, 20.0);
+    }
+}
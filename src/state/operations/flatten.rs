@@ -0,0 +1,40 @@
+// GPLv3 License
+
+use super::{GraphicalState, StateDataLoc, StateDataType};
+use crate::Error;
+use image::RgbaImage;
+
+impl GraphicalState {
+    /// Move every object out of this frame and into a frame of their own. Buffered lines stay
+    /// where they are.
+    #[inline]
+    pub fn take_objects(&mut self) -> GraphicalState {
+        self.selected = self.creation_order();
+        self.take_selected()
+    }
+
+    /// Run a filter over the selected images, or over every image if none are selected.
+    pub fn filter_images<F: Fn(&mut RgbaImage)>(&mut self, filter: F) -> Result<(), Error> {
+        let selected: Vec<usize> = self
+            .selected
+            .iter()
+            .filter(|StateDataLoc(ty, _)| *ty == StateDataType::Image)
+            .map(|StateDataLoc(_, i)| *i)
+            .collect();
+
+        self.images
+            .iter_mut()
+            .filter(|(i, _)| selected.is_empty() || selected.contains(i))
+            .try_for_each(|(_, img)| img.edit_pixels(&filter))
+    }
+
+    /// Put objects taken out by `take_objects` back into this frame, under their old IDs.
+    pub fn restore_objects(&mut self, objects: GraphicalState) {
+        self.invalidate_spatial_index();
+        objects.creation_order().into_iter().for_each(|loc| {
+            let StateDataLoc(ty, i) = loc;
+            let item = loc.item(&objects).clone_into_container();
+            ty.assoc_collection_mut(self).insert(i, item);
+        });
+    }
+}
@@ -0,0 +1,207 @@
+// GPLv3 License
+
+use super::{
+    Curve, DataObject, DataObjectContainer, GraphicalState, Group, Polyshape, StateDataLoc,
+    StateLine,
+};
+use crate::Error;
+use euclid::default::{Point2D, Vector2D};
+use itertools::Itertools;
+use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+
+// move an item, of any kind
+#[inline]
+pub(super) fn translate_item(item: &mut DataObjectContainer, offset: Vector2F) {
+    let euclid_offset: Vector2D<f32> = Vector2D::new(offset.x(), offset.y());
+    match item {
+        DataObjectContainer::Curve(Curve { ref mut curve, .. }) => curve.translate(offset),
+        DataObjectContainer::StateLine(StateLine { ref mut points, .. }) => {
+            points.iter_mut().for_each(|p| *p += euclid_offset)
+        }
+        DataObjectContainer::Polyshape(Polyshape {
+            ref mut polygon, ..
+        }) => polygon.translate(offset),
+        DataObjectContainer::Image(ref mut img) => {
+            let pos = img.position() + euclid_offset;
+            img.set_position(pos);
+        }
+        DataObjectContainer::Symbol(ref mut instance) => instance.translate(euclid_offset),
+    }
+}
+
+impl GraphicalState {
+    #[inline]
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+
+    /// The group an item is in, if any.
+    #[inline]
+    pub fn group_of(&self, loc: &StateDataLoc) -> Option<&Group> {
+        self.groups.iter().find(|g| g.contains(loc))
+    }
+
+    /// Put the selected items into a new group, taking them out of any groups they were in.
+    pub fn group_selected(&mut self, name: String) -> Result<(), Error> {
+        if self.selected.is_empty() {
+            return Err(Error::InvalidInput("nothing is selected"));
+        }
+        if self.groups.iter().any(|g| g.name == name) {
+            return Err(Error::InvalidInput(
+                "there is already a group with that name",
+            ));
+        }
+
+        let members = self.selected.clone();
+        self.groups
+            .iter_mut()
+            .for_each(|g| g.members.retain(|m| !members.contains(m)));
+        self.groups.retain(|g| !g.members.is_empty());
+        self.groups.push(Group::new(name, members));
+        Ok(())
+    }
+
+    /// Break up every group that has a selected item in it. The items stay selected.
+    #[inline]
+    pub fn ungroup_selected(&mut self) {
+        let selected = &self.selected;
+        self.groups
+            .retain(|g| !g.members.iter().any(|m| selected.contains(m)));
+    }
+
+    /// Add the rest of the members of any group with a selected item to the selection.
+    #[inline]
+    pub fn select_whole_groups(&mut self) {
+        self.selected = self.selected_with_groups();
+    }
+
+    /// Forget the group members that no longer exist, and any groups left empty.
+    pub(crate) fn prune_groups(&mut self) {
+        let mut groups = std::mem::take(&mut self.groups);
+        groups.iter_mut().for_each(|g| {
+            g.members
+                .retain(|StateDataLoc(ty, i)| ty.assoc_collection(self).data_at(*i).is_some())
+        });
+        groups.retain(|g| !g.members.is_empty());
+        self.groups = groups;
+    }
+
+    // the selected items, along with everything grouped with them
+    fn selected_with_groups(&self) -> Vec<StateDataLoc> {
+        let grouped: Vec<StateDataLoc> = self
+            .groups
+            .iter()
+            .filter(|g| g.members.iter().any(|m| self.selected.contains(m)))
+            .flat_map(|g| g.members.iter().copied())
+            .collect();
+        self.selected
+            .iter()
+            .copied()
+            .chain(grouped)
+            .unique()
+            .collect()
+    }
+
+    // run something over the selected items and their groups, putting them back afterwards
+    // and returning where they are
+    fn edit_selected_with_groups<F: FnMut(&mut DataObjectContainer)>(
+        &mut self,
+        mut f: F,
+    ) -> Vec<StateDataLoc> {
+        self.invalidate_spatial_index();
+        let items = self.selected_with_groups();
+        items.iter().for_each(|loc| {
+            let mut item = loc.take_item(self);
+            f(&mut item);
+            loc.0.assoc_collection_mut(self).insert(loc.1, item);
+        });
+        items
+    }
+
+    /// Move the selected items, and everything grouped with them, by an offset.
+    #[inline]
+    pub fn translate_selected(&mut self, offset: Vector2F) {
+        let items = self.edit_selected_with_groups(|item| translate_item(item, offset));
+        if !items.is_empty() {
+            self.record_move(items, offset);
+        }
+    }
+
+    /// Transform the selected items, and everything grouped with them. Images and symbols
+    /// can't be rotated or scaled, so they're only moved along with where their corner goes.
+    pub fn transform_selected(&mut self, transform: &Transform2F) {
+        self.edit_selected_with_groups(|item| match item {
+            DataObjectContainer::Image(img) => {
+                let pos = img.position();
+                let moved = *transform * Vector2F::new(pos.x, pos.y);
+                img.set_position(Point2D::new(moved.x(), moved.y()));
+            }
+            DataObjectContainer::Symbol(instance) => {
+                let corner = instance.bounding_box().origin;
+                let moved = *transform * Vector2F::new(corner.x, corner.y);
+                instance.translate(Vector2D::new(moved.x() - corner.x, moved.y() - corner.y));
+            }
+            item => super::transform::transform_item(item, transform),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grouped_lines_move_together() {
+        let mut state = GraphicalState::new();
+        let a = state.test_line((0.0, 0.0), (10.0, 0.0));
+        let b = state.test_line((0.0, 5.0), (10.0, 5.0));
+        let loose = state.test_line((0.0, 10.0), (10.0, 10.0));
+        state.select_only(&[a, b]);
+        state.group_selected("pair".to_string()).unwrap();
+
+        // selecting one member is enough to move the whole group
+        state.select_only(&[a]);
+        state.translate_selected(Vector2F::new(3.0, 0.0));
+        let starts: Vec<f32> = [a, b, loose]
+            .iter()
+            .map(|l| state.lines()[&l.1].points[0].x)
+            .collect();
+        assert_eq!(starts, vec![3.0, 3.0, 0.0]);
+
+        // until the group is broken up
+        state.ungroup_selected();
+        assert!(state.groups().is_empty());
+        state.translate_selected(Vector2F::new(3.0, 0.0));
+        assert_eq!(state.lines()[&a.1].points[0].x, 6.0);
+        assert_eq!(state.lines()[&b.1].points[0].x, 3.0);
+    }
+
+    #[test]
+    fn items_are_in_one_group_at_most() {
+        let mut state = GraphicalState::new();
+        let a = state.test_line((0.0, 0.0), (10.0, 0.0));
+        let b = state.test_line((0.0, 5.0), (10.0, 5.0));
+        state.select_only(&[a, b]);
+        state.group_selected("first".to_string()).unwrap();
+        state.select_only(&[b]);
+        assert!(state.group_selected("first".to_string()).is_err());
+        state.group_selected("second".to_string()).unwrap();
+
+        assert_eq!(state.group_of(&a).unwrap().name, "first");
+        assert_eq!(state.group_of(&b).unwrap().name, "second");
+        state.select_only(&[]);
+        assert!(state.group_selected("empty".to_string()).is_err());
+    }
+
+    #[test]
+    fn groups_are_saved() {
+        let mut state = GraphicalState::new();
+        let a = state.test_line((0.0, 0.0), (10.0, 0.0));
+        state.select_only(&[a]);
+        state.group_selected("solo".to_string()).unwrap();
+
+        let bytes = bincode::serialize(&state).unwrap();
+        let loaded: GraphicalState = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded.group_of(&a).unwrap().name, "solo");
+    }
+}
@@ -0,0 +1,331 @@
+// GPLv3 License
+
+use super::{
+    Curve, DataID, DataObject, DataObjectContainer, GraphicalState, ImageObject, Polyshape,
+    StateDataLoc, StateDataType, StateLine, StateOperation, HISTORY_LIMIT,
+};
+use crate::{BezierCurve, Error, Polygon, PolygonType};
+use euclid::default::Point2D;
+use pathfinder_geometry::vector::Vector2F;
+use roxmltree::{Document, Node};
+use svgtypes::{Length, Path, PathSegment, PointsParser};
+
+#[inline]
+fn to_point(v: Vector2F) -> Point2D<f32> {
+    Point2D::new(v.x(), v.y())
+}
+
+// read a numerical attribute from an element, ignoring its units
+#[inline]
+fn length_attribute(node: &Node, name: &str) -> f32 {
+    node.attribute(name)
+        .and_then(|s| s.parse::<Length>().ok())
+        .map(|l| l.num as f32)
+        .unwrap_or(0.0)
+}
+
+// SVG shapes are filled unless told otherwise
+#[inline]
+fn polygon_type(node: &Node) -> PolygonType {
+    match node.attribute("fill") {
+        Some("none") => PolygonType::Outline,
+        _ => PolygonType::Fill,
+    }
+}
+
+// build a closed polygon out of a list of points
+#[inline]
+fn closed_polygon(points: &[Vector2F], mode: PolygonType, brush: usize) -> Option<Polyshape> {
+    if points.len() < 3 {
+        return None;
+    }
+
+    let edges = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(pt1, pt2)| [to_point(*pt1), to_point(*pt2)]);
+
+    Some(Polyshape {
+        polygon: Polygon::new(edges, mode),
+        brush: brush.into(),
+    })
+}
+
+// convert SVG path data into lines and curves
+fn path_objects(
+    data: &str,
+    brush: usize,
+    objects: &mut Vec<DataObjectContainer>,
+) -> Result<(), Error> {
+    let mut path: Path = data.parse().map_err(Error::deserialize)?;
+    path.conv_to_absolute();
+
+    let mut push_line = |from: Vector2F, to: Vector2F| {
+        objects.push(DataObjectContainer::StateLine(StateLine {
+            points: [to_point(from), to_point(to)],
+            brush: brush.into(),
+            widths: None,
+        }))
+    };
+
+    let mut subpath_start = Vector2F::zero();
+    let mut current = Vector2F::zero();
+
+    // the previous control point, used to reflect smooth curves
+    let mut last_cubic_control: Option<Vector2F> = None;
+    let mut last_quad_control: Option<Vector2F> = None;
+    let mut curves = Vec::new();
+
+    for segment in path.0 {
+        let mut cubic_control = None;
+        let mut quad_control = None;
+
+        match segment {
+            PathSegment::MoveTo { x, y, .. } => {
+                current = Vector2F::new(x as f32, y as f32);
+                subpath_start = current;
+            }
+            PathSegment::LineTo { x, y, .. } => {
+                let next = Vector2F::new(x as f32, y as f32);
+                push_line(current, next);
+                current = next;
+            }
+            PathSegment::HorizontalLineTo { x, .. } => {
+                let next = Vector2F::new(x as f32, current.y());
+                push_line(current, next);
+                current = next;
+            }
+            PathSegment::VerticalLineTo { y, .. } => {
+                let next = Vector2F::new(current.x(), y as f32);
+                push_line(current, next);
+                current = next;
+            }
+            PathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+                ..
+            } => {
+                let (cp1, cp2) = (
+                    Vector2F::new(x1 as f32, y1 as f32),
+                    Vector2F::new(x2 as f32, y2 as f32),
+                );
+                let next = Vector2F::new(x as f32, y as f32);
+                curves.push(BezierCurve::from_points([current, cp1, cp2, next]));
+                cubic_control = Some(cp2);
+                current = next;
+            }
+            PathSegment::SmoothCurveTo { x2, y2, x, y, .. } => {
+                let cp1 = match last_cubic_control {
+                    Some(cp) => current + (current - cp),
+                    None => current,
+                };
+                let cp2 = Vector2F::new(x2 as f32, y2 as f32);
+                let next = Vector2F::new(x as f32, y as f32);
+                curves.push(BezierCurve::from_points([current, cp1, cp2, next]));
+                cubic_control = Some(cp2);
+                current = next;
+            }
+            PathSegment::Quadratic { x1, y1, x, y, .. } => {
+                let cp = Vector2F::new(x1 as f32, y1 as f32);
+                let next = Vector2F::new(x as f32, y as f32);
+                curves.push(BezierCurve::from_quadratic(current, cp, next));
+                quad_control = Some(cp);
+                current = next;
+            }
+            PathSegment::SmoothQuadratic { x, y, .. } => {
+                let cp = match last_quad_control {
+                    Some(cp) => current + (current - cp),
+                    None => current,
+                };
+                let next = Vector2F::new(x as f32, y as f32);
+                curves.push(BezierCurve::from_quadratic(current, cp, next));
+                quad_control = Some(cp);
+                current = next;
+            }
+            PathSegment::EllipticalArc { x, y, .. } => {
+                // arcs aren't supported yet, approximate them with a straight line
+                let next = Vector2F::new(x as f32, y as f32);
+                push_line(current, next);
+                current = next;
+            }
+            PathSegment::ClosePath { .. } => {
+                if current != subpath_start {
+                    push_line(current, subpath_start);
+                }
+                current = subpath_start;
+            }
+        }
+
+        last_cubic_control = cubic_control;
+        last_quad_control = quad_control;
+    }
+
+    objects.extend(curves.into_iter().map(|curve| {
+        DataObjectContainer::Curve(Curve {
+            curve,
+            brush: brush.into(),
+        })
+    }));
+
+    Ok(())
+}
+
+impl GraphicalState {
+    /// Import the shapes in an SVG document, returning the number of objects created.
+    ///
+    /// `<path>` and `<line>` elements become lines and curves, while `<polygon>` and `<rect>`
+    /// elements become polygons. Transforms and styling other than `fill` are ignored.
+    pub fn import_svg(&mut self, svg: &str, brush: usize) -> Result<usize, Error> {
+        let doc = Document::parse(svg).map_err(Error::deserialize)?;
+        let mut objects: Vec<DataObjectContainer> = Vec::new();
+
+        for node in doc.descendants().filter(|n| n.is_element()) {
+            match node.tag_name().name() {
+                "path" => {
+                    if let Some(data) = node.attribute("d") {
+                        path_objects(data, brush, &mut objects)?;
+                    }
+                }
+                "line" => {
+                    let from =
+                        Point2D::new(length_attribute(&node, "x1"), length_attribute(&node, "y1"));
+                    let to =
+                        Point2D::new(length_attribute(&node, "x2"), length_attribute(&node, "y2"));
+                    objects.push(DataObjectContainer::StateLine(StateLine {
+                        points: [from, to],
+                        brush: brush.into(),
+                        widths: None,
+                    }));
+                }
+                "polygon" => {
+                    let points: Vec<Vector2F> =
+                        PointsParser::from(node.attribute("points").unwrap_or(""))
+                            .map(|(x, y)| Vector2F::new(x as f32, y as f32))
+                            .collect();
+                    if let Some(poly) = closed_polygon(&points, polygon_type(&node), brush) {
+                        objects.push(DataObjectContainer::Polyshape(poly));
+                    }
+                }
+                "rect" => {
+                    let (x, y) = (length_attribute(&node, "x"), length_attribute(&node, "y"));
+                    let (width, height) = (
+                        length_attribute(&node, "width"),
+                        length_attribute(&node, "height"),
+                    );
+                    let points = [
+                        Vector2F::new(x, y),
+                        Vector2F::new(x + width, y),
+                        Vector2F::new(x + width, y + height),
+                        Vector2F::new(x, y + height),
+                    ];
+                    if let Some(poly) = closed_polygon(&points, polygon_type(&node), brush) {
+                        objects.push(DataObjectContainer::Polyshape(poly));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // insert the objects under fresh ID's
+        let count = objects.len();
+        let data_ids = self.next_data_ids(count);
+        for (did, item) in data_ids.into_iter().zip(objects) {
+            let kind = item.data_type();
+            self.history
+                .push(StateOperation::Add(StateDataLoc(kind, did)));
+            kind.assoc_collection_mut(self).insert(did, item);
+        }
+
+        while self.history.len() > HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+
+        Ok(count)
+    }
+}
+
+impl GraphicalState {
+    /// Place a PNG or JPEG image into this frame, with its top-left corner at `position`.
+    pub fn import_image(
+        &mut self,
+        bytes: Vec<u8>,
+        position: Point2D<f32>,
+        scale: f32,
+    ) -> Result<DataID, Error> {
+        let image = ImageObject::new(bytes, position, scale)?;
+
+        let did = self.next_data_id();
+        self.invalidate_spatial_index();
+        self.images.insert(did, image);
+        self.history
+            .push(StateOperation::Add(StateDataLoc(StateDataType::Image, did)));
+
+        while self.history.len() > HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+
+        Ok(did)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg">
+        <path d="M 0 0 L 10 0 C 15 0 20 5 20 10 Q 20 20 10 20 Z" />
+        <line x1="0" y1="30" x2="40" y2="30" />
+        <polygon points="0,40 10,40 5,50" />
+        <rect x="20" y="40" width="10" height="10" fill="none" />
+    </svg>"#;
+
+    #[test]
+    fn import_creates_objects() {
+        let mut state = GraphicalState::new();
+        assert_eq!(state.import_svg(SVG, 0).unwrap(), 7);
+
+        // the path has two straight segments, counting the one that closes it, and two curves
+        assert_eq!(state.lines().len(), 3);
+        assert_eq!(state.curves().len(), 2);
+        assert_eq!(state.polygons().len(), 2);
+    }
+
+    #[test]
+    fn imported_objects_get_fresh_ids() {
+        let mut state = GraphicalState::new();
+        state.import_svg(SVG, 0).unwrap();
+        state.import_svg(SVG, 0).unwrap();
+        assert_eq!(state.lines().len(), 6);
+        assert_eq!(state.polygons().len(), 4);
+    }
+
+    #[test]
+    fn quadratics_are_elevated() {
+        let mut state = GraphicalState::new();
+        state
+            .import_svg(r#"<svg><path d="M 0 0 Q 30 30 60 0" /></svg>"#, 0)
+            .unwrap();
+
+        let curve = &state.curves().values().next().unwrap().curve;
+        assert_eq!(
+            curve.points(),
+            &[
+                Vector2F::new(0.0, 0.0),
+                Vector2F::new(20.0, 20.0),
+                Vector2F::new(40.0, 20.0),
+                Vector2F::new(60.0, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_svg_is_an_error() {
+        let mut state = GraphicalState::new();
+        assert!(state.import_svg("<svg><path d=", 0).is_err());
+        assert!(state.lines().is_empty());
+    }
+}
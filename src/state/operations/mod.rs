@@ -2,7 +2,25 @@
 
 pub use super::*;
 
+mod align;
+mod boolean;
 mod buffered;
+mod duplicate;
+mod flatten;
+mod group;
+mod import;
+mod order;
+mod outline;
 mod polygonify;
 mod rasterize;
 mod select;
+mod stats;
+mod symbol;
+mod transform;
+mod tween;
+mod undo;
+mod validate;
+
+pub use align::{Alignment, Axis};
+pub use stats::FrameStats;
+pub use transform::{Symmetry, SymmetryConfig};
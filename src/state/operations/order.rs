@@ -0,0 +1,126 @@
+// GPLv3 License
+
+use super::{GraphicalState, StateDataLoc};
+use itertools::Itertools;
+
+impl GraphicalState {
+    /// Move the selected objects in front of everything else. Objects are drawn in the order
+    /// of their IDs, so this gives each of them a new one, keeping their order among themselves.
+    pub fn bring_selected_to_front(&mut self) {
+        self.invalidate_spatial_index();
+        let selected: Vec<StateDataLoc> = self
+            .selected
+            .iter()
+            .copied()
+            .sorted_by_key(|StateDataLoc(_, i)| *i)
+            .collect();
+
+        self.selected = selected
+            .into_iter()
+            .map(|old| {
+                let StateDataLoc(ty, _) = old;
+                let item = old.take_item(self);
+                let new = StateDataLoc(ty, self.next_data_id());
+                ty.assoc_collection_mut(self).insert(new.1, item);
+                self.relocate(old, new);
+                new
+            })
+            .collect();
+    }
+
+    // point everything that refers to an object at its new location
+    fn relocate(&mut self, old: StateDataLoc, new: StateDataLoc) {
+        self.history.iter_mut().for_each(|op| op.relocate(old, new));
+        self.groups
+            .iter_mut()
+            .flat_map(|group| group.members.iter_mut())
+            .filter(|loc| **loc == old)
+            .for_each(|loc| *loc = new);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{colors, Brush, Project, StateOperation, TCImage, DEFAULT_PARALLEL_THRESHOLD};
+    use parking_lot::RwLock;
+    use rayon::ThreadPoolBuilder;
+
+    #[test]
+    fn front_objects_get_the_newest_ids() {
+        let mut state = GraphicalState::new();
+        let first = state.test_line((0.0, 0.0), (10.0, 0.0));
+        let second = state.test_line((0.0, 5.0), (10.0, 5.0));
+        let third = state.test_line((0.0, 9.0), (10.0, 9.0));
+        state
+            .groups
+            .push(crate::Group::new("pair".to_string(), vec![first, second]));
+
+        state.selected = vec![second, first];
+        state.bring_selected_to_front();
+
+        let order = state.creation_order();
+        assert_eq!(order[0], third);
+        // the selection keeps its own order, and follows the objects
+        assert_eq!(state.selected, order[1..].to_vec());
+        assert_eq!(state.lines()[&order[1].1].points[0].y, 0.0);
+        assert_eq!(state.lines()[&order[2].1].points[0].y, 5.0);
+        assert_eq!(state.groups()[0].members, vec![order[1], order[2]]);
+        assert!(state
+            .history()
+            .iter()
+            .filter_map(StateOperation::added)
+            .all(|loc| state.lines().contains_key(&loc.1)));
+    }
+
+    #[test]
+    fn front_objects_are_drawn_on_top() {
+        let mut project = Project::new(8, 8, 0.5);
+        let red = project.add_brush(Brush::new(colors::RED, 1));
+        let frame = project.current_frame_mut();
+        let under = frame.test_line((0.0, 4.0), (8.0, 4.0));
+        frame.test_line((0.0, 4.0), (8.0, 4.0));
+        frame.lines_mut().get_mut(&under.1).unwrap().brush = red.into();
+
+        let top_color = |project: &Project| {
+            let target = RwLock::new((TCImage::new(8, 8), false));
+            project.current_frame().rasterize(&target, project, 0.25);
+            let img = target.into_inner().0;
+            img.get_pixel(4, 4)[0]
+        };
+        assert_eq!(top_color(&project), 0);
+
+        let frame = project.current_frame_mut();
+        frame.selected = vec![under];
+        frame.bring_selected_to_front();
+        frame.unselect();
+        assert!(top_color(&project) > 0);
+    }
+
+    #[test]
+    fn front_objects_stay_on_top_across_threads() {
+        // enough lines on top of each other to be drawn on several threads
+        let mut project = Project::new(8, 8, 0.5);
+        let red = project.add_brush(Brush::new(colors::RED, 1));
+        let frame = project.current_frame_mut();
+        let lines: Vec<StateDataLoc> = (0..DEFAULT_PARALLEL_THRESHOLD + 16)
+            .map(|_| frame.test_line((0.0, 4.0), (8.0, 4.0)))
+            .collect();
+        frame.lines_mut().get_mut(&lines[0].1).unwrap().brush = red.into();
+
+        let pool = ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let top_color = |project: &Project| {
+            let target = RwLock::new((TCImage::new(8, 8), false));
+            pool.install(|| project.current_frame().rasterize(&target, project, 0.25));
+            let img = target.into_inner().0;
+            img.get_pixel(4, 4)[0]
+        };
+        (0..10).for_each(|_| assert_eq!(top_color(&project), 0));
+
+        let frame = project.current_frame_mut();
+        frame.selected = vec![lines[0]];
+        frame.bring_selected_to_front();
+        frame.unselect();
+        (0..10).for_each(|_| assert!(top_color(&project) > 0));
+    }
+}
@@ -0,0 +1,54 @@
+// GPLv3 License
+
+use super::{GraphicalState, StateDataLoc, StateDataType};
+use crate::{Brush, BrushRef};
+use itertools::Itertools;
+use smallvec::SmallVec;
+
+impl GraphicalState {
+    /// Replace the selected lines and curves with filled polygons covering their strokes, using
+    /// `palette` to look up brush widths. Other selected items are left alone.
+    pub fn outline_selected_items(&mut self, palette: &[Brush]) {
+        let locs: SmallVec<[StateDataLoc; 12]> = self.selected.drain(..).sorted().collect();
+        // brushes are drawn as circles with the brush width as their radius
+        let width_of = |brush: &BrushRef| {
+            brush
+                .resolve(palette)
+                .map(|b| b.width() as f32 * 2.0)
+                .unwrap_or(1.0)
+        };
+
+        let mut outlines = SmallVec::<[_; 12]>::new();
+        let mut removed = SmallVec::<[StateDataLoc; 12]>::new();
+        locs.into_iter().for_each(|loc| match loc.0 {
+            StateDataType::Line => {
+                if let Some(line) = self.lines.remove(&loc.1) {
+                    outlines.push(match line.widths {
+                        // tapered widths are radii, like the brush width
+                        Some([start, end]) => line.to_outline(start.max(end) * 2.0),
+                        None => line.to_outline(width_of(&line.brush)),
+                    });
+                    removed.push(loc);
+                }
+            }
+            StateDataType::Curve => {
+                if let Some(curve) = self.curves.remove(&loc.1) {
+                    outlines.push(curve.to_outline(width_of(&curve.brush)));
+                    removed.push(loc);
+                }
+            }
+            _ => self.selected.push(loc),
+        });
+
+        // don't leave the removed items in the history
+        self.history
+            .retain(|op| op.added().is_none_or(|loc| !removed.contains(&loc)));
+        self.last_history_selected.take();
+
+        let data_id = self.current_data_id();
+        let data_ids = self.next_data_ids(outlines.len());
+        let len = outlines.len();
+        self.polygons.extend(data_ids.into_iter().zip(outlines));
+        self.update_history_add(StateDataType::Polygon, data_id, len);
+    }
+}
@@ -9,14 +9,15 @@ use euclid::default::Point2D;
 use itertools::Itertools;
 use ordered_float::NotNan;
 use pathfinder_geometry::vector::Vector2F;
-use rayon::prelude::*;
 use smallvec::SmallVec;
+use std::iter;
 
 trait HasEndPoints {
     fn endpoint1(&self) -> Vector2F;
     fn endpoint2(&self) -> Vector2F;
     fn set_endpoint1(&mut self, vctr: Vector2F);
     fn set_endpoint2(&mut self, vctr: Vector2F);
+    fn reverse(&mut self);
 }
 
 impl HasEndPoints for BezierCurve {
@@ -36,6 +37,10 @@ impl HasEndPoints for BezierCurve {
     fn set_endpoint2(&mut self, vctr: Vector2F) {
         self.points_mut()[3] = vctr;
     }
+    #[inline]
+    fn reverse(&mut self) {
+        BezierCurve::reverse(self);
+    }
 }
 
 impl<Ln: Line<f32>> HasEndPoints for Ln {
@@ -55,6 +60,12 @@ impl<Ln: Line<f32>> HasEndPoints for Ln {
     fn set_endpoint2(&mut self, vctr: Vector2F) {
         self.set_to(vctr);
     }
+    #[inline]
+    fn reverse(&mut self) {
+        let (from, to): (Vector2F, Vector2F) = (self.from(), self.to());
+        self.set_from(to);
+        self.set_to(from);
+    }
 }
 
 impl HasEndPoints for DataObjectContainer {
@@ -93,6 +104,15 @@ impl HasEndPoints for DataObjectContainer {
             _ => unreachable!(),
         }
     }
+
+    #[inline]
+    fn reverse(&mut self) {
+        match self {
+            Self::Curve(Curve { ref mut curve, .. }) => curve.reverse(),
+            Self::StateLine(StateLine { ref mut points, .. }) => HasEndPoints::reverse(points),
+            _ => unreachable!(),
+        }
+    }
 }
 
 #[inline]
@@ -100,15 +120,14 @@ fn connect_endpoints(
     t1: &mut dyn HasEndPoints,
     t2: &mut dyn HasEndPoints,
     create_new_line: bool,
+    tolerance: f32,
     new_lines: &mut SmallVec<[[Point2D<f32>; 2]; 2]>,
 ) {
-    const TOLERANCE: f32 = 2.0;
-
     // if we're creating a new line OR we're in tolerance range, set the two endpoints to be the same
     let (pt1, pt2) = (t1.endpoint2(), t2.endpoint1());
     let dist = pt1.distance_to(&pt2);
 
-    if !create_new_line || dist < TOLERANCE {
+    if !create_new_line || dist < tolerance {
         let (avg_x, avg_y) = ((pt1.x() + pt2.x()) / 2.0, (pt1.y() + pt2.y()) / 2.0);
         let avg = Vector2F::new(avg_x, avg_y);
 
@@ -120,49 +139,116 @@ fn connect_endpoints(
     }
 }
 
+// distance from a point to the closest endpoint of any edge other than the excluded one
 #[inline]
-fn reorder_endings_list(list: &mut [DataObjectContainer]) {
-    // inefficient algorithm? probably, it's O(n^2), but I can't think of another way of doing it
-    for i in 0..list.len() - 2 {
-        let (p1, p2) = list.split_at_mut(i + 1);
-        let p1: &[DataObjectContainer] = &*p1;
-        let l1 = &p1[p1.len() - 1];
-
-        let index = p2
-            .par_iter()
-            .map(|l| l.endpoint1().distance_to(&l1.endpoint2()))
-            .enumerate()
-            .filter(|(_i, l)| !l.is_nan())
-            .min_by(|(_i1, d1), (_i2, d2)| {
-                NotNan::new(*d1).unwrap().cmp(&NotNan::new(*d2).unwrap())
+fn nearest_other_endpoint(list: &[DataObjectContainer], pt: Vector2F, exclude: usize) -> f32 {
+    list.iter()
+        .enumerate()
+        .filter(|(i, _e)| *i != exclude)
+        .flat_map(|(_i, e)| iter::once(e.endpoint1()).chain(iter::once(e.endpoint2())))
+        .map(|end| pt.distance_to(&end))
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// Reorder and reorient a set of edges so that each one starts where the previous one ends.
+///
+/// Fails if the edges can't be joined into a single chain, i.e. if two edges are joined even
+/// though one of them has an endpoint closer to some other edge. If the chain isn't closed, it
+/// starts at the endpoint that is furthest from every other edge.
+fn chain_edges(list: &mut [DataObjectContainer], closed: bool) -> Result<(), &'static str> {
+    const EPSILON: f32 = 1.0e-3;
+
+    if !closed && list.len() > 1 {
+        let (index, reverse) = (0..list.len())
+            .flat_map(|i| {
+                iter::once((
+                    i,
+                    false,
+                    nearest_other_endpoint(list, list[i].endpoint1(), i),
+                ))
+                .chain(iter::once((
+                    i,
+                    true,
+                    nearest_other_endpoint(list, list[i].endpoint2(), i),
+                )))
             })
-            .unwrap()
-            .0;
-        p2.swap(0, index);
+            .filter(|(_i, _r, dist)| !dist.is_nan())
+            .max_by_key(|(_i, _r, dist)| NotNan::new(*dist).unwrap())
+            .map(|(i, r, _dist)| (i, r))
+            .ok_or("Unable to measure the distance between edges")?;
+
+        list.swap(0, index);
+        if reverse {
+            list[0].reverse();
+        }
+    }
+
+    // inefficient algorithm? probably, it's O(n^2), but I can't think of another way of doing it
+    for i in 1..list.len() {
+        let prev_end = list[i - 1].endpoint2();
+
+        // find the closest endpoint out of the remaining edges, and whether it's at the tail
+        let (index, reverse) =
+            list[i..]
+                .iter()
+                .enumerate()
+                .flat_map(|(j, e)| {
+                    iter::once((j, false, prev_end.distance_to(&e.endpoint1())))
+                        .chain(iter::once((j, true, prev_end.distance_to(&e.endpoint2()))))
+                })
+                .filter(|(_j, _r, dist)| !dist.is_nan())
+                .min_by_key(|(_j, _r, dist)| NotNan::new(*dist).unwrap())
+                .map(|(j, r, _dist)| (i + j, r))
+                .ok_or("Unable to measure the distance between edges")?;
+
+        list.swap(i, index);
+        if reverse {
+            list[i].reverse();
+        }
+    }
+
+    // make sure every join is between endpoints that are each other's closest match
+    let len = list.len();
+    if len < 2 {
+        return Ok(());
     }
+
+    let joins = if closed { len } else { len - 1 };
+    for i in 0..joins {
+        let next = (i + 1) % len;
+        let (end, start) = (list[i].endpoint2(), list[next].endpoint1());
+        let gap = end.distance_to(&start);
+
+        if gap > nearest_other_endpoint(list, end, i) + EPSILON
+            || gap > nearest_other_endpoint(list, start, next) + EPSILON
+        {
+            return Err("The selected items do not form a single chain");
+        }
+    }
+
+    Ok(())
 }
 
 impl GraphicalState {
-    /// Turn a set of beziers or lines into a polygon.
+    /// Turn a set of beziers or lines into a polygon. If `closed` is false, the last edge is not
+    /// connected back to the first, leaving an open path. When creating new lines, endpoints
+    /// closer than `tolerance` canvas pixels are snapped together instead.
     pub fn polygonify_selected_items(
         &mut self,
         brush: usize,
         create_new_line: bool,
         duplicate: bool,
+        closed: bool,
+        tolerance: f32,
     ) {
-        let locs: SmallVec<[StateDataLoc; 12]> = self.selected.drain(..).collect();
+        let locs: SmallVec<[StateDataLoc; 12]> = self.selected.drain(..).sorted().collect();
 
+        // work on copies, so nothing is lost if the items can't be turned into a polygon
         let lines: Option<SmallVec<[DataObjectContainer; 12]>> = locs
-            .into_iter()
-            .sorted()
-            .rev()
+            .iter()
             .map(|s| {
                 if let StateDataType::Line | StateDataType::Curve = s.0 {
-                    Some(if duplicate {
-                        s.item(self).clone_into_container()
-                    } else {
-                        s.take_item(self)
-                    })
+                    Some(s.item(self).clone_into_container())
                 } else {
                     None
                 }
@@ -173,11 +259,27 @@ impl GraphicalState {
         match lines {
             None => {
                 println!("Found a non-line element in the selection.");
+                self.selected.extend(locs);
+            }
+            Some(ref sm) if sm.len() < 2 => {
+                println!("Select at least two lines or curves to make a polygon.");
+                self.selected.extend(locs);
             }
             Some(mut sm) => {
-                reorder_endings_list(&mut sm);
+                if let Err(e) = chain_edges(&mut sm, closed) {
+                    println!("{}", e);
+                    self.selected.extend(locs);
+                    return;
+                }
 
-                for i in 1..=sm.len() {
+                if !duplicate {
+                    locs.iter().rev().for_each(|s| {
+                        s.take_item(self);
+                    });
+                }
+
+                let joins = if closed { sm.len() } else { sm.len() - 1 };
+                for i in 1..=joins {
                     let (p1, p2) = if i == sm.len() {
                         let (p2, p1) = sm.split_at_mut(1);
                         (p1, p2)
@@ -189,6 +291,7 @@ impl GraphicalState {
                         &mut p1[p1.len() - 1],
                         &mut p2[0],
                         create_new_line,
+                        tolerance,
                         &mut new_lines,
                     );
                 }
@@ -203,11 +306,15 @@ impl GraphicalState {
                             _ => unreachable!(),
                         })
                         .collect::<Vec<PolygonEdge>>(),
-                    PolygonType::Outline,
+                    if closed {
+                        PolygonType::Outline
+                    } else {
+                        PolygonType::Open
+                    },
                 );
                 let poly = Polyshape {
                     polygon: poly,
-                    brush,
+                    brush: brush.into(),
                 };
                 let did = self.next_data_id();
                 self.history.push(StateOperation::Add(StateDataLoc(
@@ -225,15 +332,255 @@ impl GraphicalState {
                 // generate ids ahead of time
                 let data_ids = self.next_data_ids(new_lines.len());
 
-                self.lines.extend(
-                    new_lines
-                        .into_iter()
-                        .enumerate()
-                        .map(|(i, l)| (data_ids[i], StateLine { points: l, brush })),
-                );
+                self.lines
+                    .extend(new_lines.into_iter().enumerate().map(|(i, l)| {
+                        (
+                            data_ids[i],
+                            StateLine {
+                                points: l,
+                                brush: brush.into(),
+                                widths: None,
+                            },
+                        )
+                    }));
 
                 self.last_history_selected.take();
             }
         }
     }
+
+    /// Break a polygon back up into a line or curve for each of its edges, drawn with the
+    /// polygon's brush. Does nothing if `loc` isn't a polygon.
+    pub fn explode_polygon(&mut self, loc: StateDataLoc) {
+        if loc.0 != StateDataType::Polygon {
+            return;
+        }
+        let Polyshape { polygon, brush } = match self.polygons.remove(&loc.1) {
+            Some(poly) => poly,
+            None => return,
+        };
+
+        self.selected.retain(|s| *s != loc);
+        self.history.retain(|op| op.added() != Some(loc));
+        self.last_history_selected.take();
+        self.invalidate_spatial_index();
+
+        polygon.edges().iter().for_each(|edge| {
+            let did = self.next_data_id();
+            let kind = match edge {
+                PolygonEdge::Straight(ref l) => {
+                    let points = [l.from::<Point2D<f32>>(), l.to::<Point2D<f32>>()];
+                    self.lines.insert(
+                        did,
+                        StateLine {
+                            points,
+                            brush,
+                            widths: None,
+                        },
+                    );
+                    StateDataType::Line
+                }
+                PolygonEdge::Curved(ref curve) => {
+                    self.curves.insert(
+                        did,
+                        Curve {
+                            curve: curve.clone(),
+                            brush,
+                        },
+                    );
+                    StateDataType::Curve
+                }
+            };
+            self.history
+                .push(StateOperation::Add(StateDataLoc(kind, did)));
+        });
+    }
+
+    /// Break every selected polygon back up into its edges.
+    pub fn explode_selected_polygons(&mut self) {
+        let polygons: SmallVec<[StateDataLoc; 12]> = self
+            .selected
+            .iter()
+            .copied()
+            .filter(|s| s.0 == StateDataType::Polygon)
+            .collect();
+        polygons
+            .into_iter()
+            .for_each(|loc| self.explode_polygon(loc));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BrushRef;
+
+    // every edge should start where the one before it ends
+    fn assert_chained(polygon: &Polygon, closed: bool) {
+        let edges = polygon.edges();
+        let joins = if closed { edges.len() } else { edges.len() - 1 };
+        (0..joins).for_each(|i| {
+            let end = edges[i].endpoints()[1];
+            let start = edges[(i + 1) % edges.len()].endpoints()[0];
+            assert!(end.distance_to(&start) < 1.0e-3, "edge {} is not joined", i);
+        });
+    }
+
+    #[test]
+    fn mixed_directions_form_a_triangle() {
+        let mut state = GraphicalState::new();
+        state.test_line((0.0, 0.0), (10.0, 0.0));
+        state.test_line((0.0, 10.0), (10.0, 0.0));
+        state.test_line((0.0, 0.0), (0.0, 10.0));
+        state.select_all();
+
+        state.polygonify_selected_items(0, false, false, true, 0.0);
+
+        assert!(state.lines().is_empty());
+        assert_eq!(state.polygons().len(), 1);
+        let polygon = &state.polygons().values().next().unwrap().polygon;
+        assert_eq!(polygon.edges().len(), 3);
+        assert_chained(polygon, true);
+    }
+
+    #[test]
+    fn closed_and_open_paths() {
+        let build = |closed: bool| {
+            let mut state = GraphicalState::new();
+            state.test_line((0.0, 0.0), (10.0, 0.0));
+            state.test_curve([(10.0, 0.0), (15.0, 5.0), (15.0, 5.0), (10.0, 10.0)]);
+            state.test_line((10.0, 10.0), (0.0, 10.0));
+            state.select_all();
+            state.polygonify_selected_items(0, false, false, closed, 0.0);
+            state
+        };
+
+        let closed = build(true);
+        let polygon = &closed.polygons().values().next().unwrap().polygon;
+        assert!(matches!(polygon.mode(), PolygonType::Outline));
+        assert_eq!(polygon.edges().len(), 3);
+        assert_chained(polygon, true);
+
+        // the open path leaves its ends where they were
+        let open = build(false);
+        let polygon = &open.polygons().values().next().unwrap().polygon;
+        assert!(matches!(polygon.mode(), PolygonType::Open));
+        assert_eq!(polygon.edges().len(), 3);
+        assert_chained(polygon, false);
+        let mut ends: Vec<(f32, f32)> = [
+            polygon.edges()[0].endpoints()[0],
+            polygon.edges()[2].endpoints()[1],
+        ]
+        .iter()
+        .map(|v| (v.x(), v.y()))
+        .collect();
+        ends.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(ends, vec![(0.0, 0.0), (0.0, 10.0)]);
+    }
+
+    #[test]
+    fn too_few_edges_are_rejected() {
+        for &closed in &[true, false] {
+            let mut state = GraphicalState::new();
+            state.polygonify_selected_items(0, false, false, closed, 0.0);
+            assert!(state.polygons().is_empty());
+
+            state.test_line((0.0, 0.0), (10.0, 0.0));
+            state.select_all();
+            state.polygonify_selected_items(0, false, false, closed, 0.0);
+            assert!(state.polygons().is_empty());
+            assert_eq!(state.lines().len(), 1);
+            assert_eq!(state.selected().len(), 1);
+        }
+    }
+
+    #[test]
+    fn disjoint_edges_are_rejected() {
+        let mut state = GraphicalState::new();
+        state.test_line((0.0, 0.0), (10.0, 0.0));
+        state.test_line((10.0, 0.0), (10.0, 10.0));
+        state.test_line((100.0, 100.0), (120.0, 100.0));
+        state.test_line((120.0, 100.0), (120.0, 120.0));
+        state.select_all();
+
+        state.polygonify_selected_items(0, false, false, true, 0.0);
+
+        // nothing is lost, and the items stay selected
+        assert!(state.polygons().is_empty());
+        assert_eq!(state.lines().len(), 4);
+        assert_eq!(state.selected().len(), 4);
+    }
+
+    // a triangle with its last corner left 3 pixels open
+    fn gapped_triangle(tolerance: f32) -> GraphicalState {
+        let mut state = GraphicalState::new();
+        state.test_line((0.0, 0.0), (10.0, 0.0));
+        state.test_line((10.0, 0.0), (0.0, 10.0));
+        state.test_line((0.0, 10.0), (0.0, 3.0));
+        state.select_all();
+        state.polygonify_selected_items(0, true, false, true, tolerance);
+        state
+    }
+
+    #[test]
+    fn gaps_over_the_tolerance_get_a_new_line() {
+        let state = gapped_triangle(2.0);
+        assert_eq!(state.polygons().len(), 1);
+        assert_eq!(state.lines().len(), 1);
+        let line = state.lines().values().next().unwrap();
+        assert_eq!(
+            line.points,
+            [Point2D::new(0.0, 3.0), Point2D::new(0.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn gaps_within_the_tolerance_are_snapped() {
+        let state = gapped_triangle(5.0);
+        assert!(state.lines().is_empty());
+        let polygon = &state.polygons().values().next().unwrap().polygon;
+        assert_eq!(polygon.edges().len(), 3);
+        assert_chained(polygon, true);
+    }
+
+    #[test]
+    fn exploding_gives_back_the_edges() {
+        let mut state = GraphicalState::new();
+        state.test_line((0.0, 0.0), (10.0, 0.0));
+        state.test_curve([(10.0, 0.0), (15.0, 5.0), (15.0, 5.0), (10.0, 10.0)]);
+        state.test_line((10.0, 10.0), (0.0, 10.0));
+        state.test_line((0.0, 10.0), (0.0, 0.0));
+        state.select_all();
+        state.polygonify_selected_items(1, false, false, true, 0.0);
+        assert_eq!(state.polygons().len(), 1);
+
+        state.select_all();
+        state.explode_selected_polygons();
+
+        assert!(state.polygons().is_empty());
+        assert!(state.selected().is_empty());
+        assert_eq!(state.lines().len(), 3);
+        assert_eq!(state.curves().len(), 1);
+        // and can be undone one at a time
+        state.creation_order().iter().for_each(|loc| {
+            assert!(state.history().contains(&StateOperation::Add(*loc)));
+        });
+        // the edges keep the polygon's brush
+        assert!(state
+            .lines()
+            .values()
+            .all(|l| matches!(l.brush, BrushRef::Index(1))));
+        assert!(state
+            .curves()
+            .values()
+            .all(|c| matches!(c.brush, BrushRef::Index(1))));
+    }
+
+    #[test]
+    fn only_polygons_explode() {
+        let mut state = GraphicalState::new();
+        let line = state.test_line((0.0, 0.0), (10.0, 0.0));
+        state.explode_polygon(line);
+        assert_eq!(state.lines().len(), 1);
+    }
 }
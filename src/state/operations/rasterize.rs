@@ -1,39 +1,160 @@
 // GPLv3 License
 
-use super::{BufferedLine, Curve, GraphicalState, StateDataLoc, StateDataType, StateLine};
-use crate::{colors, Brush, DrawTarget, DynamicColor, Project, Rasterizable};
-use euclid::default::Point2D;
+use super::{
+    BufferedLine, Curve, DataID, DataObject, GraphicalState, Polyshape, StateDataLoc,
+    StateDataType, StateLine,
+};
+use crate::{
+    colors, for_each_item, worth_parallelizing, Brush, BrushRef, DrawTarget, DynamicColor,
+    PixelBatch, Project, Rasterizable, TaperedLine,
+};
+use euclid::default::{Point2D, Rect};
+use itertools::Itertools;
 use parking_lot::{RwLock, RwLockUpgradableReadGuard};
 use pathfinder_geometry::{line_segment::LineSegment2F, vector::Vector2F};
 use rayon::prelude::*;
 use std::{borrow::Cow, mem};
 
-impl GraphicalState {
-    /// Rasterize this graphical state onto an image.
-    pub fn rasterize(&self, target: &DrawTarget, project: &Project) {
-        #[inline]
-        fn rasterize_item(
-            this: &GraphicalState,
-            sel_guard: &[StateDataLoc],
-            data_type: StateDataType,
-            index: &usize,
-            target: &DrawTarget,
-            item: &dyn Rasterizable,
-            ci: usize,
-            project: &Project,
-        ) {
-            // figure out the item location
-            let data_loc = StateDataLoc(data_type, *index);
-
-            let mut brush = Cow::Borrowed(project.brush(ci).expect("Brush ID Mismatch"));
-            if sel_guard.contains(&data_loc) {
-                const SELECT_COLOR: DynamicColor = DynamicColor::Solid(colors::BLUE);
-                brush.to_mut().set_color(SELECT_COLOR);
+// an object that's drawn with a brush
+#[derive(Copy, Clone)]
+enum Stroked<'a> {
+    Polygon(&'a Polyshape),
+    Curve(&'a Curve),
+    Line(&'a StateLine),
+}
+
+// what every object in a pass is drawn with
+struct RasterContext<'a> {
+    selected: &'a [StateDataLoc],
+    target: &'a DrawTarget,
+    project: &'a Project,
+    tolerance: f32,
+    seed: u64,
+    viewport: Option<&'a Rect<f32>>,
+}
+
+impl RasterContext<'_> {
+    // draw an object with its brush, adjusted for the project and for whether it's selected
+    // it goes straight onto the target, or into a batch if one is given
+    #[inline]
+    fn draw(
+        &self,
+        data_loc: StateDataLoc,
+        item: &dyn Rasterizable,
+        object: &dyn DataObject,
+        brush_ref: &BrushRef,
+        batch: Option<&mut PixelBatch>,
+    ) {
+        let StateDataLoc(data_type, index) = data_loc;
+        let mut brush = Cow::Borrowed(
+            brush_ref
+                .resolve(self.project.brushes())
+                .expect("Brush ID Mismatch"),
+        );
+
+        // skip anything that won't show up, leaving room for the brush around it
+        if let Some(viewport) = self.viewport {
+            let reach = object.reach(&brush) + 1.0;
+            let bounds = object.bounding_box().inflate(reach, reach);
+            if !bounds.intersects(viewport) {
+                return;
             }
+        }
+        if self.project.pixel_mode() && !brush.hard_edges() {
+            brush.to_mut().set_hard_edges(true);
+        }
+        if brush.jitter().is_some() {
+            // give each item its own scatter pattern, that stays the same between renders
+            let item_seed = self.seed
+                ^ (index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                ^ ((data_type as u64) << 56);
+            brush.to_mut().set_jitter_seed(item_seed);
+        }
+        if self.selected.contains(&data_loc) {
+            const SELECT_COLOR: DynamicColor = DynamicColor::Solid(colors::BLUE);
+            brush.to_mut().set_color(SELECT_COLOR);
+        }
 
-            item.rasterize(target, &*brush);
+        match batch {
+            Some(batch) => item.rasterize_into(batch, &brush, self.tolerance),
+            None => item.rasterize(self.target, &brush, self.tolerance),
         }
+    }
+
+    // draw one of the objects drawn with a brush
+    fn draw_stroked(&self, i: DataID, item: Stroked<'_>, batch: Option<&mut PixelBatch>) {
+        match item {
+            Stroked::Polygon(pl) => self.draw(
+                StateDataLoc(StateDataType::Polygon, i),
+                &pl.polygon,
+                pl,
+                &pl.brush,
+                batch,
+            ),
+            Stroked::Curve(c) => self.draw(
+                StateDataLoc(StateDataType::Curve, i),
+                &c.curve,
+                c,
+                &c.brush,
+                batch,
+            ),
+            Stroked::Line(ln) => {
+                let line = match ln.points {
+                    [Point2D { x: x1, y: y1, .. }, Point2D { x: x2, y: y2, .. }] => {
+                        LineSegment2F::new(Vector2F::new(x1, y1), Vector2F::new(x2, y2))
+                    }
+                };
+
+                let tapered;
+                let item: &dyn Rasterizable = match ln.widths {
+                    Some(widths) => {
+                        tapered = TaperedLine { line, widths };
+                        &tapered
+                    }
+                    None => &line,
+                };
+
+                self.draw(
+                    StateDataLoc(StateDataType::Line, i),
+                    item,
+                    ln,
+                    &ln.brush,
+                    batch,
+                );
+            }
+        }
+    }
+}
+
+impl GraphicalState {
+    /// Rasterize this graphical state onto an image, flattening curves to within `tolerance`.
+    #[inline]
+    pub fn rasterize(&self, target: &DrawTarget, project: &Project, tolerance: f32) {
+        self.rasterize_in(target, project, tolerance, None);
+    }
+
+    /// Rasterize only the objects that can be seen within `viewport`, in canvas coordinates.
+    /// Everything outside of it is left blank.
+    #[inline]
+    pub fn rasterize_culled(
+        &self,
+        target: &DrawTarget,
+        project: &Project,
+        tolerance: f32,
+        viewport: Rect<f32>,
+    ) {
+        self.rasterize_in(target, project, tolerance, Some(viewport));
+    }
 
+    fn rasterize_in(
+        &self,
+        target: &DrawTarget,
+        project: &Project,
+        tolerance: f32,
+        viewport: Option<Rect<f32>>,
+    ) {
+        // a seeded project scatters the same way wherever it's drawn
+        let seed = project.seed().unwrap_or(self.jitter_seed);
         let img = RwLock::upgradable_read(target);
 
         // update the bool if necessary
@@ -45,59 +166,106 @@ impl GraphicalState {
             mem::drop(img); // don't hog the lock
         }
 
-        self.polygons.par_iter().for_each(|(i, pl)| {
-            rasterize_item(
-                self,
-                &self.selected,
-                StateDataType::Polygon,
-                i,
-                target,
-                &pl.polygon,
-                pl.brush.clone(),
-                project,
-            );
-        });
+        // images go underneath everything else, in a consistent order
+        self.images
+            .iter()
+            .sorted_by_key(|(i, _img)| **i)
+            .filter(|(_i, img)| match viewport {
+                Some(ref viewport) => img.bounding_box().intersects(viewport),
+                None => true,
+            })
+            .for_each(|(i, img)| {
+                img.rasterize(target);
 
-        // draw some curves
-        self.curves
-            .par_iter()
-            .for_each(|(i, Curve { curve, brush: ci })| {
-                // get the brush we are using
-                rasterize_item(
-                    self,
-                    &self.selected,
-                    StateDataType::Curve,
-                    i,
-                    target,
-                    curve,
-                    *ci,
-                    project,
-                );
+                // outline the image if it's selected
+                if self
+                    .selected
+                    .contains(&StateDataLoc(StateDataType::Image, *i))
+                {
+                    const SELECT_BRUSH: Brush =
+                        Brush::new_const(DynamicColor::Solid(colors::BLUE), 1);
+
+                    let [a, b, c, d] = img.corners();
+                    [[a, b], [b, c], [c, d], [d, a]].iter().for_each(|side| {
+                        side.rasterize(target, &SELECT_BRUSH, tolerance);
+                    });
+                }
             });
 
-        // rasterize the lines
-        self.lines.par_iter().for_each(|(i, ln)| {
-            let line = match ln.points {
-                [Point2D { x: x1, y: y1, .. }, Point2D { x: x2, y: y2, .. }] => LineSegment2F::new(
-                    Vector2F::new(x1 as f32, y1 as f32),
-                    Vector2F::new(x2 as f32, y2 as f32),
-                ),
-            };
+        // symbols are drawn as if their objects were in this frame, moved to where they're placed
+        self.instances
+            .iter()
+            .sorted_by_key(|(i, _instance)| **i)
+            .filter(|(_i, instance)| match viewport {
+                Some(ref viewport) => instance.bounding_box().intersects(viewport),
+                None => true,
+            })
+            .for_each(|(i, instance)| {
+                if let Some(symbol) = project.symbols().get(instance.symbol) {
+                    symbol
+                        .state
+                        .placed_at(instance.offset)
+                        .rasterize_in(target, project, tolerance, viewport);
+                }
 
-            rasterize_item(
-                self,
-                &self.selected,
-                StateDataType::Line,
-                i,
-                target,
-                &line,
-                ln.brush,
-                project,
-            );
-        });
+                if self
+                    .selected
+                    .contains(&StateDataLoc(StateDataType::Symbol, *i))
+                {
+                    const SELECT_BRUSH: Brush =
+                        Brush::new_const(DynamicColor::Solid(colors::BLUE), 1);
+
+                    let [a, b, c, d] = instance.corners();
+                    [[a, b], [b, c], [c, d], [d, a]].iter().for_each(|side| {
+                        side.rasterize(target, &SELECT_BRUSH, tolerance);
+                    });
+                }
+            });
+
+        let context = RasterContext {
+            selected: &self.selected,
+            target,
+            project,
+            tolerance,
+            seed,
+            viewport: viewport.as_ref(),
+        };
+
+        // everything drawn with a brush goes in the order it was made, so newer objects are on top
+        let stroked: Vec<(DataID, Stroked<'_>)> = self
+            .polygons
+            .iter()
+            .map(|(i, pl)| (*i, Stroked::Polygon(pl)))
+            .chain(self.curves.iter().map(|(i, c)| (*i, Stroked::Curve(c))))
+            .chain(self.lines.iter().map(|(i, ln)| (*i, Stroked::Line(ln))))
+            .sorted_by_key(|(i, _item)| *i)
+            .collect();
+
+        if worth_parallelizing(stroked.len()) {
+            // threads finish in any order, so each object is drawn into a batch of its own and
+            // the batches are composited in creation order afterwards
+            let (width, height) = target.read().0.dimensions();
+            let batches: Vec<PixelBatch> = stroked
+                .par_iter()
+                .map(|(i, item)| {
+                    let mut batch = PixelBatch::new(width, height);
+                    context.draw_stroked(*i, *item, Some(&mut batch));
+                    batch
+                })
+                .collect();
+
+            let mut img = target.write();
+            batches.iter().for_each(|batch| batch.apply(&mut img.0));
+            img.1 = true;
+        } else {
+            stroked
+                .iter()
+                .for_each(|(i, item)| context.draw_stroked(*i, *item, None));
+        }
 
         // also rasterize the line buffer
-        self.buffered_lines.par_iter().for_each(|pts| {
+        let buffered = &self.buffered_lines[..];
+        for_each_item(buffered, buffered.len(), |pts| {
             const BUFFERED_BRUSH: Brush = Brush::new_const(DynamicColor::Solid(colors::RED), 3);
 
             let line = match pts {
@@ -109,7 +277,193 @@ impl GraphicalState {
                 }
             };
 
-            line.rasterize(target, &BUFFERED_BRUSH);
+            line.rasterize(target, &BUFFERED_BRUSH, tolerance);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlendMode, ColorAtom, TCImage, DEFAULT_PARALLEL_THRESHOLD};
+    use euclid::default::Size2D;
+    use ordered_float::NotNan;
+    use rayon::{ThreadPool, ThreadPoolBuilder};
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    // a grid of objects, with translucent strokes in other blend modes running across each row
+    // of it, so that drawing them in the wrong order changes what comes out
+    fn grid(count: usize) -> Project {
+        let mut project = Project::new(400, 400, 0.5);
+        let blended = |color, mode| {
+            let mut brush = Brush::new(color, 3);
+            brush.set_blend_mode(mode);
+            brush.set_opacity(NotNan::new(0.5).unwrap());
+            brush
+        };
+        let multiply = project.add_brush(blended(colors::RED, BlendMode::Multiply));
+        let screen = project.add_brush(blended(colors::BLUE, BlendMode::Screen));
+
+        let frame = project.current_frame_mut();
+        (0..count).for_each(|i| {
+            let (x, y) = ((i % 20) as f32 * 20.0, (i / 20 % 20) as f32 * 20.0);
+            match i % 3 {
+                0 => {
+                    frame.test_line((x + 2.0, y + 2.0), (x + 14.0, y + 10.0));
+                }
+                1 => {
+                    frame.test_curve([
+                        (x + 2.0, y + 2.0),
+                        (x + 6.0, y + 14.0),
+                        (x + 10.0, y + 2.0),
+                        (x + 14.0, y + 8.0),
+                    ]);
+                }
+                _ => {
+                    frame.test_polygon(&[
+                        (x + 2.0, y + 2.0),
+                        (x + 14.0, y + 4.0),
+                        (x + 6.0, y + 14.0),
+                    ]);
+                }
+            }
+
+            // a row's worth of cells is crossed by a stroke over every object in it
+            if i % 20 == 19 {
+                let across = frame.test_line((0.0, y + 8.0), (400.0, y + 6.0));
+                frame.select_only(&[across]);
+                frame.set_selected_brush(BrushRef::Index(multiply));
+                let band = frame.test_polygon(&[(0.0, y + 4.0), (400.0, y), (400.0, y + 12.0)]);
+                frame.select_only(&[band]);
+                frame.set_selected_brush(BrushRef::Index(screen));
+            }
+        });
+        frame.unselect();
+        project
+    }
+
+    #[inline]
+    fn pool(threads: usize) -> ThreadPool {
+        ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+    }
+
+    // draw the project on its own pool, rather than the global one
+    fn drawn_on(project: &Project, pool: &ThreadPool) -> TCImage {
+        let target = RwLock::new((TCImage::new(400, 400), false));
+        pool.install(|| project.current_frame().rasterize(&target, project, 0.25));
+        target.into_inner().0
+    }
+
+    #[test]
+    fn culled_objects_leave_their_region_blank() {
+        let mut project = Project::new(100, 40, 0.5);
+        let frame = project.current_frame_mut();
+        frame.test_line((5.0, 20.0), (25.0, 20.0));
+        frame.test_polygon(&[(70.0, 10.0), (90.0, 10.0), (80.0, 30.0)]);
+
+        let drawn = |viewport: Option<Rect<f32>>| {
+            let target = RwLock::new((TCImage::new(100, 40), false));
+            let frame = project.current_frame();
+            match viewport {
+                Some(viewport) => frame.rasterize_culled(&target, &project, 0.25, viewport),
+                None => frame.rasterize(&target, &project, 0.25),
+            }
+            let img = target.into_inner().0;
+            let inked =
+                |x0: u32, x1: u32| (x0..x1).any(|x| (0..40).any(|y| img.get_pixel(x, y)[3] > 0));
+            (inked(0, 50), inked(50, 100))
+        };
+
+        // only the left half is visible, so the polygon is skipped
+        let left_half = Rect::new(Point2D::new(0.0, 0.0), Size2D::new(50.0, 40.0));
+        assert_eq!(drawn(Some(left_half)), (true, false));
+        assert_eq!(drawn(None), (true, true));
+
+        // an object reaching into the viewport is still drawn
+        let overlapping = Rect::new(Point2D::new(0.0, 0.0), Size2D::new(72.0, 40.0));
+        assert_eq!(drawn(Some(overlapping)), (true, true));
+    }
+
+    // how many pixels are neither empty nor fully covered
+    fn partly_covered(img: &TCImage) -> usize {
+        img.pixels()
+            .filter(|px| px[3] > 0 && px[3] < ColorAtom::MAX)
+            .count()
+    }
+
+    #[test]
+    fn polygon_edges_are_smoothed_unless_hard() {
+        let mut project = Project::new(40, 40, 0.5);
+        project
+            .current_frame_mut()
+            .test_polygon(&[(5.5, 5.0), (35.0, 12.3), (12.7, 34.6)]);
+
+        let soft = drawn_on(&project, &pool(1));
+        assert!(partly_covered(&soft) > 0);
+        // the middle is still solid
+        assert_eq!(soft.get_pixel(17, 17)[3], ColorAtom::MAX);
+
+        project.brush_mut(0).unwrap().set_hard_edges(true);
+        let hard = drawn_on(&project, &pool(1));
+        assert_eq!(partly_covered(&hard), 0);
+        assert_eq!(hard.get_pixel(17, 17)[3], ColorAtom::MAX);
+    }
+
+    #[test]
+    fn pixel_mode_lines_are_crisp() {
+        let mut project = Project::new(40, 40, 0.5);
+        let frame = project.current_frame_mut();
+        frame.test_line((3.2, 4.7), (36.9, 31.4));
+        frame.test_polygon(&[(5.5, 25.0), (15.0, 28.3), (8.7, 36.6)]);
+        assert!(partly_covered(&drawn_on(&project, &pool(1))) > 0);
+
+        project.set_pixel_mode(true);
+        let crisp = drawn_on(&project, &pool(1));
+        assert!(crisp.pixels().any(|px| px[3] == ColorAtom::MAX));
+        assert_eq!(partly_covered(&crisp), 0);
+    }
+
+    #[test]
+    fn serial_and_parallel_draw_the_same_pixels() {
+        // enough of each kind to be spread across threads, with overlapping strokes that only
+        // come out the same if they're composited in the same order
+        let project = grid(DEFAULT_PARALLEL_THRESHOLD * 3 + 30);
+        let serial = drawn_on(&project, &pool(1));
+        assert!(serial.pixels().any(|px| px[3] > 0));
+        let parallel = pool(4);
+        (0..5).for_each(|_| assert!(serial == drawn_on(&project, &parallel)));
+    }
+
+    // run with `cargo test --release -- --ignored parallel_crossover` to see the numbers
+    #[test]
+    #[ignore]
+    fn parallel_crossover() {
+        const RUNS: u32 = 20;
+        let threads = thread::available_parallelism().map_or(4, |n| n.get());
+        let (serial, parallel) = (pool(1), pool(threads));
+        let time = |project: &Project, pool: &ThreadPool| -> Duration {
+            let start = Instant::now();
+            (0..RUNS).for_each(|_| {
+                drawn_on(project, pool);
+            });
+            start.elapsed() / RUNS
+        };
+
+        [3, 12, 24, 48, 96, 192, 400].iter().for_each(|count| {
+            let project = grid(*count);
+            println!(
+                "{} objects: {:?} on one thread, {:?} on {}",
+                count,
+                time(&project, &serial),
+                time(&project, &parallel),
+                threads
+            );
         });
     }
 }
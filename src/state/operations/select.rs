@@ -1,15 +1,79 @@
 // GPLv3 License
 
-use super::{GraphicalState, StateDataLoc, StateDataType, StateOperation};
-use crate::Point;
-use euclid::default::Point2D;
+use super::{super::spatial::SpatialIndex, GraphicalState, StateDataLoc, StateDataType};
+use crate::{Brush, BrushRef, DynamicColor, Point};
+use euclid::default::Rect;
 use itertools::Itertools;
 use ordered_float::NotNan;
-use rayon::prelude::*;
+use pathfinder_geometry::vector::Vector2F;
 use smallvec::SmallVec;
-use std::mem;
+use std::{mem, sync::atomic::Ordering};
 
 impl GraphicalState {
+    #[inline]
+    pub fn selected(&self) -> &[StateDataLoc] {
+        &self.selected
+    }
+
+    /// The combined bounds of every selected item, or `None` if nothing is selected.
+    pub fn selected_bounds(&self) -> Option<Rect<f32>> {
+        self.selected
+            .iter()
+            .map(|loc| loc.item(self).bounding_box())
+            .fold1(|a, b| a.union(&b))
+    }
+
+    /// The part of the canvas the selected items are drawn over, including how far past their
+    /// bounds their brushes reach. `palette` is used to look up brushes.
+    pub fn selected_drawn_bounds(&self, palette: &[Brush]) -> Option<Rect<f32>> {
+        self.selected
+            .iter()
+            .map(|loc| {
+                let StateDataLoc(ty, i) = loc;
+                let brush = match ty {
+                    StateDataType::Line => self.lines.get(i).map(|l| &l.brush),
+                    StateDataType::Curve => self.curves.get(i).map(|c| &c.brush),
+                    StateDataType::Polygon => self.polygons.get(i).map(|p| &p.brush),
+                    _ => None,
+                };
+                let item = loc.item(self);
+                let reach = brush
+                    .and_then(|b| b.resolve(palette))
+                    .map_or(0.0, |b| item.reach(b));
+                item.bounding_box().inflate(reach, reach)
+            })
+            .fold1(|a, b| a.union(&b))
+    }
+
+    /// Copy the selected items into a frame of their own. Nothing in the copy is selected.
+    #[inline]
+    pub fn selection_only(&self) -> GraphicalState {
+        self.subset(&self.selected)
+    }
+
+    /// Copy some of the items into a frame of their own.
+    pub fn subset(&self, items: &[StateDataLoc]) -> GraphicalState {
+        let mut state = GraphicalState::new();
+        items.iter().for_each(|loc| {
+            let StateDataLoc(ty, i) = *loc;
+            let item = loc.item(self).clone_into_container();
+            ty.assoc_collection_mut(&mut state).insert(i, item);
+        });
+        state
+            .next_data_id
+            .store(self.current_data_id(), Ordering::SeqCst);
+        state
+    }
+
+    /// Every item, in the order they were added. Data IDs are handed out in order, so this
+    /// works even for items that have fallen out of the history.
+    pub fn creation_order(&self) -> Vec<StateDataLoc> {
+        self.iter_data_objects()
+            .map(|(i, d)| StateDataLoc(d.data_type(), i))
+            .sorted_by_key(|StateDataLoc(_, i)| *i)
+            .collect()
+    }
+
     /// Unselect all items
     #[inline]
     pub fn unselect(&mut self) {
@@ -30,6 +94,7 @@ impl GraphicalState {
             ty.assoc_collection_mut(self).remove(i); // since we're going backwards, there shouldn't be
                                                      // many adverse side effects
         });
+        self.prune_groups();
     }
 
     /// Push an item from the history into the selected item.
@@ -45,8 +110,10 @@ impl GraphicalState {
             index
         };
 
-        let data_loc = match self.history[new_index] {
-            StateOperation::Add(sl) => sl.clone(),
+        // moves don't have anything to select
+        let data_loc = match self.history[new_index].added() {
+            Some(sl) => sl,
+            None => return,
         };
 
         let mut sel = &mut self.selected;
@@ -55,40 +122,121 @@ impl GraphicalState {
         }
     }
 
+    /// Find the endpoint of a line, curve or polygon edge closest to a point, if one is within
+    /// the tolerance.
+    pub fn nearest_endpoint(&self, pt: Vector2F, tolerance: f32) -> Option<Vector2F> {
+        let lines = self.lines.values().flat_map(|l| {
+            l.points
+                .iter()
+                .map(|p| Vector2F::new(p.x, p.y))
+                .collect::<SmallVec<[Vector2F; 2]>>()
+        });
+        let curves = self
+            .curves
+            .values()
+            .flat_map(|c| SmallVec::from_buf([c.curve.points()[0], c.curve.points()[3]]));
+        let polygons = self.polygons.values().flat_map(|p| {
+            p.polygon
+                .edges()
+                .iter()
+                .flat_map(|e| SmallVec::from_buf(e.endpoints()))
+                .collect::<SmallVec<[Vector2F; 8]>>()
+        });
+
+        lines
+            .chain(curves)
+            .chain(polygons)
+            .map(|end| (end, end.distance_to(&pt)))
+            .filter(|(_end, dist)| *dist <= tolerance)
+            .min_by_key(|(_end, dist)| NotNan::new(*dist).unwrap())
+            .map(|(end, _dist)| end)
+    }
+
+    /// The brushes used by the selected items, in the order they were selected. Items without a
+    /// brush, such as images, are skipped.
+    pub fn selected_brushes(&self) -> SmallVec<[BrushRef; 12]> {
+        self.selected
+            .iter()
+            .filter_map(|StateDataLoc(ty, i)| match ty {
+                StateDataType::Line => self.lines.get(i).map(|l| l.brush),
+                StateDataType::Curve => self.curves.get(i).map(|c| c.brush),
+                StateDataType::Polygon => self.polygons.get(i).map(|p| p.brush),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Draw every selected item that has a brush with the given brush instead.
+    pub fn set_selected_brush(&mut self, brush: BrushRef) {
+        let selected = &self.selected;
+        let (lines, curves, polygons) = (&mut self.lines, &mut self.curves, &mut self.polygons);
+
+        selected.iter().for_each(|StateDataLoc(ty, i)| match ty {
+            StateDataType::Line => lines.get_mut(i).into_iter().for_each(|l| l.brush = brush),
+            StateDataType::Curve => curves.get_mut(i).into_iter().for_each(|c| c.brush = brush),
+            StateDataType::Polygon => polygons
+                .get_mut(i)
+                .into_iter()
+                .for_each(|p| p.brush = brush),
+            _ => (),
+        });
+    }
+
+    /// Give each selected item its own copy of its brush, in a different color.
+    pub fn set_selected_color(&mut self, palette: &[Brush], color: DynamicColor) {
+        let selected = &self.selected;
+        let (lines, curves, polygons) = (&mut self.lines, &mut self.curves, &mut self.polygons);
+        let recolor = |brush: &mut BrushRef| {
+            if let Some(mut b) = brush.resolve(palette).copied() {
+                b.set_color(color);
+                *brush = BrushRef::Inline(b);
+            }
+        };
+
+        selected.iter().for_each(|StateDataLoc(ty, i)| match ty {
+            StateDataType::Line => lines
+                .get_mut(i)
+                .into_iter()
+                .for_each(|l| recolor(&mut l.brush)),
+            StateDataType::Curve => curves
+                .get_mut(i)
+                .into_iter()
+                .for_each(|c| recolor(&mut c.brush)),
+            StateDataType::Polygon => polygons
+                .get_mut(i)
+                .into_iter()
+                .for_each(|p| recolor(&mut p.brush)),
+            _ => (),
+        });
+    }
+
     /// Select the element closest to a click location.
     pub fn select_closest_element<P: Point<f32> + Sync>(&mut self, loc: P) {
-        // build a map of all of the lines and their associated data locations
-        // TODO: maybe cache this?
-        let point_map: Vec<(Point2D<f32>, usize, StateDataType)> = self
-            .iter_data_objects()
-            .flat_map(|(i, d)| {
-                d.points()
-                    .into_iter()
-                    .map(move |pt| (pt.into_euclid(), i, d.data_type()))
-            })
-            .collect();
-
-        if point_map.len() > 0 {
-            let mut sel = &mut self.selected;
-
-            let (_, index, item) = if let Some(i) = point_map
-                .par_iter()
-                .map(|(pt, i, d)| (pt.distance_to(&loc), i, d))
-                .filter(|(dist, i, d)| !sel.contains(&StateDataLoc(**d, **i)) && !dist.is_nan())
-                .min_by(|(dist1, _i1, _d1), (dist2, _i2, _d2)| {
-                    NotNan::new(*dist1)
-                        .unwrap()
-                        .cmp(&NotNan::new(*dist2).unwrap())
-                }) {
-                i
-            } else {
-                println!("No minimum identified");
-                return;
-            };
-
-            println!("Found index {:?} and item of type {:?}", index, item,);
-
-            sel.push(StateDataLoc(*item, *index));
+        let loc: Vector2F = Vector2F::new(loc.x(), loc.y());
+        if self.iter_data_objects().next().is_none() {
+            return;
         }
+
+        if self.spatial_index.is_none() {
+            self.spatial_index = Some(SpatialIndex::build(self));
+        }
+
+        let sel = &self.selected;
+        let closest = self
+            .spatial_index
+            .as_ref()
+            .unwrap()
+            .closest(self, loc, |l| !sel.contains(&l));
+
+        let StateDataLoc(item, index) = if let Some((_dist, l)) = closest {
+            l
+        } else {
+            println!("No minimum identified");
+            return;
+        };
+
+        println!("Found index {:?} and item of type {:?}", index, item,);
+
+        self.selected.push(StateDataLoc(item, index));
     }
 }
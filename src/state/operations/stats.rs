@@ -0,0 +1,123 @@
+// GPLv3 License
+
+use super::GraphicalState;
+use crate::{PolygonType, DEFAULT_TOLERANCE};
+use euclid::default::Rect;
+use itertools::Itertools;
+use std::fmt;
+
+/// Counts and measurements of what's in a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameStats {
+    pub lines: usize,
+    pub curves: usize,
+    pub polygons: usize,
+    pub images: usize,
+    pub symbols: usize,
+    pub buffered_lines: usize,
+    /// The combined length of every curve.
+    pub curve_length: f32,
+    /// The combined area of every filled polygon. Overlapping polygons are counted twice.
+    pub filled_area: f32,
+    /// The bounds of everything in the frame, or `None` if it's empty.
+    pub bounds: Option<Rect<f32>>,
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} lines, {} curves, {} polygons, {} images, {} symbols, {} buffered lines",
+            self.lines, self.curves, self.polygons, self.images, self.symbols, self.buffered_lines
+        )?;
+        writeln!(f, "Total curve length: {:.1}px", self.curve_length)?;
+        writeln!(f, "Total filled area: {:.1}px²", self.filled_area)?;
+        match self.bounds {
+            Some(b) => write!(
+                f,
+                "Content spans ({:.1}, {:.1}) to ({:.1}, {:.1}), {:.1}x{:.1}",
+                b.min_x(),
+                b.min_y(),
+                b.max_x(),
+                b.max_y(),
+                b.size.width,
+                b.size.height
+            ),
+            None => write!(f, "The frame is empty"),
+        }
+    }
+}
+
+impl GraphicalState {
+    /// Count and measure what's in this frame.
+    pub fn stats(&self) -> FrameStats {
+        let filled_area = self
+            .polygons
+            .values()
+            .chain(self.filled_polygons.values())
+            .filter(|p| matches!(p.polygon.mode(), PolygonType::Fill))
+            .map(|p| p.polygon.area(DEFAULT_TOLERANCE))
+            .sum();
+
+        FrameStats {
+            lines: self.lines.len(),
+            curves: self.curves.len(),
+            polygons: self.polygons.len() + self.filled_polygons.len(),
+            images: self.images.len(),
+            symbols: self.instances.len(),
+            buffered_lines: self.buffered_lines.len(),
+            curve_length: self
+                .curves
+                .values()
+                .map(|c| c.curve.length(DEFAULT_TOLERANCE))
+                .sum(),
+            filled_area,
+            bounds: self
+                .iter_data_objects()
+                .map(|(_i, d)| d.bounding_box())
+                .fold1(|a, b| a.union(&b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_count_and_measure_a_small_frame() {
+        let mut state = GraphicalState::new();
+        assert_eq!(state.stats().bounds, None);
+
+        state.test_line((0.0, 0.0), (10.0, 0.0));
+        state.test_line((0.0, 5.0), (10.0, 5.0));
+        state.test_curve([(0.0, 20.0), (10.0, 20.0), (20.0, 20.0), (30.0, 20.0)]);
+        state.test_polygon(&[(50.0, 50.0), (60.0, 50.0), (60.0, 60.0), (50.0, 60.0)]);
+
+        let stats = state.stats();
+        assert_eq!(
+            (
+                stats.lines,
+                stats.curves,
+                stats.polygons,
+                stats.images,
+                stats.symbols
+            ),
+            (2, 1, 1, 0, 0)
+        );
+        assert!(
+            (stats.curve_length - 30.0).abs() < 0.5,
+            "{}",
+            stats.curve_length
+        );
+        assert!(
+            (stats.filled_area - 100.0).abs() < 0.5,
+            "{}",
+            stats.filled_area
+        );
+
+        let bounds = stats.bounds.unwrap();
+        assert!(bounds.min_x() <= 0.0 && bounds.min_y() <= 0.0);
+        assert!(bounds.max_x() >= 60.0 && bounds.max_y() >= 60.0);
+    }
+}
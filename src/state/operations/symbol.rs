@@ -0,0 +1,66 @@
+// GPLv3 License
+
+use super::{GraphicalState, StateDataLoc, StateDataType, Symbol, SymbolInstance};
+use crate::DataID;
+use euclid::default::Vector2D;
+use pathfinder_geometry::vector::Vector2F;
+use std::sync::atomic::Ordering;
+
+impl GraphicalState {
+    /// Move the selected items out of this frame and into a frame of their own.
+    #[inline]
+    pub fn take_selected(&mut self) -> GraphicalState {
+        let taken = self.selection_only();
+        self.delete_selected();
+        taken
+    }
+
+    /// Place a symbol in this frame.
+    pub fn add_symbol_instance(&mut self, instance: SymbolInstance) -> DataID {
+        let did = self.next_data_id();
+        self.instances_mut().insert(did, instance);
+        self.update_history_add(StateDataType::Symbol, did, 1);
+        did
+    }
+
+    /// Copy everything in this frame, moved by an offset. Nothing in the copy is selected.
+    pub fn placed_at(&self, offset: Vector2D<f32>) -> GraphicalState {
+        let mut state = GraphicalState::new();
+        self.iter_data_objects().for_each(|(i, d)| {
+            let ty = d.data_type();
+            ty.assoc_collection_mut(&mut state)
+                .insert(i, d.clone_into_container());
+        });
+        state.groups = self.groups.clone();
+        state
+            .next_data_id
+            .store(self.current_data_id(), Ordering::SeqCst);
+
+        state.translate(Vector2F::new(offset.x, offset.y));
+        state
+    }
+
+    /// Tell whether any symbols are placed in this frame.
+    #[inline]
+    pub fn has_symbol_instances(&self) -> bool {
+        !self.instances.is_empty()
+    }
+
+    /// Tell whether any of the selected items are symbols.
+    #[inline]
+    pub fn symbols_selected(&self) -> bool {
+        self.selected()
+            .iter()
+            .any(|StateDataLoc(ty, _)| *ty == StateDataType::Symbol)
+    }
+
+    /// Update where every symbol placed in this frame ends up, after the symbols have changed.
+    pub fn refresh_symbol_instances(&mut self, symbols: &[Symbol]) {
+        self.invalidate_spatial_index();
+        self.instances.values_mut().for_each(|instance| {
+            if let Some(symbol) = symbols.get(instance.symbol) {
+                instance.refresh_bounds(symbol);
+            }
+        });
+    }
+}
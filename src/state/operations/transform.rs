@@ -0,0 +1,224 @@
+// GPLv3 License
+
+use super::{
+    BufferedLine, Curve, DataID, DataObject, DataObjectContainer, GraphicalState, Polyshape,
+    StateDataLoc, StateDataType, StateLine, StateOperation, HISTORY_LIMIT,
+};
+use crate::snap_to_grid;
+use euclid::default::{Point2D, Vector2D};
+use pathfinder_geometry::{transform2d::Transform2F, vector::Vector2F};
+use smallvec::SmallVec;
+use std::f32::consts::PI;
+
+/// The kind of symmetry to draw with.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Symmetry {
+    /// Mirror across the vertical axis through the center.
+    Mirror,
+    /// Repeat around the center this many times.
+    Radial(u32),
+}
+
+/// Settings for drawing with symmetry.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SymmetryConfig {
+    pub symmetry: Symmetry,
+    pub center: Point2D<f32>,
+}
+
+impl SymmetryConfig {
+    /// The transforms that produce each copy of a drawn object, not including the original.
+    pub fn transforms(&self) -> SmallVec<[Transform2F; 8]> {
+        let center = Vector2F::new(self.center.x, self.center.y);
+        let around_center = |t: Transform2F| {
+            Transform2F::from_translation(center) * t * Transform2F::from_translation(-center)
+        };
+
+        match self.symmetry {
+            Symmetry::Mirror => {
+                let mut sm = SmallVec::new();
+                sm.push(around_center(Transform2F::from_scale(Vector2F::new(
+                    -1.0, 1.0,
+                ))));
+                sm
+            }
+            Symmetry::Radial(folds) => (1..folds)
+                .map(|i| {
+                    around_center(Transform2F::from_rotation(
+                        2.0 * PI * i as f32 / folds as f32,
+                    ))
+                })
+                .collect(),
+        }
+    }
+}
+
+// apply a transform to an item, images and symbols are left alone since they can't be rotated
+#[inline]
+pub(super) fn transform_item(item: &mut DataObjectContainer, transform: &Transform2F) {
+    let transform_point = |p: &mut Point2D<f32>| {
+        let v = *transform * Vector2F::new(p.x, p.y);
+        *p = Point2D::new(v.x(), v.y());
+    };
+
+    match item {
+        DataObjectContainer::Curve(Curve { ref mut curve, .. }) => curve.transform(transform),
+        DataObjectContainer::StateLine(StateLine { ref mut points, .. }) => {
+            points.iter_mut().for_each(transform_point)
+        }
+        DataObjectContainer::Polyshape(Polyshape {
+            ref mut polygon, ..
+        }) => polygon.transform(transform),
+        DataObjectContainer::Image(_) | DataObjectContainer::Symbol(_) => (),
+    }
+}
+
+impl GraphicalState {
+    /// Move every item in this frame by an offset.
+    pub fn translate(&mut self, offset: Vector2F) {
+        let euclid_offset: Vector2D<f32> = Vector2D::new(offset.x(), offset.y());
+        let shift = |pts: &mut [Point2D<f32>; 2]| {
+            pts.iter_mut().for_each(|p| *p += euclid_offset);
+        };
+        self.invalidate_spatial_index();
+
+        self.curves
+            .values_mut()
+            .for_each(|c| c.curve.translate(offset));
+        self.lines.values_mut().for_each(|l| shift(&mut l.points));
+        self.buffered_lines
+            .iter_mut()
+            .for_each(|BufferedLine(pts)| shift(pts));
+        self.polygons
+            .values_mut()
+            .chain(self.filled_polygons.values_mut())
+            .for_each(|p| p.polygon.translate(offset));
+        self.images.values_mut().for_each(|i| {
+            let pos = i.position() + euclid_offset;
+            i.set_position(pos);
+        });
+        self.instances
+            .values_mut()
+            .for_each(|s| s.translate(euclid_offset));
+    }
+
+    /// Move every point of the lines, curves and polygons in this frame onto the quantization
+    /// grid, so that they can be stored compactly.
+    pub fn quantize_coordinates(&mut self) {
+        let snap = |v: Vector2F| Vector2F::new(snap_to_grid(v.x()), snap_to_grid(v.y()));
+        self.invalidate_spatial_index();
+
+        self.curves
+            .values_mut()
+            .for_each(|c| c.curve.points_mut().iter_mut().for_each(|p| *p = snap(*p)));
+        self.lines.values_mut().for_each(|l| {
+            l.points.iter_mut().for_each(|p| {
+                *p = Point2D::new(snap_to_grid(p.x), snap_to_grid(p.y));
+            })
+        });
+        self.polygons
+            .values_mut()
+            .chain(self.filled_polygons.values_mut())
+            .for_each(|p| p.polygon.map_points(snap));
+    }
+
+    /// Add symmetric copies of every line, curve and polygon created since `first_id`.
+    pub fn add_symmetric_copies(&mut self, first_id: DataID, config: &SymmetryConfig) {
+        let originals: SmallVec<[DataObjectContainer; 12]> = self
+            .iter_data_objects()
+            .filter(|(i, d)| {
+                *i >= first_id
+                    && d.data_type() != StateDataType::Image
+                    && d.data_type() != StateDataType::Symbol
+            })
+            .map(|(_i, d)| d.clone_into_container())
+            .collect();
+
+        let copies: SmallVec<[DataObjectContainer; 12]> = config
+            .transforms()
+            .iter()
+            .flat_map(|t| {
+                originals.iter().map(move |item| {
+                    let mut item = item.clone();
+                    transform_item(&mut item, t);
+                    item
+                })
+            })
+            .collect();
+
+        let data_ids = self.next_data_ids(copies.len());
+        for (did, item) in data_ids.into_iter().zip(copies) {
+            let kind = item.data_type();
+            self.history
+                .push(StateOperation::Add(StateDataLoc(kind, did)));
+            kind.assoc_collection_mut(self).insert(did, item);
+        }
+
+        while self.history.len() > HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // round to avoid float noise from the rotations
+    #[inline]
+    fn rounded(p: Point2D<f32>) -> (i32, i32) {
+        (p.x.round() as i32, p.y.round() as i32)
+    }
+
+    #[inline]
+    fn radial(folds: u32) -> SymmetryConfig {
+        SymmetryConfig {
+            symmetry: Symmetry::Radial(folds),
+            center: Point2D::new(50.0, 50.0),
+        }
+    }
+
+    #[test]
+    fn transforms_leave_out_the_original() {
+        assert_eq!(radial(4).transforms().len(), 3);
+        assert_eq!(radial(1).transforms().len(), 0);
+        let mirror = SymmetryConfig {
+            symmetry: Symmetry::Mirror,
+            center: Point2D::new(50.0, 50.0),
+        };
+        assert_eq!(mirror.transforms().len(), 1);
+    }
+
+    #[test]
+    fn four_fold_symmetry_makes_four_lines() {
+        let mut state = GraphicalState::new();
+        let first_id = state.current_data_id();
+        state.test_line((50.0, 50.0), (90.0, 50.0));
+        state.add_symmetric_copies(first_id, &radial(4));
+
+        assert_eq!(state.lines().len(), 4);
+        assert_eq!(state.history().len(), 4);
+
+        let mut ends: Vec<(i32, i32)> = state
+            .lines()
+            .values()
+            .map(|l| {
+                assert_eq!(rounded(l.points[0]), (50, 50));
+                rounded(l.points[1])
+            })
+            .collect();
+        ends.sort_unstable();
+        assert_eq!(ends, vec![(10, 50), (50, 10), (50, 90), (90, 50)]);
+    }
+
+    #[test]
+    fn older_objects_are_not_copied() {
+        let mut state = GraphicalState::new();
+        state.test_line((0.0, 0.0), (10.0, 0.0));
+        let first_id = state.current_data_id();
+        state.test_line((50.0, 50.0), (90.0, 50.0));
+        state.add_symmetric_copies(first_id, &radial(4));
+
+        assert_eq!(state.lines().len(), 5);
+    }
+}
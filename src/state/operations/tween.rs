@@ -0,0 +1,272 @@
+// GPLv3 License
+
+use super::{Curve, DataID, GraphicalState, Polyshape, StateLine};
+use crate::{Brush, BrushRef, DynamicColor, Polygon};
+use ordered_float::NotNan;
+use pathfinder_geometry::vector::Vector2F;
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+#[inline]
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// the brush partway between two brushes, where colors can only be blended if they're both solid
+fn tween_brush(a: &BrushRef, b: &BrushRef, t: f32, palette: &[Brush]) -> BrushRef {
+    if let (BrushRef::Index(i), BrushRef::Index(j)) = (a, b) {
+        if i == j {
+            return *a;
+        }
+    }
+
+    let (ba, bb) = match (a.resolve(palette), b.resolve(palette)) {
+        (Some(ba), Some(bb)) => (ba, bb),
+        _ => return if t < 0.5 { *a } else { *b },
+    };
+
+    let mut brush = if t < 0.5 { *ba } else { *bb };
+    if let (Some(ca), Some(cb)) = (ba.color().as_solid(), bb.color().as_solid()) {
+        brush.set_color(DynamicColor::Solid(ca.mix_linear(cb, t)));
+    }
+    brush.set_width(lerp(ba.width() as f32, bb.width() as f32, t).round() as u32);
+    let opacity = lerp(ba.opacity().into_inner(), bb.opacity().into_inner(), t);
+    brush.set_opacity(NotNan::new(opacity).unwrap_or(ba.opacity()));
+    BrushRef::Inline(brush)
+}
+
+// the brush made more transparent, for something fading in or out
+fn fade_brush(brush: &BrushRef, amount: f32, palette: &[Brush]) -> BrushRef {
+    match brush.resolve(palette) {
+        Some(b) if amount < 1.0 => {
+            let mut b = *b;
+            let opacity = b.opacity().into_inner() * amount.max(0.0);
+            b.set_opacity(NotNan::new(opacity).unwrap_or(b.opacity()));
+            BrushRef::Inline(b)
+        }
+        _ => *brush,
+    }
+}
+
+// every point of a polygon, in the order `Polygon::map_points` visits them
+#[inline]
+fn polygon_points(polygon: &Polygon) -> Vec<Vector2F> {
+    let mut points = Vec::new();
+    polygon.clone().map_points(|pt| {
+        points.push(pt);
+        pt
+    });
+    points
+}
+
+// a polygon partway between two others, if they have the same shape of edges
+fn tween_polygon(a: &Polygon, b: &Polygon, t: f32) -> Option<Polygon> {
+    let same_edges = a.edges().len() == b.edges().len()
+        && a.edges()
+            .iter()
+            .zip(b.edges())
+            .all(|(ea, eb)| ea.is_straight() == eb.is_straight());
+    let (pa, pb) = (polygon_points(a), polygon_points(b));
+    if !same_edges || pa.len() != pb.len() {
+        return None;
+    }
+
+    let mut polygon = a.clone();
+    let mut targets = pb.into_iter();
+    polygon.map_points(|pt| {
+        let target = targets.next().unwrap();
+        pt + (target - pt) * t
+    });
+    Some(polygon)
+}
+
+// match up the items of two collections by their IDs, tweening the ones in both and fading the
+// ones in only one of them
+fn tween_collection<T, F, G>(
+    a: &HashMap<DataID, T>,
+    b: &HashMap<DataID, T>,
+    t: f32,
+    mut both: F,
+    mut fade: G,
+) -> HashMap<DataID, T>
+where
+    T: Clone,
+    F: FnMut(&T, &T) -> T,
+    G: FnMut(&T, f32) -> Option<T>,
+{
+    let from_a = a.iter().filter_map(|(i, item)| match b.get(i) {
+        Some(other) => Some((*i, both(item, other))),
+        None => fade(item, 1.0 - t).map(|item| (*i, item)),
+    });
+    let from_a: Vec<(DataID, T)> = from_a.collect();
+    let from_b: Vec<(DataID, T)> = b
+        .iter()
+        .filter(|(i, _)| !a.contains_key(i))
+        .filter_map(|(i, item)| fade(item, t).map(|item| (*i, item)))
+        .collect();
+
+    from_a.into_iter().chain(from_b).collect()
+}
+
+impl GraphicalState {
+    /// Make a frame `t` of the way from this frame to another, where 0 is this frame and 1 is
+    /// the other. Objects are matched up by their type and ID. Lines, curves and polygons that
+    /// are only in one of the frames fade in or out; images and symbols can't fade, so they
+    /// appear or disappear halfway.
+    pub fn tween(&self, other: &GraphicalState, t: f32, palette: &[Brush]) -> GraphicalState {
+        let mut state = GraphicalState::new();
+        state.duration_ms = self.duration_ms;
+        let fade_step = |present: f32| present >= 0.5;
+
+        state.lines = tween_collection(
+            &self.lines,
+            &other.lines,
+            t,
+            |a, b| StateLine {
+                points: [
+                    a.points[0].lerp(b.points[0], t),
+                    a.points[1].lerp(b.points[1], t),
+                ],
+                brush: tween_brush(&a.brush, &b.brush, t, palette),
+                widths: match (a.widths, b.widths) {
+                    (Some([a0, a1]), Some([b0, b1])) => Some([lerp(a0, b0, t), lerp(a1, b1, t)]),
+                    _ if t < 0.5 => a.widths,
+                    _ => b.widths,
+                },
+            },
+            |l, amount| {
+                let mut l = l.clone();
+                l.brush = fade_brush(&l.brush, amount, palette);
+                Some(l)
+            },
+        );
+
+        state.curves = tween_collection(
+            &self.curves,
+            &other.curves,
+            t,
+            |a, b| {
+                let mut curve = a.curve.clone();
+                curve
+                    .points_mut()
+                    .iter_mut()
+                    .zip(b.curve.points())
+                    .for_each(|(pa, pb)| *pa = *pa + (*pb - *pa) * t);
+                Curve {
+                    curve,
+                    brush: tween_brush(&a.brush, &b.brush, t, palette),
+                }
+            },
+            |c, amount| {
+                let mut c = c.clone();
+                c.brush = fade_brush(&c.brush, amount, palette);
+                Some(c)
+            },
+        );
+
+        state.polygons = tween_collection(
+            &self.polygons,
+            &other.polygons,
+            t,
+            |a, b| match tween_polygon(&a.polygon, &b.polygon, t) {
+                Some(polygon) => Polyshape {
+                    polygon,
+                    brush: tween_brush(&a.brush, &b.brush, t, palette),
+                },
+                // polygons with different edges can't be blended, so switch halfway
+                None if t < 0.5 => a.clone(),
+                None => b.clone(),
+            },
+            |p, amount| {
+                let mut p = p.clone();
+                p.brush = fade_brush(&p.brush, amount, palette);
+                Some(p)
+            },
+        );
+
+        state.images = tween_collection(
+            &self.images,
+            &other.images,
+            t,
+            |a, b| {
+                let mut img = a.clone();
+                img.set_position(a.position().lerp(b.position(), t));
+                img.set_scale(lerp(a.scale(), b.scale(), t));
+                img
+            },
+            |img, amount| Some(img.clone()).filter(|_| fade_step(amount)),
+        );
+
+        state.instances = tween_collection(
+            &self.instances,
+            &other.instances,
+            t,
+            |a, b| {
+                if a.symbol != b.symbol {
+                    return if t < 0.5 { a.clone() } else { b.clone() };
+                }
+                let mut instance = a.clone();
+                instance.translate(a.offset.lerp(b.offset, t) - a.offset);
+                instance
+            },
+            |instance, amount| Some(instance.clone()).filter(|_| fade_step(amount)),
+        );
+
+        state.next_data_id.store(
+            self.current_data_id().max(other.current_data_id()),
+            Ordering::SeqCst,
+        );
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors;
+    use euclid::default::{Point2D, Vector2D};
+
+    #[test]
+    fn lines_move_between_keyframes() {
+        let palette = [Brush::new(colors::BLACK, 1)];
+        let mut from = GraphicalState::new();
+        let line = from.test_line((0.0, 0.0), (10.0, 0.0));
+        // the same line, with the same ID, somewhere else
+        let to = from.placed_at(Vector2D::new(40.0, 20.0));
+
+        let frames: Vec<GraphicalState> = (1..=3)
+            .map(|i| from.tween(&to, i as f32 / 4.0, &palette))
+            .collect();
+        let starts: Vec<Point2D<f32>> = frames
+            .iter()
+            .map(|f| f.lines()[&line.1].points[0])
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                Point2D::new(10.0, 5.0),
+                Point2D::new(20.0, 10.0),
+                Point2D::new(30.0, 15.0)
+            ]
+        );
+        assert_eq!(
+            frames[1].lines()[&line.1].points[1],
+            Point2D::new(30.0, 10.0)
+        );
+    }
+
+    #[test]
+    fn objects_in_one_keyframe_fade() {
+        let palette = [Brush::new(colors::BLACK, 1)];
+        let mut from = GraphicalState::new();
+        let line = from.test_line((0.0, 0.0), (10.0, 0.0));
+        let to = GraphicalState::new();
+
+        let opacity = |t: f32| {
+            let tweened = from.tween(&to, t, &palette);
+            let brush = tweened.lines()[&line.1].brush;
+            brush.resolve(&palette).unwrap().opacity().into_inner()
+        };
+        assert_eq!(opacity(0.25), 0.75);
+        assert_eq!(opacity(0.75), 0.25);
+    }
+}
@@ -0,0 +1,144 @@
+// GPLv3 License
+
+use super::{group::translate_item, GraphicalState, StateDataLoc, StateOperation, HISTORY_LIMIT};
+use pathfinder_geometry::vector::Vector2F;
+
+impl GraphicalState {
+    /// Start a gesture, such as a drag or a burst of nudges. Everything it moves is recorded as
+    /// one move, so that it's undone in one go.
+    #[inline]
+    pub fn begin_gesture(&mut self) {
+        self.gesture = Some(false);
+    }
+
+    /// Finish the current gesture, if there is one.
+    #[inline]
+    pub fn end_gesture(&mut self) {
+        self.gesture = None;
+    }
+
+    // put a move into the history, folding it into the last one if it's part of the same gesture
+    pub(super) fn record_move(&mut self, items: Vec<StateDataLoc>, offset: Vector2F) {
+        if self.gesture == Some(true) {
+            if let Some(StateOperation::Move(last, [dx, dy])) = self.history.last_mut() {
+                if *last == items {
+                    *dx += offset.x();
+                    *dy += offset.y();
+                    return;
+                }
+            }
+        }
+
+        if self.gesture.is_some() {
+            self.gesture = Some(true);
+        }
+        self.history
+            .push(StateOperation::Move(items, [offset.x(), offset.y()]));
+        while self.history.len() > HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+    }
+
+    /// Take back the last operation in the history, removing what it added or moving back what
+    /// it moved. Returns whether there was anything to undo.
+    pub fn undo(&mut self) -> bool {
+        self.end_gesture();
+        let op = match self.history.pop() {
+            Some(op) => op,
+            None => return false,
+        };
+        self.last_history_selected.take();
+        self.invalidate_spatial_index();
+
+        // anything deleted since can't be brought back
+        let exists =
+            |this: &Self, loc: &StateDataLoc| loc.0.assoc_collection(this).data_at(loc.1).is_some();
+        match op {
+            StateOperation::Add(loc) => {
+                if exists(self, &loc) {
+                    loc.take_item(self);
+                }
+                self.selected.retain(|s| *s != loc);
+                self.prune_groups();
+            }
+            StateOperation::Move(items, [dx, dy]) => {
+                let back = Vector2F::new(-dx, -dy);
+                for loc in &items {
+                    if !exists(self, loc) {
+                        continue;
+                    }
+                    let mut item = loc.take_item(self);
+                    translate_item(&mut item, back);
+                    loc.0.assoc_collection_mut(self).insert(loc.1, item);
+                }
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_burst_of_nudges_is_undone_at_once() {
+        let mut state = GraphicalState::new();
+        let line = state.test_line((0.0, 0.0), (10.0, 0.0));
+        let history = state.history().len();
+        state.select_only(&[line]);
+
+        state.begin_gesture();
+        (0..10).for_each(|_| state.translate_selected(Vector2F::new(1.0, 0.0)));
+        state.end_gesture();
+        assert_eq!(state.history().len(), history + 1);
+        assert_eq!(state.lines()[&line.1].points[0].x, 10.0);
+
+        assert!(state.undo());
+        assert_eq!(state.lines()[&line.1].points[0].x, 0.0);
+        assert_eq!(state.lines()[&line.1].points[1].x, 10.0);
+        assert_eq!(state.history().len(), history);
+    }
+
+    #[test]
+    fn separate_gestures_are_undone_separately() {
+        let mut state = GraphicalState::new();
+        let line = state.test_line((0.0, 0.0), (10.0, 0.0));
+        state.select_only(&[line]);
+
+        // moves outside of a gesture aren't folded together either
+        state.translate_selected(Vector2F::new(0.0, 1.0));
+        state.translate_selected(Vector2F::new(0.0, 1.0));
+        (0..2).for_each(|_| {
+            state.begin_gesture();
+            state.translate_selected(Vector2F::new(0.0, 5.0));
+            state.translate_selected(Vector2F::new(0.0, 5.0));
+            state.end_gesture();
+        });
+        assert_eq!(state.lines()[&line.1].points[0].y, 22.0);
+
+        let heights: Vec<f32> = (0..4)
+            .map(|_| {
+                state.undo();
+                state.lines()[&line.1].points[0].y
+            })
+            .collect();
+        assert_eq!(heights, vec![12.0, 2.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn undoing_an_addition_removes_it() {
+        let mut state = GraphicalState::new();
+        let kept = state.test_line((0.0, 0.0), (10.0, 0.0));
+        let added = state.test_polygon(&[(0.0, 0.0), (5.0, 0.0), (0.0, 5.0)]);
+        state.select_only(&[kept, added]);
+
+        assert!(state.undo());
+        assert!(state.polygons().is_empty());
+        assert_eq!(state.selected(), &[kept]);
+        assert!(state.undo());
+        assert!(state.is_empty());
+        assert!(!state.undo());
+    }
+}
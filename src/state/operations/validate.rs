@@ -0,0 +1,80 @@
+// GPLv3 License
+
+use super::{GraphicalState, StateDataLoc};
+use crate::BrushRef;
+
+impl GraphicalState {
+    // every brush used by the lines, curves and polygons in this frame
+    fn brush_refs(&self) -> impl Iterator<Item = &BrushRef> {
+        self.lines
+            .values()
+            .map(|l| &l.brush)
+            .chain(self.curves.values().map(|c| &c.brush))
+            .chain(self.polygons.values().map(|p| &p.brush))
+            .chain(self.filled_polygons.values().map(|p| &p.brush))
+    }
+
+    /// Describe anything in this frame that refers to a brush or symbol that doesn't exist, or
+    /// is otherwise inconsistent. `name` says which frame this is in the descriptions.
+    pub fn problems(&self, name: &str, brush_count: usize, symbol_count: usize) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let bad_brushes = self
+            .brush_refs()
+            .filter(|b| matches!(b, BrushRef::Index(i) if *i >= brush_count))
+            .count();
+        if bad_brushes > 0 {
+            problems.push(format!(
+                "{} has {} objects using brushes that aren't in the palette of {}",
+                name, bad_brushes, brush_count
+            ));
+        }
+
+        let bad_instances = self
+            .instances
+            .values()
+            .filter(|instance| instance.symbol >= symbol_count)
+            .count();
+        if bad_instances > 0 {
+            problems.push(format!(
+                "{} places {} symbols that don't exist",
+                name, bad_instances
+            ));
+        }
+
+        let missing = |loc: &StateDataLoc| loc.0.assoc_collection(self).data_at(loc.1).is_none();
+        if self.selected.iter().any(missing) {
+            problems.push(format!("{} has selected objects that don't exist", name));
+        }
+        if self.groups.iter().any(|g| g.members.iter().any(missing)) {
+            problems.push(format!("{} has groups of objects that don't exist", name));
+        }
+
+        problems
+    }
+
+    /// Fix the problems reported by `problems`. Objects using missing brushes get the first
+    /// brush instead, and missing symbols and objects are forgotten.
+    pub fn repair(&mut self, brush_count: usize, symbol_count: usize) {
+        self.invalidate_spatial_index();
+        let fix = |b: &mut BrushRef| {
+            if matches!(b, BrushRef::Index(i) if *i >= brush_count) {
+                *b = BrushRef::Index(0);
+            }
+        };
+        self.lines.values_mut().for_each(|l| fix(&mut l.brush));
+        self.curves.values_mut().for_each(|c| fix(&mut c.brush));
+        self.polygons
+            .values_mut()
+            .chain(self.filled_polygons.values_mut())
+            .for_each(|p| fix(&mut p.brush));
+
+        self.instances
+            .retain(|_, instance| instance.symbol < symbol_count);
+
+        let mut selected = std::mem::take(&mut self.selected);
+        selected.retain(|loc| loc.0.assoc_collection(self).data_at(loc.1).is_some());
+        self.selected = selected;
+        self.prune_groups();
+    }
+}
@@ -0,0 +1,231 @@
+// GPLv3 License
+
+use super::{GraphicalState, StateDataLoc};
+use pathfinder_geometry::vector::Vector2F;
+use smallvec::SmallVec;
+use std::collections::{HashMap, HashSet};
+
+/// The width and height of each cell of the index, in canvas pixels.
+const CELL_SIZE: f32 = 64.0;
+/// Objects covering more cells than this are kept in a list of their own and always checked.
+const MAX_CELLS_PER_OBJECT: i64 = 256;
+
+/// A grid over the canvas that records which objects lie in each cell, so that finding the
+/// object closest to a point only has to look at the objects near it.
+pub struct SpatialIndex {
+    cells: HashMap<(i32, i32), SmallVec<[StateDataLoc; 4]>>,
+    large: Vec<StateDataLoc>,
+    // the range of cells that have anything in them
+    min_cell: (i32, i32),
+    max_cell: (i32, i32),
+}
+
+#[inline]
+fn cell_of(x: f32, y: f32) -> (i32, i32) {
+    (
+        (x / CELL_SIZE).floor() as i32,
+        (y / CELL_SIZE).floor() as i32,
+    )
+}
+
+impl SpatialIndex {
+    /// Index every data object in a frame by its bounding box.
+    pub fn build(state: &GraphicalState) -> Self {
+        let mut index = Self {
+            cells: HashMap::new(),
+            large: Vec::new(),
+            min_cell: (i32::MAX, i32::MAX),
+            max_cell: (i32::MIN, i32::MIN),
+        };
+
+        state.iter_data_objects().for_each(|(i, d)| {
+            let loc = StateDataLoc(d.data_type(), i);
+            let bounds = d.bounding_box();
+            if !bounds.origin.x.is_finite() || !bounds.origin.y.is_finite() {
+                index.large.push(loc);
+                return;
+            }
+
+            let (x0, y0) = cell_of(bounds.min_x(), bounds.min_y());
+            let (x1, y1) = cell_of(bounds.max_x(), bounds.max_y());
+            if (x1 - x0 + 1) as i64 * (y1 - y0 + 1) as i64 > MAX_CELLS_PER_OBJECT {
+                index.large.push(loc);
+                return;
+            }
+
+            index.min_cell = (index.min_cell.0.min(x0), index.min_cell.1.min(y0));
+            index.max_cell = (index.max_cell.0.max(x1), index.max_cell.1.max(y1));
+            (x0..=x1).for_each(|x| {
+                (y0..=y1).for_each(|y| index.cells.entry((x, y)).or_default().push(loc))
+            });
+        });
+
+        index
+    }
+
+    /// Find the object closest to a point, along with its distance, skipping any object that
+    /// `include` rejects.
+    pub fn closest<F: Fn(StateDataLoc) -> bool>(
+        &self,
+        state: &GraphicalState,
+        pt: Vector2F,
+        include: F,
+    ) -> Option<(f32, StateDataLoc)> {
+        let mut best: Option<(f32, StateDataLoc)> = None;
+        let mut checked = HashSet::new();
+        let mut check = |loc: StateDataLoc, best: &mut Option<(f32, StateDataLoc)>| {
+            if !checked.insert(loc) || !include(loc) {
+                return;
+            }
+
+            let dist = loc.item(state).distance_to(pt);
+            if !dist.is_nan() && best.is_none_or(|(b, _)| dist < b) {
+                *best = Some((dist, loc));
+            }
+        };
+
+        self.large.iter().for_each(|loc| check(*loc, &mut best));
+
+        if self.cells.is_empty() {
+            return best;
+        }
+
+        // search rings of cells outwards from the point, until nothing further out can be closer
+        let (cx, cy) = cell_of(pt.x(), pt.y());
+        let last_ring = [
+            cx - self.min_cell.0,
+            self.max_cell.0 - cx,
+            cy - self.min_cell.1,
+            self.max_cell.1 - cy,
+        ]
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(0);
+
+        for ring in 0..=last_ring {
+            let mut visit = |x: i32, y: i32| {
+                if let Some(locs) = self.cells.get(&(x, y)) {
+                    locs.iter().for_each(|loc| check(*loc, &mut best));
+                }
+            };
+
+            if ring == 0 {
+                visit(cx, cy);
+            } else {
+                (cx - ring..=cx + ring).for_each(|x| {
+                    visit(x, cy - ring);
+                    visit(x, cy + ring);
+                });
+                (cy - ring + 1..cy + ring).for_each(|y| {
+                    visit(cx - ring, y);
+                    visit(cx + ring, y);
+                });
+            }
+
+            // anything in a further ring is at least this far away
+            if let Some((dist, _)) = best {
+                if dist <= ring as f32 * CELL_SIZE {
+                    break;
+                }
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::time::Instant;
+
+    // a frame scattered with short lines, curves and polygons
+    fn scattered(count: usize) -> GraphicalState {
+        let mut rng = StdRng::seed_from_u64(1891);
+        let mut state = GraphicalState::new();
+        (0..count).for_each(|i| {
+            let (x, y) = (rng.gen_range(0.0, 4000.0), rng.gen_range(0.0, 4000.0));
+            match i % 3 {
+                0 => {
+                    state.test_line((x, y), (x + 20.0, y + 5.0));
+                }
+                1 => {
+                    state.test_curve([(x, y), (x + 5.0, y + 10.0), (x + 10.0, y), (x + 15.0, y)]);
+                }
+                _ => {
+                    state.test_polygon(&[(x, y), (x + 10.0, y), (x + 5.0, y + 10.0)]);
+                }
+            }
+        });
+        // and one covering everything
+        state.test_line((-5000.0, -5000.0), (9000.0, 9000.0));
+        state
+    }
+
+    // the closest object by checking every one of them
+    fn closest_by_scan(state: &GraphicalState, pt: Vector2F) -> f32 {
+        state
+            .iter_data_objects()
+            .map(|(_, d)| d.distance_to(pt))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    #[test]
+    fn index_finds_the_same_distance_as_a_scan() {
+        let state = scattered(2000);
+        let index = SpatialIndex::build(&state);
+        let mut rng = StdRng::seed_from_u64(1);
+        (0..100).for_each(|_| {
+            let pt = Vector2F::new(rng.gen_range(-500.0, 4500.0), rng.gen_range(-500.0, 4500.0));
+            let (dist, _) = index.closest(&state, pt, |_| true).unwrap();
+            assert_eq!(dist, closest_by_scan(&state, pt));
+        });
+    }
+
+    #[test]
+    fn excluded_objects_are_skipped() {
+        let mut state = GraphicalState::new();
+        let near = state.test_line((0.0, 0.0), (10.0, 0.0));
+        let far = state.test_line((500.0, 0.0), (510.0, 0.0));
+        let index = SpatialIndex::build(&state);
+        let pt = Vector2F::new(5.0, 1.0);
+        assert_eq!(index.closest(&state, pt, |_| true).unwrap().1, near);
+        assert_eq!(index.closest(&state, pt, |l| l != near).unwrap().1, far);
+        assert!(index.closest(&state, pt, |_| false).is_none());
+    }
+
+    // run with `cargo test --release -- --ignored index_speedup` to see the numbers
+    #[test]
+    #[ignore]
+    fn index_speedup() {
+        let state = scattered(20_000);
+        let clicks: Vec<Vector2F> = (0..100)
+            .map(|i| Vector2F::new(i as f32 * 40.0, 4000.0 - i as f32 * 40.0))
+            .collect();
+
+        let start = Instant::now();
+        let index = SpatialIndex::build(&state);
+        let build = start.elapsed();
+        let start = Instant::now();
+        clicks.iter().for_each(|pt| {
+            index.closest(&state, *pt, |_| true);
+        });
+        let indexed = start.elapsed();
+        let start = Instant::now();
+        clicks.iter().for_each(|pt| {
+            closest_by_scan(&state, *pt);
+        });
+        let scanned = start.elapsed();
+
+        println!(
+            "{} clicks: {:?} to build the index, {:?} per click with it, {:?} per click without",
+            clicks.len(),
+            build,
+            indexed / clicks.len() as u32,
+            scanned / clicks.len() as u32
+        );
+    }
+}
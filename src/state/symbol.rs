@@ -0,0 +1,114 @@
+// GPLv3 License
+
+use super::{line_to_points, DataObject, DataObjectContainer, GraphicalState, StateDataType};
+use euclid::default::{Point2D, Rect, Vector2D};
+use itertools::Itertools;
+use pathfinder_geometry::vector::Vector2F;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+/// A group of objects stored once in a project, that can be placed any number of times in its
+/// frames. Changing the symbol changes every place it shows up.
+#[derive(Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub state: GraphicalState,
+}
+
+impl Symbol {
+    #[inline]
+    pub fn new(name: String, state: GraphicalState) -> Self {
+        Self { name, state }
+    }
+
+    /// The combined bounds of everything in the symbol, or an empty rectangle at the origin if
+    /// it's empty.
+    #[inline]
+    pub fn bounding_box(&self) -> Rect<f32> {
+        self.state
+            .iter_data_objects()
+            .map(|(_i, d)| d.bounding_box())
+            .fold1(|a, b| a.union(&b))
+            .unwrap_or_else(Rect::zero)
+    }
+}
+
+/// A symbol placed in a frame, moved by an offset from where the symbol's objects are.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SymbolInstance {
+    /// The index of the symbol in the project.
+    pub symbol: usize,
+    pub offset: Vector2D<f32>,
+    // where the symbol ends up, kept around so that the instance can be selected without
+    // looking at the symbol
+    bounds: Rect<f32>,
+}
+
+impl SymbolInstance {
+    #[inline]
+    pub fn new(symbol: usize, definition: &Symbol, offset: Vector2D<f32>) -> Self {
+        let mut instance = Self {
+            symbol,
+            offset,
+            bounds: Rect::zero(),
+        };
+        instance.refresh_bounds(definition);
+        instance
+    }
+
+    /// Work out the bounds again, after the symbol has been changed.
+    #[inline]
+    pub fn refresh_bounds(&mut self, definition: &Symbol) {
+        self.bounds = definition.bounding_box().translate(self.offset);
+    }
+
+    /// The corners of the instance's bounds, clockwise from the top left.
+    #[inline]
+    pub fn corners(&self) -> [Point2D<f32>; 4] {
+        let (min, max) = (self.bounds.min(), self.bounds.max());
+        [
+            min,
+            Point2D::new(max.x, min.y),
+            max,
+            Point2D::new(min.x, max.y),
+        ]
+    }
+
+    #[inline]
+    pub fn translate(&mut self, offset: Vector2D<f32>) {
+        self.offset += offset;
+        self.bounds = self.bounds.translate(offset);
+    }
+}
+
+impl DataObject for SymbolInstance {
+    #[inline]
+    fn data_type(&self) -> StateDataType {
+        StateDataType::Symbol
+    }
+
+    // like images, instances are selected by their bounding box
+    #[inline]
+    fn points(&self) -> SmallVec<[Vector2F; 4]> {
+        let [a, b, c, d] = self.corners();
+        [[a, b], [b, c], [c, d], [d, a]]
+            .iter()
+            .flat_map(line_to_points)
+            .collect()
+    }
+
+    #[inline]
+    fn bounding_box(&self) -> Rect<f32> {
+        self.bounds
+    }
+
+    #[inline]
+    fn into_container(self) -> DataObjectContainer {
+        DataObjectContainer::Symbol(self)
+    }
+
+    #[inline]
+    fn clone_into_container(&self) -> DataObjectContainer {
+        self.clone().into_container()
+    }
+}
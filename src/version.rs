@@ -0,0 +1,105 @@
+// GPLv3 License
+
+use serde::{Deserialize, Deserializer};
+use std::cell::Cell;
+
+/// The versions of the saved project layout, each named for what it changed. Whenever a saved
+/// type gains a field or changes how it's written, add a version here, point `PROJECT_VERSION`
+/// at it and read the new field with `since`, so that older projects still load.
+pub mod layout {
+    /// Projects gained a version field. Anything older is version 0.
+    pub const VERSIONED: u32 = 1;
+    /// Brushes gained an opacity.
+    pub const BRUSH_OPACITY: u32 = 2;
+    /// Brushes gained a blend mode.
+    pub const BLEND_MODES: u32 = 3;
+    /// Frames gained embedded images.
+    pub const IMAGES: u32 = 4;
+    /// Projects gained a seed for random effects.
+    pub const SEEDS: u32 = 5;
+    /// Brushes gained jitter.
+    pub const JITTER: u32 = 6;
+    /// Lines gained widths at each end.
+    pub const TAPERED_LINES: u32 = 7;
+    /// Projects gained their own endpoint tolerance.
+    pub const ENDPOINT_TOLERANCE: u32 = 8;
+    /// Projects gained a title, an author and timestamps.
+    pub const METADATA: u32 = 9;
+    /// Gradients went from two colors to any number of stops.
+    pub const GRADIENT_STOPS: u32 = 10;
+    /// Objects went from a brush index to either an index or a brush of their own.
+    pub const BRUSH_REFS: u32 = 11;
+    /// Coordinates are packed in binary formats, and projects can quantize them.
+    pub const QUANTIZED: u32 = 12;
+    /// Projects gained guides.
+    pub const GUIDES: u32 = 13;
+    /// Projects gained symbols, and frames gained instances of them.
+    pub const SYMBOLS: u32 = 14;
+    /// Frames gained groups.
+    pub const GROUPS: u32 = 15;
+    /// Brushes gained the option of hard edges.
+    pub const HARD_EDGES: u32 = 16;
+    /// Projects gained pixel art mode.
+    pub const PIXEL_MODE: u32 = 17;
+    /// Projects gained the mode they were saved in.
+    pub const SESSION_MODE: u32 = 18;
+    /// Frames gained durations.
+    pub const FRAME_DURATIONS: u32 = 19;
+    /// Projects gained a loop mode for animations.
+    pub const LOOP_MODES: u32 = 20;
+    /// Frames gained a seed for scattering jittered strokes.
+    pub const JITTER_SEEDS: u32 = 21;
+}
+
+/// The current version of the project layout.
+pub const PROJECT_VERSION: u32 = layout::JITTER_SEEDS;
+
+thread_local! {
+    // the layout version of whatever is being deserialized on this thread
+    static LOADING_VERSION: Cell<u32> = const { Cell::new(PROJECT_VERSION) };
+}
+
+/// Run a closure that deserializes something saved with the given layout version.
+#[inline]
+pub fn with_loading_version<T, F: FnOnce() -> T>(version: u32, f: F) -> T {
+    let previous = LOADING_VERSION.with(|v| v.replace(version));
+    let result = f();
+    LOADING_VERSION.with(|v| v.set(previous));
+    result
+}
+
+/// The layout version of what is being deserialized. Outside of `with_loading_version`, this
+/// is the current version.
+#[inline]
+pub fn loading_version() -> u32 {
+    LOADING_VERSION.with(|v| v.get())
+}
+
+/// Deserialize a field that was added in layout version `V`, using its default if the data is
+/// older than that.
+#[inline]
+pub fn since<'de, D, T, const V: u32>(de: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    since_or(de, V, T::default)
+}
+
+/// Deserialize a field that was added in the given layout version, using `missing` to make it
+/// if the data is older than that. Binary formats can't skip fields that aren't there, so in
+/// older data nothing is read at all.
+#[inline]
+pub fn since_or<'de, D, T, F>(de: D, version: u32, missing: F) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+    F: FnOnce() -> T,
+{
+    // self-describing formats just leave out the fields they don't have
+    if de.is_human_readable() || loading_version() >= version {
+        T::deserialize(de)
+    } else {
+        Ok(missing())
+    }
+}